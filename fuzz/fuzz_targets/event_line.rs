@@ -0,0 +1,22 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Feed arbitrary lines of an FM log to the ereport parser, which must return
+// an error rather than panic on anything malformed.
+//
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate fm_log_report;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(log) = std::str::from_utf8(data) {
+        for line in log.lines() {
+            let _ = fm_log_report::parse_ereport(line);
+        }
+    }
+});
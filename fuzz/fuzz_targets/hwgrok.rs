@@ -0,0 +1,25 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Feed arbitrary hwgrok output to its parser, and look up devices in whatever
+// it accepts, none of which may panic.
+//
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate fm_log_report;
+
+use fm_log_report::hwgrok;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(contents) = std::str::from_utf8(data) {
+        if let Ok(hwgrok) = hwgrok::parse_hwgrok(contents) {
+            let _ = hwgrok.identifiers("/pci@0,0/pci8086,2f02@1/disk@0,0");
+            let _ = hwgrok.component_label("hc:///motherboard=0/chip=0");
+        }
+    }
+});
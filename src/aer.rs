@@ -0,0 +1,161 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Decoding of the PCIe Advanced Error Reporting registers captured in the
+// payloads of PCIe ereports (ereport.io.pciex.*).  The uncorrectable and
+// correctable error status registers say which errors the device saw, and
+// the uncorrectable error severity register whether each uncorrectable error
+// was fatal, so decoding them gives the actual error type (e.g. "Completion
+// Timeout" or "Bad TLP"), which the ereport class alone often doesn't.  The
+// root error status register of a root port says which error messages it
+// received from the devices beneath it.
+//
+// The registers are read from the payload members used by fmdump
+// (pcie_ue_status, pcie_ue_sev, pcie_ce_status and pcie_rp_err_status), or
+// the same names without the "pcie_" prefix.
+//
+use serde_json::{Map, Value};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use cpu::register;
+use i18n::tr;
+use numfmt::NumberFormat;
+use DeviceHashEnt;
+
+pub const CLASS_PREFIX: &str = "ereport.io.pciex.";
+
+// The bits of the Uncorrectable Error Status register
+const UE_ERRORS: [(u32, &str); 17] = [
+    (0, "Undefined"),
+    (4, "Data Link Protocol Error"),
+    (5, "Surprise Down Error"),
+    (12, "Poisoned TLP"),
+    (13, "Flow Control Protocol Error"),
+    (14, "Completion Timeout"),
+    (15, "Completer Abort"),
+    (16, "Unexpected Completion"),
+    (17, "Receiver Overflow"),
+    (18, "Malformed TLP"),
+    (19, "ECRC Error"),
+    (20, "Unsupported Request"),
+    (21, "ACS Violation"),
+    (22, "Uncorrectable Internal Error"),
+    (23, "MC Blocked TLP"),
+    (24, "AtomicOp Egress Blocked"),
+    (25, "TLP Prefix Blocked"),
+];
+
+// The bits of the Correctable Error Status register
+const CE_ERRORS: [(u32, &str); 8] = [
+    (0, "Receiver Error"),
+    (6, "Bad TLP"),
+    (7, "Bad DLLP"),
+    (8, "REPLAY_NUM Rollover"),
+    (12, "Replay Timer Timeout"),
+    (13, "Advisory Non-Fatal Error"),
+    (14, "Corrected Internal Error"),
+    (15, "Header Log Overflow"),
+];
+
+// The bits of a root port's Root Error Status register
+const RP_ERRORS: [(u32, &str); 7] = [
+    (0, "ERR_COR Received"),
+    (1, "Multiple ERR_COR Received"),
+    (2, "ERR_FATAL/NONFATAL Received"),
+    (3, "Multiple ERR_FATAL/NONFATAL Received"),
+    (4, "First Uncorrectable Fatal"),
+    (5, "Non-Fatal Error Messages Received"),
+    (6, "Fatal Error Messages Received"),
+];
+
+fn member(payload: &Map<String, Value>, name: &str) -> Option<u64> {
+    register(payload.get(&format!("pcie_{}", name)))
+        .or_else(|| register(payload.get(name)))
+}
+
+fn bits(value: u64, names: &[(u32, &'static str)]) -> Vec<&'static str> {
+    names.iter()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+//
+// Returns each error named by the AER registers in an ereport's payload,
+// along with its type: correctable, non-fatal or fatal (or uncorrectable, if
+// the severity register wasn't captured), or message for those received by a
+// root port.
+//
+pub fn decode(payload: &Map<String, Value>)
+    -> Vec<(&'static str, &'static str)> {
+
+    let mut errors = Vec::new();
+    if let Some(status) = member(payload, "ue_status") {
+        let sev = member(payload, "ue_sev");
+        for (bit, name) in UE_ERRORS.iter() {
+            if status & (1 << bit) == 0 {
+                continue;
+            }
+            let kind = match sev {
+                Some(sev) if sev & (1 << bit) != 0 => "fatal",
+                Some(_) => "non-fatal",
+                None => "uncorrectable",
+            };
+            errors.push((*name, kind));
+        }
+    }
+    if let Some(status) = member(payload, "ce_status") {
+        errors.extend(bits(status, &CE_ERRORS).into_iter()
+            .map(|name| (name, "correctable")));
+    }
+    if let Some(status) = member(payload, "rp_err_status") {
+        errors.extend(bits(status, &RP_ERRORS).into_iter()
+            .map(|name| (name, "message")));
+    }
+    errors
+}
+
+//
+// Write the number of times each AER error was seen by the device, if any of
+// its PCIe ereports captured the registers.
+//
+pub fn write_device_aer(
+    out: &mut dyn Write,
+    devent: &DeviceHashEnt,
+    numbers: &NumberFormat
+) -> Result<(), Box<dyn Error>> {
+
+    let mut counts: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+    for ereport in devent.ereports.iter()
+        .filter(|e| e.class.starts_with(CLASS_PREFIX)) {
+        for (name, kind) in decode(&ereport.payload) {
+            *counts.entry((kind, name)).or_insert(0) += 1;
+        }
+    }
+    if counts.is_empty() {
+        return Ok(());
+    }
+
+    let heading = tr("PCIe AER Errors");
+    writeln!(out, "{}\n{}", heading, "-".repeat(heading.chars().count()))?;
+    let headings = [
+        ("error", "type", "count"),
+        ("-----", "----", "-----"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <40} {1: <14} {2}", h.0, h.1, h.2)?;
+    }
+    for ((kind, name), count) in counts {
+        writeln!(out, "{0: <40} {1: <14} {2}", name, kind,
+            numbers.int(count))?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use devpath;
+use hwgrok::HwGrok;
+use SimpleError;
+
+//
+// An alias file maps device paths (or disk serial numbers) to user-defined
+// friendly names.  The file consists of one alias per line, where each line
+// contains the device path or serial number, followed by whitespace, followed
+// by the friendly name.  Blank lines and lines starting with '#' are ignored.
+// For example:
+//
+//   # data disks on db1
+//   7JHGHT4G                                       db1-data-disk-03
+//   /pci@0,0/pci8086,6f0a@3,2/pci8086,3703@0       db1-slog
+//
+#[derive(Debug, Default)]
+pub struct AliasMap {
+    aliases: HashMap<String, String>,
+}
+
+impl AliasMap {
+    pub fn from_file(path: &str) -> Result<AliasMap, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut aliases = HashMap::new();
+
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(2, char::is_whitespace);
+            let key = fields.next().unwrap();
+            let name = match fields.next() {
+                Some(n) if !n.trim().is_empty() => n.trim(),
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "{}:{}: missing alias name", path, lineno + 1))));
+                }
+            };
+            let key = if key.starts_with('/') {
+                devpath::canonicalize(key).into()
+            } else {
+                key.to_string()
+            };
+            aliases.insert(key, name.to_string());
+        }
+
+        Ok(AliasMap { aliases })
+    }
+
+    //
+    // Look up the alias for a device.  An alias for the device path takes
+    // precedence over an alias for the serial number of the disk that the
+    // hwgrok data places at that path.
+    //
+    pub fn lookup(&self, devpath: &str, hwgrok: &HwGrok) -> Option<&str> {
+        if let Some(name) = self.aliases.get(devpath) {
+            return Some(name);
+        }
+        hwgrok.find_disk(devpath)
+            .and_then(|(_, disk)| self.aliases.get(&disk.disk_serial_number))
+            .map(|n| n.as_str())
+    }
+}
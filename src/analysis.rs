@@ -0,0 +1,286 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Predictive failure scoring for disks.  The score combines several signals
+// into a single 0-100 likelihood that a disk needs replacing soon:
+//
+//   - the mix of ereport classes, as media and hardware errors are far more
+//     indicative of a failing disk than transport errors or recovered errors
+//   - the trend in the error rate over the last week versus the week before
+//   - SMART indicators, when SMART data was supplied
+//   - how close the disk is to tripping a SERD engine in the fmd disk
+//     diagnosis, which would result in the disk being faulted
+//
+// Where asset data is available (see assets.rs), the disks' ages are listed
+// alongside their scores, and the recommendation for each disk to be replaced
+// takes its warranty into account.
+//
+use chrono::prelude::*;
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::io::Write;
+
+use assets::Asset;
+use i18n::tr;
+use {DeviceHash, DeviceHashEnt, ReportData};
+
+const SECS_PER_DAY: i64 = 86400;
+
+// Scores at or above this are placed on the "replace soon" list.
+pub const REPLACE_SOON_SCORE: u32 = 50;
+
+// The maximum contribution of each signal to the score
+const MAX_CLASS_SCORE: f64 = 40.0;
+const MAX_TREND_SCORE: f64 = 15.0;
+const MAX_SMART_SCORE: f64 = 25.0;
+const MAX_SERD_SCORE: f64 = 20.0;
+
+//
+// An approximation of the SERD engines used by the fmd disk diagnosis,
+// expressed as the number of events (N) within a time window (T, in seconds)
+// which will trip the engine.
+//
+const SERD_ENGINES: [(&str, usize, i64); 3] = [
+    ("ereport.io.scsi.cmd.disk.dev.rqs.merr", 5, 24 * 3600),
+    ("ereport.io.scsi.cmd.disk.dev.rqs.derr", 10, 24 * 3600),
+    ("ereport.io.scsi.cmd.disk.tran", 20, 3600),
+];
+
+#[derive(Debug)]
+pub struct DiskScore<'a> {
+    pub devpath: &'a str,
+    pub score: u32,
+    pub reasons: Vec<String>,
+}
+
+//
+// Returns true if the device appears to be a disk, either because hwgrok
+// places a disk at this path, or because of its path or ereport classes.
+//
+pub fn is_disk(devpath: &str, devent: &DeviceHashEnt, data: &ReportData)
+    -> bool {
+
+    data.hwgrok.find_disk(devpath).is_some() ||
+        devpath.contains("/disk@") || devpath.contains("/blkdev@") ||
+        devent.ereport_class_hash.keys().any(|c| c.contains(".disk."))
+}
+
+//
+// The largest number of events of the given class which occurred within any
+// window of the given length.
+//
+fn max_in_window(devent: &DeviceHashEnt, class: &str, window: i64) -> usize {
+    let tods: Vec<i64> = devent.ereports.iter()
+        .filter(|e| e.class == class)
+        .map(|e| e.tod[0])
+        .collect();
+
+    let mut max = 0;
+    let mut start = 0;
+    for end in 0..tods.len() {
+        while tods[end] - tods[start] >= window {
+            start += 1;
+        }
+        max = max.max(end - start + 1);
+    }
+    max
+}
+
+fn score_disk<'a>(
+    devpath: &'a str,
+    devent: &DeviceHashEnt,
+    data: &ReportData,
+    newest: i64
+) -> DiskScore<'a> {
+
+    let mut reasons = Vec::new();
+
+    //
+    // Error class mix: the error count weighted by the severity of each
+    // class, on a log scale so that a handful of media errors is significant
+    // but a flood of them doesn't swamp every other signal.
+    //
+    let weighted: f64 = devent.ereport_class_hash.iter()
+        .map(|(class, count)| {
+            data.severity.lookup(class).weight() * *count as f64
+        })
+        .sum();
+    let class_score = (weighted.ln_1p() * 8.0).min(MAX_CLASS_SCORE);
+    if class_score >= MAX_CLASS_SCORE / 2.0 {
+        reasons.push(format!("weighted error count {:.0}", weighted));
+    }
+
+    //
+    // Rate trend: errors in the last week of the log versus the prior week.
+    //
+    let week = 7 * SECS_PER_DAY;
+    let recent = devent.ereports.iter()
+        .filter(|e| e.tod[0] > newest - week).count() as f64;
+    let prior = devent.ereports.iter()
+        .filter(|e| e.tod[0] > newest - 2 * week && e.tod[0] <= newest - week)
+        .count() as f64;
+    let trend_score = if recent > prior {
+        let ratio = recent / prior.max(1.0);
+        reasons.push(format!("error rate up {:.1}x week over week", ratio));
+        (ratio * 5.0).min(MAX_TREND_SCORE)
+    } else {
+        0.0
+    };
+
+    //
+    // SMART indicators
+    //
+    let mut smart_score = 0.0;
+    if let Some(smart) = data.smart.lookup(devpath, &data.hwgrok) {
+        if smart.health_passed == Some(false) {
+            smart_score += MAX_SMART_SCORE;
+            reasons.push("SMART health check failed".to_string());
+        }
+        if let Some(n) = smart.pending.filter(|n| *n > 0) {
+            smart_score += 10.0;
+            reasons.push(format!("{} pending sectors", n));
+        }
+        if let Some(n) = smart.reallocated.filter(|n| *n > 0) {
+            smart_score += 8.0;
+            reasons.push(format!("{} reallocated sectors", n));
+        }
+        if let Some(n) = smart.crc_errors.filter(|n| *n > 0) {
+            smart_score += 3.0;
+            reasons.push(format!("{} CRC/media errors", n));
+        }
+        if let Some(n) = smart.wear_pct.filter(|n| *n >= 90) {
+            smart_score += 10.0;
+            reasons.push(format!("{}% of rated endurance used", n));
+        }
+    }
+    let smart_score = smart_score.min(MAX_SMART_SCORE);
+
+    //
+    // SERD proximity: how close the worst burst came to tripping an engine.
+    //
+    let mut serd_frac: f64 = 0.0;
+    for (class, n, t) in SERD_ENGINES.iter() {
+        let burst = max_in_window(devent, class, *t);
+        let frac = burst as f64 / *n as f64;
+        if frac >= 0.5 {
+            reasons.push(format!("{} of {} {} events within SERD window",
+                burst, n, class));
+        }
+        serd_frac = serd_frac.max(frac);
+    }
+    let serd_score = (serd_frac * MAX_SERD_SCORE).min(MAX_SERD_SCORE);
+
+    let score = (class_score + trend_score + smart_score + serd_score)
+        .round() as u32;
+
+    DiskScore { devpath, score: score.min(100), reasons }
+}
+
+//
+// Score every disk in the device hash, returning the scores ranked from most
+// to least likely to fail.
+//
+pub fn score_disks<'a>(
+    device_hash: &'a DeviceHash,
+    data: &ReportData
+) -> Vec<DiskScore<'a>> {
+
+    let newest = device_hash.values()
+        .filter_map(|d| d.ereports.last())
+        .map(|e| e.tod[0])
+        .max()
+        .unwrap_or(0);
+
+    let mut scores: Vec<DiskScore> = device_hash.iter()
+        .filter(|(devpath, devent)| is_disk(devpath, devent, data))
+        .map(|(devpath, devent)| score_disk(devpath, devent, data, newest))
+        .collect();
+    scores.sort_by(|a, b| match b.score.cmp(&a.score) {
+        Ordering::Equal => a.devpath.cmp(b.devpath),
+        o => o,
+    });
+    scores
+}
+
+pub fn write_scores(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let scores = score_disks(device_hash, data);
+    if scores.is_empty() {
+        return Ok(());
+    }
+
+    let today = Utc::now().date_naive();
+    let assets: Vec<Option<Asset>> = scores.iter()
+        .map(|s| data.assets.lookup(s.devpath, &data.hwgrok,
+            data.plugin_fields.get(s.devpath)))
+        .collect();
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Disk Failure Likelihood"))?;
+    if assets.iter().all(|a| a.is_none()) {
+        writeln!(out, "{0: <6} device", "score")?;
+        writeln!(out, "{0: <6} ------", "-----")?;
+        for score in &scores {
+            writeln!(out, "{0: <6} {1}", score.score, score.devpath)?;
+        }
+    } else {
+        writeln!(out, "{0: <6} {1: <5} device", "score", "age")?;
+        writeln!(out, "{0: <6} {1: <5} ------", "-----", "---")?;
+        for (score, asset) in scores.iter().zip(&assets) {
+            let age = asset.as_ref().and_then(|a| a.age_years(today))
+                .map(|y| format!("{}y", y))
+                .unwrap_or_else(|| "-".to_string());
+            writeln!(out, "{0: <6} {1: <5} {2}", score.score, age,
+                score.devpath)?;
+        }
+    }
+
+    let heading = tr("Replace Soon");
+    writeln!(out, "\n{}\n{}", heading,
+        "-".repeat(heading.chars().count()))?;
+    let mut found = false;
+    for (score, asset) in scores.iter().zip(&assets)
+        .filter(|(s, _)| s.score >= REPLACE_SOON_SCORE) {
+        found = true;
+        let label = data.hwgrok.find_disk(score.devpath)
+            .map(|(bay, _)| bay.bay_label.as_str())
+            .unwrap_or(score.devpath);
+        writeln!(out, "{} (score {})", label, score.score)?;
+        for reason in &score.reasons {
+            writeln!(out, "  - {}", reason)?;
+        }
+        let asset = match asset {
+            Some(a) => a,
+            None => continue,
+        };
+        let description = asset.describe(today);
+        match asset.in_warranty(today) {
+            Some(true) => {
+                writeln!(out, "  * {}: RMA under warranty", description)?;
+            }
+            Some(false) => {
+                writeln!(out, "  * {}: replace rather than RMA",
+                    description)?;
+            }
+            None if !description.is_empty() => {
+                writeln!(out, "  * {}", description)?;
+            }
+            None => (),
+        }
+    }
+    if !found {
+        writeln!(out, "none")?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
@@ -0,0 +1,305 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// An archive of the reports from previous runs.  Each run stores its report
+// in the archive directory, named by the time of the run, and then prunes the
+// archive according to the retention policy, so that scheduled runs don't need
+// a separate cleanup job.  The retention policy is in the style of backup
+// rotation schemes: the most recent reports are kept, along with the newest
+// report from each recent day and the newest report from each recent week.
+//
+// A central collection host may run an instance of this tool per host feed,
+// all sharing the same archive.  Reports are therefore named by host as well
+// as time, the retention policy is applied to each host's reports
+// separately, and updates to the archive are serialized by a lock file.
+//
+use chrono::prelude::*;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use output::{self, Compression};
+use SimpleError;
+
+const ARCHIVE_PREFIX: &str = "report-";
+const ARCHIVE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+const LOCK_NAME: &str = ".lock";
+// How long to wait for another instance to release the lock
+const LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+const LOCK_RETRY: Duration = Duration::from_millis(100);
+
+//
+// An exclusive lock on the archive directory, released when dropped.  The
+// lock is taken on the open lock file rather than by creating it, so that
+// the system releases it if the instance holding it dies, and there's no
+// stale lock to be recovered.  The lock file itself is left in place, as
+// removing it would let another instance lock a new file of the same name
+// while a third still held the old one.
+//
+struct ArchiveLock {
+    _file: fs::File,
+}
+
+impl ArchiveLock {
+    fn acquire(dir: &str) -> Result<ArchiveLock, Box<dyn Error>> {
+        let path = Path::new(dir).join(LOCK_NAME);
+        let file = fs::OpenOptions::new().write(true).create(true)
+            .truncate(false).open(&path)?;
+        let start = SystemTime::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(ArchiveLock { _file: file }),
+                Err(fs::TryLockError::WouldBlock) => (),
+                Err(fs::TryLockError::Error(e)) => return Err(Box::new(e)),
+            }
+            if start.elapsed().unwrap_or_default() > LOCK_TIMEOUT {
+                return Err(Box::new(SimpleError(format!(
+                    "timed out waiting for lock {}", path.display()))));
+            }
+            thread::sleep(LOCK_RETRY);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Retention {
+    // the number of most recent reports to keep
+    pub keep_last: usize,
+    // the number of days for which to keep the newest report of each day
+    pub keep_daily: i64,
+    // the number of weeks for which to keep the newest report of each week
+    pub keep_weekly: i64,
+}
+
+impl Default for Retention {
+    fn default() -> Retention {
+        Retention { keep_last: 10, keep_daily: 30, keep_weekly: 52 }
+    }
+}
+
+//
+// Store a report for the given host, with the file extension for its format,
+// in the archive and then prune the host's reports according to the retention
+// policy.  Returns the path the report was written to.
+//
+pub fn store(
+    dir: &str,
+    host: &str,
+    report: &[u8],
+    ext: &str,
+    compress: Compression,
+    policy: &Retention,
+    now: DateTime<Utc>
+) -> Result<String, Box<dyn Error>> {
+
+    fs::create_dir_all(dir)?;
+    let _lock = ArchiveLock::acquire(dir)?;
+
+    //
+    // Two runs for the same host within the same second would otherwise
+    // share a name, so add a sequence number to the later one.
+    //
+    let base = format!("{}{}-{}", ARCHIVE_PREFIX,
+        output::sanitize_filename(host), now.format(ARCHIVE_TIME_FORMAT));
+    let mut name = format!("{}.{}", base, ext);
+    let mut seq = 1;
+    while Path::new(dir).join(output::compressed_path(&name, compress))
+        .exists() {
+        seq += 1;
+        name = format!("{}.{}.{}", base, seq, ext);
+    }
+
+    let path = output::write_file(&Path::new(dir).join(name)
+        .to_string_lossy(), report, compress)?;
+    prune(dir, host, policy, now)?;
+    Ok(path)
+}
+
+//
+// Returns the host, the time at which an archived report was generated and
+// its sequence number within that second (1 for the first, which has none in
+// its name), from its file name, or None if the file isn't an archived report.
+//
+fn archive_info(name: &str) -> Option<(String, DateTime<Utc>, u32)> {
+    // the host name may contain '.' and '-', but the time stamp contains
+    // neither
+    let (host, rest) = name.strip_prefix(ARCHIVE_PREFIX)?.rsplit_once('-')?;
+    let mut parts = rest.split('.');
+    let stamp = parts.next()?;
+    let time = NaiveDateTime::parse_from_str(stamp, ARCHIVE_TIME_FORMAT).ok()?;
+    let seq = parts.next().and_then(|s| s.parse().ok()).unwrap_or(1);
+    Some((host.to_string(), time.and_utc(), seq))
+}
+
+//
+// Remove the archived reports for a host which the retention policy doesn't
+// keep.  Returns the names of the files removed.  The caller must hold the
+// archive lock.
+//
+fn prune(
+    dir: &str,
+    host: &str,
+    policy: &Retention,
+    now: DateTime<Utc>
+) -> Result<Vec<String>, Box<dyn Error>> {
+
+    let host = output::sanitize_filename(host);
+    let mut reports: Vec<(DateTime<Utc>, u32, String)> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter_map(|name| match archive_info(&name) {
+            Some((h, t, seq)) if h == host => Some((t, seq, name)),
+            _ => None,
+        })
+        .collect();
+    //
+    // Newest first, by the sequence number within a second rather than the
+    // name, which would put "T.json" before the later "T.2.json".
+    //
+    reports.sort_by(|a, b| b.cmp(a));
+
+    let mut keep: HashSet<&str> = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for (idx, (time, _, name)) in reports.iter().enumerate() {
+        if idx < policy.keep_last {
+            keep.insert(name);
+        }
+        let age = now.signed_duration_since(*time);
+        if age.num_days() < policy.keep_daily &&
+            days.insert(time.date_naive()) {
+            keep.insert(name);
+        }
+        if age.num_weeks() < policy.keep_weekly &&
+            weeks.insert(time.iso_week()) {
+            keep.insert(name);
+        }
+    }
+
+    let mut removed = Vec::new();
+    let pruned = reports.iter()
+        .filter(|(_, _, n)| !keep.contains(n.as_str()));
+    for (_, _, name) in pruned {
+        fs::remove_file(Path::new(dir).join(name))?;
+        removed.push(name.clone());
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{archive_info, prune, Retention};
+    use chrono::prelude::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn time(stamp: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M:%S").unwrap()
+            .and_utc()
+    }
+
+    //
+    // Creates an archive of the given (empty) reports, prunes it, and returns
+    // the names of the reports that remain.
+    //
+    fn pruned(
+        test: &str,
+        reports: &[&str],
+        policy: Retention,
+        now: &str
+    ) -> Vec<String> {
+        let dir = env::temp_dir().join(format!("fmlr-archive-{}-{}",
+            process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        for report in reports {
+            fs::write(dir.join(report), "").unwrap();
+        }
+        let dir = dir.to_string_lossy();
+        prune(&dir, "h", &policy, time(now)).unwrap();
+        let mut kept: Vec<String> = fs::read_dir(&*dir).unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        kept.sort();
+        fs::remove_dir_all(&*dir).unwrap();
+        kept
+    }
+
+    #[test]
+    fn info() {
+        let t = time("2019-06-01 12:00:00");
+        assert_eq!(archive_info("report-h-20190601T120000Z.json"),
+            Some(("h".to_string(), t, 1)));
+        assert_eq!(archive_info("report-db1.example-com-20190601T120000Z.\
+            3.txt.gz"), Some(("db1.example-com".to_string(), t, 3)));
+        assert_eq!(archive_info("report-h-20190601T120000Z.json.gz"),
+            Some(("h".to_string(), t, 1)));
+        assert_eq!(archive_info("report-h-2019-06-01.json"), None);
+        assert_eq!(archive_info("summary-h-20190601T120000Z.json"), None);
+        assert_eq!(archive_info(".lock"), None);
+    }
+
+    #[test]
+    fn keep_last() {
+        let policy = Retention { keep_last: 2, keep_daily: 0,
+            keep_weekly: 0 };
+        let kept = pruned("last", &[
+            "report-h-20190601T120000Z.json",
+            "report-h-20190601T120000Z.2.json",
+            "report-h-20190601T120000Z.3.json",
+            "report-h-20190531T120000Z.json",
+            "report-other-20190530T120000Z.json",
+        ], policy, "2019-06-01 13:00:00");
+        assert_eq!(kept, vec![
+            "report-h-20190601T120000Z.2.json",
+            "report-h-20190601T120000Z.3.json",
+            "report-other-20190530T120000Z.json",
+        ]);
+    }
+
+    #[test]
+    fn daily() {
+        let policy = Retention { keep_last: 0, keep_daily: 3,
+            keep_weekly: 0 };
+        let kept = pruned("daily", &[
+            "report-h-20190610T080000Z.json",
+            "report-h-20190610T100000Z.json",
+            "report-h-20190610T100000Z.2.json",
+            "report-h-20190609T100000Z.json",
+            "report-h-20190608T230000Z.json",
+            "report-h-20190608T010000Z.json",
+            "report-h-20190606T100000Z.json",
+        ], policy, "2019-06-10 12:00:00");
+        assert_eq!(kept, vec![
+            "report-h-20190608T230000Z.json",
+            "report-h-20190609T100000Z.json",
+            "report-h-20190610T100000Z.2.json",
+        ]);
+    }
+
+    #[test]
+    fn weekly() {
+        let policy = Retention { keep_last: 0, keep_daily: 0,
+            keep_weekly: 2 };
+        let kept = pruned("weekly", &[
+            "report-h-20190611T120000Z.json",
+            "report-h-20190610T120000Z.json",
+            "report-h-20190605T120000Z.json",
+            "report-h-20190603T120000Z.json",
+            "report-h-20190520T120000Z.json",
+        ], policy, "2019-06-12 12:00:00");
+        assert_eq!(kept, vec![
+            "report-h-20190605T120000Z.json",
+            "report-h-20190611T120000Z.json",
+        ]);
+    }
+}
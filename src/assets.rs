@@ -0,0 +1,178 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Asset age and warranty data, which is used to report how long components
+// have been in service and to qualify the replacement recommendations: a disk
+// under warranty can be returned to the vendor, whereas one out of warranty
+// is better simply replaced.  The data comes from a CSV file given with
+// --assets, with the columns:
+//
+//   component,purchase-date,warranty-end
+//
+// where the component is a device path, bay label or disk serial number, and
+// the dates are YYYY-MM-DD (either may be empty).  A header line is permitted.
+// For components not in the file, the "purchase-date", "warranty-end" and
+// "warranty-status" fields supplied by plugins or the CMDB are used.
+//
+use chrono::prelude::*;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use devpath;
+use hwgrok::HwGrok;
+use SimpleError;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Default, Clone)]
+pub struct Asset {
+    pub purchased: Option<NaiveDate>,
+    pub warranty_end: Option<NaiveDate>,
+    // the warranty status as given by the CMDB, e.g. "active" or "expired"
+    pub warranty_status: Option<String>,
+}
+
+impl Asset {
+    //
+    // Returns the component's age, in whole years, as of the given date.
+    //
+    pub fn age_years(&self, today: NaiveDate) -> Option<i32> {
+        self.purchased.map(|p| today.years_since(p).map(|y| y as i32)
+            .unwrap_or(0))
+    }
+
+    //
+    // Returns whether the component is under warranty on the given date, if
+    // known.
+    //
+    pub fn in_warranty(&self, today: NaiveDate) -> Option<bool> {
+        if let Some(end) = self.warranty_end {
+            return Some(today <= end);
+        }
+        match self.warranty_status.as_deref().map(|s| s.to_lowercase()) {
+            Some(s) if ["active", "valid", "in-warranty"].contains(&s.as_str())
+                => Some(true),
+            Some(s) if ["expired", "none", "out-of-warranty"]
+                .contains(&s.as_str()) => Some(false),
+            _ => None,
+        }
+    }
+
+    //
+    // A short description of the age and warranty, e.g. "3y, warranty to
+    // 2021-06-30".
+    //
+    pub fn describe(&self, today: NaiveDate) -> String {
+        let mut parts = Vec::new();
+        if let Some(age) = self.age_years(today) {
+            parts.push(format!("{}y old", age));
+        }
+        match (self.in_warranty(today), self.warranty_end) {
+            (Some(true), Some(end)) => {
+                parts.push(format!("warranty to {}", end.format(DATE_FORMAT)));
+            }
+            (Some(false), Some(end)) => {
+                parts.push(format!("warranty ended {}",
+                    end.format(DATE_FORMAT)));
+            }
+            (Some(true), None) => parts.push("under warranty".to_string()),
+            (Some(false), None) => parts.push("out of warranty".to_string()),
+            (None, _) => (),
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AssetData {
+    assets: HashMap<String, Asset>,
+}
+
+fn parse_date(s: &str) -> Result<Option<NaiveDate>, ()> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(s, DATE_FORMAT).map(Some).map_err(|_| ())
+}
+
+impl AssetData {
+    pub fn from_file(path: &str) -> Result<AssetData, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut assets = HashMap::new();
+
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            //
+            // Device paths may themselves contain commas, so the component is
+            // everything before the last two commas.
+            //
+            let fields: Vec<&str> = line.rsplitn(3, ',').map(|f| f.trim())
+                .collect();
+            if fields.len() != 3 {
+                return Err(Box::new(SimpleError(format!(
+                    "{}:{}: expected 3 fields", path, lineno + 1))));
+            }
+            let (purchased, warranty_end) = match (parse_date(fields[1]),
+                parse_date(fields[0])) {
+                (Ok(p), Ok(w)) => (p, w),
+                // Skip the header line, if present
+                _ if lineno == 0 => continue,
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "{}:{}: invalid date", path, lineno + 1))));
+                }
+            };
+            let component = if fields[2].starts_with('/') {
+                devpath::canonicalize(fields[2]).into()
+            } else {
+                fields[2].to_string()
+            };
+            assets.insert(component, Asset { purchased, warranty_end,
+                warranty_status: None });
+        }
+
+        Ok(AssetData { assets })
+    }
+
+    //
+    // Find the asset data for a device, by its device path or the bay label
+    // or serial number of the disk at that path, or from the fields that
+    // plugins supplied for it.
+    //
+    pub fn lookup(
+        &self,
+        devpath: &str,
+        hwgrok: &HwGrok,
+        fields: Option<&Vec<(String, String)>>
+    ) -> Option<Asset> {
+
+        if let Some(asset) = hwgrok.identifiers(devpath).iter()
+            .find_map(|id| self.assets.get(*id)) {
+            return Some(asset.clone());
+        }
+        let field = |name: &str| fields?.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.trim());
+        let asset = Asset {
+            purchased: field("purchase-date")
+                .and_then(|d| parse_date(d).ok().flatten()),
+            warranty_end: field("warranty-end")
+                .and_then(|d| parse_date(d).ok().flatten()),
+            warranty_status: field("warranty-status").map(|s| s.to_string()),
+        };
+        if asset.purchased.is_none() && asset.warranty_end.is_none() &&
+            asset.warranty_status.is_none() {
+            return None;
+        }
+        Some(asset)
+    }
+}
@@ -0,0 +1,145 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Reboot and panic boundaries.  Boot times can be supplied in a file, while
+// panics are recognized from the ireports that the OS posts when a crash dump
+// is found after a panic.  These are used to annotate the per-device timeline
+// and to report which error bursts immediately preceded a panic.
+//
+use serde_json::Value;
+
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+
+use i18n::tr;
+use {get_event_timestamp, parse_timestamp, DeviceHash, SimpleError};
+
+// The class prefix for the ireports posted following a panic
+pub const PANIC_CLASS_PREFIX: &str = "ireport.os.sunos.panic.";
+
+// How far back before a panic to look for error bursts, in seconds
+const PRE_PANIC_WINDOW: i64 = 3600;
+
+#[derive(Debug, Default)]
+pub struct Boundaries {
+    pub boots: Vec<i64>,
+    pub panics: Vec<i64>,
+}
+
+//
+// Returns the time of the panic described by a panic ireport.  The ireport is
+// posted when the dump is processed after the subsequent boot, so we prefer
+// the crash time from the payload if it is present.
+//
+pub fn panic_time(event: &Value) -> Option<i64> {
+    if let Some(t) = event.get("crashtime").and_then(|t| t.as_i64()) {
+        return Some(t);
+    }
+    event.get("__tod")?.get(0)?.as_i64()
+}
+
+impl Boundaries {
+    //
+    // The boot times file contains one timestamp per line, in any of the
+    // formats accepted by parse_timestamp().
+    //
+    pub fn read_boot_times(&mut self, path: &str)
+        -> Result<(), Box<dyn Error>> {
+
+        let contents = fs::read_to_string(path)?;
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_timestamp(line) {
+                Some(t) => self.boots.push(t),
+                None => {
+                    return Err(Box::new(SimpleError(format!(
+                        "{}:{}: invalid boot time", path, lineno + 1))));
+                }
+            }
+        }
+        self.boots.sort();
+        Ok(())
+    }
+
+    //
+    // Returns the annotation for a day in the timeline, if any reboots or
+    // panics occurred on that day.
+    //
+    pub fn day_marker(&self, day: &str) -> String {
+        let count = |times: &[i64]| times.iter()
+            .filter(|t| get_event_timestamp(**t) == day).count();
+
+        let mut markers = Vec::new();
+        match count(&self.panics) {
+            0 => (),
+            1 => markers.push("panic".to_string()),
+            n => markers.push(format!("{} panics", n)),
+        }
+        match count(&self.boots) {
+            0 => (),
+            1 => markers.push("reboot".to_string()),
+            n => markers.push(format!("{} reboots", n)),
+        }
+        if markers.is_empty() {
+            String::new()
+        } else {
+            format!("<- {}", markers.join(", "))
+        }
+    }
+}
+
+//
+// For each panic, report the devices which had error bursts in the period
+// immediately preceding it.
+//
+pub fn write_panics(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    bounds: &Boundaries
+) -> Result<(), Box<dyn Error>> {
+
+    if bounds.panics.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Panics"))?;
+    for panic in &bounds.panics {
+        let datetime = chrono::DateTime::from_timestamp(*panic, 0)
+            .unwrap_or_default();
+        writeln!(out, "{0: <40} {1}", tr("Panic Time:"),
+            datetime.format("%Y-%m-%d %H:%M:%S"))?;
+
+        let mut preceding: Vec<(&str, usize)> = device_hash.iter()
+            .map(|(devpath, devent)| {
+                let n = devent.ereports.iter()
+                    .filter(|e| e.tod[0] <= *panic &&
+                        e.tod[0] > panic - PRE_PANIC_WINDOW)
+                    .count();
+                (devpath.as_str(), n)
+            })
+            .filter(|(_, n)| *n > 0)
+            .collect();
+        preceding.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        if preceding.is_empty() {
+            writeln!(out, "no ereports in the preceding hour\n")?;
+            continue;
+        }
+        writeln!(out, "ereports in the preceding hour:")?;
+        for (devpath, n) in preceding {
+            writeln!(out, "  {0: <6} {1}", n, devpath)?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// One-line descriptions of ereport classes, printed under each class in the
+// device sections with --verbose, so that the meaning of a class can be seen
+// without reading the source of the driver or diagnosis engine which posts
+// it.  A built-in catalog covers the common classes, and can be overridden or
+// extended by the [class-docs] section of the configuration file, which maps
+// class globs to descriptions:
+//
+//   [class-docs]
+//   ereport.io.nvme.* = Error reported by the NVMe driver
+//
+// As for severities (see severity.rs), entries in the configuration file take
+// precedence over the built-in catalog, and the first matching glob wins.
+//
+use conf::ConfFile;
+use glob::glob_match;
+
+const DEFAULT_DOCS: [(&str, &str); 37] = [
+    ("ereport.io.scsi.cmd.disk.recovered",
+        "The disk recovered from an error, and the command succeeded"),
+    ("ereport.io.scsi.cmd.disk.dev.rqs.merr",
+        "The disk reported a medium error: data couldn't be read from or \
+        written to the media"),
+    ("ereport.io.scsi.cmd.disk.dev.rqs.derr",
+        "The disk returned sense data reporting an error in the device, such \
+        as a hardware error"),
+    ("ereport.io.scsi.cmd.disk.dev.serr",
+        "The disk completed a command with an unexpected SCSI status"),
+    ("ereport.io.scsi.cmd.disk.dev.uderr",
+        "The disk returned unexpected or malformed data"),
+    ("ereport.io.scsi.cmd.disk.tran",
+        "A command to the disk failed in transport (HBA, cabling, expander \
+        or link) rather than in the disk"),
+    ("ereport.io.scsi.cmd.disk.*",
+        "An error in a SCSI command to the disk"),
+    ("ereport.io.service.lost",
+        "The device's driver reported that it is no longer providing service"),
+    ("ereport.io.pciex.pl.re",
+        "Receiver Error: bad symbols or framing were received on the link"),
+    ("ereport.io.pciex.dl.btlp",
+        "Bad TLP: a transaction layer packet failed its LCRC check"),
+    ("ereport.io.pciex.dl.bdllp",
+        "Bad DLLP: a data link layer packet failed its CRC check"),
+    ("ereport.io.pciex.dl.rto",
+        "Replay Timer Timeout: a packet sent wasn't acknowledged in time"),
+    ("ereport.io.pciex.dl.rnr",
+        "Replay Number Rollover: a packet was replayed repeatedly without \
+        being acknowledged"),
+    ("ereport.io.pciex.dl.dllp",
+        "Data Link Protocol Error on the link"),
+    ("ereport.io.pciex.tl.ct",
+        "Completion Timeout: a request to the device was never completed"),
+    ("ereport.io.pciex.tl.ca",
+        "Completer Abort: the device was unable to complete a request"),
+    ("ereport.io.pciex.tl.uc",
+        "Unexpected Completion: a completion was received for no request"),
+    ("ereport.io.pciex.tl.ptlp",
+        "Poisoned TLP: a packet was received with its data marked as bad"),
+    ("ereport.io.pciex.tl.mtlp",
+        "Malformed TLP: a packet was received which broke the protocol"),
+    ("ereport.io.pciex.tl.ecrc",
+        "ECRC Error: a packet failed its end-to-end CRC check"),
+    ("ereport.io.pciex.tl.fcp",
+        "Flow Control Protocol Error on the link"),
+    ("ereport.io.pciex.rc.ce-msg",
+        "The root complex received a correctable error message from a device \
+        below it"),
+    ("ereport.io.pciex.rc.*",
+        "The root complex received an error message from a device below it"),
+    ("ereport.io.pciex.linux.*",
+        "A PCIe Advanced Error Reporting error, as logged by Linux"),
+    ("ereport.io.pciex.*",
+        "An error detected by a PCIe device, port or root complex"),
+    ("ereport.io.pci.fabric",
+        "The PCI fabric was scanned after an error, to find the devices \
+        involved"),
+    ("ereport.cpu.*mem_ce*",
+        "A corrected memory error, such as a single-bit DIMM error"),
+    ("ereport.cpu.*mem_ue*",
+        "An uncorrected memory error"),
+    ("ereport.cpu.linux.edac.ce",
+        "A corrected memory error, as reported by the Linux EDAC driver"),
+    ("ereport.cpu.linux.edac.uc",
+        "An uncorrected memory error, as reported by the Linux EDAC driver"),
+    ("ereport.cpu.*",
+        "A machine check in a processor's cores, caches, interconnect or \
+        memory controller"),
+    ("ereport.fs.zfs.checksum",
+        "ZFS read data whose checksum didn't match, and repaired it if it \
+        could"),
+    ("ereport.fs.zfs.io",
+        "An I/O by ZFS to a vdev failed"),
+    ("ereport.fs.zfs.probe_failure",
+        "A vdev failed to respond to a probe after an I/O error"),
+    ("ereport.fs.zfs.data",
+        "ZFS found data it couldn't repair (a permanent error)"),
+    ("ereport.fm.*",
+        "An error in the fault management framework itself"),
+    ("ereport.sel.*",
+        "An event from the IPMI System Event Log of the service processor"),
+];
+
+#[derive(Debug)]
+pub struct ClassDocs {
+    entries: Vec<(String, String)>,
+}
+
+impl Default for ClassDocs {
+    fn default() -> ClassDocs {
+        let entries = DEFAULT_DOCS.iter()
+            .map(|(glob, doc)| (glob.to_string(), doc.to_string()))
+            .collect();
+        ClassDocs { entries }
+    }
+}
+
+impl ClassDocs {
+    //
+    // Build the catalog from the built-in descriptions, with the entries in
+    // the [class-docs] section of the configuration file taking precedence.
+    //
+    pub fn from_conf(conf: &ConfFile) -> ClassDocs {
+        let mut entries: Vec<(String, String)> = conf.section("class-docs")
+            .iter()
+            .map(|(glob, doc)| (glob.to_string(), doc.to_string()))
+            .collect();
+        entries.extend(ClassDocs::default().entries);
+        ClassDocs { entries }
+    }
+
+    pub fn lookup(&self, class: &str) -> Option<&str> {
+        self.entries.iter()
+            .find(|(glob, _)| glob_match(glob, class))
+            .map(|(_, doc)| doc.as_str())
+    }
+}
@@ -0,0 +1,250 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Enrichment of the device sections with asset metadata, such as warranty
+// status, purchase date and owning team, from a configuration management
+// database (CMDB) with an HTTP interface.  The endpoint is given in the
+// [cmdb] section of the configuration file:
+//
+//   [cmdb]
+//   url = https://cmdb.example.com/api/assets/{id}
+//   token-file = /etc/fm-log-report/cmdb-token
+//   fields = warranty-status, purchase-date, owner-team
+//   cache-file = /var/tmp/fm-log-report-cmdb.json
+//   cache-ttl = 86400
+//   timeout = 10
+//
+// {id} is replaced with the device's serial number, where hwgrok knows it,
+// and otherwise with its device path or FMRI.  The endpoint must return a JSON
+// object, whose members (or, if "fields" is given, just those listed) are
+// shown in the device's header.  A 404 means the CMDB has no record of the
+// device.
+//
+// Lookups are cached in memory for the run and, if a cache file is given, in
+// that file for cache-ttl seconds (a day by default), so that repeated reports
+// don't query the CMDB for every device every time.  A failed lookup is
+// logged and the report generated without the device's metadata.
+//
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::process::Command;
+
+use conf::ConfFile;
+use log;
+use output;
+use upload::HeaderFile;
+use SimpleError;
+
+#[derive(Debug)]
+pub struct Cmdb {
+    url: String,
+    token_file: Option<String>,
+    fields: Vec<String>,
+    cache_file: Option<String>,
+    cache_ttl: i64,
+    timeout: u32,
+    // the cached lookups: id to the time of the lookup and the metadata
+    cache: HashMap<String, (i64, Map<String, Value>)>,
+}
+
+//
+// Percent-encode a value for inclusion in a URL path or query.
+//
+fn url_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' |
+            b'~' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+impl Cmdb {
+    //
+    // Returns the CMDB described by the configuration file, if there is one.
+    //
+    pub fn from_conf(conf: &ConfFile) -> Result<Option<Cmdb>, Box<dyn Error>> {
+        let section = conf.section("cmdb");
+        if section.is_empty() {
+            return Ok(None);
+        }
+        let mut url = None;
+        let mut cmdb = Cmdb {
+            url: String::new(),
+            token_file: None,
+            fields: Vec::new(),
+            cache_file: None,
+            cache_ttl: 86400,
+            timeout: 10,
+            cache: HashMap::new(),
+        };
+        for (key, value) in section {
+            let invalid = || SimpleError(format!("invalid value for {}: {}",
+                key, value));
+            match key {
+                "url" => url = Some(value.to_string()),
+                "token-file" => cmdb.token_file = Some(value.to_string()),
+                "fields" => {
+                    cmdb.fields = value.split(',')
+                        .map(|f| f.trim().to_string())
+                        .filter(|f| !f.is_empty())
+                        .collect();
+                }
+                "cache-file" => cmdb.cache_file = Some(value.to_string()),
+                "cache-ttl" => {
+                    cmdb.cache_ttl = value.parse().ok().filter(|t| *t >= 0)
+                        .ok_or_else(invalid)?;
+                }
+                "timeout" => {
+                    cmdb.timeout = value.parse().ok().filter(|t| *t > 0)
+                        .ok_or_else(invalid)?;
+                }
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "unknown cmdb setting: {}", key))));
+                }
+            }
+        }
+        cmdb.url = url.ok_or_else(|| SimpleError(
+            "cmdb section requires a url".to_string()))?;
+        if !cmdb.url.contains("{id}") {
+            return Err(Box::new(SimpleError(format!(
+                "cmdb url has no {{id}} placeholder: {}", cmdb.url))));
+        }
+        if let Some(path) = cmdb.cache_file.clone() {
+            cmdb.load_cache(&path);
+        }
+        Ok(Some(cmdb))
+    }
+
+    //
+    // Read in the cache file, ignoring it if it doesn't exist or can't be
+    // parsed (it will be rewritten after the next lookup).
+    //
+    fn load_cache(&mut self, path: &str) {
+        let cached: Map<String, Value> = match fs::read_to_string(path).ok()
+            .and_then(|c| serde_json::from_str(&c).ok()) {
+            Some(c) => c,
+            None => return,
+        };
+        for (id, entry) in cached {
+            let fetched = entry.get("fetched").and_then(|f| f.as_i64());
+            let metadata = entry.get("metadata").and_then(|m| m.as_object());
+            if let (Some(fetched), Some(metadata)) = (fetched, metadata) {
+                self.cache.insert(id, (fetched, metadata.clone()));
+            }
+        }
+    }
+
+    fn save_cache(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let cached: Map<String, Value> = self.cache.iter()
+            .map(|(id, (fetched, metadata))| (id.clone(),
+                json!({"fetched": fetched, "metadata": metadata})))
+            .collect();
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, serde_json::to_vec(&cached)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    //
+    // Query the CMDB for the metadata of the asset with the given id.
+    //
+    fn fetch(&self, id: &str) -> Result<Map<String, Value>, Box<dyn Error>> {
+        let url = output::expand_template(&self.url,
+            &[("id", url_encode(id))])?;
+        let mut headers = vec!["Accept: application/json".to_string()];
+        if let Some(path) = &self.token_file {
+            let token = fs::read_to_string(path)?;
+            headers.push(format!("Authorization: Bearer {}", token.trim()));
+        }
+        let headers = HeaderFile::create("cmdb", &headers)?;
+
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--max-time"])
+            .arg(self.timeout.to_string())
+            .args(["--write-out", "\n%{http_code}", "--header"])
+            .arg(format!("@{}", headers.path.display()))
+            .arg(&url)
+            .output()
+            .map_err(|e| SimpleError(format!("failed to run curl: {}", e)))?;
+        if !output.status.success() {
+            return Err(Box::new(SimpleError(format!("curl failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()))));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+        match status.trim() {
+            "404" => Ok(Map::new()),
+            "200" => match serde_json::from_str(body) {
+                Ok(Value::Object(metadata)) => Ok(metadata),
+                _ => Err(Box::new(SimpleError(format!(
+                    "{}: response is not a JSON object", url)))),
+            },
+            status => Err(Box::new(SimpleError(format!(
+                "{}: HTTP status {}", url, status)))),
+        }
+    }
+
+    //
+    // Returns the (name, value) pairs of metadata to show for the device
+    // with the given identifiers: its device key and, for disks, its bay
+    // label and serial number.
+    //
+    pub fn lookup(&mut self, identifiers: &[&str]) -> Vec<(String, String)> {
+        let id = match identifiers.get(2).or_else(|| identifiers.first()) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => return Vec::new(),
+        };
+        let now = Utc::now().timestamp();
+        let fresh = self.cache.get(&id)
+            .filter(|(fetched, _)| now - fetched < self.cache_ttl)
+            .map(|(_, metadata)| metadata.clone());
+        let metadata = match fresh {
+            Some(m) => m,
+            None => match self.fetch(&id) {
+                Ok(m) => {
+                    self.cache.insert(id.clone(), (now, m.clone()));
+                    if let Some(path) = &self.cache_file {
+                        if let Err(e) = self.save_cache(path) {
+                            log::warn(&format!("failed to write CMDB cache \
+                                {}: {}", path, e), &[("path", json!(path)),
+                                ("error", json!(e.to_string()))]);
+                        }
+                    }
+                    m
+                }
+                Err(e) => {
+                    log::warn(&format!("CMDB lookup of {} failed: {}", id, e),
+                        &[("id", json!(id)), ("error", json!(e.to_string()))]);
+                    return Vec::new();
+                }
+            },
+        };
+
+        let selected: Vec<(String, Value)> = if self.fields.is_empty() {
+            metadata.into_iter().collect()
+        } else {
+            self.fields.iter()
+                .filter_map(|f| metadata.get(f).map(|v| (f.clone(), v.clone())))
+                .collect()
+        };
+        selected.into_iter()
+            .map(|(name, value)| (name, match value {
+                Value::String(s) => s,
+                v => v.to_string(),
+            }))
+            .collect()
+    }
+}
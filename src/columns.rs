@@ -0,0 +1,150 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Formatting of the columns of the text tables.  The default widths suit
+// typical SCSI class names and device paths, but can be overridden by the
+// [columns] section of the configuration file, which can also choose how
+// over-long values are truncated and hide columns altogether:
+//
+//   [columns]
+//   class.width = 60
+//   truncate = start
+//   hide = first-seen, last-seen
+//
+use std::collections::HashMap;
+use std::error::Error;
+
+use conf::ConfFile;
+use SimpleError;
+
+// Default column widths, by column name
+const DEFAULT_WIDTHS: [(&str, usize); 4] = [
+    ("class", 40),
+    ("count", 14),
+    ("first-seen", 21),
+    ("last-seen", 21),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Truncate {
+    // Over-long values overflow their column
+    None,
+    // Drop the start of the value, which keeps the distinctive end of a
+    // long device path
+    Start,
+    // Drop the end of the value
+    End,
+}
+
+#[derive(Debug)]
+pub struct TableFormat {
+    widths: HashMap<String, usize>,
+    truncate: Truncate,
+    hidden: Vec<String>,
+}
+
+impl Default for TableFormat {
+    fn default() -> TableFormat {
+        TableFormat {
+            widths: DEFAULT_WIDTHS.iter()
+                .map(|(col, w)| (col.to_string(), *w))
+                .collect(),
+            truncate: Truncate::None,
+            hidden: Vec::new(),
+        }
+    }
+}
+
+impl TableFormat {
+    pub fn from_conf(conf: &ConfFile) -> Result<TableFormat, Box<dyn Error>> {
+        let mut fmt = TableFormat::default();
+
+        for (key, value) in conf.section("columns") {
+            if key == "truncate" {
+                fmt.truncate = match value {
+                    "none" => Truncate::None,
+                    "start" => Truncate::Start,
+                    "end" => Truncate::End,
+                    _ => {
+                        return Err(Box::new(SimpleError(format!(
+                            "invalid truncate setting: {}", value))));
+                    }
+                };
+            } else if key == "hide" {
+                fmt.hidden = value.split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+            } else if let Some(col) = key.strip_suffix(".width") {
+                let width = value.parse::<usize>().map_err(|_| {
+                    SimpleError(format!("invalid width for {}: {}", col,
+                        value))
+                })?;
+                fmt.widths.insert(col.to_string(), width);
+            } else {
+                return Err(Box::new(SimpleError(format!(
+                    "unknown columns setting: {}", key))));
+            }
+        }
+
+        Ok(fmt)
+    }
+
+    pub fn shows(&self, column: &str) -> bool {
+        !self.hidden.iter().any(|c| c == column)
+    }
+
+    //
+    // Format a value to the width of its column, truncating it if so
+    // configured.  Widths are measured in characters rather than bytes so
+    // that non-ASCII labels line up.
+    //
+    pub fn cell(&self, column: &str, text: &str) -> String {
+        let width = match self.widths.get(column) {
+            Some(w) => *w,
+            None => return text.to_string(),
+        };
+        let len = text.chars().count();
+        if len > width && width > 3 {
+            let keep = width - 3;
+            match self.truncate {
+                Truncate::None => (),
+                Truncate::Start => {
+                    let tail: String = text.chars().skip(len - keep).collect();
+                    return format!("...{}", tail);
+                }
+                Truncate::End => {
+                    let head: String = text.chars().take(keep).collect();
+                    return format!("{}...", head);
+                }
+            }
+        }
+        format!("{}{}", text, " ".repeat(width.saturating_sub(len)))
+    }
+
+    //
+    // Format a row of a table from (column, value) pairs, omitting any hidden
+    // columns.  The last column shown is not padded.
+    //
+    pub fn row(&self, cells: &[(&str, &str)]) -> String {
+        let shown: Vec<&(&str, &str)> = cells.iter()
+            .filter(|(col, _)| self.shows(col))
+            .collect();
+        let mut line = String::new();
+        for (idx, (col, text)) in shown.iter().enumerate() {
+            if idx > 0 {
+                line.push(' ');
+            }
+            if idx == shown.len() - 1 {
+                line.push_str(self.cell(col, text).trim_end());
+            } else {
+                line.push_str(&self.cell(col, text));
+            }
+        }
+        line
+    }
+}
@@ -0,0 +1,115 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Parsing of the configuration file.  The file is in a simple INI-style
+// format, consisting of named sections containing "key = value" entries:
+//
+//   # comments start with '#'
+//   [severity]
+//   ereport.io.scsi.cmd.disk.tran = info
+//
+// The order of the entries within a section is preserved, as for some
+// sections (e.g. severity) the first matching entry wins.
+//
+// When a profile is selected (see profile.rs), a section named
+// [profile.<name>.<section>] replaces the section of the same name.
+//
+use std::error::Error;
+use std::fs;
+
+use SimpleError;
+
+#[derive(Debug, Default)]
+pub struct ConfFile {
+    sections: Vec<(String, Vec<(String, String)>)>,
+    profile: Option<String>,
+}
+
+impl ConfFile {
+    pub fn from_file(path: &str) -> Result<ConfFile, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        ConfFile::parse(&contents).map_err(|e| {
+            let err: Box<dyn Error> = Box::new(SimpleError(
+                format!("{}:{}", path, e)));
+            err
+        })
+    }
+
+    pub fn parse(contents: &str) -> Result<ConfFile, String> {
+        let mut sections: Vec<(String, Vec<(String, String)>)> = Vec::new();
+
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') || line.len() < 3 {
+                    return Err(format!("{}: malformed section header",
+                        lineno + 1));
+                }
+                let name = line[1..line.len() - 1].trim().to_string();
+                sections.push((name, Vec::new()));
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(off) => (line[..off].trim(), line[off + 1..].trim()),
+                None => {
+                    return Err(format!("{}: expected key = value",
+                        lineno + 1));
+                }
+            };
+            match sections.last_mut() {
+                Some((_, entries)) => {
+                    entries.push((key.to_string(), value.to_string()));
+                }
+                None => {
+                    return Err(format!("{}: entry outside of a section",
+                        lineno + 1));
+                }
+            }
+        }
+
+        Ok(ConfFile { sections, profile: None })
+    }
+
+    //
+    // Select the named profile, which must have a section of its own.
+    //
+    pub fn set_profile(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let prefix = format!("profile.{}", name);
+        if !self.sections.iter().any(|(n, _)| *n == prefix ||
+            n.starts_with(&format!("{}.", prefix))) {
+            return Err(Box::new(SimpleError(
+                format!("unknown profile: {}", name))));
+        }
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+
+    //
+    // Returns the entries of the named section, in file order.  If the
+    // section appears more than once, the entries are concatenated.
+    //
+    pub fn section(&self, name: &str) -> Vec<(&str, &str)> {
+        if let Some(profile) = &self.profile {
+            let name = format!("profile.{}.{}", profile, name);
+            if self.sections.iter().any(|(n, _)| *n == name) {
+                return self.entries(&name);
+            }
+        }
+        self.entries(name)
+    }
+
+    fn entries(&self, name: &str) -> Vec<(&str, &str)> {
+        self.sections.iter()
+            .filter(|(n, _)| n == name)
+            .flat_map(|(_, entries)| entries.iter())
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect()
+    }
+}
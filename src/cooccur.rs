@@ -0,0 +1,132 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Analysis of which ereport classes tend to occur together in time, across
+// all devices.  For example, fabric errors reported against an HBA shortly
+// followed by transport errors against the disks behind it point at the HBA
+// (or the cabling) as the root cause, rather than the disks.
+//
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Write;
+
+use i18n::tr;
+use DeviceHash;
+
+// The default window, in seconds, within which two events are considered to
+// have occurred together
+pub const DEFAULT_WINDOW: i64 = 60;
+
+// The minimum number of co-occurrences for a pair to be reported
+const MIN_SUPPORT: u32 = 3;
+
+// The maximum number of pairs to report
+const MAX_PAIRS: usize = 10;
+
+#[derive(Debug)]
+pub struct CoOccurrence<'a> {
+    pub first: &'a str,
+    pub then: &'a str,
+    // the number of "first" events followed by a "then" event in the window
+    pub count: u32,
+    // the fraction of "first" events followed by a "then" event
+    pub confidence: f64,
+    // how much more likely a "then" event is after a "first" event than it
+    // would be by chance
+    pub lift: f64,
+}
+
+pub fn find_cooccurrences(
+    device_hash: &DeviceHash,
+    window: i64
+) -> Vec<CoOccurrence<'_>> {
+
+    let mut events: Vec<(i64, &str)> = device_hash.values()
+        .flat_map(|d| d.ereports.iter().map(|e| (e.tod[0], e.class.as_str())))
+        .collect();
+    events.sort();
+    if events.is_empty() {
+        return Vec::new();
+    }
+
+    let mut class_counts: HashMap<&str, u32> = HashMap::new();
+    for (_, class) in &events {
+        *class_counts.entry(class).or_insert(0) += 1;
+    }
+
+    //
+    // For each event, find the distinct classes of the events that followed
+    // it within the window, counting each pair at most once per event.
+    //
+    let mut pair_counts: HashMap<(&str, &str), u32> = HashMap::new();
+    for (idx, (tod, class)) in events.iter().enumerate() {
+        let mut seen = HashSet::new();
+        for (next_tod, next_class) in &events[idx + 1..] {
+            if next_tod - tod > window {
+                break;
+            }
+            if next_class != class && seen.insert(*next_class) {
+                *pair_counts.entry((class, next_class)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    //
+    // The chance of a given class turning up in a random window is estimated
+    // from the number of windows in the log period.
+    //
+    let span = (events.last().unwrap().0 - events[0].0).max(window);
+    let nwindows = (span as f64 / window as f64).max(1.0);
+
+    let mut results: Vec<CoOccurrence> = pair_counts.iter()
+        .filter(|(_, count)| **count >= MIN_SUPPORT)
+        .map(|((first, then), count)| {
+            let nfirst = class_counts[first] as f64;
+            let nthen = class_counts[then] as f64;
+            let confidence = *count as f64 / nfirst;
+            let chance = (nthen / nwindows).min(1.0);
+            CoOccurrence {
+                first,
+                then,
+                count: *count,
+                confidence,
+                lift: confidence / chance,
+            }
+        })
+        .collect();
+    results.sort_by(|a, b| b.lift.partial_cmp(&a.lift).unwrap()
+        .then(b.count.cmp(&a.count))
+        .then(a.first.cmp(b.first))
+        .then(a.then.cmp(b.then)));
+    results.truncate(MAX_PAIRS);
+    results
+}
+
+pub fn write_cooccurrences(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    window: i64
+) -> Result<(), Box<dyn Error>> {
+
+    let results = find_cooccurrences(device_hash, window);
+    if results.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Class Co-occurrence (within {}s)")
+        .replace("{}", &window.to_string()))?;
+    for co in &results {
+        writeln!(out, "{}", co.first)?;
+        writeln!(out, "  -> {0: <36} {1} times, {2:.0}% of the time, \
+            lift {3:.1}", co.then, co.count, co.confidence * 100.0, co.lift)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
@@ -0,0 +1,290 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Decoding of CPU and memory ereports (ereport.cpu.*).  These are grouped by
+// the chip, core and strand named by their detector (or by cpuid or memory
+// unum, for the cpu and mem schemes), and the machine check bank status in
+// their payload is decoded into the type of error, following the MCA error
+// codes of the Intel SDM (volume 3B, chapter 15), which AMD processors share:
+//
+//   0000 0000 0000 0001    unclassified and other simple error codes
+//   000F 0000 0000 11LL    generic cache hierarchy error
+//   000F 0000 0001 TTLL    TLB error
+//   000F 0000 1MMM CCCC    memory controller error
+//   000F 0001 RRRR TTLL    cache error
+//   000F 1PPT RRRR IILL    bus or interconnect error
+//
+// along with whether it was corrected and whether the processor context was
+// corrupted.  The bank status is taken from the payload's "bank-status"
+// member, as fmdump gives it, or "status", as the Linux input adapters give
+// it.  Each CPU or memory location then gets a section of the report listing
+// its counts of each type of error.
+//
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use get_event_timestamp;
+use hwgrok::HwGrok;
+use i18n::tr;
+use numfmt::NumberFormat;
+use Ereport;
+
+pub const CLASS_PREFIX: &str = "ereport.cpu.";
+
+// The bits of the IA32_MCi_STATUS register
+const MCI_STATUS_VAL: u64 = 1 << 63;
+const MCI_STATUS_OVER: u64 = 1 << 62;
+const MCI_STATUS_UC: u64 = 1 << 61;
+const MCI_STATUS_PCC: u64 = 1 << 57;
+
+#[derive(Debug, Default)]
+struct ErrorCounts {
+    corrected: u64,
+    uncorrected: u64,
+}
+
+#[derive(Debug, Default)]
+struct Location {
+    // the device key of the first error, by which the DIMM slot is found
+    key: String,
+    first: i64,
+    last: i64,
+    // keyed by the decoded error type
+    errors: BTreeMap<String, ErrorCounts>,
+    // the number of errors which overflowed the bank or corrupted the
+    // processor context
+    overflows: u64,
+    context_corrupt: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct CpuErrors {
+    // keyed by the location of the errors, e.g. "chip 0 core 3 strand 1"
+    locations: BTreeMap<String, Location>,
+}
+
+fn level(ll: u64) -> &'static str {
+    ["L0", "L1", "L2", "generic level"][ll as usize & 3]
+}
+
+fn transaction(tt: u64) -> &'static str {
+    ["instruction", "data", "generic", "reserved"][tt as usize & 3]
+}
+
+fn request(rrrr: u64) -> &'static str {
+    match rrrr {
+        0 => "generic",
+        1 => "read",
+        2 => "write",
+        3 => "data read",
+        4 => "data write",
+        5 => "instruction fetch",
+        6 => "prefetch",
+        7 => "eviction",
+        8 => "snoop",
+        _ => "reserved",
+    }
+}
+
+fn mem_transaction(mmm: u64) -> &'static str {
+    match mmm {
+        0 => "generic",
+        1 => "read",
+        2 => "write",
+        3 => "address/command",
+        4 => "scrubbing",
+        _ => "reserved",
+    }
+}
+
+//
+// Describe the MCA error code in bits 15:0 of a bank's status.
+//
+fn decode_mca(code: u64) -> String {
+    // bit 12 only indicates that corrected error reporting was filtered
+    let code = code & 0xefff;
+    match code {
+        0x0000 => "no error".to_string(),
+        0x0001 => "unclassified error".to_string(),
+        0x0002 => "microcode ROM parity error".to_string(),
+        0x0003 => "external error".to_string(),
+        0x0004 => "FRC error".to_string(),
+        0x0005 => "internal parity error".to_string(),
+        0x0006 => "SMM handler code access violation".to_string(),
+        0x0400 => "internal timer error".to_string(),
+        c if c & 0xfc00 == 0x0400 => "internal unclassified error".to_string(),
+        c if c & 0xfffc == 0x000c => {
+            format!("{} cache hierarchy error", level(c))
+        }
+        c if c & 0xfff0 == 0x0010 => {
+            format!("{} {} TLB error", level(c), transaction(c >> 2))
+        }
+        c if c & 0xff80 == 0x0080 => {
+            let channel = match c & 0xf {
+                0xf => String::new(),
+                n => format!(", channel {}", n),
+            };
+            format!("memory controller {} error{}",
+                mem_transaction((c >> 4) & 0x7), channel)
+        }
+        c if c & 0xff00 == 0x0100 => {
+            format!("{} {} cache {} error", level(c), transaction(c >> 2),
+                request((c >> 4) & 0xf))
+        }
+        c if c & 0x0800 != 0 => {
+            format!("{} bus/interconnect {} error{}", level(c),
+                request((c >> 4) & 0xf),
+                if c & 0x100 != 0 { " (timeout)" } else { "" })
+        }
+        c => format!("unknown MCA error code 0x{:04x}", c),
+    }
+}
+
+//
+// Bank status and similar registers may be given as numbers or as strings of
+// hex digits, with or without a leading "0x".
+//
+pub fn register(value: Option<&Value>) -> Option<u64> {
+    match value? {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => {
+            let s = s.trim();
+            u64::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16).ok()
+        }
+        _ => None,
+    }
+}
+
+//
+// Returns the location named by a device key: the chip, core and strand of
+// an hc path, or the key itself for the other schemes.
+//
+fn location(key: &str) -> String {
+    let parts: Vec<String> = key.split('/')
+        .filter_map(|c| c.split_once('='))
+        .filter(|(name, _)| ["chip", "core", "strand", "memory-controller",
+            "dram-channel", "dimm", "rank"].contains(name))
+        .map(|(name, id)| format!("{} {}", name, id))
+        .collect();
+    if parts.is_empty() {
+        key.to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+impl CpuErrors {
+    pub fn add(&mut self, key: &str, ereport: &Ereport) {
+        let tod = ereport.tod.first().cloned().unwrap_or(0);
+        let loc = self.locations.entry(location(key)).or_insert(Location {
+            key: key.to_string(),
+            first: tod,
+            last: tod,
+            ..Location::default()
+        });
+        loc.first = loc.first.min(tod);
+        loc.last = loc.last.max(tod);
+
+        let status = register(ereport.payload.get("bank-status"))
+            .or_else(|| register(ereport.payload.get("status")))
+            .filter(|s| s & MCI_STATUS_VAL != 0);
+        let (kind, uncorrected) = match status {
+            Some(status) => {
+                if status & MCI_STATUS_OVER != 0 {
+                    loc.overflows += 1;
+                }
+                if status & MCI_STATUS_PCC != 0 {
+                    loc.context_corrupt += 1;
+                }
+                (decode_mca(status & 0xffff), status & MCI_STATUS_UC != 0)
+            }
+            //
+            // Without a bank status, fall back on the ereport's class, whose
+            // leaf generally says whether the error was corrected.
+            //
+            None => {
+                let leaf = ereport.class.rsplit('.').next().unwrap_or("");
+                (ereport.class.clone(), leaf.starts_with("uc") ||
+                    leaf.starts_with("ue") || leaf.contains("_uc") ||
+                    leaf.contains("_ue"))
+            }
+        };
+        let counts = loc.errors.entry(kind).or_default();
+        if uncorrected {
+            counts.uncorrected += 1;
+        } else {
+            counts.corrected += 1;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    //
+    // Write a section for each location with CPU or memory errors, giving
+    // the DIMM slot or processor socket it's in if hwgrok knows it.
+    //
+    pub fn write(
+        &self,
+        out: &mut dyn Write,
+        numbers: &NumberFormat,
+        hwgrok: &HwGrok
+    ) -> Result<(), Box<dyn Error>> {
+
+        for (name, loc) in &self.locations {
+            writeln!(out, "{}", "=".repeat(75))?;
+            writeln!(out, "{0: <40} {1}", tr("CPU/Memory Location:"), name)?;
+            if let Some(slot) = hwgrok.find_dimm(&loc.key) {
+                writeln!(out, "{0: <40} {1}", tr("DIMM Slot:"),
+                    slot.slot_label)?;
+            } else if let Some(chip) = hwgrok.find_processor(&loc.key) {
+                writeln!(out, "{0: <40} {1}", tr("Processor Socket:"),
+                    chip.chip_label)?;
+                writeln!(out, "{0: <40} {1}", tr("Processor Brand:"),
+                    chip.chip_brand)?;
+                if let Some(cores) = chip.chip_cores {
+                    writeln!(out, "{0: <40} {1}", tr("Cores:"), cores)?;
+                }
+                if let Some(threads) = chip.chip_threads_per_core {
+                    writeln!(out, "{0: <40} {1}", tr("Threads Per Core:"),
+                        threads)?;
+                }
+            }
+            writeln!(out, "{0: <40} {1} - {2}", tr("Period:"),
+                get_event_timestamp(loc.first),
+                get_event_timestamp(loc.last))?;
+            if loc.overflows > 0 {
+                writeln!(out, "{0: <40} {1}", tr("Bank Overflows:"),
+                    numbers.int(loc.overflows))?;
+            }
+            if loc.context_corrupt > 0 {
+                writeln!(out, "{0: <40} {1}", tr("Processor Context Corrupt:"),
+                    numbers.int(loc.context_corrupt))?;
+            }
+            writeln!(out)?;
+
+            let headings = [
+                ("error type", "corrected", "uncorrected"),
+                ("----------", "---------", "-----------"),
+            ];
+            for h in &headings {
+                writeln!(out, "{0: <48} {1: <11} {2}", h.0, h.1, h.2)?;
+            }
+            for (kind, counts) in &loc.errors {
+                writeln!(out, "{0: <48} {1: <11} {2}", kind,
+                    numbers.int(counts.corrected),
+                    numbers.int(counts.uncorrected))?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
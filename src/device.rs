@@ -0,0 +1,331 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for the "device" subcommand, which generates an exhaustive report
+// for a single device rather than the summary report for every device.
+//
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::error::Error;
+use std::io::Write;
+
+use aer;
+use devpath::{self, DeviceKey};
+use diagnosis::{self, Contribution};
+use fmri;
+use i18n::tr;
+use iostat;
+use replacement;
+use sense::{self, Sense};
+use smart;
+use {get_event_time, get_event_timestamp, write_class_row, write_coverage,
+    write_device_header, write_partial, write_status, write_system,
+    write_timeline, DeviceHash, DeviceHashEnt, ReportData};
+
+// The number of most recent events to decode in the drill-down report.
+const RECENT_EVENTS: usize = 10;
+
+//
+// Payload members that are either internal to fmd or already accounted for
+// elsewhere in the report and so aren't worth printing when decoding events.
+//
+const IGNORED_MEMBERS: [&str; 5] = ["class", "detector", "version", "__ttl",
+    "__tod"];
+
+//
+// A list.suspect event, as published by fmd when it diagnoses a fault case.
+//
+#[derive(Debug, Deserialize)]
+struct SuspectList {
+    uuid: String,
+    code: Option<String>,
+    #[serde(rename = "__tod")]
+    tod: Vec<i64>,
+    #[serde(rename = "fault-list", default)]
+    fault_list: Vec<SuspectFault>,
+    // the ENAs of the ereports which led to the diagnosis, where recorded
+    #[serde(rename = "ena-list", default)]
+    ena_list: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SuspectFault {
+    class: Option<String>,
+    asru: Option<Value>,
+    fru: Option<Value>,
+    resource: Option<Value>,
+    ena: Option<Value>,
+}
+
+//
+// A summary of a diagnosed fault case (list.suspect event), recording the
+// resources that were implicated so they can be associated with a device,
+// and the ereports which contributed to the diagnosis (see diagnosis.rs).
+//
+#[derive(Debug)]
+pub struct SuspectCase {
+    pub uuid: String,
+    pub code: String,
+    pub tod: i64,
+    pub faults: Vec<String>,
+    resources: Vec<String>,
+    pub enas: Vec<u64>,
+    pub contributing: Vec<Contribution>,
+    // whether the contributing ereports were inferred rather than recorded
+    pub inferred: bool,
+}
+
+impl SuspectCase {
+    pub fn from_json(event: &Value) -> Option<SuspectCase> {
+        let list: SuspectList = serde_json::from_value(event.clone()).ok()?;
+        let tod = *list.tod.first()?;
+
+        let mut faults = Vec::new();
+        let mut resources = Vec::new();
+        let mut enas: Vec<u64> = list.ena_list.iter()
+            .filter_map(diagnosis::parse_ena)
+            .collect();
+        for fault in &list.fault_list {
+            if let Some(class) = &fault.class {
+                faults.push(class.clone());
+            }
+            for fmri in [&fault.asru, &fault.fru, &fault.resource].iter()
+                .filter_map(|f| f.as_ref()) {
+                if let Some(s) = fmri::from_json(fmri) {
+                    resources.push(s);
+                }
+            }
+            enas.extend(fault.ena.as_ref().and_then(diagnosis::parse_ena));
+        }
+
+        Some(SuspectCase {
+            uuid: list.uuid,
+            code: list.code.unwrap_or_else(|| "-".to_string()),
+            tod,
+            faults,
+            resources,
+            enas,
+            contributing: Vec::new(),
+            inferred: false,
+        })
+    }
+
+    pub fn implicates(&self, devpath: &str) -> bool {
+        self.resources.iter().any(|r| r == devpath)
+    }
+}
+
+//
+// Resolve the user-supplied device argument to a key in the device hash.  The
+// argument can either be a device path (or FMRI string) as it appears in the
+// summary report, a label or serial number from the hwgrok data or a
+// user-defined alias.
+//
+pub fn resolve_device(
+    arg: &str,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Option<DeviceKey> {
+
+    let hwgrok = &data.hwgrok;
+
+    if let Some((key, _)) = device_hash.get_key_value(arg) {
+        return Some(key.clone());
+    }
+    for devpath in device_hash.keys() {
+        if data.aliases.lookup(devpath, hwgrok) == Some(arg) {
+            return Some(devpath.clone());
+        }
+    }
+    if arg.starts_with('/') {
+        let canon = devpath::canonicalize(arg);
+        if device_hash.contains_key(&canon) {
+            return Some(canon);
+        }
+    }
+
+    for drive_bay in &hwgrok.drive_bays {
+        if let Some(disk) = &drive_bay.bay_disk {
+            if drive_bay.bay_label == arg || disk.disk_serial_number == arg {
+                return disk.devpath();
+            }
+        }
+    }
+    for pci_dev in &hwgrok.pci_devices {
+        if pci_dev.pci_label == arg {
+            return Some(devpath::canonicalize(&pci_dev.pci_device_path));
+        }
+    }
+    for nic in &hwgrok.nics {
+        if [&nic.nic_label, &nic.nic_name, &nic.nic_mac_addr].iter()
+            .any(|id| !id.is_empty() && id.as_str() == arg) {
+            return Some(devpath::canonicalize(&nic.nic_device_path));
+        }
+    }
+
+    //
+    // Memory ereports may have been detected by any of a DIMM's ranks, so
+    // the first of its detectors with ereports is taken.
+    //
+    let mut dimm_keys: Vec<&DeviceKey> = device_hash.keys()
+        .filter(|key| hwgrok.find_dimm(key).is_some_and(|slot| {
+            slot.slot_label == arg ||
+                slot.dimm.as_ref().is_some_and(|d| d.dimm_serial == arg)
+        }))
+        .collect();
+    dimm_keys.sort();
+    dimm_keys.first().map(|key| (*key).clone())
+}
+
+fn format_payload(payload: &serde_json::Map<String, Value>) -> String {
+    let mut members: Vec<String> = Vec::new();
+    for (name, value) in payload.iter() {
+        if IGNORED_MEMBERS.contains(&name.as_str()) {
+            continue;
+        }
+        members.push(format!("{}={}", name, value));
+    }
+    members.join(" ")
+}
+
+pub fn write_device_report(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let cases = &data.cases;
+    writeln!(out)?;
+    write_system(out, data)?;
+    write_coverage(out, data)?;
+    write_partial(out, data)?;
+    writeln!(out, "{}", "=".repeat(75))?;
+    write_device_header(out, devpath, devent, data)?;
+
+    let devent = match devent {
+        Some(d) => d,
+        None => {
+            writeln!(out, "{0: <40} {1}\n", tr("Total ereports:"), 0)?;
+            data.faults.write_device(out, devpath)?;
+            return write_cases(out, devpath, cases);
+        }
+    };
+    writeln!(out, "{0: <40} {1}", tr("Total ereports:"),
+        data.numbers.int(devent.ereports.len() as u64))?;
+    write_status(out, devpath, data)?;
+    iostat::write_device_io(out, devpath, devent, &data.iostats,
+        &data.hwgrok)?;
+    if let (Some(first), Some(last)) =
+        (devent.ereports.first(), devent.ereports.last()) {
+        writeln!(out, "{0: <40} {1}", tr("First Seen:"),
+            get_event_time(first))?;
+        writeln!(out, "{0: <40} {1}", tr("Last Seen:"), get_event_time(last))?;
+    }
+
+    //
+    // Walk the ereports to find the first and last occurrence of each class.
+    // The ereports are stored in the order they were logged.
+    //
+    let mut classes: Vec<(&str, u32, String, String)> = Vec::new();
+    for (ereport_class, count) in devent.ereport_class_hash.iter() {
+        let mut matching = devent.ereports.iter()
+            .filter(|e| e.class == *ereport_class);
+        let first = matching.next().map(get_event_time).unwrap_or_default();
+        let last = matching.next_back().map(get_event_time)
+            .unwrap_or_else(|| first.clone());
+        classes.push((ereport_class, *count, first, last));
+    }
+    classes.sort_by(|a, b| a.2.cmp(&b.2));
+
+    let headings = [
+        ("class", "# occurences", "first seen", "last seen"),
+        ("-----", "------------", "----------", "---------"),
+    ];
+    writeln!(out)?;
+    smart::write_smart(out, devpath, &data.smart, &data.hwgrok)?;
+    replacement::write_replacements(out, devent, &data.numbers)?;
+    aer::write_device_aer(out, devent, &data.numbers)?;
+    sense::write_device_sense(out, devent, &data.numbers)?;
+    for (class, count, first, last) in &headings {
+        writeln!(out, "{}", data.columns.row(&[("class", class),
+            ("count", count), ("first-seen", first), ("last-seen", last)]))?;
+    }
+    for (ereport_class, count, first, last) in &classes {
+        let count = data.numbers.int(*count as u64);
+        write_class_row(out, ereport_class, &[("count", &count),
+            ("first-seen", first), ("last-seen", last)], data)?;
+    }
+
+    let heading = tr("Event Timeline");
+    writeln!(out, "\n{}\n{}", heading,
+        "-".repeat(heading.chars().count()))?;
+    write_timeline(out, devpath, devent, data)?;
+
+    let heading = tr("Most Recent Events");
+    writeln!(out, "\n{}\n{}", heading,
+        "-".repeat(heading.chars().count()))?;
+    let skip = devent.ereports.len().saturating_sub(RECENT_EVENTS);
+    for ereport in devent.ereports.iter().skip(skip) {
+        let sev = data.severity.lookup(&ereport.class);
+        writeln!(out, "{0: <21} {1}", get_event_time(ereport),
+            sev.paint(&ereport.class, data.color))?;
+        let payload = format_payload(&ereport.payload);
+        if !payload.is_empty() {
+            writeln!(out, "{0: <21} {1}", "", payload)?;
+        }
+        let errors = aer::decode(&ereport.payload);
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors.iter()
+                .map(|(name, kind)| format!("{} ({})", name, kind))
+                .collect();
+            writeln!(out, "{0: <21} AER: {1}", "", errors.join(", "))?;
+        }
+        if let Some(sense) = Sense::from_payload(&ereport.payload)
+            .filter(|_| ereport.class.starts_with(sense::CLASS_PREFIX)) {
+            let line = format!("{0: <21} Sense: {1} {2}", "", sense.code(),
+                sense.description().unwrap_or(""));
+            writeln!(out, "{}", line.trim_end())?;
+        }
+    }
+
+    writeln!(out)?;
+    data.faults.write_device(out, devpath)?;
+    write_cases(out, devpath, cases)
+}
+
+fn write_cases(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    cases: &[SuspectCase]
+) -> Result<(), Box<dyn Error>> {
+
+    let heading = tr("Related Fault Cases");
+    writeln!(out, "{}\n{}", heading,
+        "-".repeat(heading.chars().count()))?;
+    let mut found = false;
+    for case in cases.iter().filter(|c| c.implicates(devpath)) {
+        found = true;
+        writeln!(out, "{0: <40} {1}", tr("Case UUID:"), case.uuid)?;
+        writeln!(out, "{0: <40} {1}", tr("Diagnosed:"),
+            get_event_timestamp(case.tod))?;
+        writeln!(out, "{0: <40} {1}", tr("Message ID:"), case.code)?;
+        for fault in &case.faults {
+            writeln!(out, "{0: <40} {1}", tr("Fault Class:"), fault)?;
+        }
+        let count: u32 = case.contributing.iter().map(|c| c.count).sum();
+        writeln!(out, "{0: <40} {1}{2}", tr("Contributing Ereports:"), count,
+            if case.inferred { " (inferred)" } else { "" })?;
+        writeln!(out)?;
+    }
+    if !found {
+        writeln!(out, "none\n")?;
+    }
+
+    Ok(())
+}
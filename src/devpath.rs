@@ -0,0 +1,344 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+
+use fmri;
+
+//
+// The same physical device can be named by several variants of its /devices
+// path, depending on which driver or minor node generated the telemetry.  For
+// example, all of the following refer to the same disk:
+//
+//   /pci@0,0/pci15d9,808@0/iport@ff/disk@w5000cca26652bfb9,0
+//   /pci@0,0/pci15d9,808@0/iport@ff/disk@w5000CCA26652BFB9,0:a
+//   /pci@0,0/pci15d9,808@0/iport@ff/disk@w5000cca26652bfb9,0:q,raw
+//   /devices/pci@0,0/pci15d9,808@0//iport@ff/disk@w5000cca26652bfb9
+//
+// A DevicePath holds the canonical form of such a path, suitable for use as a
+// hash key: any leading /devices is dropped, redundant separators are
+// collapsed, the trailing minor node name (which is how partitions/slices are
+// represented) is stripped, unit-addresses are converted to lowercase, and a
+// trailing ",0" (LUN 0, or PCI function 0, which may be left implied) is
+// removed from the unit-address of the last component.
+//
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DevicePath(String);
+
+impl DevicePath {
+    pub fn new(path: &str) -> DevicePath {
+        let path = match path.strip_prefix("/devices/") {
+            Some(rest) => rest,
+            None => path,
+        };
+        let mut canon = String::with_capacity(path.len());
+
+        let components: Vec<&str> = path.split('/')
+            .filter(|c| !c.is_empty())
+            .collect();
+        let ncomponents = components.len();
+
+        for (idx, component) in components.iter().enumerate() {
+            let last = idx == ncomponents - 1;
+
+            //
+            // Minor node names can only appear on the last component of the
+            // path.
+            //
+            let component = if last {
+                match component.find(':') {
+                    Some(off) => &component[..off],
+                    None => component,
+                }
+            } else {
+                component
+            };
+
+            canon.push('/');
+            match component.find('@') {
+                Some(off) => {
+                    let mut addr = component[off + 1..].to_lowercase();
+                    if last && addr.ends_with(",0") {
+                        addr.truncate(addr.len() - 2);
+                    }
+                    canon.push_str(&component[..off]);
+                    canon.push('@');
+                    canon.push_str(&addr);
+                }
+                None => canon.push_str(component),
+            }
+        }
+
+        if canon.is_empty() {
+            canon.push('/');
+        }
+        DevicePath(canon)
+    }
+}
+
+impl fmt::Display for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl DevicePath {
+    //
+    // Returns the path with any slice or partition stripped (see whole_disk).
+    //
+    pub fn whole_disk(self) -> DevicePath {
+        DevicePath(whole_disk(&self.0))
+    }
+}
+
+impl From<DevicePath> for String {
+    fn from(path: DevicePath) -> String {
+        path.0
+    }
+}
+
+//
+// The key of a device in the device hash, which is either the canonical form
+// of its device path, or for a device identified by an FMRI (such as the hc
+// FMRI of a CPU, DIMM or drive bay), the FMRI without its authority (see
+// fmri::from_str).  A key can only be made from one of those, so that the
+// variants of a device's name can't end up as separate entries.  Keys can
+// still be looked up by string, as they borrow as one.
+//
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceKey(String);
+
+impl DeviceKey {
+    pub fn from_fmri(fmri: &str) -> DeviceKey {
+        DeviceKey(fmri::from_str(fmri))
+    }
+
+    //
+    // Returns the key for a device named by a path or an FMRI, as given by a
+    // user, a script or a stored report.
+    //
+    pub fn parse(name: &str) -> DeviceKey {
+        if name.contains("://") {
+            DeviceKey::from_fmri(name)
+        } else {
+            DevicePath::new(name).into()
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<DevicePath> for DeviceKey {
+    fn from(path: DevicePath) -> DeviceKey {
+        DeviceKey(path.0)
+    }
+}
+
+impl From<DeviceKey> for String {
+    fn from(key: DeviceKey) -> String {
+        key.0
+    }
+}
+
+impl Deref for DeviceKey {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for DeviceKey {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for DeviceKey {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for DeviceKey {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<DeviceKey> for str {
+    fn eq(&self, other: &DeviceKey) -> bool {
+        self == other.0
+    }
+}
+
+impl PartialEq<DeviceKey> for &str {
+    fn eq(&self, other: &DeviceKey) -> bool {
+        *self == other.0
+    }
+}
+
+impl fmt::Display for DeviceKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+//
+// Returns the key in the device hash for a device path, its canonical form.
+//
+pub fn canonicalize(path: &str) -> DeviceKey {
+    DevicePath::new(path).into()
+}
+
+//
+// Check whether the given string is a logical disk name of the form
+// c<N>[t<target>]d<N>, where the target is either a decimal number or a WWN.
+//
+fn is_ctd(name: &str) -> bool {
+    let all_digits = |s: &str| !s.is_empty() &&
+        s.chars().all(|c| c.is_ascii_digit());
+
+    //
+    // As the target may be a WWN containing the hex digit 'd', the disk
+    // number is whatever follows the last 'd'.
+    //
+    let (ct, disk) = match name.rfind('d') {
+        Some(off) => (&name[..off], &name[off + 1..]),
+        None => return false,
+    };
+    if !all_digits(disk) {
+        return false;
+    }
+    let ct = match ct.strip_prefix('c') {
+        Some(ct) => ct,
+        None => return false,
+    };
+    match ct.find('t') {
+        Some(off) => {
+            let target = &ct[off + 1..];
+            all_digits(&ct[..off]) && !target.is_empty() &&
+                target.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => all_digits(ct),
+    }
+}
+
+//
+// Strip any slice (s<N>) or fdisk partition (p<N>) component from a disk
+// device name so that events detected against individual partitions can be
+// rolled up to the whole disk.  Logical device names under /dev/rdsk are
+// also rewritten to their /dev/dsk equivalent.  Partitions of /devices paths
+// are represented by minor nodes, which canonicalize() already strips.
+//
+pub fn whole_disk(path: &str) -> String {
+    let path = path.replacen("/dev/rdsk/", "/dev/dsk/", 1);
+    let (dir, name) = match path.rfind('/') {
+        Some(off) => (&path[..off + 1], &path[off + 1..]),
+        None => ("", path.as_str()),
+    };
+
+    if let Some(off) = name.rfind(['s', 'p']) {
+        let (disk, part) = (&name[..off], &name[off + 1..]);
+        if !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()) &&
+            is_ctd(disk) {
+            return format!("{}{}", dir, disk);
+        }
+    }
+    path.to_string()
+}
+
+//
+// Node names of pseudo devices which don't correspond to replaceable hardware.
+//
+const VIRTUAL_NODES: [&str; 5] = ["lofi", "zfs", "zvol", "ramdisk", "md"];
+
+//
+// Returns true if the device path refers to a virtual or pseudo device, such
+// as a lofi device, zvol or ramdisk, rather than to physical hardware.
+//
+pub fn is_virtual(path: &str) -> bool {
+    if path.starts_with("/pseudo/") || path.starts_with("/dev/zvol/") ||
+        path.starts_with("/dev/lofi/") || path.starts_with("/dev/rlofi/") ||
+        path.starts_with("/dev/ramdisk/") {
+        return true;
+    }
+    path.split('/').any(|component| {
+        let node = component.split('@').next().unwrap_or("");
+        VIRTUAL_NODES.contains(&node)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonicalize, is_virtual, whole_disk, DeviceKey, DevicePath};
+
+    #[test]
+    fn canonical() {
+        let disk = "/pci@0,0/pci15d9,808@0/iport@ff/disk@w5000cca26652bfb9";
+        for path in &[
+            "/pci@0,0/pci15d9,808@0/iport@ff/disk@w5000cca26652bfb9,0",
+            "/pci@0,0/pci15d9,808@0/iport@ff/disk@w5000CCA26652BFB9,0:a",
+            "/pci@0,0/pci15d9,808@0/iport@ff/disk@w5000cca26652bfb9,0:q,raw",
+            "/devices/pci@0,0/pci15d9,808@0//iport@ff/disk@w5000cca26652bfb9",
+            "pci@0,0/pci15d9,808@0/iport@ff/disk@w5000cca26652bfb9/",
+        ] {
+            assert_eq!(canonicalize(path), disk, "{}", path);
+        }
+
+        // only the last component loses its ",0" or minor name
+        assert_eq!(DevicePath::new("/pci@0,0/pci8086,2f02@1,0").to_string(),
+            "/pci@0,0/pci8086,2f02@1");
+        assert_eq!(DevicePath::new("/pci@0,0/pci8086,2f02@1,1").to_string(),
+            "/pci@0,0/pci8086,2f02@1,1");
+        assert_eq!(DevicePath::new("/pseudo/lofi@0:1").to_string(),
+            "/pseudo/lofi@0");
+        assert_eq!(DevicePath::new("").to_string(), "/");
+        assert_eq!(DevicePath::new("//").to_string(), "/");
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(DeviceKey::parse("/devices/pci@0,0/pci8086,2f02@1,0:devctl"),
+            "/pci@0,0/pci8086,2f02@1");
+        assert_eq!(DeviceKey::parse("hc://:chassis-id=S1/chassis=0/bay=5"),
+            "hc:///chassis=0/bay=5");
+        assert_eq!(DeviceKey::parse("dev:///:devid=id1,sd@n5000cca2//pci@0,0/\
+            disk@w5000cca2,0"), "/pci@0,0/disk@w5000cca2");
+        assert_eq!(DeviceKey::from_fmri("cpu:///cpuid=3"), "cpu:///cpuid=3");
+    }
+
+    #[test]
+    fn whole_disks() {
+        assert_eq!(whole_disk("/dev/rdsk/c0t0d0s0"), "/dev/dsk/c0t0d0");
+        assert_eq!(whole_disk("/dev/dsk/c1t5000CCA26652BFB9d0p1"),
+            "/dev/dsk/c1t5000CCA26652BFB9d0");
+        assert_eq!(whole_disk("c2d0s7"), "c2d0");
+        assert_eq!(whole_disk("/dev/dsk/c0t0d0"), "/dev/dsk/c0t0d0");
+        assert_eq!(whole_disk("/dev/dsk/c0t0d0s"), "/dev/dsk/c0t0d0s");
+        assert_eq!(whole_disk("/dev/zvol/dsk/zones/swap"),
+            "/dev/zvol/dsk/zones/swap");
+        assert_eq!(whole_disk("/dev/dsk/ct0d0s0"), "/dev/dsk/ct0d0s0");
+        assert_eq!(DevicePath::new("/dev/rdsk/c0t0d0s0").whole_disk()
+            .to_string(), "/dev/dsk/c0t0d0");
+    }
+
+    #[test]
+    fn virtual_devices() {
+        assert!(is_virtual("/pseudo/lofi@1"));
+        assert!(is_virtual("/dev/zvol/dsk/zones/swap"));
+        assert!(is_virtual("/pci@0,0/ramdisk@1"));
+        assert!(is_virtual("/zfs"));
+        assert!(!is_virtual("/pci@0,0/pci15d9,808@0/iport@ff/disk@w5000"));
+        assert!(!is_virtual("/pci@0,0/mdio@1"));
+    }
+}
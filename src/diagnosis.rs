@@ -0,0 +1,154 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Correlation of diagnosed fault cases (list.suspect events) with the
+// ereports that led to them, so that the report shows which error telemetry
+// resulted in an actual diagnosis.  Where the suspect list records the ENAs
+// (error numeric associations) of its ereports, in its "ena-list" member or in
+// the "ena" member of each fault, the ereports with those ENAs are the
+// contributing ones.  Otherwise, they are inferred to be the ereports logged
+// against the implicated devices up to the time of the diagnosis.
+//
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+
+use device::SuspectCase;
+use i18n::tr;
+use numfmt::NumberFormat;
+use {get_event_timestamp, DeviceHash};
+
+//
+// The ereports of one class, logged against one device, which contributed to
+// a diagnosis.
+//
+#[derive(Debug)]
+pub struct Contribution {
+    pub devpath: String,
+    pub class: String,
+    pub count: u32,
+}
+
+//
+// Parse an ENA, which fmdump gives as a hex string but other sources may give
+// as a number.
+//
+pub fn parse_ena(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => {
+            let s = s.trim();
+            match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => s.parse().ok(),
+            }
+        }
+        _ => None,
+    }
+}
+
+//
+// Find the ereports which contributed to each case.
+//
+pub fn correlate(
+    cases: &mut [SuspectCase],
+    device_hash: &DeviceHash
+) {
+    let mut by_ena: HashMap<u64, (&str, &str)> = HashMap::new();
+    if cases.iter().any(|c| !c.enas.is_empty()) {
+        for (devpath, devent) in device_hash.iter() {
+            for ereport in &devent.ereports {
+                if let Some(ena) = ereport.payload.get("ena")
+                    .and_then(parse_ena) {
+                    by_ena.insert(ena, (devpath, &ereport.class));
+                }
+            }
+        }
+    }
+
+    for case in cases.iter_mut() {
+        let mut counts: BTreeMap<(&str, &str), u32> = BTreeMap::new();
+        if case.enas.is_empty() {
+            for (devpath, devent) in device_hash.iter()
+                .filter(|(devpath, _)| case.implicates(devpath)) {
+                for ereport in devent.ereports.iter()
+                    .filter(|e| e.tod.first().is_some_and(|t| *t <= case.tod)) {
+                    *counts.entry((devpath, &ereport.class)).or_insert(0) += 1;
+                }
+            }
+        } else {
+            for ena in &case.enas {
+                if let Some(key) = by_ena.get(ena) {
+                    *counts.entry(*key).or_insert(0) += 1;
+                }
+            }
+        }
+        case.inferred = case.enas.is_empty();
+        case.contributing = counts.into_iter()
+            .map(|((devpath, class), count)| Contribution {
+                devpath: devpath.to_string(),
+                class: class.to_string(),
+                count,
+            })
+            .collect();
+    }
+}
+
+//
+// Write each diagnosed case with the ereports which contributed to it.
+//
+pub fn write_diagnoses(
+    out: &mut dyn Write,
+    cases: &[SuspectCase],
+    numbers: &NumberFormat
+) -> Result<(), Box<dyn Error>> {
+
+    if cases.is_empty() {
+        return Ok(());
+    }
+    let mut cases: Vec<&SuspectCase> = cases.iter().collect();
+    cases.sort_by_key(|c| c.tod);
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Diagnosed Cases"))?;
+    for case in cases {
+        writeln!(out, "{0: <40} {1}", tr("Case UUID:"), case.uuid)?;
+        writeln!(out, "{0: <40} {1}", tr("Diagnosed:"),
+            get_event_timestamp(case.tod))?;
+        writeln!(out, "{0: <40} {1}", tr("Message ID:"), case.code)?;
+        for fault in &case.faults {
+            writeln!(out, "{0: <40} {1}", tr("Fault Class:"), fault)?;
+        }
+        if case.contributing.is_empty() {
+            writeln!(out, "{0: <40} none\n", tr("Contributing Ereports:"))?;
+            continue;
+        }
+        writeln!(out, "{0: <40} {1}\n", tr("Contributing Ereports:"),
+            if case.inferred {
+                "inferred from the implicated devices"
+            } else {
+                "recorded by ENA"
+            })?;
+        let headings = [
+            ("class", "# ereports", "device"),
+            ("-----", "----------", "------"),
+        ];
+        for h in &headings {
+            writeln!(out, "{0: <40} {1: <12} {2}", h.0, h.1, h.2)?;
+        }
+        for c in &case.contributing {
+            writeln!(out, "{0: <40} {1: <12} {2}", c.class,
+                numbers.int(c.count as u64), c.devpath)?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,190 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Attribution of devices to the driver (and driver instance) that manages
+// them, so that the ereports for all instances of a driver can be rolled up
+// together: errors spread across every nvme instance suggest a driver or
+// firmware problem rather than a single bad device.  The driver is found, in
+// order of preference, from:
+//
+//   - the system's path_to_inst file, if given, which records the driver and
+//     instance number bound to each device path
+//   - "driver-name" (or "driver") and "instance" members in the ereport
+//     payloads, as included by some drivers and input formats
+//   - the node name of the last component of the device path, for the
+//     generic node names whose driver is well known (e.g. "disk" for sd)
+//
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+
+use devpath::{self, DeviceKey};
+use i18n::tr;
+use {DeviceHash, DeviceHashEnt};
+
+//
+// Generic node names and the drivers that normally attach to them.
+//
+const NODE_DRIVERS: [(&str, &str); 8] = [
+    ("disk", "sd"),
+    ("cdrom", "sd"),
+    ("tape", "st"),
+    ("blkdev", "blkdev"),
+    ("nvme", "nvme"),
+    ("iport", "mpt_sas"),
+    ("ses", "ses"),
+    ("smp", "smp"),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Driver {
+    pub name: String,
+    pub instance: Option<u64>,
+}
+
+impl fmt::Display for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.instance {
+            Some(i) => write!(f, "{}{}", self.name, i),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DriverMap {
+    // canonical device path to driver, from path_to_inst
+    bindings: HashMap<DeviceKey, Driver>,
+}
+
+impl DriverMap {
+    //
+    // Read a path_to_inst file, in which each line gives a quoted device
+    // path, an instance number and a quoted driver name:
+    //
+    //   "/pci@0,0/pci8086,2f02@1/pci15d9,808@0" 0 "mpt_sas"
+    //
+    pub fn from_file(path: &str) -> Result<DriverMap, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut map = DriverMap::default();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                continue;
+            }
+            let instance = match fields[1].parse() {
+                Ok(i) => i,
+                Err(_) => continue,
+            };
+            let devpath = devpath::canonicalize(fields[0].trim_matches('"'));
+            map.bindings.insert(devpath, Driver {
+                name: fields[2].trim_matches('"').to_string(),
+                instance: Some(instance),
+            });
+        }
+        Ok(map)
+    }
+
+    //
+    // Returns the driver managing the device, if it can be determined.
+    //
+    pub fn lookup(&self, devpath: &str, devent: &DeviceHashEnt)
+        -> Option<Driver> {
+
+        if let Some(driver) = self.bindings.get(devpath) {
+            return Some(driver.clone());
+        }
+        for ereport in &devent.ereports {
+            let name = ereport.payload.get("driver-name")
+                .or_else(|| ereport.payload.get("driver"))
+                .and_then(|n| n.as_str());
+            if let Some(name) = name {
+                let instance = ereport.payload.get("instance")
+                    .and_then(|i| i.as_u64());
+                return Some(Driver { name: name.to_string(), instance });
+            }
+        }
+        if !devpath.starts_with('/') {
+            return None;
+        }
+        let node = devpath.rsplit('/').next().unwrap_or("")
+            .split('@').next().unwrap_or("");
+        NODE_DRIVERS.iter().find(|(n, _)| *n == node)
+            .map(|(_, d)| Driver { name: d.to_string(), instance: None })
+    }
+}
+
+#[derive(Default)]
+struct Rollup<'a> {
+    instances: Vec<String>,
+    devices: usize,
+    ereports: usize,
+    classes: HashMap<&'a str, u32>,
+}
+
+//
+// Write a summary of the ereports for all of the devices managed by each
+// driver.
+//
+pub fn write_driver_rollup(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    drivers: &DriverMap
+) -> Result<(), Box<dyn Error>> {
+
+    let mut rollups: BTreeMap<String, Rollup> = BTreeMap::new();
+    for (devpath, devent) in device_hash.iter() {
+        let driver = match drivers.lookup(devpath, devent) {
+            Some(d) => d,
+            None => continue,
+        };
+        let rollup = rollups.entry(driver.name.clone()).or_default();
+        if driver.instance.is_some() {
+            rollup.instances.push(driver.to_string());
+        }
+        rollup.devices += 1;
+        rollup.ereports += devent.ereports.len();
+        for (class, count) in &devent.ereport_class_hash {
+            *rollup.classes.entry(class.as_str()).or_insert(0) += count;
+        }
+    }
+    if rollups.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Ereports By Driver"))?;
+    let headings = [
+        ("driver", "devices", "ereports", "most frequent class"),
+        ("------", "-------", "--------", "-------------------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <12} {1: <8} {2: <9} {3}", h.0, h.1, h.2, h.3)?;
+    }
+    for (name, rollup) in &mut rollups {
+        let top = rollup.classes.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(class, count)| format!("{} ({})", class, count))
+            .unwrap_or_default();
+        writeln!(out, "{0: <12} {1: <8} {2: <9} {3}", name, rollup.devices,
+            rollup.ereports, top)?;
+        if !rollup.instances.is_empty() {
+            rollup.instances.sort_by_key(|i| (i.len(), i.clone()));
+            writeln!(out, "{0: <12} instances: {1}", "",
+                rollup.instances.join(", "))?;
+        }
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Matching of the disks described by hwgrok to the devices of the device
+// hash.  Matching by device path alone breaks when the disks have been
+// re-enumerated (e.g. after an HBA was moved to another slot) since hwgrok was
+// run, so each disk is matched by the first of:
+//
+//   devid     the devid carried by the device's ereports, either as given by
+//             hwgrok or by the WWN it encodes, which also appears in the
+//             unit-address of the disk's device path (disk@w<WWN>,0)
+//   serial    the serial number carried by the device's ereports, or encoded
+//             in their devid (as it is for SATA disks)
+//   path      the disk's canonical device path
+//
+// The devid and serial number of a device are those of its latest ereport
+// carrying one, as an in-place replacement (see replacement.rs) leaves the
+// new disk at the old one's path.
+//
+// Conversely, the ereports of dev-scheme detectors which gave an hc-scheme
+// FMRI but no device path are moved to the device hwgrok gives that FMRI
+// for, so that they're attributed to the hardware.
+//
+use std::collections::hash_map::Entry;
+use std::collections::HashSet;
+
+use devpath::{self, DeviceKey};
+use hwgrok::{HwGrok, HwGrokDisk};
+use {DeviceHash, DeviceHashEnt, Ereport};
+
+//
+// The identity of a device, as carried by its ereports.
+//
+#[derive(Default)]
+struct Identity<'a> {
+    devid: Option<&'a str>,
+    serial: Option<&'a str>,
+}
+
+fn identity(devent: &DeviceHashEnt) -> Identity<'_> {
+    let mut id = Identity::default();
+    for ereport in devent.ereports.iter().rev() {
+        let payload = |member: &str| ereport.payload.get(member)
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty());
+        if id.devid.is_none() {
+            id.devid = ereport.detector.devid.as_deref()
+                .or_else(|| payload("devid"));
+        }
+        if id.serial.is_none() {
+            id.serial = payload("serial").or_else(|| payload("serial-number"));
+        }
+        if id.devid.is_some() && id.serial.is_some() {
+            break;
+        }
+    }
+    id
+}
+
+//
+// Returns the WWN encoded in a devid (id1,sd@n<WWN>), or in the unit-address
+// of a device path (.../disk@w<WWN>,0), in lowercase.
+//
+fn devid_wwn(devid: &str) -> Option<String> {
+    let (_, id) = devid.split_once('@')?;
+    wwn(id.strip_prefix('n')?)
+}
+
+fn path_wwn(path: &str) -> Option<String> {
+    let leaf = path.rsplit('/').next()?;
+    let (_, addr) = leaf.split_once('@')?;
+    wwn(addr.strip_prefix('w')?)
+}
+
+fn wwn(s: &str) -> Option<String> {
+    let hex: String = s.chars().take_while(|c| c.is_ascii_hexdigit())
+        .collect();
+    if hex.len() < 16 {
+        return None;
+    }
+    Some(hex.to_lowercase())
+}
+
+fn devid_matches(disk: &HwGrokDisk, devid: &str) -> bool {
+    if !disk.disk_devid.is_empty() {
+        return disk.disk_devid == devid;
+    }
+    match (devid_wwn(devid), path_wwn(&disk.disk_device_path)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn serial_matches(disk: &HwGrokDisk, id: &Identity) -> bool {
+    let serial = disk.disk_serial_number.trim();
+    if serial.is_empty() {
+        return false;
+    }
+    //
+    // The devid of a SATA disk is made up of its model and serial number,
+    // separated by underscores, e.g.
+    // id1,sd@SATA_____ST4000NM0033-9ZM_Z1Z3ABCD.
+    //
+    id.serial == Some(serial) || id.devid.is_some_and(|devid|
+        devid.ends_with(&format!("_{}", serial)))
+}
+
+fn by_devid(disk: &HwGrokDisk, _: &str, id: &Identity) -> bool {
+    id.devid.is_some_and(|devid| devid_matches(disk, devid))
+}
+
+fn by_serial(disk: &HwGrokDisk, _: &str, id: &Identity) -> bool {
+    serial_matches(disk, id)
+}
+
+fn by_path(disk: &HwGrokDisk, key: &str, _: &Identity) -> bool {
+    devpath::canonicalize(&disk.disk_device_path) == key
+}
+
+// The ways of matching a disk to a device, in order of preference
+const CHAIN: [fn(&HwGrokDisk, &str, &Identity) -> bool; 3] =
+    [by_devid, by_serial, by_path];
+
+//
+// Match each hwgrok disk to a device, recording the device's key in the disk.
+// Every disk is first tried by devid, then those left by serial number, and
+// only then by path, so that a disk which now has another's old path isn't
+// mistaken for it.
+//
+pub fn match_disks(
+    hwgrok: &mut HwGrok,
+    device_hash: &DeviceHash
+) {
+    let mut ids: Vec<(&DeviceKey, Identity)> = device_hash.iter()
+        .map(|(key, devent)| (key, identity(devent)))
+        .collect();
+    ids.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut disks: Vec<&mut HwGrokDisk> = hwgrok.drive_bays.iter_mut()
+        .filter_map(|bay| bay.bay_disk.as_mut())
+        .collect();
+    for disk in disks.iter_mut() {
+        disk.disk_key = None;
+        disk.disk_displaced = false;
+    }
+
+    let mut matched: HashSet<&str> = HashSet::new();
+    for matches in CHAIN.iter() {
+        for disk in disks.iter_mut().filter(|d| d.disk_key.is_none()) {
+            let found = ids.iter().find(|(key, id)|
+                !matched.contains(key.as_str()) && matches(disk, key, id));
+            if let Some((key, _)) = found {
+                matched.insert(key.as_str());
+                disk.disk_key = Some((*key).clone());
+            }
+        }
+    }
+
+    //
+    // A disk without ereports keeps its own path, unless another disk has
+    // been matched to the device there.
+    //
+    for disk in disks.iter_mut().filter(|d| d.disk_key.is_none()) {
+        let path = devpath::canonicalize(&disk.disk_device_path);
+        if matched.contains(path.as_str()) {
+            disk.disk_displaced = true;
+        }
+    }
+}
+
+//
+// Move the entries keyed by the hc-scheme FMRI of a dev-scheme detector (see
+// device_key()) to the PCI device or disk which hwgrok gives for that FMRI,
+// recording the FMRI as a merged path.  Entries of hc-scheme detectors are
+// left as they are, being the ereports of the component itself.
+//
+pub fn resolve_fmris(
+    hwgrok: &HwGrok,
+    device_hash: &mut DeviceHash
+) {
+    let mut moves: Vec<(DeviceKey, DeviceKey)> = device_hash.iter()
+        .filter(|(key, devent)| key.starts_with("hc://") &&
+            devent.ereports.iter().all(|e| e.detector.scheme == "dev"))
+        .filter_map(|(key, _)| Some((key.clone(), hwgrok.find_devpath(key)?)))
+        .collect();
+    moves.sort();
+
+    for (fmri, path) in moves {
+        let mut devent = match device_hash.remove(&fmri) {
+            Some(devent) => devent,
+            None => continue,
+        };
+        devent.merged_paths.push(fmri);
+        match device_hash.entry(path) {
+            Entry::Vacant(entry) => {
+                entry.insert(devent);
+            }
+            Entry::Occupied(mut entry) => entry.get_mut().merge(devent),
+        }
+    }
+}
+
+//
+// Returns the device path to which resolve_fmris() will move an ereport keyed
+// by the hc-scheme FMRI of its dev-scheme detector, so that the ereport can
+// be filtered by it while the logs are read.
+//
+pub fn resolved_key(
+    hwgrok: &HwGrok,
+    ereport: &Ereport,
+    key: &DeviceKey
+) -> Option<DeviceKey> {
+    if ereport.detector.scheme != "dev" || !key.starts_with("hc://") {
+        return None;
+    }
+    hwgrok.find_devpath(key)
+}
@@ -0,0 +1,185 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// The "Environmental" section of the report, which lists the power supplies,
+// fans and chassis described by hwgrok with each of their sensors' readings,
+// alongside the ereports attributed to them: sensor and threshold ereports
+// (ereport.sensor.*) whose hc-scheme detector is the component or lies
+// within it, and SEL records merged with --sel.  A PSU or fan problem often
+// shows up as errors elsewhere (e.g. a hot chassis causing disk errors), so
+// its state is worth seeing in one place.
+//
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use fmri;
+use hwgrok::{self, HwGrok, HwGrokSensor};
+use i18n::tr;
+use thresholds;
+use DeviceHash;
+
+struct Component<'a> {
+    label: &'a str,
+    fmri: &'a str,
+    sensors: &'a [HwGrokSensor],
+    // whether the ereports of the components within it are its own
+    nested: bool,
+}
+
+impl<'a> Component<'a> {
+    //
+    // Returns true if the ereports of the device key are attributed to the
+    // component.
+    //
+    fn owns(&self, key: &str) -> bool {
+        if self.nested {
+            hwgrok::within(key, self.fmri)
+        } else {
+            !self.fmri.is_empty() &&
+                fmri::from_str(key) == fmri::from_str(self.fmri)
+        }
+    }
+
+    //
+    // Returns the number of ereports of each class attributed to the
+    // component.
+    //
+    fn classes<'b>(
+        &self,
+        device_hash: &'b DeviceHash
+    ) -> BTreeMap<&'b str, u32> {
+
+        let mut classes = BTreeMap::new();
+        for (key, devent) in device_hash {
+            if !self.owns(key) {
+                continue;
+            }
+            for ereport in &devent.ereports {
+                *classes.entry(ereport.class.as_str()).or_insert(0) += 1;
+            }
+        }
+        classes
+    }
+}
+
+//
+// Returns the PSUs and fans, followed by the chassis if it has sensors.
+//
+fn components(hwgrok: &HwGrok) -> Vec<Component<'_>> {
+    let psus = hwgrok.psus.iter().map(|p| Component {
+        label: &p.psu_label,
+        fmri: &p.psu_fmri,
+        sensors: &p.psu_sensors,
+        nested: true,
+    });
+    let fans = hwgrok.fans.iter().map(|f| Component {
+        label: &f.fan_label,
+        fmri: &f.fan_fmri,
+        sensors: &f.fan_sensors,
+        nested: true,
+    });
+    let mut comps: Vec<Component> = psus.chain(fans).collect();
+    if !hwgrok.chassis.chassis_sensors.is_empty() {
+        comps.push(Component {
+            label: "chassis",
+            fmri: &hwgrok.chassis.chassis_fmri,
+            sensors: &hwgrok.chassis.chassis_sensors,
+            // the chassis's drive bays and so on aren't the chassis itself
+            nested: false,
+        });
+    }
+    comps
+}
+
+fn describe_sensor(sensor: &HwGrokSensor) -> (String, String) {
+    match sensor.sensor_reading {
+        Some(reading) => {
+            let units = sensor.sensor_units.as_deref().unwrap_or("");
+            let status = match thresholds::check_sensor(sensor) {
+                Some(breach) => breach.to_string(),
+                None => "ok".to_string(),
+            };
+            (format!("{}{}", reading, units), status)
+        }
+        None => ("-".to_string(), "-".to_string()),
+    }
+}
+
+pub fn write_environment(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let comps = components(hwgrok);
+    if comps.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Environmental"))?;
+    let headings = [
+        ("component", "sensor", "reading", "status", "ereports"),
+        ("---------", "------", "-------", "------", "--------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <12} {1: <24} {2: <12} {3: <10} {4}", h.0, h.1,
+            h.2, h.3, h.4)?;
+    }
+
+    let mut attributed = Vec::new();
+    for comp in &comps {
+        let classes = comp.classes(device_hash);
+        let nereports: u32 = classes.values().sum();
+        //
+        // The component's ereport count is given on its first row, and its
+        // label only there too, so that its sensors read as a group.
+        //
+        let mut rows: Vec<(String, String, String)> = comp.sensors.iter()
+            .map(|s| {
+                let (reading, status) = describe_sensor(s);
+                (s.sensor_name.clone(), reading, status)
+            })
+            .collect();
+        if rows.is_empty() {
+            rows.push(("-".to_string(), "-".to_string(), "-".to_string()));
+        }
+        for (i, (sensor, reading, status)) in rows.into_iter().enumerate() {
+            let (label, count) = if i == 0 {
+                (comp.label, nereports.to_string())
+            } else {
+                ("", String::new())
+            };
+            let row = format!("{0: <12} {1: <24} {2: <12} {3: <10} {4}",
+                label, sensor, reading, status, count);
+            writeln!(out, "{}", row.trim_end())?;
+        }
+        if nereports > 0 {
+            attributed.push((comp.label, classes));
+        }
+    }
+    writeln!(out)?;
+
+    if !attributed.is_empty() {
+        let headings = [
+            ("component", "class", "count"),
+            ("---------", "-----", "-----"),
+        ];
+        for h in &headings {
+            writeln!(out, "{0: <12} {1: <52} {2}", h.0, h.1, h.2)?;
+        }
+        for (label, classes) in attributed {
+            for (class, count) in classes {
+                writeln!(out, "{0: <12} {1: <52} {2}", label, class, count)?;
+            }
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// The errors returned by the library's public entry points, run() and the
+// parsers of ereports and hwgrok or fmtopo output, so that callers can tell a
+// missing file from a corrupt log line or bad hwgrok output without matching
+// on messages.  Internally, errors are still passed around as Box<dyn Error>,
+// with those worth distinguishing raised as an FmLogError, and are recovered
+// from the box at the public boundary; anything else, such as an invalid
+// configuration, is returned as Other.
+//
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum FmLogError {
+    // A file couldn't be opened or read
+    Io { path: Option<String>, source: io::Error },
+    // A line of the FM log isn't valid JSON, or isn't an event
+    JsonParse { line_no: usize, source: serde_json::Error },
+    // An ereport is well-formed, but its contents can't be used
+    InvalidEvent { line_no: usize, reason: String },
+    // An ereport's detector lacks the member its scheme requires
+    MissingDetector { scheme: String, member: &'static str },
+    // An ereport's detector is in a scheme which isn't supported
+    UnsupportedScheme(String),
+    // The hwgrok output couldn't be parsed
+    HwgrokParse { path: Option<String>, source: serde_json::Error },
+    // The fmtopo output couldn't be parsed
+    TopoParse { path: Option<String>, source: serde_json::Error },
+    // A saved report has a version of the report model this one can't load
+    SchemaVersion(String),
+    Other(Box<dyn Error>),
+}
+
+impl FmLogError {
+    //
+    // Returns the error with the line of the FM log it was found on, for
+    // errors raised while parsing a single line.
+    //
+    pub fn at_line(self, n: usize) -> FmLogError {
+        match self {
+            FmLogError::JsonParse { source, .. } => {
+                FmLogError::JsonParse { line_no: n, source }
+            }
+            FmLogError::InvalidEvent { reason, .. } => {
+                FmLogError::InvalidEvent { line_no: n, reason }
+            }
+            e => e,
+        }
+    }
+
+    //
+    // A short name for the kind of error, for structured diagnostics.
+    //
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FmLogError::Io { .. } => "io",
+            FmLogError::JsonParse { .. } => "json-parse",
+            FmLogError::InvalidEvent { .. } => "invalid-event",
+            FmLogError::MissingDetector { .. } => "missing-detector",
+            FmLogError::UnsupportedScheme(_) => "unsupported-scheme",
+            FmLogError::HwgrokParse { .. } => "hwgrok-parse",
+            FmLogError::TopoParse { .. } => "topo-parse",
+            FmLogError::SchemaVersion(_) => "schema-version",
+            FmLogError::Other(_) => "other",
+        }
+    }
+}
+
+impl fmt::Display for FmLogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FmLogError::Io { path: Some(path), source } => {
+                write!(f, "{}: {}", path, source)
+            }
+            FmLogError::Io { path: None, source } => write!(f, "{}", source),
+            FmLogError::JsonParse { line_no, source } => {
+                write!(f, "line {}: invalid event: {}", line_no, source)
+            }
+            FmLogError::InvalidEvent { line_no, reason } => {
+                write!(f, "line {}: {}", line_no, reason)
+            }
+            FmLogError::MissingDetector { scheme, member } => {
+                write!(f, "{} detector has no {}", scheme, member)
+            }
+            FmLogError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported detector scheme: {}", scheme)
+            }
+            FmLogError::HwgrokParse { path: Some(path), source } => {
+                write!(f, "{}: invalid hwgrok output: {}", path, source)
+            }
+            FmLogError::HwgrokParse { path: None, source } => {
+                write!(f, "invalid hwgrok output: {}", source)
+            }
+            FmLogError::TopoParse { path: Some(path), source } => {
+                write!(f, "{}: invalid fmtopo output: {}", path, source)
+            }
+            FmLogError::TopoParse { path: None, source } => {
+                write!(f, "invalid fmtopo output: {}", source)
+            }
+            FmLogError::SchemaVersion(version) => {
+                write!(f, "unsupported report schema version: {}", version)
+            }
+            FmLogError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for FmLogError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FmLogError::Io { source, .. } => Some(source),
+            FmLogError::JsonParse { source, .. } => Some(source),
+            FmLogError::HwgrokParse { source, .. } => Some(source),
+            FmLogError::TopoParse { source, .. } => Some(source),
+            FmLogError::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FmLogError {
+    fn from(e: io::Error) -> FmLogError {
+        FmLogError::Io { path: None, source: e }
+    }
+}
+
+//
+// Recover an FmLogError, or an I/O error, from a boxed error.
+//
+impl From<Box<dyn Error>> for FmLogError {
+    fn from(e: Box<dyn Error>) -> FmLogError {
+        let e = match e.downcast::<FmLogError>() {
+            Ok(e) => return *e,
+            Err(e) => e,
+        };
+        match e.downcast::<io::Error>() {
+            Ok(e) => FmLogError::from(*e),
+            Err(e) => FmLogError::Other(e),
+        }
+    }
+}
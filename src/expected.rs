@@ -0,0 +1,225 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Expected background error rates.  Some ereport classes are logged at a low
+// rate by healthy hardware (e.g. correctable PCIe errors on some NICs), and
+// flagging every device that logs them buries the devices that need
+// attention.  The [expected-rates] section of the configuration file gives
+// the number of errors per day that is normal for ereport classes matching a
+// glob, optionally only on devices managed by a given driver:
+//
+//   [expected-rates]
+//   ereport.io.pciex.*.ce* = 10
+//   mlxcx:ereport.io.pciex.*.ce* = 50
+//
+// Each class of a device's ereports is counted against the first entry which
+// matches it, and a device's rate for an entry is the number of its ereports
+// counted against the entry per day of the log, from its oldest to its newest
+// event (and at least a day).  Classes whose rate is within the expected
+// envelope are background noise, and don't count towards the device's status;
+// a device whose rate is beyond it is flagged.
+//
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::Write;
+
+use conf::ConfFile;
+use drivers::DriverMap;
+use glob::glob_match;
+use i18n::tr;
+use numfmt::NumberFormat;
+use {DeviceHash, SimpleError};
+
+const SECS_PER_DAY: f64 = 86400.0;
+
+#[derive(Debug)]
+struct Expectation {
+    driver: Option<String>,
+    class: String,
+    per_day: f64,
+}
+
+impl Expectation {
+    fn label(&self) -> String {
+        match &self.driver {
+            Some(driver) => format!("{}:{}", driver, self.class),
+            None => self.class.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExpectedRates {
+    entries: Vec<Expectation>,
+}
+
+//
+// A device's rate of the ereports counted against one expectation.
+//
+#[derive(Debug)]
+pub struct Assessment<'a> {
+    pub devpath: &'a str,
+    // the expectation's class glob, prefixed with its driver if it has one
+    pub expectation: String,
+    pub classes: Vec<&'a str>,
+    pub rate: f64,
+    pub expected: f64,
+}
+
+impl<'a> Assessment<'a> {
+    pub fn exceeded(&self) -> bool {
+        self.rate > self.expected
+    }
+}
+
+impl ExpectedRates {
+    //
+    // Returns the expected rates from the configuration file, or None if
+    // there aren't any.
+    //
+    pub fn from_conf(conf: &ConfFile)
+        -> Result<Option<ExpectedRates>, Box<dyn Error>> {
+
+        let mut entries = Vec::new();
+        for (key, value) in conf.section("expected-rates") {
+            let per_day = value.parse::<f64>().ok()
+                .filter(|r| r.is_finite() && *r >= 0.0)
+                .ok_or_else(|| SimpleError(format!(
+                    "invalid expected rate for {}: {}", key, value)))?;
+            let (driver, class) = match key.find(':') {
+                Some(off) => (Some(key[..off].to_string()), &key[off + 1..]),
+                None => (None, key),
+            };
+            if class.is_empty() || driver.as_deref() == Some("") {
+                return Err(Box::new(SimpleError(format!(
+                    "invalid expected-rates entry: {}", key))));
+            }
+            entries.push(Expectation { driver, class: class.to_string(),
+                per_day });
+        }
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(ExpectedRates { entries }))
+    }
+
+    //
+    // Returns each device's rate for each expectation its ereports are
+    // counted against, those furthest beyond their envelope first.
+    //
+    pub fn assess<'a>(
+        &self,
+        device_hash: &'a DeviceHash,
+        drivers: &DriverMap
+    ) -> Vec<Assessment<'a>> {
+
+        let tods = device_hash.values()
+            .flat_map(|d| d.ereports.first().into_iter()
+                .chain(d.ereports.last()))
+            .map(|e| e.tod[0]);
+        let (oldest, newest) = tods.fold((i64::MAX, i64::MIN),
+            |(lo, hi), tod| (lo.min(tod), hi.max(tod)));
+        let days = ((newest - oldest) as f64 / SECS_PER_DAY).max(1.0);
+
+        let mut assessments = Vec::new();
+        for (devpath, devent) in device_hash.iter() {
+            let driver = drivers.lookup(devpath, devent).map(|d| d.name);
+            let mut counted: Vec<(usize, Vec<&str>, u32)> = Vec::new();
+            for (class, count) in devent.ereport_class_hash.iter() {
+                let found = self.entries.iter().position(|e| {
+                    glob_match(&e.class, class) &&
+                    match (&e.driver, &driver) {
+                        (None, _) => true,
+                        (Some(d), Some(name)) => glob_match(d, name),
+                        (Some(_), None) => false,
+                    }
+                });
+                let idx = match found {
+                    Some(idx) => idx,
+                    None => continue,
+                };
+                match counted.iter_mut().find(|c| c.0 == idx) {
+                    Some(c) => {
+                        c.1.push(class);
+                        c.2 += count;
+                    }
+                    None => counted.push((idx, vec![class.as_str()], *count)),
+                }
+            }
+            for (idx, mut classes, count) in counted {
+                let entry = &self.entries[idx];
+                classes.sort_unstable();
+                assessments.push(Assessment {
+                    devpath,
+                    expectation: entry.label(),
+                    classes,
+                    rate: count as f64 / days,
+                    expected: entry.per_day,
+                });
+            }
+        }
+        assessments.sort_by(|a, b| {
+            let ratio = |a: &Assessment| a.rate /
+                a.expected.max(f64::MIN_POSITIVE);
+            ratio(b).total_cmp(&ratio(a)).then(a.devpath.cmp(b.devpath))
+                .then(a.expectation.cmp(&b.expectation))
+        });
+        assessments
+    }
+
+    //
+    // Returns the classes of each device's ereports which are within their
+    // expected rate.
+    //
+    pub fn background<'a>(
+        &self,
+        device_hash: &'a DeviceHash,
+        drivers: &DriverMap
+    ) -> HashMap<&'a str, HashSet<&'a str>> {
+
+        let mut background: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for a in self.assess(device_hash, drivers) {
+            if !a.exceeded() {
+                background.entry(a.devpath).or_default().extend(a.classes);
+            }
+        }
+        background
+    }
+
+    pub fn write_exceedances(
+        &self,
+        out: &mut dyn Write,
+        device_hash: &DeviceHash,
+        drivers: &DriverMap,
+        numbers: &NumberFormat
+    ) -> Result<(), Box<dyn Error>> {
+
+        let exceeded: Vec<Assessment> = self.assess(device_hash, drivers)
+            .into_iter().filter(|a| a.exceeded()).collect();
+        if exceeded.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{}\n", tr("Expected Rate Exceedances"))?;
+        writeln!(out, "Devices logging ereports at more than the expected \
+            background rate.\n")?;
+        let headings = ["rate/day", "expected/day", "device", "class"];
+        writeln!(out, "{0: <10} {1: <13} {2: <40} {3}", headings[0],
+            headings[1], headings[2], headings[3])?;
+        writeln!(out, "{0: <10} {1: <13} {2: <40} -----", "--------",
+            "------------", "------")?;
+        for a in &exceeded {
+            writeln!(out, "{0: <10} {1: <13} {2: <40} {3}",
+                numbers.float(a.rate, 1), numbers.float(a.expected, 1),
+                a.devpath, a.expectation)?;
+        }
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Export of the ereport counts as CSV, for importing into spreadsheets and BI
+// tools.  Each row gives the number of ereports of one class logged against
+// one device on one day (UTC):
+//
+//   device,class,date,count
+//   /pci@0,0/pci8086,2f02@1/...,ereport.io.scsi.cmd.disk.tran,2019-03-01,4
+//
+// The rows are sorted by device, class and date.
+//
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use devpath::DeviceKey;
+use {get_event_timestamp, DeviceHash, DeviceHashEnt};
+
+//
+// Quote a CSV field, if needed, as described in RFC 4180.  Device paths
+// routinely contain commas.
+//
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn write_csv_header(out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "device,class,date,count")?;
+    Ok(())
+}
+
+//
+// Write the rows for a single device.
+//
+pub fn write_device_csv(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    devent: &DeviceHashEnt
+) -> Result<(), Box<dyn Error>> {
+
+    let mut counts: BTreeMap<(&str, String), u32> = BTreeMap::new();
+    for ereport in &devent.ereports {
+        *counts.entry((&ereport.class, get_event_timestamp(ereport.tod[0])))
+            .or_insert(0) += 1;
+    }
+    let devpath = csv_field(devpath);
+    for ((class, date), count) in &counts {
+        writeln!(out, "{},{},{},{}", devpath, csv_field(class), date, count)?;
+    }
+    Ok(())
+}
+
+pub fn write_csv(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash
+) -> Result<(), Box<dyn Error>> {
+
+    let mut devpaths: Vec<&DeviceKey> = device_hash.keys().collect();
+    devpaths.sort();
+
+    write_csv_header(out)?;
+    for devpath in devpaths {
+        write_device_csv(out, devpath, &device_hash[devpath])?;
+    }
+    Ok(())
+}
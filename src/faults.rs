@@ -0,0 +1,138 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for the fault.* events in the fault log, each of which records a
+// fault diagnosed by fmd: its class, the case (uuid) it belongs to and the
+// FRU, ASRU and resource it implicates.  Faults are shown in the section of
+// the device whose device path or FMRI they name, and those which don't name
+// any device in the report are listed in a section of their own.
+//
+use serde_json::Value;
+
+use std::error::Error;
+use std::io::Write;
+
+use fmri;
+use i18n::tr;
+use {get_event_timestamp, DeviceHash};
+
+#[derive(Debug)]
+pub struct Fault {
+    pub uuid: String,
+    pub class: String,
+    pub tod: i64,
+    pub certainty: Option<u64>,
+    pub fru: Option<String>,
+    pub asru: Option<String>,
+    pub resource: Option<String>,
+}
+
+impl Fault {
+    pub fn from_json(event: &Value) -> Option<Fault> {
+        let fmri = |member: &str| event.get(member).and_then(fmri::from_json);
+        Some(Fault {
+            uuid: event.get("uuid").and_then(|u| u.as_str()).unwrap_or("-")
+                .to_string(),
+            class: event.get("class")?.as_str()?.to_string(),
+            tod: event.get("__tod").and_then(|t| t.get(0))
+                .and_then(|t| t.as_i64()).unwrap_or(0),
+            certainty: event.get("certainty").and_then(|c| c.as_u64()),
+            fru: fmri("fru"),
+            asru: fmri("asru"),
+            resource: fmri("resource"),
+        })
+    }
+
+    pub fn implicates(&self, devpath: &str) -> bool {
+        [&self.fru, &self.asru, &self.resource].iter()
+            .any(|r| r.as_deref() == Some(devpath))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Faults {
+    faults: Vec<Fault>,
+}
+
+fn write_fault(
+    out: &mut dyn Write,
+    fault: &Fault
+) -> Result<(), Box<dyn Error>> {
+
+    writeln!(out, "{0: <40} {1}", tr("Fault Class:"), fault.class)?;
+    writeln!(out, "{0: <40} {1}", tr("Diagnosed:"),
+        get_event_timestamp(fault.tod))?;
+    writeln!(out, "{0: <40} {1}", tr("Case UUID:"), fault.uuid)?;
+    if let Some(certainty) = fault.certainty {
+        writeln!(out, "{0: <40} {1}%", tr("Certainty:"), certainty)?;
+    }
+    for (label, fmri) in [("FRU:", &fault.fru), ("ASRU:", &fault.asru),
+        ("Resource:", &fault.resource)] {
+        if let Some(fmri) = fmri {
+            writeln!(out, "{0: <40} {1}", tr(label), fmri)?;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+impl Faults {
+    pub fn add(&mut self, fault: Fault) {
+        self.faults.push(fault);
+    }
+
+    pub fn implicates(&self, devpath: &str) -> bool {
+        self.faults.iter().any(|f| f.implicates(devpath))
+    }
+
+    //
+    // Write the faults implicating the given device, if there are any.
+    //
+    pub fn write_device(
+        &self,
+        out: &mut dyn Write,
+        devpath: &str
+    ) -> Result<(), Box<dyn Error>> {
+
+        let mut faults = self.faults.iter().filter(|f| f.implicates(devpath))
+            .peekable();
+        if faults.peek().is_none() {
+            return Ok(());
+        }
+        let heading = tr("Faults");
+        writeln!(out, "{}\n{}", heading,
+            "-".repeat(heading.chars().count()))?;
+        for fault in faults {
+            write_fault(out, fault)?;
+        }
+        Ok(())
+    }
+
+    //
+    // Write the faults which don't implicate any device in the report.
+    //
+    pub fn write_unattached(
+        &self,
+        out: &mut dyn Write,
+        device_hash: &DeviceHash
+    ) -> Result<(), Box<dyn Error>> {
+
+        let mut faults: Vec<&Fault> = self.faults.iter()
+            .filter(|f| !device_hash.keys().any(|d| f.implicates(d)))
+            .collect();
+        if faults.is_empty() {
+            return Ok(());
+        }
+        faults.sort_by_key(|f| f.tod);
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{}\n", tr("Faults Without A Reported Device"))?;
+        for fault in faults {
+            write_fault(out, fault)?;
+        }
+        Ok(())
+    }
+}
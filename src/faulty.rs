@@ -0,0 +1,232 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Reconciliation of the ereports against the open cases listed by "fmadm
+// faulty", which catches both diagnoses that fmd hasn't made (a device logging
+// many errors with no case open against it) and cases that may be stale (an
+// open case whose resources have stopped logging errors, perhaps because the
+// part was replaced without the case being repaired).  The thresholds are
+// set by the [reconcile] section of the configuration file:
+//
+//   [reconcile]
+//   recent-days = 7
+//   min-events = 10
+//
+// "Recent" ereports are those within recent-days of the newest event in the
+// log.  A device is flagged if it has at least min-events recent ereports but
+// isn't a resource of any open case.
+//
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+
+use conf::ConfFile;
+use fmri;
+use hwgrok::HwGrok;
+use i18n::tr;
+use {DeviceHash, SimpleError};
+
+const SECS_PER_DAY: i64 = 86400;
+
+#[derive(Debug)]
+pub struct OpenCase {
+    uuid: String,
+    msgid: String,
+    time: String,
+    // the affected resources, FRUs and so on, normalized as device hash keys
+    resources: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct FaultyCases {
+    cases: Vec<OpenCase>,
+    recent_days: i64,
+    min_events: usize,
+}
+
+//
+// Returns any FMRIs in a line of fmadm's output, which may be bare or
+// parenthesized after a label, e.g. "Slot 05" (hc://...).
+//
+fn find_fmris(line: &str) -> Vec<String> {
+    line.split_whitespace()
+        .map(|w| w.trim_matches(|c| c == '(' || c == ')' || c == ','))
+        .filter(|w| w.contains("://"))
+        .map(fmri::from_str)
+        .collect()
+}
+
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36 && s.chars().filter(|c| *c == '-').count() == 4 &&
+        s.chars().all(|c| c == '-' || c.is_ascii_hexdigit())
+}
+
+impl FaultyCases {
+    //
+    // Read the saved output of "fmadm faulty", along with the reconciliation
+    // settings from the configuration file.
+    //
+    pub fn from_file(
+        path: &str,
+        conf: &ConfFile
+    ) -> Result<FaultyCases, Box<dyn Error>> {
+
+        let mut faulty = FaultyCases {
+            cases: Vec::new(),
+            recent_days: 7,
+            min_events: 10,
+        };
+        for (key, value) in conf.section("reconcile") {
+            let invalid = || SimpleError(format!("invalid value for {}: {}",
+                key, value));
+            match key {
+                "recent-days" => {
+                    faulty.recent_days = value.parse().ok().filter(|d| *d > 0)
+                        .ok_or_else(invalid)?;
+                }
+                "min-events" => {
+                    faulty.min_events = value.parse().ok().filter(|n| *n > 0)
+                        .ok_or_else(invalid)?;
+                }
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "unknown reconcile setting: {}", key))));
+                }
+            }
+        }
+
+        let contents = fs::read_to_string(path)?;
+        //
+        // Each case starts with a summary line giving its time, event ID,
+        // message ID and severity, followed by "Name : value" lines, some of
+        // which (such as the resource status) continue onto further lines.
+        //
+        let mut in_resource = false;
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() >= 5 && is_uuid(fields[3]) {
+                faulty.cases.push(OpenCase {
+                    uuid: fields[3].to_string(),
+                    msgid: fields[4].to_string(),
+                    time: fields[..3].join(" "),
+                    resources: Vec::new(),
+                });
+                in_resource = false;
+                continue;
+            }
+            let case = match faulty.cases.last_mut() {
+                Some(c) => c,
+                None => continue,
+            };
+            let value = match line.split_once(" : ") {
+                Some((name, value)) => {
+                    in_resource = ["Affects", "FRU", "Problem in",
+                        "Resource"].contains(&name.trim());
+                    value
+                }
+                None => line,
+            };
+            if in_resource {
+                for fmri in find_fmris(value) {
+                    if !case.resources.contains(&fmri) {
+                        case.resources.push(fmri);
+                    }
+                }
+            }
+        }
+        Ok(faulty)
+    }
+
+    //
+    // Returns the device hash keys that the case implicates, including the
+    // disks in any drive bays it names.
+    //
+    fn case_devices(&self, case: &OpenCase, hwgrok: &HwGrok) -> Vec<String> {
+        let mut devices = case.resources.clone();
+        for bay in &hwgrok.drive_bays {
+            if let Some(disk) = &bay.bay_disk {
+                let named = case.resources.iter().any(|r|
+                    *r == fmri::from_str(&bay.bay_fmri) ||
+                    *r == fmri::from_str(&disk.disk_fmri));
+                if named {
+                    devices.extend(disk.devpath().map(String::from));
+                }
+            }
+        }
+        devices
+    }
+
+    pub fn write_reconciliation(
+        &self,
+        out: &mut dyn Write,
+        device_hash: &DeviceHash,
+        hwgrok: &HwGrok
+    ) -> Result<(), Box<dyn Error>> {
+
+        let newest = device_hash.values()
+            .filter_map(|d| d.ereports.last())
+            .map(|e| e.tod[0])
+            .max()
+            .unwrap_or(0);
+        let recent_start = newest - self.recent_days * SECS_PER_DAY;
+        let recent = |devpath: &str| device_hash.get(devpath)
+            .map(|d| d.ereports.iter().filter(|e| e.tod[0] > recent_start)
+                .count())
+            .unwrap_or(0);
+
+        let case_devices: Vec<Vec<String>> = self.cases.iter()
+            .map(|c| self.case_devices(c, hwgrok))
+            .collect();
+
+        let mut undiagnosed: Vec<(usize, &str)> = device_hash.keys()
+            .map(|devpath| (recent(devpath), devpath.as_str()))
+            .filter(|(count, devpath)| *count >= self.min_events &&
+                !case_devices.iter().flatten().any(|d| d == devpath))
+            .collect();
+        undiagnosed.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+
+        let quiet: Vec<&OpenCase> = self.cases.iter().zip(&case_devices)
+            .filter(|(_, devices)| devices.iter().all(|d| recent(d) == 0))
+            .map(|(case, _)| case)
+            .collect();
+
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{}\n", tr("Open Case Reconciliation"))?;
+        writeln!(out, "{} open case(s); recent ereports are those in the {} \
+            day(s) before the newest.\n", self.cases.len(), self.recent_days)?;
+        if undiagnosed.is_empty() && quiet.is_empty() {
+            writeln!(out, "No inconsistencies found.\n")?;
+            return Ok(());
+        }
+
+        if !undiagnosed.is_empty() {
+            writeln!(out, "Devices with at least {} recent ereports but no \
+                open case:\n", self.min_events)?;
+            writeln!(out, "{0: <10} device", "ereports")?;
+            writeln!(out, "{0: <10} ------", "--------")?;
+            for (count, devpath) in &undiagnosed {
+                writeln!(out, "{0: <10} {1}", count, devpath)?;
+            }
+            writeln!(out)?;
+        }
+        if !quiet.is_empty() {
+            writeln!(out, "Open cases with no recent ereports against their \
+                resources:\n")?;
+            writeln!(out, "{0: <16} {1: <37} {2: <15} resources", "time",
+                "event-id", "msg-id")?;
+            writeln!(out, "{0: <16} {1: <37} {2: <15} ---------", "----",
+                "--------", "------")?;
+            for case in &quiet {
+                writeln!(out, "{0: <16} {1: <37} {2: <15} {3}", case.time,
+                    case.uuid, case.msgid, case.resources.join(" "))?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
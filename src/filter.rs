@@ -0,0 +1,151 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Filtering of ereports by class, given with --class and --exclude-class,
+// and by device, given with --device, which is applied as the logs are read
+// so that the ereports filtered out play no part in the report.  An ereport
+// is kept if its class matches any of the --class globs (or none were given),
+// and none of the --exclude-class globs:
+//
+//   --class 'ereport.io.pciex.*' --exclude-class 'ereport.io.pciex.*.ce*'
+//
+// and if its device matches any of the --device patterns (or none were
+// given).  A device pattern is matched against the device path or FMRI the
+// ereport is attributed to, and is one of:
+//
+//   *sd@5*                 a glob, if it has a '*' or '?', which must match
+//                          the whole path or FMRI
+//   =/devices/.../disk@5   an exact path or FMRI, if it starts with '=', which
+//                          is canonicalized just as the ereports' paths are
+//   /pci@0,0/pci8086,2030  anything else, which need only be contained in the
+//                          path or FMRI, so that the devices under an HBA or
+//                          in a bay can be picked out
+//
+// Paths in globs and partial paths are canonicalized too, as far as they can
+// be (see canonical_pattern()), so may be given with or without the leading
+// /devices, a minor node or a trailing ",0", and in either case.  An ereport
+// whose detector was only given as an hc-scheme FMRI is matched by the device
+// path hwgrok gives for it as well, as that's where it's reported (see
+// enrich::resolve_fmris).  Lastly, an ereport must satisfy each of the --query
+// expressions (see query.rs).  Other events, such as fault diagnoses, aren't
+// affected.
+//
+use std::error::Error;
+
+use devpath::{DeviceKey, DevicePath};
+use fmri;
+use glob::glob_match;
+use query::Query;
+
+#[derive(Debug, Default)]
+pub struct EventFilter {
+    pub classes: Vec<String>,
+    pub exclude_classes: Vec<String>,
+    pub devices: Vec<String>,
+    pub queries: Vec<Query>,
+}
+
+//
+// Returns the canonical form of a path pattern, which may be a glob or only
+// part of a path, as a DevicePath would have it: without a leading /devices
+// or a minor node, and with its unit-addresses in lowercase.  A trailing ",0"
+// is only dropped from the last component of a path, so the pattern is
+// returned both with and without it, the latter only being of use if it ends
+// the path.
+//
+fn canonical_pattern(pattern: &str) -> (String, String) {
+    let pattern = match pattern.starts_with("/devices/") {
+        true => &pattern["/devices".len()..],
+        false => pattern,
+    };
+    let components: Vec<&str> = pattern.split('/').collect();
+    let last = components.len() - 1;
+    let mut canon: Vec<String> = components.iter().enumerate()
+        .map(|(idx, component)| {
+            let component = match component.find(':') {
+                Some(off) if idx == last => &component[..off],
+                _ => component,
+            };
+            match component.find('@') {
+                Some(off) => format!("{}@{}", &component[..off],
+                    component[off + 1..].to_lowercase()),
+                None => component.to_string(),
+            }
+        })
+        .collect();
+
+    let full = canon.join("/");
+    if canon[last].contains('@') && canon[last].ends_with(",0") {
+        let len = canon[last].len() - 2;
+        canon[last].truncate(len);
+    }
+    (full, canon.join("/"))
+}
+
+//
+// Returns true if the device path or FMRI matches the --device pattern.
+//
+fn device_match(pattern: &str, key: &str) -> bool {
+    if let Some(exact) = pattern.strip_prefix('=') {
+        return if exact.contains("://") {
+            fmri::from_str(exact) == key
+        } else {
+            String::from(DevicePath::new(exact)) == key
+        };
+    }
+    let glob = pattern.contains(['*', '?']);
+    if pattern.contains("://") {
+        return match glob {
+            true => glob_match(pattern, key),
+            false => key.contains(&fmri::from_str(pattern)),
+        };
+    }
+    let (full, end) = canonical_pattern(pattern);
+    if glob {
+        glob_match(&full, key) || glob_match(&end, key)
+    } else {
+        key.contains(&full) || key.ends_with(&end)
+    }
+}
+
+impl EventFilter {
+    //
+    // Returns true if ereports of the given class are to be kept.
+    //
+    pub fn wants_class(&self, class: &str) -> bool {
+        (self.classes.is_empty() ||
+            self.classes.iter().any(|g| glob_match(g, class))) &&
+            !self.exclude_classes.iter().any(|g| glob_match(g, class))
+    }
+
+    //
+    // Returns true if ereports attributed to the given device key are to be
+    // kept, or those keyed by an hc-scheme FMRI which hwgrok resolves to the
+    // given device path.
+    //
+    pub fn wants_device(
+        &self,
+        key: &DeviceKey,
+        resolved: Option<&DeviceKey>
+    ) -> bool {
+        self.devices.is_empty() || self.devices.iter().any(|p|
+            device_match(p, key) ||
+            resolved.is_some_and(|r| device_match(p, r)))
+    }
+
+    //
+    // Returns true if the ereport in the given line satisfies the queries.
+    // The line is only parsed if there are any.
+    //
+    pub fn wants_event(&self, line: &str) -> Result<bool, Box<dyn Error>> {
+        if self.queries.is_empty() {
+            return Ok(true);
+        }
+        let event: serde_json::Value = serde_json::from_str(line)?;
+        Ok(self.queries.iter().all(|q| q.matches(&event)))
+    }
+}
@@ -0,0 +1,184 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Fleet mode, which processes a directory containing the FM logs and hwgrok
+// output from many hosts and computes failure and error-rate statistics for
+// each drive model, so that the reliability of different SKUs can be compared.
+//
+// The directory must contain one subdirectory per host, named for the host,
+// each of which contains the FM log as errlog.json and the hwgrok output as
+// hwgrok.json.
+//
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use conf::ConfFile;
+use hwgrok::HwGrok;
+use input::InputReader;
+use log;
+use numfmt::NumberFormat;
+use plugin::PluginSet;
+use severity::SeverityMap;
+use {process_hwgrok_data, read_fmlog, Config, LogData, Lookups};
+
+pub const FMLOG_NAME: &str = "errlog.json";
+pub const HWGROK_NAME: &str = "hwgrok.json";
+const SECS_PER_DAY: f64 = 86400.0;
+const DAYS_PER_YEAR: f64 = 365.0;
+
+//
+// Statistics for the population of drives of a given model.
+//
+#[derive(Debug, Default)]
+struct Cohort {
+    drives: u32,
+    drives_with_errors: u32,
+    drives_faulted: u32,
+    drive_days: f64,
+    total_ereports: u64,
+    // the number of ereports for each drive in the cohort
+    ereport_counts: Vec<u64>,
+}
+
+//
+// Returns the given percentile of a sorted list of values, using the
+// nearest-rank method.
+//
+fn percentile(sorted: &[u64], pct: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn process_host(
+    config: &Config,
+    hostdir: &Path,
+    input: &InputReader,
+    severity: &SeverityMap,
+    plugins: &mut PluginSet,
+    cohorts: &mut BTreeMap<String, Cohort>
+) -> Result<(), Box<dyn Error>> {
+
+    let fmlog_path = hostdir.join(FMLOG_NAME);
+    let hwgrok_path = hostdir.join(HWGROK_NAME);
+    let hwgrok = if hwgrok_path.exists() {
+        process_hwgrok_data(&hwgrok_path.to_string_lossy())?
+    } else {
+        HwGrok::default()
+    };
+    let lookups = Lookups { severity, hwgrok: &hwgrok };
+    let LogData { device_hash, cases, .. } = read_fmlog(config,
+        &[fmlog_path.to_string_lossy().into_owned()], None, input, &lookups,
+        plugins)?;
+
+    //
+    // We use the time spanned by the log as the observation period for the
+    // drives in this host.
+    //
+    let tods: Vec<i64> = device_hash.values()
+        .flat_map(|d| d.ereports.iter().map(|e| e.tod[0]))
+        .collect();
+    let span_days = match (tods.iter().min(), tods.iter().max()) {
+        (Some(min), Some(max)) => ((max - min) as f64 / SECS_PER_DAY).max(1.0),
+        _ => 1.0,
+    };
+
+    for drive_bay in &hwgrok.drive_bays {
+        let disk = match &drive_bay.bay_disk {
+            Some(d) => d,
+            None => continue,
+        };
+        let path = disk.devpath();
+        let nereports = path.as_ref().and_then(|p| device_hash.get(p))
+            .map(|d| d.ereports.len() as u64).unwrap_or(0);
+
+        let cohort = cohorts.entry(disk.disk_model.trim().to_string())
+            .or_default();
+        cohort.drives += 1;
+        cohort.drive_days += span_days;
+        cohort.total_ereports += nereports;
+        cohort.ereport_counts.push(nereports);
+        if nereports > 0 {
+            cohort.drives_with_errors += 1;
+        }
+        if path.is_some_and(|p| cases.iter().any(|c| c.implicates(&p))) {
+            cohort.drives_faulted += 1;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_fleet_report(
+    out: &mut dyn Write,
+    config: &Config,
+    dir: &str,
+    conf: &ConfFile
+) -> Result<(), Box<dyn Error>> {
+
+    let input = &InputReader::new(config.input_format, conf)?;
+    let severity = &SeverityMap::from_conf(conf)?;
+    let numbers = &NumberFormat::from_conf(conf)?;
+    let plugins = &mut PluginSet::from_conf(conf)?;
+
+    let mut hostdirs: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    hostdirs.sort();
+
+    let mut cohorts: BTreeMap<String, Cohort> = BTreeMap::new();
+    let mut nhosts = 0;
+    for hostdir in &hostdirs {
+        if !hostdir.join(FMLOG_NAME).exists() {
+            log::warn(&format!("{}: no {} - skipping", hostdir.display(),
+                FMLOG_NAME), &[("host", json!(hostdir.display().to_string()))]);
+            continue;
+        }
+        process_host(config, hostdir, input, severity, plugins,
+            &mut cohorts)?;
+        nhosts += 1;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "{0: <40} {1}", "Hosts:", nhosts)?;
+    writeln!(out, "{0: <40} {1}\n", "Drive Models:", cohorts.len())?;
+
+    for (model, cohort) in cohorts.iter_mut() {
+        cohort.ereport_counts.sort();
+        let drive_years = cohort.drive_days / DAYS_PER_YEAR;
+
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{0: <40} {1}", "Drive Model:", model)?;
+        writeln!(out, "{0: <40} {1}", "Drives:",
+            numbers.int(cohort.drives as u64))?;
+        writeln!(out, "{0: <40} {1}", "Drive Years Observed:",
+            numbers.float(drive_years, 1))?;
+        writeln!(out, "{0: <40} {1}", "Drives With Errors:",
+            numbers.int(cohort.drives_with_errors as u64))?;
+        writeln!(out, "{0: <40} {1}", "Drives Faulted:",
+            numbers.int(cohort.drives_faulted as u64))?;
+        writeln!(out, "{0: <40} {1}%", "Annualized Failure Rate:",
+            numbers.float(100.0 * cohort.drives_faulted as f64 / drive_years,
+            2))?;
+        writeln!(out, "{0: <40} {1}", "Ereports Per Drive Year:",
+            numbers.float(cohort.total_ereports as f64 / drive_years, 1))?;
+        writeln!(out, "{0: <40} {1} / {2} / {3}", "Ereports p50/p90/p99:",
+            numbers.int(percentile(&cohort.ereport_counts, 50.0)),
+            numbers.int(percentile(&cohort.ereport_counts, 90.0)),
+            numbers.int(percentile(&cohort.ereport_counts, 99.0)))?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Parsing of FMRIs (fault management resource identifiers), which name the
+// detectors of ereports and the resources implicated by faults, into the
+// strings used as keys in the device hash.  Devices named by dev-scheme FMRIs
+// are keyed by their bare device path, so that events from dev- and
+// hc-scheme detectors which name the same path are grouped together, and the
+// other schemes by an FMRI string without any authority, e.g.:
+//
+//   hc:///motherboard=0/chip=1/core=3
+//   cpu:///cpuid=3
+//   mem:///unum=SOCKET 1 CHANNEL 0
+//   fmd:///module/disk-transport
+//
+// FMRIs may be given as in the JSON output of fmdump (an object with a
+// "scheme" member) or as strings, as printed by fmadm.
+//
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use devpath::DevicePath;
+
+//
+// The id of an hc-list element is a string, but some tools which convert
+// events to JSON render numeric ids as numbers.
+//
+pub fn hc_id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+pub fn deserialize_hc_id<'de, D>(deserializer: D) -> Result<String, D::Error>
+    where D: Deserializer<'de> {
+
+    let value = Value::deserialize(deserializer)?;
+    hc_id(&value).ok_or_else(|| serde::de::Error::custom(
+        "hc-id is neither a string nor a number"))
+}
+
+//
+// Returns the key for the hc path given as (name, id) pairs.
+//
+pub fn hc_key<'a, I>(pairs: I) -> String
+    where I: IntoIterator<Item = (&'a str, &'a str)> {
+
+    let mut key = String::from("hc://");
+    for (name, id) in pairs {
+        key.push_str(&format!("/{}={}", name, id));
+    }
+    key
+}
+
+//
+// Returns the key for an FMRI given in its JSON form.
+//
+pub fn from_json(fmri: &Value) -> Option<String> {
+    match fmri.get("scheme")?.as_str()? {
+        "dev" => {
+            let path = fmri.get("device-path")?.as_str()?;
+            Some(DevicePath::new(path).into())
+        }
+        "hc" => {
+            let mut pairs = Vec::new();
+            for hcpair in fmri.get("hc-list")?.as_array()? {
+                pairs.push((hcpair.get("hc-name")?.as_str()?,
+                    hc_id(hcpair.get("hc-id")?)?));
+            }
+            Some(hc_key(pairs.iter().map(|(n, i)| (*n, i.as_str()))))
+        }
+        "cpu" => Some(format!("cpu:///cpuid={}",
+            fmri.get("cpuid")?.as_u64()?)),
+        "mem" => Some(format!("mem:///unum={}",
+            fmri.get("unum")?.as_str()?)),
+        "fmd" => Some(format!("fmd:///module/{}",
+            fmri.get("mod-name")?.as_str()?)),
+        _ => None,
+    }
+}
+
+//
+// Returns the key for an FMRI given as a string, as printed by fmadm: the
+// bare device path for the dev scheme, and no authority for the others.
+//
+pub fn from_str(fmri: &str) -> String {
+    if let Some(rest) = fmri.strip_prefix("dev://") {
+        // e.g. dev:///:devid=id1,sd@n5000cca2531b1025//pci@0,0/...
+        let path = match rest.strip_prefix("/:") {
+            Some(auth) => match auth.find("//") {
+                Some(off) => &auth[off + 1..],
+                None => rest,
+            },
+            None => rest,
+        };
+        return DevicePath::new(path).into();
+    }
+    // e.g. hc://:product-id=...:chassis-id=.../ses-enclosure=0/bay=5
+    for scheme in ["hc", "cpu", "mem", "fmd"] {
+        let prefix = format!("{}://", scheme);
+        if let Some(rest) = fmri.strip_prefix(&prefix) {
+            return match rest.find('/') {
+                Some(off) => format!("{}{}", prefix, &rest[off..]),
+                None => fmri.to_string(),
+            };
+        }
+    }
+    fmri.to_string()
+}
@@ -0,0 +1,290 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Follow mode (--follow), for watching a hardware incident as it unfolds.
+// After the report has been generated from the FM log, the log is kept open
+// and the events appended to it are read every so many seconds, added to the
+// device hash and the other data read from the log, and the report is written
+// out again if there were any, until SIGTERM or SIGINT is received.  On a
+// terminal, each report replaces the last; files given with -o are replaced.
+//
+// Only complete lines are read, as the last line of a log being written to
+// may not have been finished.  If the log is truncated, it's followed again
+// from its start.
+//
+use chrono::prelude::*;
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, IsTerminal, Seek, SeekFrom, Write};
+use std::iter;
+use std::mem;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use enrich;
+use input;
+use log;
+use multipath;
+use output;
+use signals;
+use staleness;
+use status;
+use timing::Timings;
+use {diagnosis, find_device, outputs, read_event, render, template_vars};
+use {Config, FmLogError, Lines, LogData, Loaded, Lookups, Mode, SimpleError};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+//
+// The FM log being followed.
+//
+pub struct Tail {
+    path: String,
+    reader: BufReader<fs::File>,
+    // the start of a line which hasn't been finished
+    partial: String,
+    // the number of bytes read, including those of the partial line
+    offset: u64,
+    // the number of complete lines read
+    line_no: usize,
+}
+
+impl Tail {
+    pub fn open(path: &str) -> Result<Tail, Box<dyn Error>> {
+        let io_error = |e| FmLogError::Io { path: Some(path.to_string()),
+            source: e };
+        let mut file = fs::File::open(path).map_err(io_error)?;
+        if let Some(program) = input::decompressor(&mut file)
+            .map_err(io_error)? {
+            return Err(Box::new(SimpleError(format!(
+                "--follow can't be used with a log compressed with {}",
+                program))));
+        }
+        Ok(Tail {
+            path: path.to_string(),
+            reader: BufReader::new(file),
+            partial: String::new(),
+            offset: 0,
+            line_no: 0,
+        })
+    }
+
+    //
+    // Returns the next complete line of the log, or None if there isn't one
+    // yet.
+    //
+    fn next_line(&mut self) -> Option<io::Result<String>> {
+        match self.reader.read_line(&mut self.partial) {
+            Ok(0) => None,
+            Ok(n) => {
+                self.offset += n as u64;
+                if !self.partial.ends_with('\n') {
+                    return None;
+                }
+                self.line_no += 1;
+                let line = mem::take(&mut self.partial);
+                Some(Ok(line.trim_end_matches(['\n', '\r']).to_string()))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    //
+    // If the log has been truncated, start reading it again from the start.
+    //
+    fn check_truncated(&mut self) -> io::Result<()> {
+        if self.reader.get_ref().metadata()?.len() >= self.offset {
+            return Ok(());
+        }
+        log::warn("FM log truncated - following it from the start",
+            &[("path", json!(self.path))]);
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.partial.clear();
+        self.offset = 0;
+        self.line_no = 0;
+        Ok(())
+    }
+}
+
+//
+// Returns the lines of the log for the report's first reading of it.
+//
+pub fn lines(tail: &Rc<RefCell<Tail>>) -> Lines {
+    let tail = Rc::clone(tail);
+    Box::new(iter::from_fn(move || tail.borrow_mut().next_line()))
+}
+
+//
+// Add the events appended to the log since it was last read, returning the
+// number added.
+//
+fn update(
+    config: &Config,
+    tail: &mut Tail,
+    loaded: &mut Loaded,
+    newest_event: &mut Option<i64>
+) -> Result<usize, Box<dyn Error>> {
+
+    tail.check_truncated()
+        .map_err(|e| FmLogError::Io { path: Some(tail.path.clone()),
+            source: e })?;
+
+    let data = &mut loaded.data;
+    let mut log_data = LogData {
+        device_hash: mem::take(&mut loaded.device_hash),
+        cases: mem::take(&mut data.cases),
+        faults: mem::take(&mut data.faults),
+        zfs: mem::take(&mut data.zfs),
+        cpus: mem::take(&mut data.cpus),
+        panics: mem::take(&mut data.boundaries.panics),
+        other_events: mem::take(&mut data.other_events),
+        newest_event: *newest_event,
+        partial: None,
+        coverage: None,
+        timings: Timings::default(),
+    };
+    let mut added = 0;
+    let mut result = Ok(());
+    while let Some(line) = tail.next_line() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                result = Err(FmLogError::Io { path: Some(tail.path.clone()),
+                    source: e });
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        //
+        // A bad event is skipped rather than ending the watch, as it may be
+        // that of a log being rewritten.
+        //
+        let lookups = Lookups { severity: &data.severity,
+            hwgrok: &data.hwgrok };
+        match read_event(config, &mut log_data, &line, tail.line_no,
+            &lookups, &mut loaded.plugins) {
+            Ok(_) => added += 1,
+            Err(e) => log::warn(&format!("skipping event: {}", e),
+                &[("line", json!(tail.line_no)),
+                ("error", json!(e.to_string()))]),
+        }
+    }
+
+    loaded.device_hash = log_data.device_hash;
+    data.cases = log_data.cases;
+    data.faults = log_data.faults;
+    data.zfs = log_data.zfs;
+    data.cpus = log_data.cpus;
+    data.boundaries.panics = log_data.panics;
+    data.other_events = log_data.other_events;
+    *newest_event = log_data.newest_event;
+    result?;
+    if added == 0 {
+        return Ok(0);
+    }
+
+    let device_hash = &mut loaded.device_hash;
+    data.boundaries.panics.sort();
+    data.boundaries.panics.dedup();
+    enrich::resolve_fmris(&data.hwgrok, device_hash);
+    if config.merge_multipath {
+        multipath::consolidate(device_hash);
+    }
+    enrich::match_disks(&mut data.hwgrok, device_hash);
+    data.alt_paths = multipath::find_paths(device_hash);
+    diagnosis::correlate(&mut data.cases, device_hash);
+    if !loaded.plugins.is_empty() {
+        for devpath in device_hash.keys() {
+            if !data.plugin_fields.contains_key(devpath) {
+                let fields = loaded.plugins.enrich(devpath,
+                    &data.hwgrok.identifiers(devpath))?;
+                data.plugin_fields.insert(devpath.clone(), fields);
+            }
+        }
+    }
+    if let (Some(max_age), Some(newest)) = (config.max_age, *newest_event) {
+        data.stale = staleness::check(max_age, Some(newest),
+            Utc::now().timestamp());
+    }
+    data.statuses = status::evaluate(device_hash, data);
+
+    Ok(added)
+}
+
+//
+// Write the report to each of its outputs.  On a terminal, the screen is
+// cleared first so that the report replaces the last one.
+//
+fn write(config: &Config, loaded: &Loaded) -> Result<(), Box<dyn Error>> {
+    let devpath = match &config.mode {
+        Mode::Device(arg) => Some(find_device(arg, &loaded.device_hash,
+            &loaded.data)?),
+        _ => None,
+    };
+    for (path, format) in outputs(config) {
+        let report = render(config, format, &loaded.device_hash,
+            &loaded.data, devpath.as_ref())?;
+        match path {
+            Some(template) => {
+                let path = output::expand_template(template,
+                    &template_vars(config, format))?;
+                output::write_file(&path, &report, config.compress)?;
+            }
+            None => {
+                let stdout = io::stdout();
+                let mut out = stdout.lock();
+                if out.is_terminal() {
+                    write!(out, "\x1b[H\x1b[2J")?;
+                }
+                out.write_all(&report)?;
+                out.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+//
+// Write the report, and then again each time events are added to the log,
+// checking for them at the given interval in seconds.
+//
+pub fn follow(
+    config: &Config,
+    tail: &Rc<RefCell<Tail>>,
+    interval: u64,
+    loaded: Loaded
+) -> Result<(), Box<dyn Error>> {
+
+    let mut loaded = loaded;
+    let mut newest_event = None;
+    signals::install();
+    write(config, &loaded)?;
+
+    let interval = Duration::from_secs(interval);
+    let mut next = Instant::now() + interval;
+    while !signals::shutdown_requested() {
+        if Instant::now() < next {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        next = Instant::now() + interval;
+        let added = update(config, &mut tail.borrow_mut(), &mut loaded,
+            &mut newest_event)?;
+        if added > 0 {
+            log::info(&format!("{} new event(s)", added),
+                &[("events", json!(added))]);
+            write(config, &loaded)?;
+        }
+    }
+    Ok(())
+}
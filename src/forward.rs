@@ -0,0 +1,92 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for the "forward" subcommand, which reads the FM log, applies the
+// configured filters to its ereports and re-emits those which pass, as the
+// original JSON lines, unchanged.  This allows the filtering done for the
+// report to be used as a preprocessing stage for other FMA tooling.
+//
+// The filters are the same as for the report: --since and --until, --class
+// and --exclude-class, --device, --query, --min-severity and the devices
+// excluded by --exclude-virtual.  Given -H, ereports with only an hc-scheme
+// FMRI for their detector are matched by --device against the device path
+// hwgrok gives for them, as in the report.  Events other than ereports are
+// always forwarded, as are the ereports which the report doesn't parse (ZFS
+// and fmd log ereports) unless they fall outside the window of time, are
+// filtered out by class or query or are below the minimum severity.
+//
+use std::error::Error;
+use std::io::Write;
+
+use enrich;
+use hwgrok::HwGrok;
+use input::InputReader;
+use logset;
+use severity::SeverityMap;
+use {device_key, parse_ereport, Config, FmEvent};
+
+//
+// Returns true if the ereport in the given line passes the filters.
+//
+fn passes(
+    config: &Config,
+    line: &str,
+    hwgrok: &HwGrok
+) -> Result<bool, Box<dyn Error>> {
+
+    let mut ereport = parse_ereport(line)?;
+    Ok(match device_key(config, &mut ereport) {
+        Some(key) => config.filter.wants_device(&key,
+            enrich::resolved_key(hwgrok, &ereport, &key).as_ref()),
+        None => false,
+    })
+}
+
+//
+// Write each event which passes the filters to the given writer, returning
+// the number forwarded.
+//
+pub fn forward(
+    out: &mut dyn Write,
+    config: &Config,
+    input: &InputReader,
+    severity: &SeverityMap,
+    hwgrok: &HwGrok
+) -> Result<usize, Box<dyn Error>> {
+
+    let mut forwarded = 0;
+    let lines = logset::open_logs(input, &config.fmlog_paths)?.into_iter()
+        .flat_map(|(_, lines)| lines);
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: FmEvent = serde_json::from_str(&line)?;
+        if event.class.starts_with("ereport.") &&
+            (!config.in_window(event.tod.first().cloned()) ||
+            !config.filter.wants_class(&event.class) ||
+            !config.filter.wants_event(&line)?) {
+            continue;
+        }
+        if let Some(min) = config.min_severity {
+            if event.class.starts_with("ereport.") &&
+                severity.lookup(&event.class) < min {
+                continue;
+            }
+        }
+        if event.class.starts_with("ereport.") &&
+            !event.class.starts_with("ereport.fs.") &&
+            !event.class.starts_with("ereport.fm.fmd.log_") &&
+            !passes(config, &line, hwgrok)? {
+            continue;
+        }
+        writeln!(out, "{}", line.trim_end())?;
+        forwarded += 1;
+    }
+    Ok(forwarded)
+}
@@ -0,0 +1,72 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --fail-on-class, which makes the run exit with a non-zero status
+// if ereports of the given classes appear in the logs, so that burn-in scripts
+// can reject a machine directly from the report run.  Each gate is given as
+// "<glob>[:count]", and trips if the number of matching ereports across all
+// devices exceeds the count, which defaults to zero.
+//
+use std::error::Error;
+use std::str::FromStr;
+
+use glob::glob_match;
+use {DeviceHash, SimpleError};
+
+// The exit status when a gate has tripped, outside of check mode
+pub const FAIL_ON_CLASS_STATUS: i32 = 1;
+
+#[derive(Debug)]
+pub struct ClassGate {
+    pub pattern: String,
+    pub count: usize,
+}
+
+impl FromStr for ClassGate {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<ClassGate, Box<dyn Error>> {
+        let (pattern, count) = match s.rsplit_once(':') {
+            Some((p, c)) => match c.parse::<usize>() {
+                Ok(c) => (p, c),
+                Err(_) => {
+                    return Err(Box::new(SimpleError(
+                        format!("invalid count in --fail-on-class: {}", s))));
+                }
+            },
+            None => (s, 0),
+        };
+        if pattern.is_empty() {
+            return Err(Box::new(SimpleError(
+                format!("invalid --fail-on-class pattern: {}", s))));
+        }
+        Ok(ClassGate { pattern: pattern.to_string(), count })
+    }
+}
+
+//
+// Returns a description of each gate which has tripped, along with the number
+// of matching ereports.
+//
+pub fn evaluate<'a>(
+    gates: &'a [ClassGate],
+    device_hash: &DeviceHash
+) -> Vec<(&'a ClassGate, usize)> {
+
+    gates.iter().filter_map(|gate| {
+        let matched: usize = device_hash.values()
+            .flat_map(|devent| devent.ereport_class_hash.iter())
+            .filter(|(class, _)| glob_match(&gate.pattern, class))
+            .map(|(_, count)| *count as usize)
+            .sum();
+        if matched > gate.count {
+            Some((gate, matched))
+        } else {
+            None
+        }
+    }).collect()
+}
@@ -0,0 +1,526 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Generation of synthetic FM logs and matching hwgrok output, for
+// benchmarking, demonstrations and testing pipelines without real customer
+// data.  Each host has a chassis of disks behind an HBA and a number of NICs,
+// which log a background of ereports at a steady rate, plus a number of
+// bursts, each of many ereports of one class from one device in a short
+// window.  The shape of the data is taken from the [generate] section of the
+// configuration file:
+//
+//   [generate]
+//   hosts = 1
+//   disks = 12
+//   nics = 2
+//   days = 30
+//   rate = 0.5
+//   bursts = 2
+//   burst-size = 50
+//   burst-window = 600
+//   disk-classes = ereport.io.scsi.cmd.disk.tran, ...
+//   nic-classes = ereport.io.pciex.pl.re, ...
+//   seed = 1
+//
+// The rate is the mean number of background ereports per device per day, and
+// the burst window is in seconds.  The logs end at the time given by "end"
+// (in seconds since the epoch), or at the current time.  The same seed always
+// produces the same data, so that runs can be compared.
+//
+// A single host's FM log and hwgrok output are written to the directory as
+// errlog.json and hwgrok.json; for more than one host, each is written to a
+// subdirectory named for the host, as the fleet subcommand expects.
+//
+use chrono::prelude::*;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use conf::ConfFile;
+use fleet::{FMLOG_NAME, HWGROK_NAME};
+use SimpleError;
+
+const SECS_PER_DAY: f64 = 86400.0;
+
+const HBA_PATH: &str = "/pci@0,0/pci8086,2f02@1/pci15d9,808@0";
+
+const DISK_CLASSES: [&str; 4] = [
+    "ereport.io.scsi.cmd.disk.tran",
+    "ereport.io.scsi.cmd.disk.recovered",
+    "ereport.io.scsi.cmd.disk.dev.rqs.derr",
+    "ereport.io.scsi.cmd.disk.dev.rqs.merr",
+];
+
+const NIC_CLASSES: [&str; 3] = [
+    "ereport.io.pciex.pl.re",
+    "ereport.io.pciex.dl.btlp",
+    "ereport.io.pciex.dl.bdllp",
+];
+
+//
+// The shape of the synthetic data.
+//
+#[derive(Debug)]
+pub struct Synthetic {
+    pub hosts: u32,
+    pub disks: u32,
+    pub nics: u32,
+    pub days: u32,
+    // the mean number of background ereports per device per day
+    pub rate: f64,
+    pub bursts: u32,
+    pub burst_size: u32,
+    // the length of each burst, in seconds
+    pub burst_window: i64,
+    pub disk_classes: Vec<String>,
+    pub nic_classes: Vec<String>,
+    // the time of the end of the logs, or None for the current time
+    pub end: Option<i64>,
+    pub seed: u64,
+}
+
+impl Default for Synthetic {
+    fn default() -> Synthetic {
+        Synthetic {
+            hosts: 1,
+            disks: 12,
+            nics: 2,
+            days: 30,
+            rate: 0.5,
+            bursts: 2,
+            burst_size: 50,
+            burst_window: 600,
+            disk_classes: DISK_CLASSES.iter().map(|c| c.to_string())
+                .collect(),
+            nic_classes: NIC_CLASSES.iter().map(|c| c.to_string()).collect(),
+            end: None,
+            seed: 1,
+        }
+    }
+}
+
+//
+// A small xorshift generator, which is all that's needed to vary the data and
+// keeps it reproducible from the seed.
+//
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // splitmix the seed, so that neighbouring seeds (and 0) are usable
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // a value in [0, 1)
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // a value in [0, n)
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        self.next() % n
+    }
+}
+
+//
+// A device which logs ereports.
+//
+struct Device {
+    path: String,
+    devid: Option<String>,
+    classes: Vec<String>,
+}
+
+fn parse_classes(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+fn disk_wwn(host: u32, disk: u32) -> String {
+    format!("5000cca2{:04x}{:04x}", host, disk)
+}
+
+fn nic_path(nic: u32) -> String {
+    format!("/pci@0,0/pci8086,6f08@{:x}/pci8086,1572@0", nic + 3)
+}
+
+impl Synthetic {
+    pub fn from_conf(conf: &ConfFile) -> Result<Synthetic, Box<dyn Error>> {
+        let mut synth = Synthetic::default();
+
+        for (key, value) in conf.section("generate") {
+            let invalid = || SimpleError(format!("invalid value for {}: {}",
+                key, value));
+            match key {
+                "hosts" => {
+                    synth.hosts = value.parse().ok().filter(|n| *n > 0)
+                        .ok_or_else(invalid)?;
+                }
+                "disks" => {
+                    synth.disks = value.parse().map_err(|_| invalid())?;
+                }
+                "nics" => synth.nics = value.parse().map_err(|_| invalid())?,
+                "days" => {
+                    synth.days = value.parse().ok().filter(|n| *n > 0)
+                        .ok_or_else(invalid)?;
+                }
+                "rate" => {
+                    synth.rate = value.parse().ok()
+                        .filter(|r: &f64| r.is_finite() && *r >= 0.0)
+                        .ok_or_else(invalid)?;
+                }
+                "bursts" => {
+                    synth.bursts = value.parse().map_err(|_| invalid())?;
+                }
+                "burst-size" => {
+                    synth.burst_size = value.parse().map_err(|_| invalid())?;
+                }
+                "burst-window" => {
+                    synth.burst_window = value.parse().ok()
+                        .filter(|w| *w > 0).ok_or_else(invalid)?;
+                }
+                "disk-classes" => synth.disk_classes = parse_classes(value),
+                "nic-classes" => synth.nic_classes = parse_classes(value),
+                "end" => {
+                    synth.end = Some(value.parse().map_err(|_| invalid())?);
+                }
+                "seed" => synth.seed = value.parse().map_err(|_| invalid())?,
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "unknown generate setting: {}", key))));
+                }
+            }
+        }
+        if synth.disk_classes.is_empty() || synth.nic_classes.is_empty() {
+            return Err(Box::new(SimpleError(
+                "generate classes must not be empty".to_string())));
+        }
+
+        Ok(synth)
+    }
+
+    //
+    // The name of each host, which is also its subdirectory when there's
+    // more than one.
+    //
+    pub fn host_name(&self, host: u32) -> String {
+        format!("host{:02}", host + 1)
+    }
+
+    fn devices(&self, host: u32) -> Vec<Device> {
+        let disks = (0..self.disks).map(|d| {
+            let wwn = disk_wwn(host, d);
+            Device {
+                path: format!("{}/iport@f/disk@w{},0", HBA_PATH, wwn),
+                devid: Some(format!("id1,sd@n{}", wwn)),
+                classes: self.disk_classes.clone(),
+            }
+        });
+        let nics = (0..self.nics).map(|n| Device {
+            path: nic_path(n),
+            devid: None,
+            classes: self.nic_classes.clone(),
+        });
+        disks.chain(nics).collect()
+    }
+
+    //
+    // Returns the FM log of the given host, as fmdump -AVj would write it.
+    //
+    pub fn fmlog(&self, host: u32) -> String {
+        let mut rng = Rng::new(self.seed ^ (u64::from(host) << 32));
+        let end = self.end.unwrap_or_else(|| Utc::now().timestamp());
+        let span = i64::from(self.days) * SECS_PER_DAY as i64;
+        let start = end - span;
+        let devices = self.devices(host);
+
+        let mut events: Vec<(i64, i64, &Device, &str)> = Vec::new();
+        let mut event = |rng: &mut Rng, tod: i64, device, class| {
+            let nsecs = rng.below(1_000_000_000) as i64;
+            events.push((tod.min(end), nsecs, device, class));
+        };
+        for device in &devices {
+            //
+            // The number of background ereports varies around the mean, so
+            // that not every device looks the same.
+            //
+            let mean = self.rate * f64::from(self.days);
+            let count = (mean * 2.0 * rng.unit()).round() as u64;
+            for _ in 0..count {
+                let tod = start + rng.below(span as u64) as i64;
+                let class = &device.classes[rng.below(
+                    device.classes.len() as u64) as usize];
+                event(&mut rng, tod, device, class);
+            }
+        }
+        if !devices.is_empty() {
+            for _ in 0..self.bursts {
+                let device = &devices[rng.below(devices.len() as u64)
+                    as usize];
+                let class = &device.classes[rng.below(
+                    device.classes.len() as u64) as usize];
+                let begin = start + rng.below(span as u64) as i64;
+                for _ in 0..self.burst_size {
+                    let tod = begin +
+                        rng.below(self.burst_window as u64) as i64;
+                    event(&mut rng, tod, device, class);
+                }
+            }
+        }
+        events.sort_by_key(|(tod, nsecs, _, _)| (*tod, *nsecs));
+
+        let mut log = String::new();
+        for (ena, (tod, nsecs, device, class)) in events.iter().enumerate() {
+            let mut ereport = json!({
+                "class": class,
+                "ena": format!("0x{:016x}", ((ena as u64 + 1) << 12) | 1),
+                "detector": {
+                    "version": 0,
+                    "scheme": "dev",
+                    "device-path": device.path,
+                },
+                "__ttl": 1,
+                "__tod": [tod, nsecs],
+            });
+            if let Some(devid) = &device.devid {
+                ereport["devid"] = json!(devid);
+            }
+            // medium errors carry the sense data of an unrecovered read
+            if class.starts_with("ereport.io.scsi.cmd.disk.dev.rqs.") {
+                ereport["key"] = json!(3);
+                ereport["asc"] = json!(0x11);
+                ereport["ascq"] = json!(0);
+            }
+            log.push_str(&ereport.to_string());
+            log.push('\n');
+        }
+        log
+    }
+
+    //
+    // Returns the hwgrok output of the given host, describing its chassis and
+    // the devices in its FM log.
+    //
+    pub fn hwgrok(&self, host: u32) -> String {
+        let leds = |types: &[&str]| -> Vec<serde_json::Value> {
+            types.iter().map(|t| json!({ "type": t, "mode": "off" }))
+                .collect()
+        };
+        let mut pci_devices = vec![json!({
+            "hc-fmri": "hc:///motherboard=0/hostbridge=0/pciexrc=0/\
+                pciexbus=1/pciexdev=0/pciexfn=0",
+            "label": "HBA",
+            "pci-vendor-name": "LSI Logic / Symbios Logic",
+            "pci-device-name": "SAS3008 PCI-Express Fusion-MPT SAS-3",
+            "pci-subsystem-name": "AOC-S3008L-L8e",
+            "device-path": HBA_PATH,
+        })];
+        for n in 0..self.nics {
+            pci_devices.push(json!({
+                "hc-fmri": format!("hc:///motherboard=0/hostbridge={0}/\
+                    pciexrc={0}/pciexbus={1}/pciexdev=0/pciexfn=0", n + 1,
+                    n + 2),
+                "label": format!("NIC {}", n),
+                "pci-vendor-name": "Intel Corporation",
+                "pci-device-name": "Ethernet Controller X710 for 10GbE SFP+",
+                "pci-subsystem-name": "Ethernet Converged Network Adapter \
+                    X710",
+                "device-path": nic_path(n),
+            }));
+        }
+        let nics: Vec<serde_json::Value> = (0..self.nics).map(|n| json!({
+            "label": format!("NIC {}", n),
+            "name": format!("i40e{}", n),
+            "mac-address": format!("3c:fd:fe:{:02x}:{:02x}:{:02x}",
+                (host >> 8) & 0xff, host & 0xff, n & 0xff),
+            "manufacturer": "Intel Corporation",
+            "model": "Ethernet Controller X710 for 10GbE SFP+",
+            "firmware-revision": "6.01 0x800035cf 1.1747.0",
+            "device-path": nic_path(n),
+        })).collect();
+        let drive_bays: Vec<serde_json::Value> = (0..self.disks).map(|d| {
+            let fmri = format!("hc:///chassis=0/bay={}", d);
+            let wwn = disk_wwn(host, d);
+            json!({
+                "hc-fmri": fmri,
+                "label": format!("Front Disk {}", d),
+                "disk": {
+                    "hc-fmri": format!("{}/disk=0", fmri),
+                    "manufacturer": "HGST",
+                    "model": "HUH721212AL4200",
+                    "serial-number": format!("8DG{:05}", host * 1000 + d),
+                    "firmware-revision": "A3D0",
+                    "device-path": format!("{}/iport@f/disk@w{},0", HBA_PATH,
+                        wwn),
+                    "size-in-bytes": 12_000_138_625_024u64,
+                    "speed-in-rpm": 7200,
+                },
+                "leds": leds(&["service", "ok2rm"]),
+            })
+        }).collect();
+        let processors: Vec<serde_json::Value> = (0..2).map(|c| json!({
+            "hc-fmri": format!("hc:///motherboard=0/chip={}", c),
+            "label": format!("CPU{}", c + 1),
+            "processor-brand": "Intel(r) Xeon(r) CPU E5-2690 v4 @ 2.60GHz",
+            "number-of-cores": 14,
+            "hardware-threads-per-core": 2,
+            "leds": [],
+        })).collect();
+        let psus: Vec<serde_json::Value> = (0..2).map(|p| json!({
+            "hc-fmri": format!("hc:///psu={}", p),
+            "label": format!("PS{}", p + 1),
+            "manufacturer": "SUPERMICRO",
+            "model": "PWS-1K28P-SQ",
+            "firmware-revision": "1.0",
+            "leds": [],
+        })).collect();
+        let hwgrok = json!({
+            "chassis": {
+                "hc-fmri": "hc:///chassis=0",
+                "manufacturer": "Supermicro",
+                "model": "SSG-6048R-E1CR24H",
+                "serial-number": format!("S{:08}", host),
+                "leds": leds(&["service", "locate"]),
+            },
+            "pci-devices": pci_devices,
+            "drive-bays": drive_bays,
+            "processors": processors,
+            "memory": [],
+            "power-supplies": psus,
+            "fans": [],
+            "network-interfaces": nics,
+            "bios": {
+                "vendor": "American Megatrends Inc.",
+                "version": "3.1",
+                "release-date": "06/08/2018",
+            },
+        });
+        format!("{:#}\n", hwgrok)
+    }
+
+    //
+    // Writes the FM log and hwgrok output of every host to the directory,
+    // returning the number of ereports written.
+    //
+    pub fn write(&self, dir: &str) -> Result<usize, Box<dyn Error>> {
+        let mut ereports = 0;
+        for host in 0..self.hosts {
+            let hostdir = if self.hosts == 1 {
+                Path::new(dir).to_path_buf()
+            } else {
+                Path::new(dir).join(self.host_name(host))
+            };
+            fs::create_dir_all(&hostdir).map_err(|e| SimpleError(format!(
+                "failed to create {}: {}", hostdir.display(), e)))?;
+            let fmlog = self.fmlog(host);
+            ereports += fmlog.lines().count();
+            for (name, contents) in [(FMLOG_NAME, fmlog),
+                (HWGROK_NAME, self.hwgrok(host))] {
+                let path = hostdir.join(name);
+                fs::write(&path, contents).map_err(|e| SimpleError(format!(
+                    "failed to write {}: {}", path.display(), e)))?;
+            }
+        }
+        Ok(ereports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Synthetic, SECS_PER_DAY};
+    use conf::ConfFile;
+    use hwgrok::parse_hwgrok;
+    use parse_ereport;
+
+    fn synthetic(conf: &str) -> Synthetic {
+        Synthetic::from_conf(&ConfFile::parse(conf).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn conf() {
+        let synth = synthetic("[generate]\nhosts = 3\ndisks = 4\n\
+            rate = 1.5\ndisk-classes = a, b,\nend = 1560000000\n");
+        assert_eq!(synth.hosts, 3);
+        assert_eq!(synth.disks, 4);
+        assert_eq!(synth.rate, 1.5);
+        assert_eq!(synth.disk_classes, vec!["a", "b"]);
+        assert_eq!(synth.end, Some(1560000000));
+
+        for conf in &["hosts = 0", "rate = -1", "disk-classes = ,",
+            "burst-window = 0", "color = blue"] {
+            let conf = ConfFile::parse(&format!("[generate]\n{}\n", conf))
+                .unwrap();
+            assert!(Synthetic::from_conf(&conf).is_err());
+        }
+    }
+
+    #[test]
+    fn fmlog() {
+        let synth = synthetic("[generate]\ndisks = 3\nnics = 1\ndays = 10\n\
+            rate = 2\nbursts = 1\nburst-size = 20\nend = 1560000000\n\
+            seed = 7\n");
+        let fmlog = synth.fmlog(0);
+        assert_eq!(fmlog, synth.fmlog(0));
+        assert_ne!(fmlog, synth.fmlog(1));
+
+        let start = 1560000000 - 10 * SECS_PER_DAY as i64;
+        let mut last = 0;
+        let mut ereports = 0;
+        for line in fmlog.lines() {
+            let ereport = parse_ereport(line).unwrap();
+            assert!(ereport.tod[0] >= start && ereport.tod[0] <= 1560000000);
+            assert!(ereport.tod[0] >= last);
+            last = ereport.tod[0];
+            assert_eq!(ereport.detector.scheme, "dev");
+            assert!(synth.disk_classes.contains(&ereport.class) ||
+                synth.nic_classes.contains(&ereport.class));
+            ereports += 1;
+        }
+        assert!(ereports >= 20);
+    }
+
+    #[test]
+    fn hwgrok() {
+        let synth = synthetic("[generate]\ndisks = 5\nnics = 2\n\
+            end = 1560000000\n");
+        let hwgrok = parse_hwgrok(&synth.hwgrok(0)).unwrap();
+        assert_eq!(hwgrok.drive_bays.len(), 5);
+        assert_eq!(hwgrok.pci_devices.len(), 3);
+        assert_eq!(hwgrok.nics.len(), 2);
+
+        //
+        // Every device which logs ereports is one that hwgrok describes.
+        //
+        let paths: Vec<&str> = hwgrok.drive_bays.iter()
+            .filter_map(|b| b.bay_disk.as_ref())
+            .map(|d| d.disk_device_path.as_str())
+            .chain(hwgrok.pci_devices.iter()
+                .map(|p| p.pci_device_path.as_str()))
+            .collect();
+        for line in synth.fmlog(0).lines() {
+            let ereport = parse_ereport(line).unwrap();
+            let path = ereport.detector.device_path.unwrap();
+            assert!(paths.contains(&path.as_str()), "{}", path);
+        }
+    }
+}
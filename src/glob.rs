@@ -0,0 +1,42 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+
+//
+// Simple shell-style glob matching, where '*' matches any sequence of
+// characters (including an empty one) and '?' matches any single character.
+// This is sufficient for matching event classes such as "ereport.io.pciex.*".
+//
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let pat: Vec<char> = pattern.chars().collect();
+    let txt: Vec<char> = s.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // the position of the last '*' seen, and of the text when we saw it
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < txt.len() {
+        if p < pat.len() && (pat[p] == '?' || pat[p] == txt[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pat.len() && pat[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            // Let the last '*' absorb one more character and retry.
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    while p < pat.len() && pat[p] == '*' {
+        p += 1;
+    }
+    p == pat.len()
+}
@@ -0,0 +1,491 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// A GraphQL endpoint for the "serve" subcommand, so that UIs can fetch nested
+// device -> class -> daily count data in one round trip, selecting just the
+// fields they need.  Only the subset of GraphQL needed for that is supported:
+// a single query operation made up of fields, aliases, arguments (string and
+// integer literals) and selection sets.  Fragments, variables and directives
+// aren't supported.  The schema is:
+//
+//   type Query {
+//     devices(device: String, class: String, since: String): [Device]
+//     device(name: String!): Device
+//   }
+//   type Device {
+//     path: String
+//     alias: String
+//     owner: String
+//     total: Int
+//     classes(class: String): [ClassCount]
+//     daily: [DayCount]
+//   }
+//   type ClassCount {
+//     class: String
+//     severity: String
+//     count: Int
+//     daily: [DayCount]
+//   }
+//   type DayCount {
+//     date: String
+//     count: Int
+//   }
+//
+// The arguments to devices() filter the ereports just as the query parameters
+// to /report do.
+//
+use serde_json::{Map, Value};
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+use device;
+use devpath::DeviceKey;
+use glob::glob_match;
+use server::filter_devices;
+use {get_event_timestamp, parse_timestamp, DeviceHash, DeviceHashEnt,
+    ReportData, SimpleError};
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Str(String),
+    Int(i64),
+    Punct(char),
+}
+
+#[derive(Debug)]
+struct Field {
+    alias: Option<String>,
+    name: String,
+    args: HashMap<String, Value>,
+    selection: Vec<Field>,
+}
+
+impl Field {
+    fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+
+    fn str_arg(&self, name: &str) -> Result<Option<&str>, Box<dyn Error>> {
+        match self.args.get(name) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(err(&format!("argument \"{}\" of \"{}\" must be a \
+                string", name, self.name))),
+        }
+    }
+}
+
+fn err(msg: &str) -> Box<dyn Error> {
+    Box::new(SimpleError(msg.to_string()))
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == '#' {
+            // comments run to the end of the line
+            while chars.next().is_some_and(|c| c != '\n') {}
+        } else if "{}():".contains(c) {
+            tokens.push(Token::Punct(c));
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => s.push('\n'),
+                        Some('t') => s.push('\t'),
+                        Some(c) => s.push(c),
+                        None => return Err(err("unterminated string")),
+                    },
+                    Some(c) => s.push(c),
+                    None => return Err(err("unterminated string")),
+                }
+            }
+            tokens.push(Token::Str(s));
+        } else if c == '-' || c.is_ascii_digit() {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c != '-' && !c.is_ascii_digit() {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            let n = s.parse().map_err(|_| err(&format!("invalid number: {}",
+                s)))?;
+            tokens.push(Token::Int(n));
+        } else if c == '_' || c.is_ascii_alphabetic() {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c != '_' && !c.is_ascii_alphanumeric() {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(s));
+        } else {
+            return Err(err(&format!("unexpected character: {}", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+// The deepest nesting of selection sets accepted, well beyond what the schema
+// needs, so that a hostile query can't exhaust the stack
+const MAX_DEPTH: usize = 32;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // the nesting of the selection set being parsed
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Punct(p)) if *p == c => Ok(()),
+            _ => Err(err(&format!("expected '{}'", c))),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Name(n)) => Ok(n.clone()),
+            _ => Err(err("expected a field name")),
+        }
+    }
+
+    //
+    // Parse an operation: an optional "query" keyword and operation name,
+    // followed by the selection set.
+    //
+    fn operation(&mut self) -> Result<Vec<Field>, Box<dyn Error>> {
+        if self.peek() == Some(&Token::Name("query".to_string())) {
+            self.next();
+            if let Some(Token::Name(_)) = self.peek() {
+                self.next();
+            }
+        }
+        let selection = self.selection_set()?;
+        if self.peek().is_some() {
+            return Err(err("only a single query operation is supported"));
+        }
+        Ok(selection)
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Field>, Box<dyn Error>> {
+        self.expect('{')?;
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(err(&format!("selections are nested more than {} \
+                deep", MAX_DEPTH)));
+        }
+        let mut fields = Vec::new();
+        while self.peek() != Some(&Token::Punct('}')) {
+            if self.peek().is_none() {
+                return Err(err("expected '}'"));
+            }
+            fields.push(self.field()?);
+        }
+        self.next();
+        self.depth -= 1;
+        Ok(fields)
+    }
+
+    fn field(&mut self) -> Result<Field, Box<dyn Error>> {
+        let mut alias = None;
+        let mut name = self.name()?;
+        if self.peek() == Some(&Token::Punct(':')) {
+            self.next();
+            alias = Some(name);
+            name = self.name()?;
+        }
+
+        let mut args = HashMap::new();
+        if self.peek() == Some(&Token::Punct('(')) {
+            self.next();
+            while self.peek() != Some(&Token::Punct(')')) {
+                let arg = self.name()?;
+                self.expect(':')?;
+                let value = match self.next() {
+                    Some(Token::Str(s)) => json!(s),
+                    Some(Token::Int(n)) => json!(n),
+                    _ => return Err(err(&format!("invalid value for argument \
+                        \"{}\"", arg))),
+                };
+                args.insert(arg, value);
+            }
+            self.next();
+        }
+
+        let selection = if self.peek() == Some(&Token::Punct('{')) {
+            self.selection_set()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Field { alias, name, args, selection })
+    }
+}
+
+fn parse(query: &str) -> Result<Vec<Field>, Box<dyn Error>> {
+    let mut parser = Parser { tokens: tokenize(query)?, pos: 0, depth: 0 };
+    parser.operation()
+}
+
+//
+// Check that a field of an object type has a selection set, and that a field
+// of a scalar type doesn't.
+//
+fn check_selection(field: &Field, object: bool) -> Result<(), Box<dyn Error>> {
+    if object && field.selection.is_empty() {
+        return Err(err(&format!("field \"{}\" requires a selection set",
+            field.name)));
+    }
+    if !object && !field.selection.is_empty() {
+        return Err(err(&format!("field \"{}\" is a scalar", field.name)));
+    }
+    Ok(())
+}
+
+fn resolve_days(
+    days: &BTreeMap<String, u32>,
+    selection: &[Field]
+) -> Result<Value, Box<dyn Error>> {
+
+    let mut list = Vec::new();
+    for (date, count) in days {
+        let mut obj = Map::new();
+        for field in selection {
+            check_selection(field, false)?;
+            let value = match field.name.as_str() {
+                "date" => json!(date),
+                "count" => json!(count),
+                "__typename" => json!("DayCount"),
+                f => return Err(err(&format!("no field \"{}\" on DayCount",
+                    f))),
+            };
+            obj.insert(field.key().to_string(), value);
+        }
+        list.push(Value::Object(obj));
+    }
+    Ok(Value::Array(list))
+}
+
+fn resolve_classes(
+    devent: &DeviceHashEnt,
+    pattern: Option<&str>,
+    selection: &[Field],
+    data: &ReportData
+) -> Result<Value, Box<dyn Error>> {
+
+    let mut classes: Vec<(&String, &u32)> = devent.ereport_class_hash.iter()
+        .filter(|(class, _)| pattern.map(|p| glob_match(p, class))
+            .unwrap_or(true))
+        .collect();
+    classes.sort();
+
+    let mut list = Vec::new();
+    for (class, count) in classes {
+        let mut obj = Map::new();
+        for field in selection {
+            let value = match field.name.as_str() {
+                "daily" => {
+                    check_selection(field, true)?;
+                    let mut days = BTreeMap::new();
+                    for ereport in devent.ereports.iter()
+                        .filter(|e| &e.class == class) {
+                        *days.entry(get_event_timestamp(ereport.tod[0]))
+                            .or_insert(0) += 1;
+                    }
+                    resolve_days(&days, &field.selection)?
+                }
+                name => {
+                    check_selection(field, false)?;
+                    match name {
+                        "class" => json!(class),
+                        "severity" => json!(data.severity.lookup(class)
+                            .to_string()),
+                        "count" => json!(count),
+                        "__typename" => json!("ClassCount"),
+                        f => return Err(err(&format!("no field \"{}\" on \
+                            ClassCount", f))),
+                    }
+                }
+            };
+            obj.insert(field.key().to_string(), value);
+        }
+        list.push(Value::Object(obj));
+    }
+    Ok(Value::Array(list))
+}
+
+fn resolve_device(
+    devpath: &DeviceKey,
+    devent: &DeviceHashEnt,
+    selection: &[Field],
+    data: &ReportData
+) -> Result<Value, Box<dyn Error>> {
+
+    let mut obj = Map::new();
+    for field in selection {
+        let value = match field.name.as_str() {
+            "classes" => {
+                check_selection(field, true)?;
+                resolve_classes(devent, field.str_arg("class")?,
+                    &field.selection, data)?
+            }
+            "daily" => {
+                check_selection(field, true)?;
+                let days: BTreeMap<String, u32> = devent.ereport_ts_hash
+                    .iter()
+                    .map(|(ts, count)| (ts.clone(), *count))
+                    .collect();
+                resolve_days(&days, &field.selection)?
+            }
+            name => {
+                check_selection(field, false)?;
+                match name {
+                    "path" => json!(devpath.as_str()),
+                    "alias" => json!(data.aliases.lookup(devpath,
+                        &data.hwgrok)),
+                    "owner" => json!(data.owners.lookup(devpath,
+                        &data.hwgrok)),
+                    "total" => json!(devent.ereports.len()),
+                    "__typename" => json!("Device"),
+                    f => return Err(err(&format!("no field \"{}\" on Device",
+                        f))),
+                }
+            }
+        };
+        obj.insert(field.key().to_string(), value);
+    }
+    Ok(Value::Object(obj))
+}
+
+fn resolve_query(
+    fields: &[Field],
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<Value, Box<dyn Error>> {
+
+    let mut obj = Map::new();
+    for field in fields {
+        let value = match field.name.as_str() {
+            "devices" => {
+                check_selection(field, true)?;
+                let device = match field.str_arg("device")? {
+                    Some(arg) => match device::resolve_device(arg,
+                        device_hash, data) {
+                        Some(devpath) => Some(devpath),
+                        None => {
+                            obj.insert(field.key().to_string(), json!([]));
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                let since = match field.str_arg("since")? {
+                    Some(s) => Some(parse_timestamp(s).ok_or_else(||
+                        err(&format!("invalid time: {}", s)))?),
+                    None => None,
+                };
+                let filtered = filter_devices(device_hash, device.as_ref(),
+                    field.str_arg("class")?, since)?;
+                let mut devpaths: Vec<&DeviceKey> = filtered.keys().collect();
+                devpaths.sort();
+                let mut list = Vec::new();
+                for devpath in devpaths {
+                    list.push(resolve_device(devpath, &filtered[devpath],
+                        &field.selection, data)?);
+                }
+                Value::Array(list)
+            }
+            "device" => {
+                check_selection(field, true)?;
+                let arg = field.str_arg("name")?.ok_or_else(||
+                    err("field \"device\" requires a \"name\" argument"))?;
+                match device::resolve_device(arg, device_hash, data)
+                    .and_then(|d| device_hash.get_key_value(&d)) {
+                    Some((devpath, devent)) => resolve_device(devpath, devent,
+                        &field.selection, data)?,
+                    None => Value::Null,
+                }
+            }
+            "__typename" => json!("Query"),
+            f => return Err(err(&format!("no field \"{}\" on Query", f))),
+        };
+        obj.insert(field.key().to_string(), value);
+    }
+    Ok(Value::Object(obj))
+}
+
+//
+// Execute a query, returning the GraphQL response document.  Errors are
+// reported within the document rather than as an Err, as GraphQL clients
+// expect.
+//
+pub fn execute(
+    query: &str,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Value {
+    match parse(query).and_then(|fields| resolve_query(&fields, device_hash,
+        data)) {
+        Ok(value) => json!({ "data": value }),
+        Err(e) => json!({ "errors": [{ "message": e.to_string() }] }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, MAX_DEPTH};
+
+    #[test]
+    fn parsing() {
+        let fields = parse("query Q { devices(class: \"ereport.*\") { path \
+            n: count } }").unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name, "devices");
+        assert_eq!(fields[0].args["class"], "ereport.*");
+        assert_eq!(fields[0].selection.len(), 2);
+        assert_eq!(fields[0].selection[1].key(), "n");
+        assert_eq!(fields[0].selection[1].name, "count");
+
+        assert!(parse("{ devices { path }").is_err());
+        assert!(parse("{ devices } { path }").is_err());
+        assert!(parse("{ devices(n: x) }").is_err());
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth| format!("{}{}", "{ a ".repeat(depth),
+            "}".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&nested(100_000)).is_err());
+    }
+}
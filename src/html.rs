@@ -0,0 +1,236 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Rendering of the report as a standalone HTML page, which keeps its
+// structure when attached to a ticket, unlike the text report when pasted.
+// The page opens with a table of contents linking to each device, and each
+// device has a collapsible section giving its identity (including the disk or
+// PCI details from hwgrok), its ereport class counts and the day-by-day
+// distribution of its ereports, followed by the full text of its section of
+// the report.  The analysis sections follow, as text.  The page has no
+// external dependencies, so that it can be viewed offline.
+//
+use chrono::prelude::*;
+
+use std::error::Error;
+use std::io::Write;
+
+use devpath::DeviceKey;
+use i18n::tr;
+use {device_identity, ordered_devices, write_analysis, write_device_section,
+    DeviceHash, DeviceHashEnt, ReportData};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+nav li { font-family: monospace; }
+details { border: 1px solid #ccc; border-radius: 4px; margin: 0.5em 0;
+    padding: 0.5em; }
+summary { cursor: pointer; font-family: monospace; font-weight: bold; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { border: 1px solid #ddd; padding: 0.2em 0.6em; text-align: left; }
+td.num { text-align: right; }
+pre { background: #f6f6f6; padding: 0.5em; overflow-x: auto; }
+.badge { border-radius: 3px; color: #fff; font-family: monospace;
+    font-size: 0.85em; padding: 0 0.4em; }
+.badge-ok { background: #2e7d32; }
+.badge-watch { background: #f9a825; }
+.badge-degraded { background: #ef6c00; }
+.badge-critical { background: #c62828; }
+.partial { background: #fff3e0; border: 1px solid #ef6c00; padding: 0.5em; }
+";
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+//
+// Returns the device's status badge, or nothing if it has no status.
+//
+fn badge(devpath: &DeviceKey, data: &ReportData) -> String {
+    match data.statuses.get(devpath) {
+        Some(status) => {
+            let name = status.status.to_string();
+            format!(" <span class=\"badge badge-{}\" title=\"{}\">{}</span>",
+                name.to_lowercase(), escape(&status.reasons.join("; ")), name)
+        }
+        None => String::new(),
+    }
+}
+
+fn write_head(out: &mut dyn Write, title: &str) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", escape(title))?;
+    writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE)?;
+    writeln!(out, "<h1>{}</h1>", escape(title))?;
+    writeln!(out, "<p>Generated {}</p>",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC"))?;
+    Ok(())
+}
+
+fn write_tail(out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "</body>\n</html>")?;
+    Ok(())
+}
+
+fn write_table(
+    out: &mut dyn Write,
+    headings: &[&str],
+    rows: &[(String, String)],
+    numeric: bool
+) -> Result<(), Box<dyn Error>> {
+
+    writeln!(out, "<table>")?;
+    if !headings.is_empty() {
+        write!(out, "<tr>")?;
+        for h in headings {
+            write!(out, "<th>{}</th>", escape(h))?;
+        }
+        writeln!(out, "</tr>")?;
+    }
+    let class = if numeric { " class=\"num\"" } else { "" };
+    for (name, value) in rows {
+        writeln!(out, "<tr><td>{}</td><td{}>{}</td></tr>", escape(name), class,
+            escape(value))?;
+    }
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+fn write_device(
+    out: &mut dyn Write,
+    id: &str,
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let total = devent.map(|d| d.ereports.len()).unwrap_or(0);
+    writeln!(out, "<details id=\"{}\" open>", id)?;
+    writeln!(out, "<summary>{} ({} ereports){}</summary>", escape(devpath),
+        total, badge(devpath, data))?;
+    write_table(out, &[], &device_identity(devpath, devent, data), false)?;
+
+    if let Some(devent) = devent {
+        let mut classes: Vec<(&String, &u32)> = devent.ereport_class_hash
+            .iter().collect();
+        classes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let rows: Vec<(String, String)> = classes.iter()
+            .map(|(class, count)| (class.to_string(), count.to_string()))
+            .collect();
+        writeln!(out, "<h3>{}</h3>", escape(tr("Ereport Classes")))?;
+        write_table(out, &["class", "count"], &rows, true)?;
+
+        let rows: Vec<(String, String)> = devent.ereports_ts.iter()
+            .map(|ts| (ts.clone(), devent.ereport_ts_hash[ts].to_string()))
+            .collect();
+        writeln!(out, "<h3>{}</h3>",
+            escape(tr("Event Occurrence Distribution")))?;
+        write_table(out, &["date", "count"], &rows, true)?;
+
+        let mut text = Vec::new();
+        write_device_section(&mut text, devpath, devent, data)?;
+        writeln!(out, "<details>\n<summary>{}</summary>",
+            escape(tr("Full text")))?;
+        writeln!(out, "<pre>{}</pre>\n</details>",
+            escape(&String::from_utf8_lossy(&text)))?;
+    }
+    writeln!(out, "</details>")?;
+    Ok(())
+}
+
+//
+// Write the whole report as an HTML page.
+//
+pub fn write_html_report(
+    out: &mut dyn Write,
+    title: &str,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    write_head(out, title)?;
+    let system: Vec<String> = data.hwgrok.system_identity().iter()
+        .map(|(name, value)| format!("<strong>{}</strong> {}",
+            escape(tr(&format!("{}:", name))), escape(value)))
+        .collect();
+    if !system.is_empty() {
+        writeln!(out, "<p>{}</p>", system.join("<br>\n"))?;
+    }
+    if let Some(coverage) = &data.coverage {
+        writeln!(out, "<p><strong>{}</strong> {}<br>\n<strong>{}</strong> {}\
+            </p>", escape(tr("FM Logs:")), escape(&coverage.logs.join(", ")),
+            escape(tr("Coverage:")), escape(&coverage.to_string()))?;
+    }
+    if let Some(partial) = &data.partial {
+        writeln!(out, "<p class=\"partial\"><strong>{}</strong> {}</p>",
+            escape(tr("Partial Report:")), escape(&partial.to_string()))?;
+    }
+    let (phys, virt) = ordered_devices(device_hash, data);
+    let devices: Vec<(String, &DeviceKey, &DeviceHashEnt)> = phys.iter()
+        .chain(virt.iter())
+        .enumerate()
+        .map(|(i, (devpath, devent))| (format!("device-{}", i), *devpath,
+            *devent))
+        .collect();
+
+    writeln!(out, "<nav>\n<h2>{}</h2>\n<ul>", escape(tr("Contents")))?;
+    for (id, devpath, devent) in &devices {
+        let label = data.hwgrok.location_label(devpath)
+            .or_else(|| data.aliases.lookup(devpath, &data.hwgrok))
+            .map(|l| format!("{} - ", escape(l)))
+            .unwrap_or_default();
+        writeln!(out, "<li><a href=\"#{}\">{}{}</a> ({} ereports){}</li>",
+            id, label, escape(devpath), devent.ereports.len(),
+            badge(devpath, data))?;
+    }
+    writeln!(out, "<li><a href=\"#analysis\">{}</a></li>",
+        escape(tr("Analysis")))?;
+    writeln!(out, "</ul>\n</nav>")?;
+
+    writeln!(out, "<h2>{}</h2>", escape(tr("Devices")))?;
+    for (i, (id, devpath, devent)) in devices.iter().enumerate() {
+        if i == phys.len() {
+            writeln!(out, "<h2>{}</h2>", escape(tr("Virtual Devices")))?;
+        }
+        write_device(out, id, devpath, Some(devent), data)?;
+    }
+
+    let mut text = Vec::new();
+    write_analysis(&mut text, device_hash, data)?;
+    writeln!(out, "<h2 id=\"analysis\">{}</h2>", escape(tr("Analysis")))?;
+    writeln!(out, "<pre>{}</pre>", escape(&String::from_utf8_lossy(&text)))?;
+
+    write_tail(out)
+}
+
+//
+// Write a single device's report as an HTML page.
+//
+pub fn write_html_device(
+    out: &mut dyn Write,
+    title: &str,
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    write_head(out, title)?;
+    write_device(out, "device-0", devpath, devent, data)?;
+    write_tail(out)
+}
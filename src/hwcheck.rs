@@ -0,0 +1,323 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for the "check-hwgrok" subcommand, which checks a hwgrok file
+// against the sections and fields that the report's hardware enrichment
+// relies on (see hwgrok.rs).  Any missing or mistyped field prevents the file
+// from being loaded at all, so each problem is listed along with the
+// enrichment that depends on its section, followed by a summary of the
+// enrichment that each section provides.
+//
+use serde_json::Value;
+
+use std::error::Error;
+use std::io::Write;
+
+use input;
+
+enum Kind {
+    Str,
+    Num,
+    Obj(&'static [Field]),
+    List(&'static [Field]),
+}
+
+struct Field {
+    name: &'static str,
+    kind: Kind,
+    required: bool,
+}
+
+const fn req(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind, required: true }
+}
+
+const fn opt(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind, required: false }
+}
+
+const LED: &[Field] = &[req("type", Kind::Str), req("mode", Kind::Str)];
+
+const SENSOR: &[Field] = &[req("name", Kind::Str), req("type", Kind::Str)];
+
+const DISK: &[Field] = &[
+    req("hc-fmri", Kind::Str),
+    req("manufacturer", Kind::Str),
+    req("model", Kind::Str),
+    req("serial-number", Kind::Str),
+    req("firmware-revision", Kind::Str),
+    req("device-path", Kind::Str),
+    req("size-in-bytes", Kind::Num),
+];
+
+const DIMM: &[Field] = &[
+    req("hc-fmri", Kind::Str),
+    req("manufacturer", Kind::Str),
+    req("part-number", Kind::Str),
+    opt("serial-number", Kind::Str),
+    req("type", Kind::Str),
+    req("size-in-bytes", Kind::Num),
+    req("leds", Kind::List(LED)),
+];
+
+struct Section {
+    field: Field,
+    enrichment: &'static str,
+}
+
+const SECTIONS: &[Section] = &[
+    Section {
+        field: req("chassis", Kind::Obj(&[
+            req("hc-fmri", Kind::Str),
+            req("manufacturer", Kind::Str),
+            req("model", Kind::Str),
+            opt("serial-number", Kind::Str),
+            req("leds", Kind::List(LED)),
+            opt("sensors", Kind::List(SENSOR)),
+        ])),
+        enrichment: "system identity, chassis LEDs and sensor thresholds",
+    },
+    Section {
+        field: req("pci-devices", Kind::List(&[
+            req("hc-fmri", Kind::Str),
+            req("label", Kind::Str),
+            req("pci-vendor-name", Kind::Str),
+            req("pci-device-name", Kind::Str),
+            req("pci-subsystem-name", Kind::Str),
+            req("device-path", Kind::Str),
+        ])),
+        enrichment: "PCIe device identity and slot labels",
+    },
+    Section {
+        field: req("drive-bays", Kind::List(&[
+            req("hc-fmri", Kind::Str),
+            req("label", Kind::Str),
+            opt("disk", Kind::Obj(DISK)),
+            req("leds", Kind::List(LED)),
+        ])),
+        enrichment: "disk identity, bay labels and LEDs, lookup by bay",
+    },
+    Section {
+        field: req("processors", Kind::List(&[
+            req("hc-fmri", Kind::Str),
+            req("label", Kind::Str),
+            req("processor-brand", Kind::Str),
+            opt("number-of-cores", Kind::Num),
+            opt("hardware-threads-per-core", Kind::Num),
+            req("leds", Kind::List(LED)),
+            opt("sensors", Kind::List(SENSOR)),
+        ])),
+        enrichment: "processor labels, identity, LEDs and sensor thresholds",
+    },
+    Section {
+        field: req("memory", Kind::List(&[
+            req("hc-fmri", Kind::Str),
+            req("label", Kind::Str),
+            opt("dimm", Kind::Obj(DIMM)),
+        ])),
+        enrichment: "DIMM slot labels, identity and LEDs",
+    },
+    Section {
+        field: opt("network-interfaces", Kind::List(&[
+            opt("hc-fmri", Kind::Str),
+            opt("label", Kind::Str),
+            opt("name", Kind::Str),
+            req("mac-address", Kind::Str),
+            opt("manufacturer", Kind::Str),
+            req("model", Kind::Str),
+            req("firmware-revision", Kind::Str),
+            req("device-path", Kind::Str),
+        ])),
+        enrichment: "NIC identity, MAC addresses and firmware",
+    },
+    Section {
+        field: opt("bios", Kind::Obj(&[
+            opt("vendor", Kind::Str),
+            req("version", Kind::Str),
+            opt("release-date", Kind::Str),
+        ])),
+        enrichment: "BIOS version in the report header",
+    },
+    Section {
+        field: opt("service-processor", Kind::Obj(&[
+            req("hc-fmri", Kind::Str),
+            req("firmware-revision", Kind::Str),
+            req("mac-address", Kind::Str),
+            req("ipv4-address", Kind::Str),
+            req("ipv4-subnet", Kind::Str),
+            req("ipv4-gateway", Kind::Str),
+            req("ipv4-config-type", Kind::Str),
+        ])),
+        enrichment: "service processor details",
+    },
+    Section {
+        field: req("power-supplies", Kind::List(&[
+            req("hc-fmri", Kind::Str),
+            req("label", Kind::Str),
+            req("manufacturer", Kind::Str),
+            req("model", Kind::Str),
+            req("firmware-revision", Kind::Str),
+            req("leds", Kind::List(LED)),
+            opt("sensors", Kind::List(SENSOR)),
+        ])),
+        enrichment: "PSU labels, LEDs, sensor thresholds and SEL attribution",
+    },
+    Section {
+        field: req("fans", Kind::List(&[
+            req("hc-fmri", Kind::Str),
+            req("label", Kind::Str),
+            req("leds", Kind::List(LED)),
+            opt("sensors", Kind::List(SENSOR)),
+        ])),
+        enrichment: "fan labels, LEDs, sensor thresholds and SEL attribution",
+    },
+];
+
+//
+// Check the value against the field's kind, adding a description of each
+// problem found to "problems".
+//
+fn check_value(
+    path: &str,
+    kind: &Kind,
+    value: &Value,
+    problems: &mut Vec<String>
+) {
+    match (kind, value) {
+        (Kind::Str, Value::String(_)) | (Kind::Num, Value::Number(_)) => (),
+        (Kind::Obj(fields), Value::Object(_)) => {
+            check_fields(path, fields, value, problems);
+        }
+        (Kind::List(fields), Value::Array(elements)) => {
+            for (i, element) in elements.iter().enumerate() {
+                let elpath = format!("{}[{}]", path, i);
+                match element {
+                    Value::Object(_) => {
+                        check_fields(&elpath, fields, element, problems);
+                    }
+                    _ => problems.push(format!("{} is not an object", elpath)),
+                }
+            }
+        }
+        (kind, _) => {
+            let expected = match kind {
+                Kind::Str => "a string",
+                Kind::Num => "a number",
+                Kind::Obj(_) => "an object",
+                Kind::List(_) => "an array",
+            };
+            problems.push(format!("{} is not {}", path, expected));
+        }
+    }
+}
+
+fn check_fields(
+    path: &str,
+    fields: &[Field],
+    value: &Value,
+    problems: &mut Vec<String>
+) {
+    for field in fields {
+        let fpath = format!("{}.{}", path, field.name);
+        match value.get(field.name) {
+            // An optional field may be null as well as absent.
+            None | Some(Value::Null) if !field.required => (),
+            None => problems.push(format!("{} is missing", fpath)),
+            Some(v) => check_value(&fpath, &field.kind, v, problems),
+        }
+    }
+}
+
+//
+// Check the hwgrok file at the given path, writing out the results.  Returns
+// true if the file can be loaded.
+//
+pub fn check_hwgrok(
+    out: &mut dyn Write,
+    path: &str
+) -> Result<bool, Box<dyn Error>> {
+
+    let contents = input::read_to_string(path)?;
+    let hwgrok: Value = match serde_json::from_str(&contents) {
+        Ok(v) => v,
+        Err(e) => {
+            writeln!(out, "{}: invalid JSON: {}", path, e)?;
+            writeln!(out, "No hardware enrichment will be available.")?;
+            return Ok(false);
+        }
+    };
+    if !hwgrok.is_object() {
+        writeln!(out, "{}: not a JSON object", path)?;
+        writeln!(out, "No hardware enrichment will be available.")?;
+        return Ok(false);
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "hwgrok Schema Check: {}\n", path)?;
+    let headings = [
+        ("section", "entries", "status", "enrichment"),
+        ("-------", "-------", "------", "----------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <18} {1: <8} {2: <10} {3}", h.0, h.1, h.2, h.3)?;
+    }
+
+    let mut all_problems = Vec::new();
+    for section in SECTIONS {
+        let field = &section.field;
+        let value = hwgrok.get(field.name);
+        let mut problems = Vec::new();
+        let (entries, status) = match value {
+            None | Some(Value::Null) if !field.required => {
+                ("-".to_string(), "absent")
+            }
+            None => {
+                problems.push(format!("{} is missing", field.name));
+                ("-".to_string(), "MISSING")
+            }
+            Some(v) => {
+                check_value(field.name, &field.kind, v, &mut problems);
+                let entries = match v {
+                    Value::Array(a) => a.len(),
+                    _ => 1,
+                };
+                //
+                // An empty section loads, but provides no enrichment.
+                //
+                (entries.to_string(), if !problems.is_empty() {
+                    "INVALID"
+                } else if entries == 0 {
+                    "empty"
+                } else {
+                    "ok"
+                })
+            }
+        };
+        writeln!(out, "{0: <18} {1: <8} {2: <10} {3}", field.name, entries,
+            status, section.enrichment)?;
+        all_problems.extend(problems.into_iter()
+            .map(|p| (p, section.enrichment)));
+    }
+    writeln!(out)?;
+
+    if all_problems.is_empty() {
+        writeln!(out, "The file can be loaded, and the above enrichment is \
+            available for the sections which aren't empty or absent.")?;
+        return Ok(true);
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "Problems\n")?;
+    for (problem, enrichment) in &all_problems {
+        writeln!(out, "{}\n    (needed for {})", problem, enrichment)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "The file can't be loaded as it is, so no hardware \
+        enrichment will be available.")?;
+
+    Ok(false)
+}
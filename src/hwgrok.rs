@@ -4,6 +4,10 @@
 extern crate serde;
 use serde::Deserialize;
 
+use devpath::{self, DeviceKey};
+use fmri;
+use FmLogError;
+
 //
 // The following structures are used to hold a partial deserialization of the
 // JSON output from hwgrok (https://github.com/joyent/hwgrok)
@@ -22,6 +26,9 @@ pub struct HwGrok {
     #[serde(rename = "power-supplies")]
     pub psus: Vec<HwGrokPSU>,
     pub fans: Vec<HwGrokFan>,
+    pub bios: Option<HwGrokBios>,
+    #[serde(rename = "network-interfaces", default)]
+    pub nics: Vec<HwGrokNetworkInterfaces>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -50,8 +57,22 @@ pub struct HwGrokChassis {
     pub chassis_manufacturer: String,
     #[serde(rename = "model")]
     pub chassis_model: String,
+    #[serde(rename = "serial-number", default)]
+    pub chassis_serial: String,
     #[serde(rename = "leds")]
     pub chassis_leds: Vec<HwGrokLED>,
+    #[serde(rename = "sensors", default)]
+    pub chassis_sensors: Vec<HwGrokSensor>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HwGrokBios {
+    #[serde(rename = "vendor", default)]
+    pub bios_vendor: String,
+    #[serde(rename = "version")]
+    pub bios_version: String,
+    #[serde(rename = "release-date", default)]
+    pub bios_date: String,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -62,8 +83,14 @@ pub struct HwGrokProcessors {
     pub chip_label: String,
     #[serde(rename = "processor-brand")]
     pub chip_brand: String,
+    #[serde(rename = "number-of-cores")]
+    pub chip_cores: Option<u64>,
+    #[serde(rename = "hardware-threads-per-core")]
+    pub chip_threads_per_core: Option<u64>,
     #[serde(rename = "leds")]
     pub chip_leds: Vec<HwGrokLED>,
+    #[serde(rename = "sensors", default)]
+    pub chip_sensors: Vec<HwGrokSensor>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -83,6 +110,8 @@ pub struct HwGrokDimm {
     pub dimm_manufacturer: String,
     #[serde(rename = "part-number")]
     pub dimm_part: String,
+    #[serde(rename = "serial-number", default)]
+    pub dimm_serial: String,
     #[serde(rename = "type")]
     pub dimm_type: String,
     #[serde(rename = "size-in-bytes")]
@@ -107,6 +136,26 @@ pub struct HwGrokPciDevices {
     pub pci_device_path: String,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct HwGrokNetworkInterfaces {
+    #[serde(rename = "hc-fmri", default)]
+    pub nic_fmri: String,
+    #[serde(rename = "label", default)]
+    pub nic_label: String,
+    #[serde(rename = "name", default)]
+    pub nic_name: String,
+    #[serde(rename = "mac-address")]
+    pub nic_mac_addr: String,
+    #[serde(rename = "manufacturer", default)]
+    pub nic_manufacturer: String,
+    #[serde(rename = "model")]
+    pub nic_model: String,
+    #[serde(rename = "firmware-revision")]
+    pub nic_firmware_rev: String,
+    #[serde(rename = "device-path")]
+    pub nic_device_path: String,
+}
+
 #[derive(Debug, Default, Deserialize)]
 pub struct HwGrokDriveBay {
     #[serde(rename = "hc-fmri")]
@@ -137,6 +186,30 @@ pub struct HwGrokDisk {
     pub disk_size: u64,
     #[serde(rename = "speed-in-rpm")]
     pub disk_rpm: Option<u64>,
+    #[serde(rename = "devid", default)]
+    pub disk_devid: String,
+    // the key of the device the disk was matched to (see enrich.rs)
+    #[serde(skip)]
+    pub disk_key: Option<DeviceKey>,
+    // set if the disk wasn't matched to a device, and its device path is now
+    // another disk's
+    #[serde(skip)]
+    pub disk_displaced: bool,
+}
+
+impl HwGrokDisk {
+    //
+    // Returns the device hash key of the disk: that of the device it was
+    // matched to, or else its canonical device path, unless that's now
+    // another disk's.
+    //
+    pub fn devpath(&self) -> Option<DeviceKey> {
+        match &self.disk_key {
+            Some(key) => Some(key.clone()),
+            None if self.disk_displaced => None,
+            None => Some(devpath::canonicalize(&self.disk_device_path)),
+        }
+    }
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -153,6 +226,8 @@ pub struct HwGrokPSU {
     pub psu_firmware_rev: String,
     #[serde(rename = "leds")]
     pub psu_leds: Vec<HwGrokLED>,
+    #[serde(rename = "sensors", default)]
+    pub psu_sensors: Vec<HwGrokSensor>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -163,6 +238,28 @@ pub struct HwGrokFan {
     pub fan_label: String,
     #[serde(rename = "leds")]
     pub fan_leds: Vec<HwGrokLED>,
+    #[serde(rename = "sensors", default)]
+    pub fan_sensors: Vec<HwGrokSensor>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HwGrokSensor {
+    #[serde(rename = "name")]
+    pub sensor_name: String,
+    #[serde(rename = "type")]
+    pub sensor_type: String,
+    #[serde(rename = "reading")]
+    pub sensor_reading: Option<f64>,
+    #[serde(rename = "units")]
+    pub sensor_units: Option<String>,
+    #[serde(rename = "threshold-upper-non-critical")]
+    pub sensor_upper_warning: Option<f64>,
+    #[serde(rename = "threshold-upper-critical")]
+    pub sensor_upper_critical: Option<f64>,
+    #[serde(rename = "threshold-lower-non-critical")]
+    pub sensor_lower_warning: Option<f64>,
+    #[serde(rename = "threshold-lower-critical")]
+    pub sensor_lower_critical: Option<f64>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -172,3 +269,177 @@ pub struct HwGrokLED {
     #[serde(rename = "mode")]
     pub led_mode: String,
 }
+
+//
+// Parse the output of hwgrok.  Malformed input results in an error, never a
+// panic.
+//
+pub fn parse_hwgrok(contents: &str) -> Result<HwGrok, FmLogError> {
+    serde_json::from_str(contents)
+        .map_err(|e| FmLogError::HwgrokParse { path: None, source: e })
+}
+
+//
+// Returns true if the hc-scheme FMRI or device key names the component with
+// the given hwgrok FMRI, or one within it.  hwgrok's FMRIs have an authority,
+// which device keys don't, so both are compared without it.
+//
+pub fn within(key: &str, fmri: &str) -> bool {
+    if !key.starts_with("hc://") || fmri.is_empty() {
+        return false;
+    }
+    let key = fmri::from_str(key);
+    let prefix = fmri::from_str(fmri);
+    key == prefix || key.starts_with(&format!("{}/", prefix))
+}
+
+impl HwGrok {
+    //
+    // Find the drive bay, and the disk within it, which is the device with
+    // the given canonical device path.
+    //
+    pub fn find_disk(&self, devpath: &str)
+        -> Option<(&HwGrokDriveBay, &HwGrokDisk)> {
+
+        for drive_bay in &self.drive_bays {
+            if let Some(disk) = &drive_bay.bay_disk {
+                if disk.devpath().is_some_and(|k| k == devpath) {
+                    return Some((drive_bay, disk));
+                }
+            }
+        }
+        None
+    }
+
+    //
+    // Returns the device hash key of the PCI device or disk with the given
+    // hc-scheme FMRI, or of the disk in the drive bay it names, for ereports
+    // whose detector gave no device path.
+    //
+    pub fn find_devpath(&self, fmri: &str) -> Option<DeviceKey> {
+        let key = fmri::from_str(fmri);
+        let matches = |f: &str| !f.is_empty() && fmri::from_str(f) == key;
+        for bay in &self.drive_bays {
+            if let Some(disk) = &bay.bay_disk {
+                if matches(&bay.bay_fmri) || matches(&disk.disk_fmri) {
+                    return disk.devpath();
+                }
+            }
+        }
+        self.pci_devices.iter()
+            .find(|p| matches(&p.pci_fmri) && !p.pci_device_path.is_empty())
+            .map(|p| devpath::canonicalize(&p.pci_device_path))
+    }
+
+    //
+    // Find the DIMM slot named by the given hc-scheme FMRI or device key, or
+    // holding the component it names: memory ereports are generally detected
+    // by the DIMM, or by one of its ranks, rather than by the slot.
+    //
+    pub fn find_dimm(&self, key: &str) -> Option<&HwGrokDimmSlot> {
+        self.memory.iter().find(|slot| within(key, &slot.slot_fmri) ||
+            slot.dimm.as_ref().is_some_and(|d| within(key, &d.dimm_fmri)))
+    }
+
+    //
+    // Find the processor named by the given hc-scheme FMRI or device key, or
+    // holding the core, strand or memory controller it names, so that the
+    // errors of a core can be attributed to the socket to be replaced.
+    //
+    pub fn find_processor(&self, key: &str) -> Option<&HwGrokProcessors> {
+        self.processors.iter().find(|chip| within(key, &chip.chip_fmri))
+    }
+
+    //
+    // Find the network interface whose device path matches the given
+    // canonical device path.
+    //
+    pub fn find_nic(&self, devpath: &str) -> Option<&HwGrokNetworkInterfaces> {
+        self.nics.iter()
+            .find(|n| devpath::canonicalize(&n.nic_device_path) == devpath)
+    }
+
+    //
+    // Returns the label of the processor, PSU, fan or drive bay with the
+    // given FMRI, or of the DIMM slot or processor it names or lies within.
+    //
+    pub fn component_label(&self, fmri: &str) -> Option<&str> {
+        let chips = self.processors.iter()
+            .map(|c| (&c.chip_fmri, &c.chip_label));
+        let psus = self.psus.iter().map(|p| (&p.psu_fmri, &p.psu_label));
+        let fans = self.fans.iter().map(|f| (&f.fan_fmri, &f.fan_label));
+        let bays = self.drive_bays.iter()
+            .map(|b| (&b.bay_fmri, &b.bay_label));
+        chips.chain(psus).chain(fans).chain(bays)
+            .find(|(f, _)| !f.is_empty() && fmri::from_str(f) == fmri)
+            .map(|(_, label)| label.as_str())
+            .or_else(|| self.find_dimm(fmri).map(|s| s.slot_label.as_str()))
+            .or_else(|| self.find_processor(fmri)
+                .map(|c| c.chip_label.as_str()))
+    }
+
+    //
+    // Returns the physical location label of the device or component with
+    // the given canonical path or FMRI: the drive bay of a disk, the slot of
+    // a PCI device or network interface, or the label of a processor, DIMM
+    // slot, PSU or fan.
+    //
+    pub fn location_label(&self, devpath: &str) -> Option<&str> {
+        if let Some((bay, _)) = self.find_disk(devpath) {
+            return Some(&bay.bay_label);
+        }
+        self.pci_devices.iter()
+            .find(|p| devpath::canonicalize(&p.pci_device_path) == devpath)
+            .map(|p| p.pci_label.as_str())
+            .filter(|label| !label.is_empty())
+            .or_else(|| self.find_nic(devpath).map(|n| n.nic_label.as_str()))
+            .or_else(|| self.component_label(devpath))
+            .filter(|label| !label.is_empty())
+    }
+
+    //
+    // Returns the (name, value) rows identifying the system as a whole, for
+    // the header of the report, so that each report of a fleet says which
+    // machine it's of.  Only those which hwgrok gave a value for are
+    // returned.
+    //
+    pub fn system_identity(&self) -> Vec<(&'static str, &str)> {
+        let bios = self.bios.as_ref();
+        let sp = self.sp.as_ref();
+        let rows = [
+            ("System Manufacturer", self.chassis.chassis_manufacturer.trim()),
+            ("System Product", self.chassis.chassis_model.trim()),
+            ("Chassis Serial", self.chassis.chassis_serial.trim()),
+            ("BIOS Version", bios.map_or("", |b| b.bios_version.trim())),
+            ("SP Firmware", sp.map_or("", |s| s.sp_fw_version.trim())),
+        ];
+        rows.iter().filter(|(_, value)| !value.is_empty()).cloned().collect()
+    }
+
+    //
+    // Returns the names by which a user might refer to the device at the
+    // given canonical path: the path itself and, for disks and DIMMs, the bay
+    // or slot label and serial number, or for network interfaces, the
+    // interface name and MAC address.
+    //
+    pub fn identifiers<'a>(&'a self, devpath: &'a str) -> Vec<&'a str> {
+        let mut ids = vec![devpath];
+        if let Some((bay, disk)) = self.find_disk(devpath) {
+            ids.push(&bay.bay_label);
+            ids.push(disk.disk_serial_number.trim());
+        } else if let Some(slot) = self.find_dimm(devpath) {
+            ids.push(&slot.slot_label);
+            if let Some(dimm) = &slot.dimm {
+                if !dimm.dimm_serial.trim().is_empty() {
+                    ids.push(dimm.dimm_serial.trim());
+                }
+            }
+        } else if let Some(nic) = self.find_nic(devpath) {
+            ids.extend([nic.nic_name.as_str(), nic.nic_mac_addr.as_str()]
+                .iter()
+                .map(|id| id.trim())
+                .filter(|id| !id.is_empty()));
+        }
+        ids
+    }
+}
@@ -0,0 +1,280 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --hwgrok-snapshot, which takes hwgrok output captured at several
+// times, each given as "<time>=<file>", and finds the hardware changes between
+// consecutive snapshots:
+//
+//   - a disk inserted in, removed from or replaced in a drive bay, or its
+//     firmware changed
+//   - a DIMM installed in, removed from or replaced in a slot
+//   - a network interface or PCI device added or removed, or a network
+//     interface's firmware changed
+//   - the BIOS version changed
+//
+// A change is dated by the first snapshot it was seen in, and is listed in
+// the "Hardware Changes" section.  It's also marked on the timeline of the
+// devices it concerns, so that a change in a device's error rate can be
+// lined up with, say, its replacement or a firmware upgrade.
+//
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::io::Write;
+use std::str::FromStr;
+
+use devpath::{self, DeviceKey};
+use hwgrok::{self, HwGrok, HwGrokDisk, HwGrokDriveBay,
+    HwGrokNetworkInterfaces, HwGrokPciDevices};
+use i18n::tr;
+use {get_event_timestamp, parse_timestamp, SimpleError};
+
+#[derive(Debug)]
+pub struct HwSnapshot {
+    pub time: i64,
+    pub path: String,
+}
+
+impl FromStr for HwSnapshot {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<HwSnapshot, Box<dyn Error>> {
+        let invalid = || Box::new(SimpleError(
+            format!("invalid --hwgrok-snapshot (expected TIME=FILE): {}", s)));
+        let (time, path) = s.split_once('=').ok_or_else(invalid)?;
+        let time = parse_timestamp(time).ok_or_else(invalid)?;
+        if path.is_empty() {
+            return Err(invalid());
+        }
+        Ok(HwSnapshot { time, path: path.to_string() })
+    }
+}
+
+#[derive(Debug)]
+pub struct HwChange {
+    time: i64,
+    location: String,
+    description: String,
+    // the device paths or FMRIs of the components concerned
+    keys: Vec<DeviceKey>,
+}
+
+#[derive(Debug, Default)]
+pub struct HwHistory {
+    changes: Vec<HwChange>,
+}
+
+//
+// Returns a description of a component for the changes, by its serial number
+// if it has one.
+//
+fn serial(serial: &str) -> String {
+    match serial.trim() {
+        "" => String::new(),
+        s => format!(" (serial {})", s),
+    }
+}
+
+//
+// Compare a pair of snapshots, adding the changes from the first to the
+// second, dated at the second.
+//
+fn compare(
+    time: i64,
+    prev: &HwGrok,
+    next: &HwGrok,
+    changes: &mut Vec<HwChange>
+) {
+    let mut change = |location: &str, description: String,
+        keys: Vec<DeviceKey>| {
+        changes.push(HwChange {
+            time,
+            location: location.to_string(),
+            description,
+            keys,
+        });
+    };
+
+    let same_bay = |a: &HwGrokDriveBay, b: &HwGrokDriveBay|
+        a.bay_fmri == b.bay_fmri && a.bay_label == b.bay_label;
+    let path = |d: &HwGrokDisk| devpath::canonicalize(&d.disk_device_path);
+    for bay in &next.drive_bays {
+        let old = prev.drive_bays.iter()
+            .find(|b| same_bay(b, bay))
+            .and_then(|b| b.bay_disk.as_ref());
+        match (old, &bay.bay_disk) {
+            (None, Some(new)) => change(&bay.bay_label,
+                format!("disk inserted{}", serial(&new.disk_serial_number)),
+                vec![path(new)]),
+            (Some(old), None) => change(&bay.bay_label,
+                format!("disk removed{}", serial(&old.disk_serial_number)),
+                vec![path(old)]),
+            (Some(old), Some(new)) if old.disk_serial_number.trim() !=
+                new.disk_serial_number.trim() => change(&bay.bay_label,
+                format!("disk replaced (serial {} -> {})",
+                    old.disk_serial_number.trim(),
+                    new.disk_serial_number.trim()),
+                vec![path(old), path(new)]),
+            (Some(old), Some(new)) if old.disk_firmware_rev.trim() !=
+                new.disk_firmware_rev.trim() => change(&bay.bay_label,
+                format!("disk firmware {} -> {}", old.disk_firmware_rev.trim(),
+                    new.disk_firmware_rev.trim()),
+                vec![path(new)]),
+            _ => (),
+        }
+    }
+    for bay in &prev.drive_bays {
+        let gone = !next.drive_bays.iter().any(|b| same_bay(b, bay));
+        if let (true, Some(old)) = (gone, &bay.bay_disk) {
+            change(&bay.bay_label,
+                format!("disk removed{}", serial(&old.disk_serial_number)),
+                vec![path(old)]);
+        }
+    }
+
+    for slot in &next.memory {
+        let old = prev.memory.iter()
+            .find(|s| s.slot_fmri == slot.slot_fmri)
+            .and_then(|s| s.dimm.as_ref());
+        let keys = vec![DeviceKey::from_fmri(&slot.slot_fmri)];
+        match (old, &slot.dimm) {
+            (None, Some(new)) => change(&slot.slot_label,
+                format!("DIMM installed{}", serial(&new.dimm_serial)), keys),
+            (Some(old), None) => change(&slot.slot_label,
+                format!("DIMM removed{}", serial(&old.dimm_serial)), keys),
+            (Some(old), Some(new)) if old.dimm_serial.trim() !=
+                new.dimm_serial.trim() => change(&slot.slot_label,
+                format!("DIMM replaced (serial {} -> {})",
+                    old.dimm_serial.trim(), new.dimm_serial.trim()), keys),
+            _ => (),
+        }
+    }
+
+    let nic_key = |n: &HwGrokNetworkInterfaces|
+        devpath::canonicalize(&n.nic_device_path);
+    let nic_label = |n: &HwGrokNetworkInterfaces| if n.nic_name.is_empty() {
+        n.nic_label.clone()
+    } else {
+        n.nic_name.clone()
+    };
+    for nic in &next.nics {
+        let label = &nic_label(nic);
+        match prev.nics.iter().find(|n| nic_key(n) == nic_key(nic)) {
+            None => change(label, format!("network interface added ({})",
+                nic.nic_model.trim()), vec![nic_key(nic)]),
+            Some(old) if old.nic_firmware_rev.trim() !=
+                nic.nic_firmware_rev.trim() => change(label,
+                format!("network interface firmware {} -> {}",
+                    old.nic_firmware_rev.trim(), nic.nic_firmware_rev.trim()),
+                vec![nic_key(nic)]),
+            _ => (),
+        }
+    }
+    for nic in prev.nics.iter()
+        .filter(|old| !next.nics.iter().any(|n| nic_key(n) == nic_key(old))) {
+        change(&nic_label(nic), format!("network interface removed ({})",
+            nic.nic_model.trim()), vec![nic_key(nic)]);
+    }
+
+    let pci_key = |p: &HwGrokPciDevices|
+        devpath::canonicalize(&p.pci_device_path);
+    for (from, to, what) in [(prev, next, "removed"), (next, prev, "added")] {
+        for pci in from.pci_devices.iter().filter(|p|
+            !to.pci_devices.iter().any(|q| pci_key(q) == pci_key(p))) {
+            change(&pci.pci_label, format!("PCI device {} ({})", what,
+                pci.pci_device_name.trim()), vec![pci_key(pci)]);
+        }
+    }
+
+    let bios = |h: &HwGrok| h.bios.as_ref()
+        .map_or(String::new(), |b| b.bios_version.trim().to_string());
+    if bios(prev) != bios(next) {
+        change("system", format!("BIOS {} -> {}", bios(prev), bios(next)),
+            Vec::new());
+    }
+}
+
+impl HwChange {
+    //
+    // Returns true if the change concerns the device with the given key.
+    //
+    fn concerns(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| !k.is_empty() &&
+            (*k == key || hwgrok::within(key, k)))
+    }
+}
+
+impl HwHistory {
+    //
+    // Find the changes between each of the snapshots and the next.
+    //
+    pub fn from_snapshots(mut snapshots: Vec<(i64, HwGrok)>) -> HwHistory {
+        snapshots.sort_by_key(|(time, _)| *time);
+        let mut changes = Vec::new();
+        for pair in snapshots.windows(2) {
+            let ((_, prev), (time, next)) = (&pair[0], &pair[1]);
+            let start = changes.len();
+            compare(*time, prev, next, &mut changes);
+            changes[start..].sort_by(|a, b| a.location.cmp(&b.location));
+        }
+        HwHistory { changes }
+    }
+
+    //
+    // Returns the days on which the device with the given key changed.
+    //
+    pub fn days(&self, key: &str) -> BTreeSet<String> {
+        self.changes.iter()
+            .filter(|c| c.concerns(key))
+            .map(|c| get_event_timestamp(c.time))
+            .collect()
+    }
+
+    //
+    // Returns the annotation for a day in a device's timeline, if the device
+    // changed on that day.
+    //
+    pub fn day_marker(&self, key: &str, day: &str) -> String {
+        let changes: Vec<&str> = self.changes.iter()
+            .filter(|c| c.concerns(key) && get_event_timestamp(c.time) == day)
+            .map(|c| c.description.as_str())
+            .collect();
+        if changes.is_empty() {
+            String::new()
+        } else {
+            format!("<- {}", changes.join(", "))
+        }
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        if self.changes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{}\n", tr("Hardware Changes"))?;
+        let headings = [
+            ("date", "location", "change"),
+            ("----", "--------", "------"),
+        ];
+        for h in &headings {
+            writeln!(out, "{0: <12} {1: <16} {2}", h.0, h.1, h.2)?;
+        }
+        for change in &self.changes {
+            let location = if change.location.is_empty() {
+                "-"
+            } else {
+                &change.location
+            };
+            writeln!(out, "{0: <12} {1: <16} {2}",
+                get_event_timestamp(change.time), location,
+                change.description)?;
+        }
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Translation of the report's section titles and field labels, so that
+// reports handed to datacenter providers who don't read English have headers
+// in their language.  The data itself - device paths, ereport classes, dates
+// and so on - is never translated.  The language is chosen with --lang, and
+// catalogs for German ("de") and French ("fr") are built in.  A catalog for
+// another language, or corrections to a built-in one, can be given in the
+// configuration file, with each English message (exactly as it appears in
+// the report, including any trailing colon) mapped to its translation:
+//
+//   [messages.nl]
+//   Device Path: = Apparaatpad:
+//   Total ereports: = Totaal ereports:
+//
+// Messages without a translation are shown in English.
+//
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::OnceLock;
+
+use conf::ConfFile;
+use SimpleError;
+
+const DE: &[(&str, &str)] = &[
+    ("Age/Warranty", "Alter/Garantie"),
+    ("Age/Warranty:", "Alter/Garantie:"),
+    ("Also Reachable Via:", "Auch erreichbar über:"),
+    ("Analysis", "Analyse"),
+    ("ASRU:", "ASRU:"),
+    ("Bank Overflows:", "Bank-Überläufe:"),
+    ("BIOS Version:", "BIOS-Version:"),
+    ("Case UUID:", "Fall-UUID:"),
+    ("Case Updates", "Fallaktualisierungen"),
+    ("Certainty:", "Sicherheit:"),
+    ("Chassis Serial:", "Seriennummer des Gehäuses:"),
+    ("Class Co-occurrence (within {}s)",
+        "Gemeinsames Auftreten von Klassen (innerhalb von {}s)"),
+    ("Component Label:", "Komponentenbezeichnung:"),
+    ("Contents", "Inhalt"),
+    ("Contributing Ereports:", "Beitragende Ereports:"),
+    ("Cores", "Kerne"),
+    ("Cores:", "Kerne:"),
+    ("Correctable errors:", "Korrigierbare Fehler:"),
+    ("Coverage:", "Zeitraum:"),
+    ("CPU/Memory Location:", "CPU-/Speicherposition:"),
+    ("Device Alias", "Gerätealias"),
+    ("Device Alias:", "Gerätealias:"),
+    ("Device Name", "Gerätename"),
+    ("Device Name:", "Gerätename:"),
+    ("Device Path", "Gerätepfad"),
+    ("Device Path:", "Gerätepfad:"),
+    ("Device:", "Gerät:"),
+    ("Devices", "Geräte"),
+    ("Devices By Owner", "Geräte nach Eigentümer"),
+    ("Diagnosed Cases", "Diagnostizierte Fälle"),
+    ("Diagnosed:", "Diagnostiziert:"),
+    ("DIMM Manufacturer", "DIMM-Hersteller"),
+    ("DIMM Manufacturer:", "DIMM-Hersteller:"),
+    ("DIMM Part Number", "DIMM-Teilenummer"),
+    ("DIMM Part Number:", "DIMM-Teilenummer:"),
+    ("DIMM Serial", "Seriennummer des DIMM"),
+    ("DIMM Serial:", "Seriennummer des DIMM:"),
+    ("DIMM Size", "DIMM-Größe"),
+    ("DIMM Slot:", "DIMM-Steckplatz:"),
+    ("Disk Failure Likelihood", "Ausfallwahrscheinlichkeit der Festplatten"),
+    ("Disk Location:", "Festplattenposition:"),
+    ("Disk Manufacturer", "Festplattenhersteller"),
+    ("Disk Manufacturer:", "Festplattenhersteller:"),
+    ("Disk Model", "Festplattenmodell"),
+    ("Disk Model:", "Festplattenmodell:"),
+    ("Disk Replaced:", "Festplatte ersetzt:"),
+    ("Disk Serial", "Seriennummer der Festplatte"),
+    ("Disk Serial:", "Seriennummer der Festplatte:"),
+    ("Disk Size", "Festplattengröße"),
+    ("Drive Bays Without Ereports:", "Laufwerksschächte ohne Ereports:"),
+    ("Driver", "Treiber"),
+    ("Driver:", "Treiber:"),
+    ("Enclosure Health", "Zustand der Gehäuse"),
+    ("Enclosure:", "Gehäuse:"),
+    ("Environmental", "Umgebung"),
+    ("Ereport Classes", "Ereport-Klassen"),
+    ("Ereports By Driver", "Ereports nach Treiber"),
+    ("Ereports Per TB:", "Ereports pro TB:"),
+    ("Error Rate Alerts", "Fehlerratenwarnungen"),
+    ("Error Rate By I/O Volume", "Fehlerrate nach E/A-Volumen"),
+    ("Event Occurrence Distribution", "Verteilung der Ereignisse"),
+    ("Event Timeline", "Zeitachse der Ereignisse"),
+    ("Expected Rate Exceedances", "Überschreitungen der erwarteten Raten"),
+    ("Fault Class:", "Fehlerklasse:"),
+    ("Faults", "Fehler"),
+    ("Faults Without A Reported Device", "Fehler ohne gemeldetes Gerät"),
+    ("Firmware Rev", "Firmware-Version"),
+    ("Firmware Rev:", "Firmware-Version:"),
+    ("First Seen:", "Zuerst gesehen:"),
+    ("FM Logs:", "FM-Protokolle:"),
+    ("FRU:", "FRU:"),
+    ("Full text", "Volltext"),
+    ("Hardware Changes", "Hardwareänderungen"),
+    ("Healthy Inventory", "Fehlerfreies Inventar"),
+    ("I/O Transferred:", "Übertragene E/A:"),
+    ("Indicator Summary", "Übersicht der Anzeigen"),
+    ("Informational Reports", "Informationsmeldungen"),
+    ("Interface", "Schnittstelle"),
+    ("Interface:", "Schnittstelle:"),
+    ("Last Seen:", "Zuletzt gesehen:"),
+    ("Location", "Position"),
+    ("MAC Address", "MAC-Adresse"),
+    ("MAC Address:", "MAC-Adresse:"),
+    ("Merged Path:", "Zusammengeführter Pfad:"),
+    ("Message ID:", "Meldungs-ID:"),
+    ("Most Recent Events", "Neueste Ereignisse"),
+    ("NIC Firmware", "NIC-Firmware"),
+    ("NIC Firmware:", "NIC-Firmware:"),
+    ("NIC Model", "NIC-Modell"),
+    ("NIC Model:", "NIC-Modell:"),
+    ("Open Case Reconciliation", "Abgleich offener Fälle"),
+    ("Other Events", "Sonstige Ereignisse"),
+    ("Owner", "Eigentümer"),
+    ("Owner:", "Eigentümer:"),
+    ("Panic Time:", "Panic-Zeitpunkt:"),
+    ("Panics", "Panics"),
+    ("Partial Report:", "Unvollständiger Bericht:"),
+    ("PCI Devices Without Ereports:", "PCI-Geräte ohne Ereports:"),
+    ("PCIe AER Errors", "PCIe-AER-Fehler"),
+    ("PCIe Errors By Port", "PCIe-Fehler nach Port"),
+    ("Period:", "Zeitraum:"),
+    ("Physical Location Index", "Index der physischen Positionen"),
+    ("Pool GUID:", "Pool-GUID:"),
+    ("Port:", "Port:"),
+    ("Processor Brand", "Prozessormodell"),
+    ("Processor Brand:", "Prozessormodell:"),
+    ("Processor Context Corrupt:", "Prozessorkontext beschädigt:"),
+    ("Processor Socket:", "Prozessorsockel:"),
+    ("Related Fault Cases", "Zugehörige Fehlerfälle"),
+    ("Replace Soon", "Bald ersetzen"),
+    ("Resource:", "Ressource:"),
+    ("SCSI Sense Data", "SCSI-Sense-Daten"),
+    ("Sensor Threshold Breaches", "Überschreitungen von Sensorgrenzwerten"),
+    ("Slot:", "Steckplatz:"),
+    ("SMART Attributes", "SMART-Attribute"),
+    ("SP Firmware:", "SP-Firmware:"),
+    ("Status", "Status"),
+    ("Status:", "Status:"),
+    ("Subsystem Name", "Subsystemname"),
+    ("Subsystem Name:", "Subsystemname:"),
+    ("Sysevents", "Systemereignisse"),
+    ("System Manufacturer:", "Systemhersteller:"),
+    ("System Product:", "Systemprodukt:"),
+    ("Temperature/Error Correlation", "Korrelation von Temperatur und Fehlern"),
+    ("Threads Per Core", "Threads pro Kern"),
+    ("Threads Per Core:", "Threads pro Kern:"),
+    ("Total ereports", "Ereports gesamt"),
+    ("Total ereports:", "Ereports gesamt:"),
+    ("Uncorrectable errors:", "Nicht korrigierbare Fehler:"),
+    ("Unknown Devices", "Unbekannte Geräte"),
+    ("Upsets", "Störungen"),
+    ("Vendor Name", "Herstellername"),
+    ("Vendor Name:", "Herstellername:"),
+    ("Virtual Devices", "Virtuelle Geräte"),
+    ("ZFS Pool:", "ZFS-Pool:"),
+    ("{} LED:", "{}-LED:"),
+];
+
+const FR: &[(&str, &str)] = &[
+    ("Age/Warranty", "Âge/Garantie"),
+    ("Age/Warranty:", "Âge/Garantie :"),
+    ("Also Reachable Via:", "Également accessible via :"),
+    ("Analysis", "Analyse"),
+    ("ASRU:", "ASRU :"),
+    ("Bank Overflows:", "Débordements de banc :"),
+    ("BIOS Version:", "Version du BIOS :"),
+    ("Case UUID:", "UUID du cas :"),
+    ("Case Updates", "Mises à jour des cas"),
+    ("Certainty:", "Certitude :"),
+    ("Chassis Serial:", "Numéro de série du châssis :"),
+    ("Class Co-occurrence (within {}s)",
+        "Cooccurrence des classes (à moins de {}s)"),
+    ("Component Label:", "Étiquette du composant :"),
+    ("Contents", "Sommaire"),
+    ("Contributing Ereports:", "Ereports contributifs :"),
+    ("Cores", "Cœurs"),
+    ("Cores:", "Cœurs :"),
+    ("Correctable errors:", "Erreurs corrigeables :"),
+    ("Coverage:", "Période couverte :"),
+    ("CPU/Memory Location:", "Emplacement CPU/mémoire :"),
+    ("Device Alias", "Alias du périphérique"),
+    ("Device Alias:", "Alias du périphérique :"),
+    ("Device Name", "Nom du périphérique"),
+    ("Device Name:", "Nom du périphérique :"),
+    ("Device Path", "Chemin du périphérique"),
+    ("Device Path:", "Chemin du périphérique :"),
+    ("Device:", "Périphérique :"),
+    ("Devices", "Périphériques"),
+    ("Devices By Owner", "Périphériques par propriétaire"),
+    ("Diagnosed Cases", "Cas diagnostiqués"),
+    ("Diagnosed:", "Diagnostiqué :"),
+    ("DIMM Manufacturer", "Fabricant de la barrette DIMM"),
+    ("DIMM Manufacturer:", "Fabricant de la barrette DIMM :"),
+    ("DIMM Part Number", "Référence de la barrette DIMM"),
+    ("DIMM Part Number:", "Référence de la barrette DIMM :"),
+    ("DIMM Serial", "Numéro de série de la barrette DIMM"),
+    ("DIMM Serial:", "Numéro de série de la barrette DIMM :"),
+    ("DIMM Size", "Taille de la barrette DIMM"),
+    ("DIMM Slot:", "Emplacement DIMM :"),
+    ("Disk Failure Likelihood", "Probabilité de défaillance des disques"),
+    ("Disk Location:", "Emplacement du disque :"),
+    ("Disk Manufacturer", "Fabricant du disque"),
+    ("Disk Manufacturer:", "Fabricant du disque :"),
+    ("Disk Model", "Modèle du disque"),
+    ("Disk Model:", "Modèle du disque :"),
+    ("Disk Replaced:", "Disque remplacé :"),
+    ("Disk Serial", "Numéro de série du disque"),
+    ("Disk Serial:", "Numéro de série du disque :"),
+    ("Disk Size", "Taille du disque"),
+    ("Drive Bays Without Ereports:", "Baies de disque sans ereports :"),
+    ("Driver", "Pilote"),
+    ("Driver:", "Pilote :"),
+    ("Enclosure Health", "État des boîtiers"),
+    ("Enclosure:", "Boîtier :"),
+    ("Environmental", "Environnement"),
+    ("Ereport Classes", "Classes d'ereports"),
+    ("Ereports By Driver", "Ereports par pilote"),
+    ("Ereports Per TB:", "Ereports par To :"),
+    ("Error Rate Alerts", "Alertes de taux d'erreur"),
+    ("Error Rate By I/O Volume", "Taux d'erreur par volume d'E/S"),
+    ("Event Occurrence Distribution", "Répartition des événements"),
+    ("Event Timeline", "Chronologie des événements"),
+    ("Expected Rate Exceedances", "Dépassements des taux attendus"),
+    ("Fault Class:", "Classe de panne :"),
+    ("Faults", "Pannes"),
+    ("Faults Without A Reported Device", "Pannes sans périphérique signalé"),
+    ("Firmware Rev", "Version du micrologiciel"),
+    ("Firmware Rev:", "Version du micrologiciel :"),
+    ("First Seen:", "Première occurrence :"),
+    ("FM Logs:", "Journaux FM :"),
+    ("FRU:", "FRU :"),
+    ("Full text", "Texte intégral"),
+    ("Hardware Changes", "Modifications du matériel"),
+    ("Healthy Inventory", "Inventaire sans erreurs"),
+    ("I/O Transferred:", "E/S transférées :"),
+    ("Indicator Summary", "Résumé des voyants"),
+    ("Informational Reports", "Rapports d'information"),
+    ("Interface", "Interface"),
+    ("Interface:", "Interface :"),
+    ("Last Seen:", "Dernière occurrence :"),
+    ("Location", "Emplacement"),
+    ("MAC Address", "Adresse MAC"),
+    ("MAC Address:", "Adresse MAC :"),
+    ("Merged Path:", "Chemin fusionné :"),
+    ("Message ID:", "ID du message :"),
+    ("Most Recent Events", "Événements les plus récents"),
+    ("NIC Firmware", "Micrologiciel de la carte réseau"),
+    ("NIC Firmware:", "Micrologiciel de la carte réseau :"),
+    ("NIC Model", "Modèle de la carte réseau"),
+    ("NIC Model:", "Modèle de la carte réseau :"),
+    ("Open Case Reconciliation", "Rapprochement des cas ouverts"),
+    ("Other Events", "Autres événements"),
+    ("Owner", "Propriétaire"),
+    ("Owner:", "Propriétaire :"),
+    ("Panic Time:", "Heure de la panique :"),
+    ("Panics", "Paniques"),
+    ("Partial Report:", "Rapport partiel :"),
+    ("PCI Devices Without Ereports:", "Périphériques PCI sans ereports :"),
+    ("PCIe AER Errors", "Erreurs PCIe AER"),
+    ("PCIe Errors By Port", "Erreurs PCIe par port"),
+    ("Period:", "Période :"),
+    ("Physical Location Index", "Index des emplacements physiques"),
+    ("Pool GUID:", "GUID du pool :"),
+    ("Port:", "Port :"),
+    ("Processor Brand", "Modèle du processeur"),
+    ("Processor Brand:", "Modèle du processeur :"),
+    ("Processor Context Corrupt:", "Contexte du processeur corrompu :"),
+    ("Processor Socket:", "Socket du processeur :"),
+    ("Related Fault Cases", "Cas de panne associés"),
+    ("Replace Soon", "À remplacer prochainement"),
+    ("Resource:", "Ressource :"),
+    ("SCSI Sense Data", "Données de sense SCSI"),
+    ("Sensor Threshold Breaches", "Dépassements de seuils des capteurs"),
+    ("Slot:", "Emplacement PCI :"),
+    ("SMART Attributes", "Attributs SMART"),
+    ("SP Firmware:", "Micrologiciel du SP :"),
+    ("Status", "État"),
+    ("Status:", "État :"),
+    ("Subsystem Name", "Nom du sous-système"),
+    ("Subsystem Name:", "Nom du sous-système :"),
+    ("Sysevents", "Événements système"),
+    ("System Manufacturer:", "Fabricant du système :"),
+    ("System Product:", "Produit du système :"),
+    ("Temperature/Error Correlation", "Corrélation température/erreurs"),
+    ("Threads Per Core", "Threads par cœur"),
+    ("Threads Per Core:", "Threads par cœur :"),
+    ("Total ereports", "Total des ereports"),
+    ("Total ereports:", "Total des ereports :"),
+    ("Uncorrectable errors:", "Erreurs non corrigeables :"),
+    ("Unknown Devices", "Périphériques inconnus"),
+    ("Upsets", "Perturbations"),
+    ("Vendor Name", "Nom du fabricant"),
+    ("Vendor Name:", "Nom du fabricant :"),
+    ("Virtual Devices", "Périphériques virtuels"),
+    ("ZFS Pool:", "Pool ZFS :"),
+    ("{} LED:", "Voyant {} :"),
+];
+
+// Messages may be written from anywhere in the report, so the catalog is
+// global.
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+//
+// Select the language of the report, from the built-in catalogs and any
+// [messages.<lang>] section of the configuration file.
+//
+pub fn set_lang(lang: &str, conf: &ConfFile) -> Result<(), Box<dyn Error>> {
+    let builtin: &[(&str, &str)] = match lang {
+        "en" => &[],
+        "de" => DE,
+        "fr" => FR,
+        _ => &[],
+    };
+    let section = conf.section(&format!("messages.{}", lang));
+    if lang != "en" && builtin.is_empty() && section.is_empty() {
+        return Err(Box::new(SimpleError(format!(
+            "no message catalog for language {}", lang))));
+    }
+    let catalog: HashMap<String, String> = builtin.iter()
+        .copied()
+        .chain(section)
+        .map(|(msg, translation)| (msg.to_string(), translation.to_string()))
+        .collect();
+    // A catalog is only ever selected once per run.
+    let _ = CATALOG.set(catalog);
+    Ok(())
+}
+
+//
+// Returns the translation of a message into the report's language.
+//
+pub fn tr(msg: &str) -> &str {
+    CATALOG.get()
+        .and_then(|catalog| catalog.get(msg))
+        .map(|t| t.as_str())
+        .unwrap_or(msg)
+}
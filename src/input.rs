@@ -0,0 +1,246 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Input adapters, which allow logs from sources other than fmdump to be
+// processed.  Each adapter converts the records it reads into events of the
+// same form as fmdump -AVj produces - a JSON object with a class, a detector
+// and a __tod - so that the rest of the tool needn't know where they came
+// from.
+//
+// Logs compressed with gzip or bzip2, such as rotated logs and archived
+// hwgrok snapshots, are recognized by their magic numbers and decompressed on
+// the fly, whatever their names.
+//
+use serde_json::Value;
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+
+use conf::ConfFile;
+use jsonmap::JsonMapping;
+use log;
+use mcelog;
+use preview::{self, Partial};
+use rasdaemon;
+use {FmLogError, SimpleError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputFormat {
+    // JSON events as produced by fmdump -AVj
+    Fmdump,
+    // A rasdaemon database, or the output of ras-mc-ctl --errors
+    Rasdaemon,
+    // mcelog text or JSON output
+    Mcelog,
+    // Arbitrary JSON events, adapted by the [mapping] configuration
+    Json,
+}
+
+impl FromStr for InputFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<InputFormat, Box<dyn Error>> {
+        match s {
+            "fmdump" => Ok(InputFormat::Fmdump),
+            "rasdaemon" => Ok(InputFormat::Rasdaemon),
+            "mcelog" => Ok(InputFormat::Mcelog),
+            "json" => Ok(InputFormat::Json),
+            _ => Err(Box::new(SimpleError(
+                format!("unsupported input format: {}", s)))),
+        }
+    }
+}
+
+pub type Lines = Box<dyn Iterator<Item = io::Result<String>>>;
+
+// The magic numbers of compressed files, and the programs which decompress
+// them.
+const DECOMPRESSORS: [(&[u8], &str); 2] = [
+    (&[0x1f, 0x8b], "gzip"),
+    (b"BZh", "bzip2"),
+];
+
+//
+// The output of a program, such as a decompressor reading a file.  Its
+// failure, such as on a truncated file, is reported as an error at the end of
+// the output, rather than passing for the end of the file.
+//
+struct ProgramOutput {
+    program: &'static str,
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for ProgramOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("{} failed: {}",
+                    self.program, status)));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for ProgramOutput {
+    fn drop(&mut self) {
+        // the output may not have been read to the end
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+//
+// Run the program with the given arguments and input, returning a reader of
+// its output.
+//
+pub fn run_program(program: &'static str, args: &[&str], stdin: Stdio)
+    -> Result<Box<dyn BufRead>, FmLogError> {
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| FmLogError::Other(Box::new(SimpleError(format!(
+            "failed to run {}: {}", program, e)))))?;
+    let stdout = child.stdout.take().unwrap();
+    Ok(Box::new(BufReader::new(ProgramOutput { program, child, stdout })))
+}
+
+//
+// Returns the program which decompresses the file, if it's compressed,
+// leaving the file at its start.
+//
+pub fn decompressor(file: &mut fs::File) -> io::Result<Option<&'static str>> {
+    let mut magic = Vec::new();
+    file.by_ref().take(3).read_to_end(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(DECOMPRESSORS.iter()
+        .find(|(m, _)| magic.starts_with(m))
+        .map(|(_, program)| *program))
+}
+
+//
+// Open the file at the given path for reading, decompressing it if it's
+// compressed.
+//
+pub fn open(path: &str) -> Result<Box<dyn BufRead>, FmLogError> {
+    let io_error = |e| FmLogError::Io { path: Some(path.to_string()),
+        source: e };
+    let mut file = fs::File::open(path).map_err(io_error)?;
+    let program = match decompressor(&mut file).map_err(io_error)? {
+        Some(program) => program,
+        None => return Ok(Box::new(BufReader::new(file))),
+    };
+    run_program(program, &["-dc"], Stdio::from(file))
+}
+
+//
+// Returns the contents of the file at the given path, decompressed if it's
+// compressed.
+//
+pub fn read_to_string(path: &str) -> Result<String, FmLogError> {
+    let mut contents = String::new();
+    open(path)?.read_to_string(&mut contents)
+        .map_err(|e| FmLogError::Io { path: Some(path.to_string()),
+            source: e })?;
+    Ok(contents)
+}
+
+#[derive(Debug)]
+pub struct InputReader {
+    format: InputFormat,
+    mapping: Option<JsonMapping>,
+}
+
+impl InputReader {
+    pub fn new(format: InputFormat, conf: &ConfFile)
+        -> Result<InputReader, Box<dyn Error>> {
+
+        let mapping = JsonMapping::from_conf(conf)?;
+        if format == InputFormat::Json && mapping.is_none() {
+            return Err(Box::new(SimpleError(
+                "the json input format requires a [mapping] section in the \
+                configuration file".to_string())));
+        }
+        Ok(InputReader { format, mapping })
+    }
+
+    //
+    // Returns the events in the log at the given path, one JSON object per
+    // line.
+    //
+    pub fn read_events(&self, path: &str) -> Result<Lines, Box<dyn Error>> {
+        match self.format {
+            InputFormat::Fmdump => Ok(Box::new(open(path)?.lines())),
+            InputFormat::Rasdaemon => {
+                let events = rasdaemon::read_events(path)?;
+                Ok(Box::new(events.into_iter().map(|e| Ok(e.to_string()))))
+            }
+            InputFormat::Mcelog => {
+                let events = mcelog::read_events(path)?;
+                Ok(Box::new(events.into_iter().map(|e| Ok(e.to_string()))))
+            }
+            InputFormat::Json => {
+                let contents = read_to_string(path)?;
+                let mapping = self.mapping.as_ref().unwrap();
+                let mut events = Vec::new();
+                for value in serde_json::Deserializer::from_str(&contents)
+                    .into_iter::<Value>() {
+                    let values = match value? {
+                        Value::Array(a) => a,
+                        v => vec![v],
+                    };
+                    for v in values {
+                        match mapping.map_event(v) {
+                            Some(ev) => events.push(ev),
+                            None => {
+                                log::warn("event has no class, device or \
+                                    timestamp - skipping", &[]);
+                            }
+                        }
+                    }
+                }
+                Ok(Box::new(events.into_iter().map(|e| Ok(e.to_string()))))
+            }
+        }
+    }
+
+    //
+    // Returns the events in the last bytes of the log at the given path, and
+    // how much of the log they cover if that's not all of it.  Only fmdump
+    // logs, with one event per line, can be read from the middle, and only
+    // if they aren't compressed.
+    //
+    pub fn read_tail(&self, path: &str, bytes: u64)
+        -> Result<(Lines, Option<Partial>), Box<dyn Error>> {
+
+        if self.format != InputFormat::Fmdump {
+            return Err(Box::new(SimpleError(
+                "--preview is only supported for fmdump input".to_string())));
+        }
+        let mut file = fs::File::open(path).map_err(|e| FmLogError::Io {
+            path: Some(path.to_string()),
+            source: e,
+        })?;
+        if let Some(program) = decompressor(&mut file)? {
+            return Err(Box::new(SimpleError(format!(
+                "--preview is not supported for logs compressed with {}",
+                program))));
+        }
+        let (reader, partial) = preview::open_tail(path, bytes)?;
+        Ok((Box::new(reader.lines()), partial))
+    }
+}
@@ -0,0 +1,207 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Comparison of the devices with ereports against the hardware inventory
+// given by hwgrok, in both directions:
+//
+//   - the "Healthy Inventory" section, written with --healthy-inventory,
+//     lists the drive bays and PCI devices described by hwgrok that have no
+//     ereports.  The report otherwise only shows the hardware with ereports,
+//     so this gives a view of the whole system, and a device which is known
+//     to have ereports but shows up here points to the matching of the
+//     ereports to the hwgrok data having failed.
+//
+//   - the "Unknown Devices" section lists the device paths with ereports
+//     which hwgrok doesn't know of, which points to hardware that has been
+//     removed since the ereports were logged, or again to a matching problem.
+//
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::io::Write;
+
+use devpath::{self, DeviceKey};
+use hwgrok::HwGrok;
+use i18n::tr;
+use {DeviceHash, DeviceHashEnt};
+
+//
+// Returns true if the device hash has an entry for the given device path, or
+// for one of the given hc-scheme FMRIs.
+//
+fn has_ereports(
+    device_hash: &DeviceHash,
+    path: Option<&DeviceKey>,
+    fmris: &[&str]
+) -> bool {
+    path.is_some_and(|p| !p.is_empty() && device_hash.contains_key(p)) ||
+        fmris.iter().any(|f| !f.is_empty() &&
+            device_hash.contains_key(&DeviceKey::from_fmri(f)))
+}
+
+//
+// Shorten a description to the width of its column.
+//
+fn clip(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(width - 3).collect();
+    format!("{}...", head)
+}
+
+pub fn write_healthy(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    if hwgrok.drive_bays.is_empty() && hwgrok.pci_devices.is_empty() {
+        return Ok(());
+    }
+
+    let mut rows: Vec<(&str, &str, String, String)> = Vec::new();
+    let mut nbays = 0;
+    for bay in &hwgrok.drive_bays {
+        let disk = match &bay.bay_disk {
+            Some(disk) => disk,
+            None => {
+                if !has_ereports(device_hash, None, &[&bay.bay_fmri]) {
+                    nbays += 1;
+                    rows.push(("bay", &bay.bay_label, "(empty)".to_string(),
+                        String::new()));
+                }
+                continue;
+            }
+        };
+        let fmris = [bay.bay_fmri.as_str(), disk.disk_fmri.as_str()];
+        if !has_ereports(device_hash, disk.devpath().as_ref(), &fmris) {
+            nbays += 1;
+            //
+            // The path shown is the one hwgrok gave, even if the disk was
+            // matched to a device elsewhere (see enrich.rs).
+            //
+            rows.push(("bay", &bay.bay_label, format!("{} {}",
+                disk.disk_model.trim(), disk.disk_serial_number.trim()),
+                devpath::canonicalize(&disk.disk_device_path).into()));
+        }
+    }
+    let mut npci = 0;
+    for pci in &hwgrok.pci_devices {
+        let path = devpath::canonicalize(&pci.pci_device_path);
+        if !has_ereports(device_hash, Some(&path), &[&pci.pci_fmri]) {
+            npci += 1;
+            rows.push(("pci", &pci.pci_label, format!("{} {}",
+                pci.pci_vendor_name.trim(), pci.pci_device_name.trim()),
+                path.into()));
+        }
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Healthy Inventory"))?;
+    writeln!(out, "{0: <40} {1} / {2}", tr("Drive Bays Without Ereports:"),
+        nbays, hwgrok.drive_bays.len())?;
+    writeln!(out, "{0: <40} {1} / {2}\n", tr("PCI Devices Without Ereports:"),
+        npci, hwgrok.pci_devices.len())?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let headings = [
+        ("type", "location", "description", "device path"),
+        ("----", "--------", "-----------", "-----------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <6} {1: <16} {2: <36} {3}", h.0, h.1, h.2, h.3)?;
+    }
+    for (kind, label, description, path) in rows {
+        let label = if label.is_empty() { "-" } else { label };
+        let path = if path.is_empty() { "-".to_string() } else { path };
+        writeln!(out, "{0: <6} {1: <16} {2: <36} {3}", kind, label,
+            clip(description.trim(), 36), path)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+//
+// Returns the device paths known to hwgrok: those of its disks (including any
+// other device a disk was matched to), PCI devices and network interfaces.
+//
+fn known_paths(hwgrok: &HwGrok) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    for disk in hwgrok.drive_bays.iter().filter_map(|b| b.bay_disk.as_ref()) {
+        paths.extend(disk.devpath().map(String::from));
+        paths.insert(devpath::canonicalize(&disk.disk_device_path).into());
+    }
+    paths.extend(hwgrok.pci_devices.iter()
+        .map(|p| devpath::canonicalize(&p.pci_device_path).into()));
+    paths.extend(hwgrok.nics.iter()
+        .map(|n| devpath::canonicalize(&n.nic_device_path).into()));
+    paths.remove("");
+    paths.remove("/");
+    paths
+}
+
+//
+// Returns true if the device path is known to hwgrok, or is the path of a
+// bus or bridge (such as a PCIe root port or an HBA's iport) leading to a
+// device it knows.
+//
+fn is_known(known: &BTreeSet<String>, path: &str) -> bool {
+    if known.contains(path) {
+        return true;
+    }
+    let prefix = format!("{}/", path);
+    known.range(prefix.clone()..).next()
+        .is_some_and(|k| k.starts_with(&prefix))
+}
+
+//
+// Write the device paths with ereports that hwgrok doesn't know of.  Only
+// /devices paths are considered, as logical device names (/dev/...) and
+// virtual devices can't be found in hwgrok anyway.
+//
+pub fn write_unknown(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let known = known_paths(hwgrok);
+    if known.is_empty() {
+        return Ok(());
+    }
+
+    let mut unknown: Vec<(&DeviceKey, &DeviceHashEnt)> = device_hash.iter()
+        .filter(|(path, _)| path.starts_with('/') &&
+            !path.starts_with("/dev/") && !devpath::is_virtual(path) &&
+            !is_known(&known, path))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort_by(|a, b| a.0.cmp(b.0));
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Unknown Devices"))?;
+    let headings = [
+        ("last seen", "ereports", "device path"),
+        ("---------", "--------", "-----------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <12} {1: <10} {2}", h.0, h.1, h.2)?;
+    }
+    for (path, devent) in unknown {
+        let last = devent.ereports_ts.last().map_or("-", |ts| ts.as_str());
+        writeln!(out, "{0: <12} {1: <10} {2}", last, devent.ereports.len(),
+            path)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
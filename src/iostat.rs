@@ -0,0 +1,162 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Normalization of error counts by I/O volume.  A disk that has transferred
+// hundreds of terabytes will naturally log more errors than one that sat idle,
+// so comparing raw counts unfairly singles out the busiest disks.  Given the
+// I/O volume per disk over the log window (e.g. from kstat or iostat), we
+// report errors per terabyte transferred.
+//
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+
+use devpath;
+use hwgrok::HwGrok;
+use i18n::tr;
+use {DeviceHash, DeviceHashEnt, SimpleError};
+
+const BYTES_PER_TB: f64 = 1e12;
+
+#[derive(Debug, Default)]
+pub struct IoStats {
+    // bytes transferred, hashed by device path, bay label or serial number
+    bytes: HashMap<String, u64>,
+}
+
+impl IoStats {
+    //
+    // The I/O statistics file is CSV with the columns:
+    //
+    //   device,bytes_read,bytes_written
+    //
+    // where the device is a device path, a bay label or a disk serial number.
+    // A header line is permitted.
+    //
+    pub fn from_file(path: &str) -> Result<IoStats, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut bytes = HashMap::new();
+
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            //
+            // Device paths may themselves contain commas, so split off the
+            // numeric fields from the right.
+            //
+            let mut fields: Vec<&str> = line.rsplitn(3, ',')
+                .map(|f| f.trim()).collect();
+            fields.reverse();
+            if fields.len() != 3 {
+                return Err(Box::new(SimpleError(format!(
+                    "{}:{}: expected 3 fields", path, lineno + 1))));
+            }
+            let (nread, nwritten) = match (fields[1].parse::<u64>(),
+                fields[2].parse::<u64>()) {
+                (Ok(r), Ok(w)) => (r, w),
+                _ if lineno == 0 => continue,
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "{}:{}: invalid byte count", path, lineno + 1))));
+                }
+            };
+            let device = if fields[0].starts_with('/') {
+                devpath::canonicalize(fields[0]).into()
+            } else {
+                fields[0].to_string()
+            };
+            *bytes.entry(device).or_insert(0) += nread + nwritten;
+        }
+
+        Ok(IoStats { bytes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn lookup(&self, devpath: &str, hwgrok: &HwGrok) -> Option<u64> {
+        hwgrok.identifiers(devpath).iter()
+            .find_map(|id| self.bytes.get(*id))
+            .cloned()
+    }
+}
+
+fn per_tb(nereports: usize, bytes: u64) -> Option<f64> {
+    if bytes == 0 {
+        None
+    } else {
+        Some(nereports as f64 / (bytes as f64 / BYTES_PER_TB))
+    }
+}
+
+//
+// Write the I/O volume and normalized error rate into a device's section.
+//
+pub fn write_device_io(
+    out: &mut dyn Write,
+    devpath: &str,
+    devent: &DeviceHashEnt,
+    iostats: &IoStats,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let bytes = match iostats.lookup(devpath, hwgrok) {
+        Some(b) => b,
+        None => return Ok(()),
+    };
+    writeln!(out, "{0: <40} {1:.2} TB", tr("I/O Transferred:"),
+        bytes as f64 / BYTES_PER_TB)?;
+    if let Some(rate) = per_tb(devent.ereports.len(), bytes) {
+        writeln!(out, "{0: <40} {1:.2}", tr("Ereports Per TB:"), rate)?;
+    }
+    Ok(())
+}
+
+//
+// Rank the devices by their errors per terabyte transferred.
+//
+pub fn write_io_ranking(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    iostats: &IoStats,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let mut rates: Vec<(&str, usize, u64, f64)> = device_hash.iter()
+        .filter_map(|(devpath, devent)| {
+            let bytes = iostats.lookup(devpath, hwgrok)?;
+            let rate = per_tb(devent.ereports.len(), bytes)?;
+            Some((devpath.as_str(), devent.ereports.len(), bytes, rate))
+        })
+        .collect();
+    if rates.is_empty() {
+        return Ok(());
+    }
+    rates.sort_by(|a, b| b.3.total_cmp(&a.3).then(a.0.cmp(b.0)));
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Error Rate By I/O Volume"))?;
+    let headings = [
+        ("ereports", "TB", "per TB", "device"),
+        ("--------", "--", "------", "------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <10} {1: <10} {2: <10} {3}", h.0, h.1, h.2, h.3)?;
+    }
+    for (devpath, nereports, bytes, rate) in rates {
+        writeln!(out, "{0: <10} {1: <10} {2: <10} {3}", nereports,
+            format!("{:.2}", bytes as f64 / BYTES_PER_TB),
+            format!("{:.2}", rate), devpath)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// A configurable mapping from arbitrary JSON events to the (class, detector,
+// timestamp) model used by the rest of the tool, so that telemetry from
+// sources other than FMA can be reported on.  The mapping is given in the
+// [mapping] section of the configuration file, as jq-like path expressions
+// locating each member within an event:
+//
+//   [mapping]
+//   class = .event.type
+//   device = .source.device // .source.host
+//   timestamp = .ts
+//   class-prefix = ereport.acme.
+//   timestamp-units = ms
+//
+// A path is a sequence of steps, each either a member name (.name or
+// ."quoted name") or an array index ([0]); "." on its own is the whole
+// event.  Alternative paths can be separated by "//", in which case the first
+// to locate a non-null value is used.
+//
+// The class is prefixed with class-prefix, if given; classes which then start
+// with "ereport." are aggregated by device just like ereports from FMA, and
+// others are tallied with the other events.  Devices which start with '/' are
+// treated as device paths, and others are named by an hc-scheme FMRI of the
+// form hc:///device=<name>.  Numeric timestamps are in the given units (s, ms,
+// us or ns; the default is s) since the epoch, and strings are parsed as for
+// the --since option.
+//
+use serde_json::Value;
+
+use std::error::Error;
+
+use conf::ConfFile;
+use parse_timestamp;
+use SimpleError;
+
+#[derive(Debug)]
+enum Step {
+    Member(String),
+    Index(usize),
+}
+
+//
+// A path expression: a list of alternative paths, each a list of steps.
+//
+#[derive(Debug)]
+pub struct PathExpr {
+    alternatives: Vec<Vec<Step>>,
+}
+
+fn parse_quoted(chars: &mut std::iter::Peekable<std::str::Chars>)
+    -> Result<String, String> {
+    // the opening quote has already been consumed
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some(c) => s.push(c),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(c) => s.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    let mut chars = path.trim().chars().peekable();
+    if chars.peek() != Some(&'.') {
+        return Err(format!("path must start with '.': {}", path.trim()));
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => match chars.peek() {
+                None | Some('[') => (),
+                Some('"') => {
+                    chars.next();
+                    steps.push(Step::Member(parse_quoted(&mut chars)?));
+                }
+                Some(_) => {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c == '.' || c == '[' {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                    steps.push(Step::Member(name));
+                }
+            },
+            '[' => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    steps.push(Step::Member(parse_quoted(&mut chars)?));
+                } else {
+                    let mut index = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c == ']' {
+                            break;
+                        }
+                        index.push(c);
+                        chars.next();
+                    }
+                    let index = index.trim().parse().map_err(|_|
+                        format!("invalid index: {}", index))?;
+                    steps.push(Step::Index(index));
+                }
+                if chars.next() != Some(']') {
+                    return Err(format!("expected ']' in {}", path.trim()));
+                }
+            }
+            c => return Err(format!("unexpected '{}' in {}", c, path.trim())),
+        }
+    }
+    Ok(steps)
+}
+
+impl PathExpr {
+    pub fn parse(expr: &str) -> Result<PathExpr, String> {
+        let alternatives = expr.split("//")
+            .map(parse_path)
+            .collect::<Result<_, _>>()?;
+        Ok(PathExpr { alternatives })
+    }
+
+    //
+    // Returns the value located by the first alternative path which locates
+    // a non-null value.
+    //
+    pub fn lookup<'a>(&self, event: &'a Value) -> Option<&'a Value> {
+        self.alternatives.iter().find_map(|steps| {
+            let mut value = event;
+            for step in steps {
+                value = match step {
+                    Step::Member(name) => value.get(name)?,
+                    Step::Index(idx) => value.get(idx)?,
+                };
+            }
+            if value.is_null() { None } else { Some(value) }
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct JsonMapping {
+    class: PathExpr,
+    device: PathExpr,
+    timestamp: PathExpr,
+    class_prefix: String,
+    // the number of timestamp units per second
+    units_per_sec: f64,
+}
+
+fn as_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+impl JsonMapping {
+    //
+    // Build the mapping from the [mapping] section of the configuration
+    // file, returning None if there isn't one.
+    //
+    pub fn from_conf(conf: &ConfFile)
+        -> Result<Option<JsonMapping>, Box<dyn Error>> {
+
+        let entries = conf.section("mapping");
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let get = |key: &str| entries.iter().rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v);
+        let path = |key: &str| -> Result<PathExpr, Box<dyn Error>> {
+            let expr = get(key).ok_or_else(|| SimpleError(format!(
+                "[mapping] requires a {} path", key)))?;
+            PathExpr::parse(expr).map_err(|e| {
+                let err: Box<dyn Error> = Box::new(SimpleError(
+                    format!("[mapping] {}: {}", key, e)));
+                err
+            })
+        };
+        let units_per_sec = match get("timestamp-units").unwrap_or("s") {
+            "s" => 1.0,
+            "ms" => 1e3,
+            "us" => 1e6,
+            "ns" => 1e9,
+            u => {
+                return Err(Box::new(SimpleError(format!(
+                    "[mapping] invalid timestamp-units: {}", u))));
+            }
+        };
+
+        Ok(Some(JsonMapping {
+            class: path("class")?,
+            device: path("device")?,
+            timestamp: path("timestamp")?,
+            class_prefix: get("class-prefix").unwrap_or("").to_string(),
+            units_per_sec,
+        }))
+    }
+
+    fn timestamp(&self, value: &Value) -> Option<i64> {
+        match value {
+            Value::Number(n) => {
+                Some((n.as_f64()? / self.units_per_sec).floor() as i64)
+            }
+            Value::String(s) => parse_timestamp(s),
+            _ => None,
+        }
+    }
+
+    //
+    // Convert an event into an fmdump-style event, keeping its members as the
+    // payload.  Returns None if the class, device or timestamp can't be found.
+    //
+    pub fn map_event(&self, event: Value) -> Option<Value> {
+        let class = format!("{}{}", self.class_prefix,
+            as_string(self.class.lookup(&event)?));
+        let device = as_string(self.device.lookup(&event)?);
+        let tod = self.timestamp(self.timestamp.lookup(&event)?)?;
+        let detector = if device.starts_with('/') {
+            json!({ "scheme": "dev", "device-path": device })
+        } else {
+            json!({ "scheme": "hc",
+                "hc-list": [{ "hc-name": "device", "hc-id": device }] })
+        };
+
+        let mut mapped = match event {
+            Value::Object(obj) => obj,
+            v => {
+                let mut obj = serde_json::Map::new();
+                obj.insert("value".to_string(), v);
+                obj
+            }
+        };
+        mapped.insert("class".to_string(), json!(class));
+        mapped.insert("detector".to_string(), detector);
+        mapped.insert("__tod".to_string(), json!([tod, 0]));
+        Some(Value::Object(mapped))
+    }
+}
@@ -0,0 +1,111 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Reporting of the fault and locate indicator (LED) states captured by
+// hwgrok, so that field techs can confirm whether the LEDs that are already
+// lit agree with the devices flagged by the report.
+//
+use std::error::Error;
+use std::io::Write;
+use std::ptr;
+
+use hwgrok::{HwGrok, HwGrokLED};
+use i18n::tr;
+use DeviceHash;
+
+fn led_mode<'a>(leds: &'a [HwGrokLED], led_type: &str) -> &'a str {
+    leds.iter()
+        .find(|l| l.led_type == led_type)
+        .map(|l| l.led_mode.as_str())
+        .unwrap_or("-")
+}
+
+//
+// Write out the state of each of a component's indicators.
+//
+pub fn write_leds(
+    out: &mut dyn Write,
+    leds: &[HwGrokLED]
+) -> Result<(), Box<dyn Error>> {
+
+    for led in leds {
+        let mut label = led.led_type.clone();
+        if let Some(first) = label.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        writeln!(out, "{0: <40} {1}", tr("{} LED:").replace("{}", &label),
+            led.led_mode)?;
+    }
+    Ok(())
+}
+
+//
+// Write a summary of the fault and locate LED states for every drive bay and
+// DIMM slot, noting where the fault LED disagrees with the ereport telemetry:
+// either the fault LED is lit on a device with no ereports, or a device has
+// ereports but its fault LED is off.
+//
+pub fn write_led_summary(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    if hwgrok.drive_bays.is_empty() && hwgrok.memory.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Indicator Summary"))?;
+    let headings = [
+        ("location", "fault", "locate", "ereports", "note"),
+        ("--------", "-----", "------", "--------", "----"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <16} {1: <8} {2: <8} {3: <10} {4}", h.0, h.1, h.2,
+            h.3, h.4)?;
+    }
+
+    let mut rows: Vec<(&str, &[HwGrokLED], Option<usize>)> = Vec::new();
+    for bay in &hwgrok.drive_bays {
+        let nereports = bay.bay_disk.as_ref().map(|disk| {
+            disk.devpath().and_then(|path| device_hash.get(&path))
+                .map(|d| d.ereports.len()).unwrap_or(0)
+        });
+        rows.push((&bay.bay_label, &bay.bay_leds, nereports));
+    }
+    for slot in &hwgrok.memory {
+        if let Some(dimm) = &slot.dimm {
+            let nereports = device_hash.iter()
+                .filter(|(key, _)| hwgrok.find_dimm(key)
+                    .is_some_and(|s| ptr::eq(s, slot)))
+                .map(|(_, d)| d.ereports.len())
+                .sum();
+            rows.push((&slot.slot_label, &dimm.dimm_leds, Some(nereports)));
+        }
+    }
+
+    for (label, leds, nereports) in rows {
+        let fault = led_mode(leds, "fault");
+        let note = match (fault, nereports) {
+            ("on", Some(0)) => "fault LED lit but no ereports",
+            ("on", None) => "fault LED lit on empty bay",
+            ("off", Some(n)) if n > 0 => "ereports but fault LED off",
+            _ => "",
+        };
+        let count = match nereports {
+            Some(n) => n.to_string(),
+            None => "empty".to_string(),
+        };
+        let row = format!("{0: <16} {1: <8} {2: <8} {3: <10} {4}", label,
+            fault, led_mode(leds, "locate"), count, note);
+        writeln!(out, "{}", row.trim_end())?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
@@ -11,34 +11,424 @@ use chrono::prelude::*;
 extern crate serde;
 use serde::Deserialize;
 
+#[macro_use]
+extern crate serde_json;
+
+use std::cell::RefCell;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
 use std::fs;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::iter;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
 
-mod hwgrok;
+pub mod hwgrok;
 use hwgrok::HwGrok;
 
+mod aer;
+mod alias;
+mod analysis;
+use alias::AliasMap;
+mod archive;
+mod assets;
+use assets::AssetData;
+pub use archive::Retention;
+mod boots;
+mod cmdb;
+mod classdoc;
+use classdoc::ClassDocs;
+use boots::Boundaries;
+mod columns;
+use columns::TableFormat;
+mod conf;
+use conf::ConfFile;
+mod cooccur;
+mod cpu;
+use cpu::CpuErrors;
+mod device;
+mod diagnosis;
+mod export;
+mod drivers;
+mod enrich;
+mod environment;
+use drivers::DriverMap;
+mod error;
+pub use error::FmLogError;
+mod expected;
+use expected::ExpectedRates;
+mod faults;
+use faults::{Fault, Faults};
+mod faulty;
+use faulty::FaultyCases;
+mod filter;
+pub use filter::EventFilter;
+mod fleet;
+mod fmri;
+mod follow;
+use follow::Tail;
+mod forward;
+mod gate;
+pub use gate::ClassGate;
+mod generate;
+pub use generate::Synthetic;
+mod glob;
+mod graphql;
+mod html;
+mod hwcheck;
+mod hwhistory;
+pub use hwhistory::HwSnapshot;
+use hwhistory::HwHistory;
+mod i18n;
+use i18n::tr;
+mod input;
+pub use input::InputFormat;
+use input::{InputReader, Lines};
+mod inventory;
+mod iostat;
+mod jsonmap;
+use iostat::IoStats;
+mod leds;
+mod live;
+mod location;
+pub mod log;
+mod logset;
+use logset::Coverage;
+mod markdown;
+mod mcelog;
+mod memstats;
+mod model;
+pub use model::{load_report, migrate_report, SCHEMA_VERSION};
+mod devpath;
+use devpath::{DeviceKey, DevicePath};
+use device::SuspectCase;
+mod multipath;
+mod numfmt;
+use numfmt::NumberFormat;
+mod other;
+mod output;
+pub use output::{Compression, OutputSpec, ReportFormat};
+use other::OtherEvents;
+mod owners;
+use owners::OwnerMap;
+mod pager;
+mod pcie;
+mod plugin;
+mod preview;
+use preview::Partial;
+pub use preview::parse_preview_size;
+mod profile;
+pub use profile::profile_options;
+use plugin::PluginSet;
+mod query;
+pub use query::Query;
+mod rasdaemon;
+mod rates;
+mod rawevents;
+use rates::RateAlerts;
+mod reader;
+mod regex;
+pub use reader::EreportReader;
+mod replacement;
+mod sel;
+mod sense;
+mod ses;
+use ses::SesData;
+mod server;
+mod signals;
+mod severity;
+pub use severity::Severity;
+use severity::SeverityMap;
+mod smart;
+use smart::SmartData;
+mod staleness;
+pub use staleness::parse_age;
+mod status;
+use status::{DeviceStatus, Status};
+mod summary;
+pub use summary::SummaryDest;
+use summary::RunSummary;
+mod thermal;
+use thermal::SensorHistory;
+mod thresholds;
+mod timing;
+mod topo;
+mod upload;
+pub use upload::Upload;
+mod validate;
+use timing::{Phase, Timings};
+mod zfs;
+use zfs::ZfsPools;
+
+#[derive(Debug)]
+pub enum Mode {
+    // Generate the summary report covering all devices
+    Report,
+    // Generate an exhaustive report for a single device path or label
+    Device(String),
+    // Generate population statistics across a directory of host bundles
+    Fleet(String),
+    // Summarize the devices with problems and exit with a status reflecting
+    // the most severe of them
+    Check,
+    // Answer HTTP requests for the report on the given address
+    Serve(String),
+    // Check a hwgrok file against the fields needed for enrichment
+    CheckHwgrok(String),
+    // Re-emit the events which pass the filters, unchanged
+    Forward,
+    // Write synthetic FM logs and hwgrok output to the given directory
+    Generate(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SplitBy {
+    // Write a separate report file for each device
+    Device,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortBy {
+    // Order the device sections by device path
+    Path,
+    // Order the device sections by physical location label
+    Location,
+}
+
+// Exit statuses for check mode, following the conventions of monitoring
+// plugins.
+pub const CHECK_OK: i32 = 0;
+pub const CHECK_WARNING: i32 = 1;
+pub const CHECK_CRITICAL: i32 = 2;
+pub const CHECK_UNKNOWN: i32 = 3;
+
 #[derive(Debug)]
 pub struct Config {
-    pub fmlog_path: String,
+    pub fmlog_paths: Vec<String>,
+    pub input_format: InputFormat,
     pub hwgrok_path: Option<String>,
+    // the output of fmtopo -V -j, read in place of hwgrok's
+    pub topo_path: Option<String>,
+    // hwgrok output captured at earlier times, to find hardware changes
+    pub hwgrok_snapshots: Vec<HwSnapshot>,
+    pub config_path: Option<String>,
+    pub profile: Option<String>,
+    pub alias_path: Option<String>,
+    pub smart_path: Option<String>,
+    pub sel_path: Option<String>,
+    pub faulty_path: Option<String>,
+    pub path_to_inst: Option<String>,
+    pub ses_paths: Vec<String>,
+    pub sensor_path: Option<String>,
+    pub assets_path: Option<String>,
+    pub boot_times_path: Option<String>,
+    pub iostat_path: Option<String>,
+    pub no_pager: bool,
+    pub pidfile: Option<String>,
+    pub outputs: Vec<OutputSpec>,
+    pub compress: Compression,
+    pub format: ReportFormat,
+    pub split_by: Option<SplitBy>,
+    pub sort_by: SortBy,
+    pub lang: Option<String>,
+    pub verbose: bool,
+    // whether to list the hwgrok components without ereports
+    pub healthy_inventory: bool,
+    pub hostname: Option<String>,
+    pub archive_dir: Option<String>,
+    pub retention: Retention,
+    pub output_dir: Option<String>,
+    pub whole_disk: bool,
+    pub merge_multipath: bool,
+    pub exclude_virtual: bool,
+    pub cooccur_window: i64,
+    pub min_severity: Option<Severity>,
+    // the window of time, as seconds since the epoch, outside of which
+    // ereports are dropped
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub filter: EventFilter,
+    pub fail_on_class: Vec<ClassGate>,
+    pub max_age: Option<i64>,
+    // the interval in seconds at which a followed log is read again
+    pub follow: Option<u64>,
+    // the device whose raw ereports are exported in place of the report
+    pub export_events: Option<String>,
+    pub preview: Option<u64>,
+    pub summary: Option<SummaryDest>,
+    pub timing: bool,
+    pub validate: bool,
+    pub upload: Option<Upload>,
+    pub color: bool,
+    pub mode: Mode,
 }
 
 impl Config {
-    pub fn new(fmlog_path: String, hwgrok_path: Option<String>) -> Config {
-        Config { fmlog_path, hwgrok_path }
+    pub fn new(fmlog_paths: Vec<String>, hwgrok_path: Option<String>)
+        -> Config {
+
+        Config {
+            fmlog_paths,
+            input_format: InputFormat::Fmdump,
+            hwgrok_path,
+            topo_path: None,
+            hwgrok_snapshots: Vec::new(),
+            config_path: None,
+            profile: None,
+            alias_path: None,
+            smart_path: None,
+            sel_path: None,
+            faulty_path: None,
+            path_to_inst: None,
+            ses_paths: Vec::new(),
+            sensor_path: None,
+            assets_path: None,
+            boot_times_path: None,
+            iostat_path: None,
+            no_pager: false,
+            pidfile: None,
+            outputs: Vec::new(),
+            compress: Compression::None,
+            format: ReportFormat::Text,
+            split_by: None,
+            sort_by: SortBy::Path,
+            lang: None,
+            verbose: false,
+            healthy_inventory: false,
+            hostname: None,
+            archive_dir: None,
+            retention: Retention::default(),
+            output_dir: None,
+            whole_disk: false,
+            merge_multipath: false,
+            exclude_virtual: false,
+            cooccur_window: cooccur::DEFAULT_WINDOW,
+            min_severity: None,
+            since: None,
+            until: None,
+            filter: EventFilter::default(),
+            fail_on_class: Vec::new(),
+            max_age: None,
+            follow: None,
+            export_events: None,
+            preview: None,
+            summary: None,
+            timing: false,
+            validate: false,
+            upload: None,
+            color: false,
+            mode: Mode::Report,
+        }
     }
+
+    //
+    // Returns true if an ereport at the given time is within the window given
+    // by --since and --until: at or after the one and before the other.
+    // Ereports without a time are kept.
+    //
+    fn in_window(&self, tod: Option<i64>) -> bool {
+        match tod {
+            Some(t) => self.since.is_none_or(|since| t >= since) &&
+                self.until.is_none_or(|until| t < until),
+            None => true,
+        }
+    }
+}
+
+type DeviceHash = HashMap<DeviceKey, DeviceHashEnt>;
+
+//
+// Everything gathered from reading in an FM log.
+//
+struct LogData {
+    device_hash: DeviceHash,
+    cases: Vec<SuspectCase>,
+    faults: Faults,
+    zfs: ZfsPools,
+    cpus: CpuErrors,
+    panics: Vec<i64>,
+    other_events: OtherEvents,
+    // the time of the newest event in the log
+    newest_event: Option<i64>,
+    // how much of the log was read, if only its tail was
+    partial: Option<Partial>,
+    // the logs read and the time they cover, if there was more than one
+    coverage: Option<Coverage>,
+    // time spent reading and parsing the events, and the number read
+    timings: Timings,
+}
+
+//
+// What the ereports are looked up in as they're read: the severities of their
+// classes, and hwgrok, for the device paths which their FMRIs resolve to.
+//
+struct Lookups<'a> {
+    severity: &'a SeverityMap,
+    hwgrok: &'a HwGrok,
+}
+
+//
+// The supplementary data, beyond the device hash itself, which is used to
+// augment each device's section of the report.
+//
+struct ReportData {
+    hwgrok: HwGrok,
+    aliases: AliasMap,
+    // the team or service owning each device
+    owners: OwnerMap,
+    smart: SmartData,
+    ses: SesData,
+    sensors: SensorHistory,
+    assets: AssetData,
+    iostats: IoStats,
+    boundaries: Boundaries,
+    other_events: OtherEvents,
+    cases: Vec<SuspectCase>,
+    faults: Faults,
+    zfs: ZfsPools,
+    cpus: CpuErrors,
+    // the changes between the hwgrok snapshots
+    hw_history: HwHistory,
+    // other device paths via which a multipathed device was reached
+    alt_paths: HashMap<DeviceKey, Vec<DeviceKey>>,
+    cooccur_window: i64,
+    sort_by: SortBy,
+    severity: SeverityMap,
+    rate_alerts: Option<RateAlerts>,
+    // the normal background rates of noisy ereport classes
+    expected_rates: Option<ExpectedRates>,
+    // open cases from fmadm faulty, to reconcile the ereports against
+    faulty: Option<FaultyCases>,
+    // the driver managing each device
+    drivers: DriverMap,
+    columns: TableFormat,
+    numbers: NumberFormat,
+    color: bool,
+    // additional information about each device supplied by plugins
+    plugin_fields: HashMap<DeviceKey, Vec<(String, String)>>,
+    // the status badge of each device
+    statuses: HashMap<DeviceKey, DeviceStatus>,
+    // descriptions of the ereport classes, if the report is verbose
+    class_docs: Option<ClassDocs>,
+    healthy_inventory: bool,
+    // why the log is stale, if it's older than --max-age
+    stale: Option<String>,
+    // how much of the log was read, if the report is a preview
+    partial: Option<Partial>,
+    // the logs read and the time they cover, if there was more than one
+    coverage: Option<Coverage>,
 }
 
 #[derive(Debug)]
-struct SimpleError(String);                                              
+struct SimpleError(String);
+
+impl Error for SimpleError {}
 
-impl Error for SimpleError {}                                            
-                                                                     
 impl fmt::Display for SimpleError {                                      
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -48,23 +438,29 @@ impl fmt::Display for SimpleError {
 #[derive(Debug, Deserialize)]
 struct FmEvent {
     class: String,
+    #[serde(rename = "__tod", default)]
+    tod: Vec<i64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct Ereport {
     class: String,
     detector: Detector,
     #[serde(rename = "__tod")]
     tod: Vec<i64>,
+    // all remaining payload members, which vary by ereport class
+    #[serde(flatten)]
+    payload: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Detector {
+#[derive(Clone, Debug, Deserialize)]
+pub struct Detector {
     scheme: String,
 
     // fields specific to dev-scheme detectors
     #[serde(rename = "device-path")]
     device_path: Option<String>,
+    devid: Option<String>,
 
     // fields specific to hc-scheme detectors
     #[serde(rename = "hc-list")]
@@ -73,38 +469,88 @@ struct Detector {
     // fields specific to fmd-scheme detectors
     #[serde(rename = "mod-name")]
     mod_name: Option<String>,
+
+    // fields specific to cpu-scheme detectors
+    cpuid: Option<u64>,
+
+    // fields specific to mem-scheme detectors
+    unum: Option<String>,
+}
+
+impl Ereport {
+    pub fn class(&self) -> &str {
+        &self.class
+    }
+
+    pub fn detector(&self) -> &Detector {
+        &self.detector
+    }
+
+    // The event time, as seconds and nanoseconds
+    pub fn tod(&self) -> &[i64] {
+        &self.tod
+    }
+
+    pub fn payload(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.payload
+    }
 }
 
 impl Detector {
-    pub fn get_fmristr(&mut self) -> Result<String, Box<dyn Error>> {
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    pub fn device_path(&self) -> Option<&str> {
+        self.device_path.as_deref()
+    }
+
+    //
+    // Returns the key of the detector's hc-scheme FMRI, if it has one.  This
+    // is also given by some dev-scheme detectors.
+    //
+    fn hc_fmristr(&self) -> Option<String> {
+        let hc_list = self.hc_list.as_ref()?;
+        Some(fmri::hc_key(hc_list.iter().map(|hcpair|
+            (hcpair.hc_name.as_str(), hcpair.hc_id.as_str()))))
+    }
+
+    pub fn get_fmristr(&self) -> Result<String, FmLogError> {
+        let missing = |member| FmLogError::MissingDetector {
+            scheme: self.scheme.clone(),
+            member,
+        };
         match self.scheme.as_ref() {
             "dev" => {
-                Ok(format!("dev://{}", self.device_path.as_mut().unwrap()))
-            }
-            "hc" => {
-                let mut fmristr = String::from("hc://");
-                for hcpair in self.hc_list.as_mut().unwrap() {
-                    fmristr.push_str(format!("/{}={}", hcpair.hc_name,
-                        hcpair.hc_id).as_ref());
-                }
-                Ok(fmristr)
+                let path = self.device_path.as_ref()
+                    .ok_or_else(|| missing("device-path"))?;
+                Ok(format!("dev://{}", path))
             }
+            "hc" => self.hc_fmristr().ok_or_else(|| missing("hc-list")),
             "fmd" => {
-                Ok(format!("fmd:///mpdule/{}", self.mod_name.as_mut().unwrap()))
+                let mod_name = self.mod_name.as_ref()
+                    .ok_or_else(|| missing("mod-name"))?;
+                Ok(format!("fmd:///module/{}", mod_name))
             }
-            _ => {
-                Err(Box::new(SimpleError(
-                    format!("unsupported detector scheme"))))
+            "cpu" => {
+                let cpuid = self.cpuid.ok_or_else(|| missing("cpuid"))?;
+                Ok(format!("cpu:///cpuid={}", cpuid))
             }
+            "mem" => {
+                let unum = self.unum.as_ref()
+                    .ok_or_else(|| missing("unum"))?;
+                Ok(format!("mem:///unum={}", unum))
+            }
+            _ => Err(FmLogError::UnsupportedScheme(self.scheme.clone())),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 struct HcPair {
     #[serde(rename = "hc-name")]
     hc_name: String,
-    #[serde(rename = "hc-id")]
+    #[serde(rename = "hc-id", deserialize_with = "fmri::deserialize_hc_id")]
     hc_id: String,
 }
 
@@ -114,6 +560,8 @@ pub struct DeviceHashEnt {
     ereport_ts_hash: HashMap<String, u32>,
     ereports: Vec<Ereport>,
     ereports_ts: Vec<String>,
+    // other device paths whose events have been consolidated into this entry
+    merged_paths: Vec<DeviceKey>,
 }
 
 impl DeviceHashEnt {
@@ -132,38 +580,113 @@ impl DeviceHashEnt {
             ereport_ts_hash,
             ereports,
             ereports_ts,
+            merged_paths: Vec::new(),
+        }
+    }
+
+    //
+    // Fold the events from another entry into this one, keeping the ereports
+    // and the day-by-day distribution in chronological order.
+    //
+    pub fn merge(&mut self, other: DeviceHashEnt) {
+        for (class, count) in other.ereport_class_hash {
+            *self.ereport_class_hash.entry(class).or_insert(0) += count;
+        }
+        for (ts, count) in other.ereport_ts_hash {
+            match self.ereport_ts_hash.entry(ts.clone()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(count);
+                    self.ereports_ts.push(ts);
+                }
+                Entry::Occupied(mut entry) => {
+                    *entry.get_mut() += count;
+                }
+            }
         }
+        self.ereports_ts.sort();
+        self.ereports.extend(other.ereports);
+        self.ereports.sort_by_key(|e| (e.tod[0], e.tod.get(1).cloned()));
+        self.merged_paths.extend(other.merged_paths);
     }
 }
 
 fn get_event_timestamp(ev_tod_secs: i64) -> String {
-    let naive = NaiveDateTime::from_timestamp(ev_tod_secs, 0);
-    let datetime: DateTime<Utc> = DateTime::from_utc(naive, Utc);
+    let datetime: DateTime<Utc> = DateTime::from_timestamp(ev_tod_secs, 0)
+        .unwrap_or_default();
     datetime.format("%Y-%m-%d").to_string()
 }
 
 //
-// The Device Hash is a HashMap of DevHashEnt structs, hashed by a string that
-// uniquely indentifies the ereport detector.  For I/O ereports, we use the
-// device path as the hash key.  For other hardware ereports, we use string
-// representation of the HC-scheme FMRI. The DevHashEnt struct itself contains
+// Parse a user-supplied timestamp, which can be given as seconds since the
+// epoch, as an RFC3339 date or as a "YYYY-MM-DD[ HH:MM:SS]" date in UTC.
+//
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<i64>() {
+        return Some(secs);
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.timestamp());
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+        return Some(dt.and_utc().timestamp());
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Some(d.and_hms_opt(0, 0, 0)?.and_utc().timestamp());
+    }
+    None
+}
+
+// The latest event time accepted (the end of the year 9999), which keeps the
+// date arithmetic done on event times from overflowing.
+const MAX_TOD: i64 = 253402300799;
+
+//
+// Parse an ereport from a line of the FM log.  Malformed input results in an
+// error, never a panic, and an ereport that's returned is guaranteed to have
+// an event time within range, which the rest of the code relies on.  Errors
+// are reported against line 1, for the caller to place with at_line().
+//
+pub fn parse_ereport(line: &str) -> Result<Ereport, FmLogError> {
+    let ereport: Ereport = serde_json::from_str(line)
+        .map_err(|e| FmLogError::JsonParse { line_no: 1, source: e })?;
+    let reason = match ereport.tod.first() {
+        Some(secs) if (0..=MAX_TOD).contains(secs) => return Ok(ereport),
+        Some(secs) => format!("{}: event time out of range: {}",
+            ereport.class, secs),
+        None => format!("{}: empty __tod", ereport.class),
+    };
+    Err(FmLogError::InvalidEvent { line_no: 1, reason })
+}
+
+fn get_event_time(ereport: &Ereport) -> String {
+    let datetime: DateTime<Utc> = DateTime::from_timestamp(ereport.tod[0], 0)
+        .unwrap_or_default();
+    datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+//
+// The Device Hash is a HashMap of DevHashEnt structs, hashed by a DeviceKey
+// (see devpath.rs) that uniquely indentifies the ereport detector.  For I/O
+// ereports, we use the device path as the hash key.  For other hardware
+// ereports, we use the HC-scheme FMRI. The DevHashEnt struct itself contains
 // a vector of ereports associated with that device path and a hash table of
 // ereport counts hashed by the ereport class name as well as a hash table of
 // ereport counts hashed by the day - using a string timestamp of the form
 // <YYYY>-<MM>-<DD>
 //
 // XXX - should this be a method on DevHashEnt?
-// 
+//
 fn process_event(
-    device_hash: &mut HashMap<String, DeviceHashEnt>,
-    key: &str,
+    device_hash: &mut DeviceHash,
+    key: &DeviceKey,
     ereport: Ereport
 ) -> Result<(), Box<dyn Error>> {
 
     let ts = get_event_timestamp(ereport.tod[0]);
     let mut new_ts = false;
 
-    match device_hash.entry(key.to_string()) {
+    match device_hash.entry(key.clone()) {
         Entry::Vacant(entry) => {
             entry.insert(DeviceHashEnt::new(ereport, ts));
         }
@@ -200,136 +723,1687 @@ fn process_event(
 //
 // XXX - should this be moved to a new() method on HwGrok?
 //
-fn process_hwgrok_data(hwgrok_path: &str) -> Result<HwGrok, Box<dyn Error>> {
+fn process_hwgrok_data(hwgrok_path: &str) -> Result<HwGrok, FmLogError> {
 
-    let hwgrok_contents = fs::read_to_string(&hwgrok_path)?;
-    let hwgrok : HwGrok = serde_json::from_str(&hwgrok_contents)?;
-    
-    Ok(hwgrok)
-}
+    let hwgrok_contents = input::read_to_string(hwgrok_path)?;
 
-pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
-    
-    let hwgrok : HwGrok = match &config.hwgrok_path {        
-        Some(path) => {
-            process_hwgrok_data(&path)?
-        }
-        None => { HwGrok::default() }
-    };
+    hwgrok::parse_hwgrok(&hwgrok_contents).map_err(|e| match e {
+        FmLogError::HwgrokParse { source, .. } => FmLogError::HwgrokParse {
+            path: Some(hwgrok_path.to_string()),
+            source,
+        },
+        e => e,
+    })
+}
 
-    let fmlogs = fs::File::open(&config.fmlog_path)?;
-    let reader = BufReader::new(fmlogs);
+//
+// Read in the file containing the output of fmtopo -V -j, converting it into
+// an HwGrok struct as if it were hwgrok's (see topo.rs).
+//
+fn process_topo_data(topo_path: &str) -> Result<HwGrok, FmLogError> {
 
-    let mut device_hash = HashMap::new();
+    let topo_contents = input::read_to_string(topo_path)?;
 
-    for l in reader.lines() {
-        let line = l.unwrap();
+    topo::parse_topo(&topo_contents).map_err(|e| match e {
+        FmLogError::TopoParse { source, .. } => FmLogError::TopoParse {
+            path: Some(topo_path.to_string()),
+            source,
+        },
+        e => e,
+    })
+}
 
-        let event: FmEvent = serde_json::from_str(&line)?;
+//
+// If the hwgrok data contains a disk, PCIE device, network interface, DIMM or
+// processor matching this device path then write out its identity information.
+//
+fn write_hw_identity(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
 
-        // For now we only have code to handle ereport events.
-        if !event.class.starts_with("ereport.") {
-            continue;
+    if devpath.starts_with("/pci") && devpath.contains("disk") {
+        //
+        // If we can find a disk matching this device path in the hwgrok
+        // data then augment the report with that information.
+        //
+        for drive_bay in &hwgrok.drive_bays {
+            if let Some(disk) = &drive_bay.bay_disk {
+                if disk.devpath().as_ref() == Some(devpath) {
+                    writeln!(out, "{0: <40} {1}", tr("Disk Location:"),
+                        drive_bay.bay_label)?;
+                    writeln!(out, "{0: <40} {1}", tr("Disk Manufacturer:"),
+                        disk.disk_manufacturer)?;
+                    writeln!(out, "{0: <40} {1}", tr("Disk Model:"),
+                        disk.disk_model)?;
+                    writeln!(out, "{0: <40} {1}", tr("Disk Serial:"),
+                        disk.disk_serial_number)?;
+                    writeln!(out, "{0: <40} {1}", tr("Firmware Rev:"),
+                        disk.disk_firmware_rev)?;
+                    leds::write_leds(out, &drive_bay.bay_leds)?;
+                    continue;
+                }
+            }
+        }
+    } else if devpath.starts_with("/pci") {
+        //
+        // If we can find a PCIE device matching this device path in the
+        // hwgrok data then augment the report with that information.
+        //
+        for pci_dev in &hwgrok.pci_devices {
+            if *devpath == devpath::canonicalize(&pci_dev.pci_device_path) {
+                writeln!(out, "{0: <40} {1}", tr("Vendor Name:"),
+                    pci_dev.pci_vendor_name)?;
+                writeln!(out, "{0: <40} {1}", tr("Device Name:"),
+                    pci_dev.pci_device_name)?;
+                writeln!(out, "{0: <40} {1}", tr("Subsystem Name:"),
+                    pci_dev.pci_subsystem_name)?;
+                continue;
+            }
+        }
+        if let Some(nic) = hwgrok.find_nic(devpath) {
+            if !nic.nic_name.is_empty() {
+                writeln!(out, "{0: <40} {1}", tr("Interface:"),
+                    nic.nic_name)?;
+            }
+            writeln!(out, "{0: <40} {1}", tr("MAC Address:"),
+                nic.nic_mac_addr)?;
+            writeln!(out, "{0: <40} {1}", tr("NIC Model:"), nic.nic_model)?;
+            writeln!(out, "{0: <40} {1}", tr("NIC Firmware:"),
+                nic.nic_firmware_rev)?;
         }
+    } else if let Some(slot) = hwgrok.find_dimm(devpath) {
         //
-        // For now we skip ZFS ereports, as serdes has problems dealing with
-        // some of the numeric fields.
+        // Memory ereports are attributed to the DIMM slot holding the DIMM,
+        // or the rank of it, which detected them.
         //
-        if event.class.starts_with("ereport.fs.") {
-            continue;
+        writeln!(out, "{0: <40} {1}", tr("DIMM Slot:"), slot.slot_label)?;
+        if let Some(dimm) = &slot.dimm {
+            writeln!(out, "{0: <40} {1}", tr("DIMM Manufacturer:"),
+                dimm.dimm_manufacturer)?;
+            writeln!(out, "{0: <40} {1}", tr("DIMM Part Number:"),
+                dimm.dimm_part)?;
+            if !dimm.dimm_serial.is_empty() {
+                writeln!(out, "{0: <40} {1}", tr("DIMM Serial:"),
+                    dimm.dimm_serial)?;
+            }
+            leds::write_leds(out, &dimm.dimm_leds)?;
         }
+    } else if let Some(chip) = hwgrok.find_processor(devpath) {
         //
-        // We also skip fmd ereports related to log errors as their payload
-        // doesn't contain a detector member.
+        // CPU ereports are attributed to the socket of the core, strand or
+        // memory controller which detected them.
         //
-        if event.class.starts_with("ereport.fm.fmd.log_") {
-            continue;
+        writeln!(out, "{0: <40} {1}", tr("Processor Socket:"),
+            chip.chip_label)?;
+        writeln!(out, "{0: <40} {1}", tr("Processor Brand:"),
+            chip.chip_brand)?;
+        if let Some(cores) = chip.chip_cores {
+            writeln!(out, "{0: <40} {1}", tr("Cores:"), cores)?;
         }
+        if let Some(threads) = chip.chip_threads_per_core {
+            writeln!(out, "{0: <40} {1}", tr("Threads Per Core:"), threads)?;
+        }
+        leds::write_leds(out, &chip.chip_leds)?;
+    } else if let Some(label) = hwgrok.component_label(devpath) {
+        //
+        // Events from the SEL are attributed to hwgrok components by their
+        // FMRI.
+        //
+        writeln!(out, "{0: <40} {1}", tr("Component Label:"), label)?;
+    }
 
-        let mut ereport: Ereport = serde_json::from_str(&line)?;
+    Ok(())
+}
 
-        match ereport.detector.scheme.as_str() {
-            "dev" => {
-                let dp = ereport.detector.device_path.as_mut().unwrap().clone();
-                process_event(&mut device_hash, &dp, ereport)?;
-            }
-            "hc" | "fmd" => {
-                match &ereport.detector.get_fmristr() {
-                    Ok(fmri) => {
-                        process_event(&mut device_hash, &fmri, ereport)?;
-                    }
-                    Err(_) => {
-                        eprintln!("failed to get fmri");
-                    }
-                }
-            }
-            _ => {
-                eprintln!("unsupported detector scheme - skipping");
-            }
-        }
-    }
-
-    // Iterate through the device hash and generate a simple report
-    println!();
-    for (devpath, ref devent) in device_hash.iter() {
-        println!("{}", "=".repeat(75));
-        println!("{0: <40} {1}", "Device Path:", devpath);
-        if devpath.starts_with("/pci") && devpath.contains("disk") {
-            //
-            // If we can find a disk matching this device path in the hwgrok
-            // data then augment the report with that information.
-            //
-            for drive_bay in &hwgrok.drive_bays {
-                match &drive_bay.bay_disk {
-                    Some(disk) => {
-                        if disk.disk_device_path == devpath.to_string() {
-                            println!("{0: <40} {1}", "Disk Location:",
-                                drive_bay.bay_label);
-                            println!("{0: <40} {1}", "Disk Manufacturer:",
-                                disk.disk_manufacturer);
-                            println!("{0: <40} {1}", "Disk Model:",
-                                disk.disk_model);
-                            println!("{0: <40} {1}", "Disk Serial:",
-                                disk.disk_serial_number);
-                            println!("{0: <40} {1}", "Firmware Rev:",
-                                disk.disk_firmware_rev);
-                            continue;
-                        }
-                    }
-                    None => ()
-                }
-            }
-        } else if devpath.starts_with("/pci") {
-            //
-            // If we can find a PCIE device matching this device path in the
-            // hwgrok data then augment the report with that information.
-            //
-            for pci_dev in &hwgrok.pci_devices {
-                if devpath.to_string() == pci_dev.pci_device_path {
-                    println!("{0: <40} {1}", "Vendor Name:",
-                        pci_dev.pci_vendor_name);
-                    println!("{0: <40} {1}", "Device Name:",
-                        pci_dev.pci_device_name);
-                    println!("{0: <40} {1}", "Subsystem Name:",
-                        pci_dev.pci_subsystem_name);
-                    continue;
-                }
-            }
+//
+// Returns the (name, value) rows identifying the device, for the report
+// formats which present them as a table: the same as in the text report's
+// device header, along with the hardware details from hwgrok.
+//
+fn device_identity(
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Vec<(String, String)> {
+
+    let mut rows = vec![(tr("Device Path").to_string(),
+        devpath.to_string())];
+    let mut add = |name: &str, value: &str| {
+        rows.push((tr(name).to_string(), value.to_string()));
+    };
+    if let Some(alias) = data.aliases.lookup(devpath, &data.hwgrok) {
+        add("Device Alias", alias);
+    }
+    if let Some(owner) = data.owners.lookup(devpath, &data.hwgrok) {
+        add("Owner", owner);
+    }
+    if let Some(label) = data.hwgrok.location_label(devpath) {
+        add("Location", label);
+    }
+    if let Some(asset) = data.assets.lookup(devpath, &data.hwgrok,
+        data.plugin_fields.get(devpath)) {
+        add("Age/Warranty", &asset.describe(Utc::now().date_naive()));
+    }
+    if let Some(driver) = devent.and_then(|d| data.drivers.lookup(devpath, d)) {
+        add("Driver", &driver.to_string());
+    }
+    if let Some((_, disk)) = data.hwgrok.find_disk(devpath) {
+        add("Disk Manufacturer", &disk.disk_manufacturer);
+        add("Disk Model", &disk.disk_model);
+        add("Disk Serial", &disk.disk_serial_number);
+        add("Firmware Rev", &disk.disk_firmware_rev);
+        add("Disk Size", &format!("{} bytes",
+            data.numbers.int(disk.disk_size)));
+    }
+    if let Some(dimm) = data.hwgrok.find_dimm(devpath)
+        .and_then(|slot| slot.dimm.as_ref()) {
+        add("DIMM Manufacturer", &dimm.dimm_manufacturer);
+        add("DIMM Part Number", &dimm.dimm_part);
+        if !dimm.dimm_serial.is_empty() {
+            add("DIMM Serial", &dimm.dimm_serial);
+        }
+        add("DIMM Size", &format!("{} bytes",
+            data.numbers.int(dimm.dimm_size)));
+    }
+    if let Some(chip) = data.hwgrok.find_processor(devpath) {
+        add("Processor Brand", &chip.chip_brand);
+        if let Some(cores) = chip.chip_cores {
+            add("Cores", &cores.to_string());
+        }
+        if let Some(threads) = chip.chip_threads_per_core {
+            add("Threads Per Core", &threads.to_string());
+        }
+    }
+    for pci_dev in &data.hwgrok.pci_devices {
+        if devpath::canonicalize(&pci_dev.pci_device_path) == *devpath {
+            add("Vendor Name", &pci_dev.pci_vendor_name);
+            add("Device Name", &pci_dev.pci_device_name);
+            add("Subsystem Name", &pci_dev.pci_subsystem_name);
+        }
+    }
+    if let Some(nic) = data.hwgrok.find_nic(devpath) {
+        if !nic.nic_name.is_empty() {
+            add("Interface", &nic.nic_name);
+        }
+        add("MAC Address", &nic.nic_mac_addr);
+        add("NIC Model", &nic.nic_model);
+        add("NIC Firmware", &nic.nic_firmware_rev);
+    }
+    if let Some(fields) = data.plugin_fields.get(devpath) {
+        for (name, value) in fields {
+            add(name, value);
+        }
+    }
+    add("Total ereports", &devent.map(|d| d.ereports.len()).unwrap_or(0)
+        .to_string());
+    if let Some(status) = data.statuses.get(devpath) {
+        add("Status", &status.to_string());
+    }
+    rows
+}
+
+//
+// Write out the lines identifying a device at the top of its section of the
+// report.
+//
+fn write_device_header(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    writeln!(out, "{0: <40} {1}", tr("Device Path:"), devpath)?;
+    if let Some(alias) = data.aliases.lookup(devpath, &data.hwgrok) {
+        writeln!(out, "{0: <40} {1}", tr("Device Alias:"), alias)?;
+    }
+    if let Some(owner) = data.owners.lookup(devpath, &data.hwgrok) {
+        writeln!(out, "{0: <40} {1}", tr("Owner:"), owner)?;
+    }
+    if let Some(fields) = data.plugin_fields.get(devpath) {
+        for (name, value) in fields {
+            writeln!(out, "{0: <40} {1}", format!("{}:", name), value)?;
+        }
+    }
+    if let Some(asset) = data.assets.lookup(devpath, &data.hwgrok,
+        data.plugin_fields.get(devpath)) {
+        let description = asset.describe(Utc::now().date_naive());
+        if !description.is_empty() {
+            writeln!(out, "{0: <40} {1}", tr("Age/Warranty:"), description)?;
+        }
+    }
+    if let Some(devent) = devent {
+        if let Some(driver) = data.drivers.lookup(devpath, devent) {
+            writeln!(out, "{0: <40} {1}", tr("Driver:"), driver)?;
+        }
+        for path in &devent.merged_paths {
+            writeln!(out, "{0: <40} {1}", tr("Merged Path:"), path)?;
+        }
+    }
+    if let Some(paths) = data.alt_paths.get(devpath) {
+        for path in paths {
+            writeln!(out, "{0: <40} {1}", tr("Also Reachable Via:"), path)?;
+        }
+    }
+    write_hw_identity(out, devpath, &data.hwgrok)
+}
+
+//
+// Write out a device's status badge, highlighted by its severity.
+//
+fn write_status(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    if let Some(status) = data.statuses.get(devpath) {
+        let badge = status.status.severity()
+            .paint(&status.status.to_string(), data.color);
+        write!(out, "{0: <40} {1}", tr("Status:"), badge)?;
+        if status.reasons.is_empty() {
+            writeln!(out)?;
+        } else {
+            writeln!(out, " - {}", status.reasons.join("; "))?;
+        }
+    }
+    Ok(())
+}
+
+type DeviceList<'a> = Vec<(&'a DeviceKey, &'a DeviceHashEnt)>;
+
+//
+// Returns the physical and the virtual devices, each in the order in which
+// they are reported.
+//
+fn ordered_devices<'a>(
+    device_hash: &'a DeviceHash,
+    data: &ReportData
+) -> (DeviceList<'a>, DeviceList<'a>) {
+
+    let (mut virt, mut phys): (Vec<_>, Vec<_>) = device_hash.iter()
+        .partition(|(devpath, _)| devpath::is_virtual(devpath));
+    match data.sort_by {
+        SortBy::Path => {
+            phys.sort_by_key(|(devpath, _)| *devpath);
+            virt.sort_by_key(|(devpath, _)| *devpath);
         }
-        println!("{0: <40} {1}\n", "Total ereports:", devent.ereports.len());
-        println!("{0: <40} {1}", "class", "# occurences");
-        println!("{0: <40} {1}", "-----", "------------");
-        for (ereport_class, ref erptent) in devent.ereport_class_hash.iter() {
-            println!("{0: <40} {1}", ereport_class, erptent);
+        SortBy::Location => {
+            location::sort_by_location(&mut phys, &data.hwgrok);
+            location::sort_by_location(&mut virt, &data.hwgrok);
         }
-        println!("\nEvent Occurrence Distribution");
-        println!("-----------------------------");
-        for idx in 0..devent.ereports_ts.len() {
-            let ent = devent.ereport_ts_hash.get(&devent.ereports_ts[idx]);
-            println!("{0: <40} {1}", devent.ereports_ts[idx], ent.unwrap());
+    }
+    (phys, virt)
+}
+
+//
+// Iterate through the device hash and generate a simple report.  The report
+// is written to the supplied writer rather than directly to stdout so that the
+// caller can decide where it ends up (e.g. a pager).
+//
+fn write_report(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    writeln!(out)?;
+    write_system(out, data)?;
+    write_coverage(out, data)?;
+    write_partial(out, data)?;
+    let (phys, virt) = ordered_devices(device_hash, data);
+    location::write_location_index(out, &phys, &data.hwgrok)?;
+
+    for (devpath, devent) in phys {
+        write_device_section(out, devpath, devent, data)?;
+    }
+
+    //
+    // Pseudo devices aren't replaceable hardware, so they are reported
+    // separately to avoid confusion.
+    //
+    if !virt.is_empty() {
+        writeln!(out, "{}", "#".repeat(75))?;
+        writeln!(out, "{}", tr("Virtual Devices"))?;
+        writeln!(out, "{}\n", "#".repeat(75))?;
+        for (devpath, devent) in virt {
+            write_device_section(out, devpath, devent, data)?;
         }
-        println!();
     }
 
+    write_analysis(out, device_hash, data)
+}
+
+//
+// Identify the system the report is of, if the hwgrok data says what it is.
+//
+fn write_system(
+    out: &mut dyn Write,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let rows = data.hwgrok.system_identity();
+    if rows.is_empty() {
+        return Ok(());
+    }
+    for (name, value) in rows {
+        writeln!(out, "{0: <40} {1}", tr(&format!("{}:", name)), value)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+//
+// Note the logs a report was built from, and the time they cover, if there
+// was more than one.
+//
+fn write_coverage(
+    out: &mut dyn Write,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    if let Some(coverage) = &data.coverage {
+        writeln!(out, "{0: <40} {1}", tr("FM Logs:"),
+            coverage.logs.join(", "))?;
+        writeln!(out, "{0: <40} {1}\n", tr("Coverage:"), coverage)?;
+    }
+    Ok(())
+}
+
+//
+// Label a report built from a preview of the log as partial, with how much of
+// the log was read.
+//
+fn write_partial(
+    out: &mut dyn Write,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    if let Some(partial) = &data.partial {
+        writeln!(out, "{}", "*".repeat(75))?;
+        writeln!(out, "{0: <40} {1}", tr("Partial Report:"), partial)?;
+        writeln!(out, "{}\n", "*".repeat(75))?;
+    }
+    Ok(())
+}
+
+//
+// Write the sections of the report which follow the device sections, and
+// which look across all of the devices.
+//
+fn write_analysis(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    if !data.zfs.is_empty() {
+        data.zfs.write(out, &data.numbers)?;
+    }
+    if !data.cpus.is_empty() {
+        data.cpus.write(out, &data.numbers, &data.hwgrok)?;
+    }
+    if !data.sensors.is_empty() {
+        thermal::write_correlation(out, device_hash, &data.sensors, data)?;
+    }
+    data.other_events.write(out, &data.numbers)?;
+    data.faults.write_unattached(out, device_hash)?;
+    diagnosis::write_diagnoses(out, &data.cases, &data.numbers)?;
+    boots::write_panics(out, device_hash, &data.boundaries)?;
+    analysis::write_scores(out, device_hash, data)?;
+    if let Some(rate_alerts) = &data.rate_alerts {
+        rate_alerts.write_alerts(out, device_hash, &data.numbers)?;
+    }
+    if let Some(expected_rates) = &data.expected_rates {
+        expected_rates.write_exceedances(out, device_hash, &data.drivers,
+            &data.numbers)?;
+    }
+    if let Some(faulty) = &data.faulty {
+        faulty.write_reconciliation(out, device_hash, &data.hwgrok)?;
+    }
+    if !data.iostats.is_empty() {
+        iostat::write_io_ranking(out, device_hash, &data.iostats,
+            &data.hwgrok)?;
+    }
+    pcie::write_pcie_rollup(out, device_hash, &data.hwgrok)?;
+    cooccur::write_cooccurrences(out, device_hash, data.cooccur_window)?;
+    drivers::write_driver_rollup(out, device_hash, &data.drivers)?;
+    data.owners.write_rollup(out, device_hash, data)?;
+    environment::write_environment(out, device_hash, &data.hwgrok)?;
+    thresholds::write_breaches(out, &data.hwgrok)?;
+    leds::write_led_summary(out, device_hash, &data.hwgrok)?;
+    data.hw_history.write(out)?;
+    data.ses.write(out)?;
+    inventory::write_unknown(out, device_hash, &data.hwgrok)?;
+    if data.healthy_inventory {
+        inventory::write_healthy(out, device_hash, &data.hwgrok)?;
+    }
+
+    Ok(())
+}
+
+//
+// Write a row of a table of ereport classes, with the class highlighted by
+// its severity, and followed by its description if the report is verbose.
+//
+fn write_class_row(
+    out: &mut dyn Write,
+    class: &str,
+    cells: &[(&str, &str)],
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let sev = data.severity.lookup(class);
+    let doc = data.class_docs.as_ref().and_then(|d| d.lookup(class));
+    let rest = data.columns.row(cells);
+    let class = if rest.is_empty() {
+        data.columns.row(&[("class", class)])
+    } else {
+        data.columns.cell("class", class)
+    };
+    let class = sev.paint(&class, data.color);
+    if rest.is_empty() {
+        writeln!(out, "{}", class)?;
+    } else {
+        writeln!(out, "{} {}", class, rest)?;
+    }
+    if let Some(doc) = doc {
+        writeln!(out, "    {}", doc)?;
+    }
+    Ok(())
+}
+
+fn write_device_section(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    devent: &DeviceHashEnt,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    write_device_header(out, devpath, Some(devent), data)?;
+    writeln!(out, "{0: <40} {1}", tr("Total ereports:"),
+        data.numbers.int(devent.ereports.len() as u64))?;
+    write_status(out, devpath, data)?;
+    iostat::write_device_io(out, devpath, devent, &data.iostats,
+        &data.hwgrok)?;
+    writeln!(out)?;
+    smart::write_smart(out, devpath, &data.smart, &data.hwgrok)?;
+    replacement::write_replacements(out, devent, &data.numbers)?;
+    aer::write_device_aer(out, devent, &data.numbers)?;
+    sense::write_device_sense(out, devent, &data.numbers)?;
+    writeln!(out, "{}", data.columns.row(&[("class", "class"),
+        ("count", "# occurences")]))?;
+    writeln!(out, "{}", data.columns.row(&[("class", "-----"),
+        ("count", "------------")]))?;
+    for (ereport_class, erptent) in devent.ereport_class_hash.iter() {
+        let count = data.numbers.int(*erptent as u64);
+        write_class_row(out, ereport_class, &[("count", &count)], data)?;
+    }
+    let heading = tr("Event Occurrence Distribution");
+    writeln!(out, "\n{}\n{}", heading,
+        "-".repeat(heading.chars().count()))?;
+    write_timeline(out, devpath, devent, data)?;
+    writeln!(out)?;
+    data.faults.write_device(out, devpath)?;
+
+    Ok(())
+}
+
+//
+// Write the number of a device's ereports on each day, along with the days
+// of any hardware changes to the device.  Each day is annotated with the
+// changes, reboots and panics on it.
+//
+fn write_timeline(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    devent: &DeviceHashEnt,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let mut days: BTreeSet<String> = devent.ereports_ts.iter().cloned()
+        .collect();
+    days.extend(data.hw_history.days(devpath));
+    for day in &days {
+        let count = match devent.ereport_ts_hash.get(day) {
+            Some(n) => data.numbers.int(*n as u64),
+            None => "-".to_string(),
+        };
+        let markers: Vec<String> = [data.boundaries.day_marker(day),
+            data.hw_history.day_marker(devpath, day)].iter()
+            .filter(|m| !m.is_empty())
+            .cloned()
+            .collect();
+        let line = format!("{0: <40} {1: <8} {2}", day, count,
+            markers.join(" "));
+        writeln!(out, "{}", line.trim_end())?;
+    }
     Ok(())
 }
+
+//
+// Write a terse summary of the devices whose status is WATCH or worse, worst
+// first, and return the corresponding check mode exit status.
+//
+fn write_check(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<i32, Box<dyn Error>> {
+
+    //
+    // The devices are listed by their status badges, which take the severity
+    // of their ereports, faults, sensor thresholds, failure scores and error
+    // rates into account.
+    //
+    let mut problems: Vec<(&DeviceKey, &DeviceStatus)> = data.statuses.iter()
+        .filter(|(_, status)| status.status > Status::Ok)
+        .collect();
+    problems.sort_by(|a, b| b.1.status.cmp(&a.1.status).then(a.0.cmp(b.0)));
+
+    let alerts = match &data.rate_alerts {
+        Some(rate_alerts) => rate_alerts.evaluate(device_hash),
+        None => Vec::new(),
+    };
+
+    //
+    // A stale log is critical whatever it contains, as the devices' statuses
+    // can't be trusted.
+    //
+    let worst = problems.first().map(|p| p.1.status);
+    let (status, label) = match worst {
+        _ if data.stale.is_some() => (CHECK_CRITICAL, "CRITICAL"),
+        Some(Status::Degraded) | Some(Status::Critical) =>
+            (CHECK_CRITICAL, "CRITICAL"),
+        Some(_) => (CHECK_WARNING, "WARNING"),
+        None => (CHECK_OK, "OK"),
+    };
+    write!(out, "{}: {} device(s) with status WATCH or worse", label,
+        problems.len())?;
+    if alerts.is_empty() {
+        writeln!(out)?;
+    } else {
+        writeln!(out, ", {} with rising error rates", alerts.len())?;
+    }
+    if let Some(stale) = &data.stale {
+        writeln!(out, "{0: <10} {1}", "stale", stale)?;
+    }
+    if let Some(partial) = &data.partial {
+        writeln!(out, "{0: <10} {1}", "partial", partial)?;
+    }
+    for (devpath, status) in &problems {
+        let name = data.aliases.lookup(devpath, &data.hwgrok)
+            .unwrap_or(devpath);
+        let count = device_hash.get(*devpath).map(|d| d.ereports.len())
+            .unwrap_or(0);
+        writeln!(out, "{0} {1} ({2} ereports; {3})",
+            status.status.severity().paint(
+            &format!("{0: <10}", status.status.to_string()), data.color),
+            name, count, status.reasons.join("; "))?;
+    }
+    for alert in &alerts {
+        let name = data.aliases.lookup(alert.devpath, &data.hwgrok)
+            .unwrap_or(alert.devpath);
+        writeln!(out, "{0: <10} {1} ({2} ereports/day, up from {3}/day)",
+            "rate", name, data.numbers.float(alert.recent_rate, 1),
+            data.numbers.float(alert.baseline_rate, 1))?;
+    }
+
+    Ok(status)
+}
+
+//
+// Returns the key under which an ereport is recorded in the device hash: the
+// device path of its detector or, for other detector schemes, its FMRI.  None
+// is returned if the ereport is to be skipped, either because it has no usable
+// detector or because the device is excluded.
+//
+fn device_key(config: &Config, ereport: &mut Ereport) -> Option<DeviceKey> {
+    match ereport.detector.scheme.as_str() {
+        "dev" => {
+            let mut dp = match &ereport.detector.device_path {
+                Some(path) => DevicePath::new(path),
+                //
+                // Without a device path, the ereport is keyed by the hc-scheme
+                // FMRI of its detector, to be resolved to the device hwgrok
+                // gives for it once the logs have been read (see
+                // enrich::resolve_fmris).
+                //
+                None if ereport.detector.hc_list.is_some() => {
+                    return ereport.detector.hc_fmristr()
+                        .map(|fmri| DeviceKey::from_fmri(&fmri));
+                }
+                None => {
+                    log::warn("dev detector has no device-path - skipping",
+                        &[("class", json!(ereport.class))]);
+                    return None;
+                }
+            };
+            if config.whole_disk {
+                dp = dp.whole_disk();
+            }
+            let dp = DeviceKey::from(dp);
+            if config.exclude_virtual && devpath::is_virtual(&dp) {
+                return None;
+            }
+            Some(dp)
+        }
+        "hc" | "fmd" | "cpu" | "mem" => {
+            match ereport.detector.get_fmristr() {
+                Ok(fmri) => Some(DeviceKey::from_fmri(&fmri)),
+                Err(_) => {
+                    log::warn("failed to get fmri - skipping",
+                        &[("class", json!(ereport.class))]);
+                    None
+                }
+            }
+        }
+        _ => {
+            log::warn("unsupported detector scheme - skipping",
+                &[("class", json!(ereport.class)),
+                ("scheme", json!(ereport.detector.scheme))]);
+            None
+        }
+    }
+}
+
+//
+// Read a single event from an FM log into the data read so far, returning its
+// time.
+//
+fn read_event(
+    config: &Config,
+    log: &mut LogData,
+    line: &str,
+    line_no: usize,
+    lookups: &Lookups,
+    plugins: &mut PluginSet
+) -> Result<Option<i64>, Box<dyn Error>> {
+
+    let event: FmEvent = serde_json::from_str(line)
+        .map_err(|e| FmLogError::JsonParse { line_no, source: e })?;
+    let tod = event.tod.first().cloned();
+    log.newest_event = log.newest_event.max(tod);
+
+    //
+    // Suspect lists are retained so that diagnosed fault cases can be
+    // associated with the devices they implicate.
+    //
+    if event.class == "list.suspect" {
+        let value: serde_json::Value = serde_json::from_str(line)?;
+        if let Some(case) = SuspectCase::from_json(&value) {
+            log.cases.push(case);
+        }
+        return Ok(tod);
+    }
+    if event.class.starts_with("fault.") {
+        let value: serde_json::Value = serde_json::from_str(line)?;
+        if let Some(fault) = Fault::from_json(&value) {
+            log.faults.add(fault);
+        }
+        return Ok(tod);
+    }
+    if event.class.starts_with(boots::PANIC_CLASS_PREFIX) {
+        let value: serde_json::Value = serde_json::from_str(line)?;
+        if let Some(t) = boots::panic_time(&value) {
+            log.panics.push(t);
+        }
+        return Ok(tod);
+    }
+
+    //
+    // Other events, such as upsets and sysevents, aren't associated with
+    // a device and are simply tallied by class.
+    //
+    if !event.class.starts_with("ereport.") {
+        log.other_events.add(&event.class, tod.unwrap_or(0));
+        return Ok(tod);
+    }
+    if !config.in_window(tod) || !config.filter.wants_class(&event.class) ||
+        !config.filter.wants_event(line)? {
+        return Ok(tod);
+    }
+    //
+    // ZFS ereports are detected by pool and vdev rather than by device,
+    // so are gathered separately.  Other filesystem ereports are skipped.
+    //
+    if event.class.starts_with("ereport.fs.") {
+        let wanted = config.min_severity
+            .is_none_or(|min| lookups.severity.lookup(&event.class) >= min);
+        if event.class.starts_with(zfs::CLASS_PREFIX) && wanted {
+            log.zfs.add(&serde_json::from_str(line)?);
+        }
+        return Ok(tod);
+    }
+    //
+    // We also skip fmd ereports related to log errors as their payload
+    // doesn't contain a detector member.
+    //
+    if event.class.starts_with("ereport.fm.fmd.log_") {
+        return Ok(tod);
+    }
+
+    if let Some(min) = config.min_severity {
+        if lookups.severity.lookup(&event.class) < min {
+            return Ok(tod);
+        }
+    }
+
+    let mut ereport = parse_ereport(line)
+        .map_err(|e| e.at_line(line_no))?;
+
+    let key = match device_key(config, &mut ereport) {
+        Some(key) => key,
+        None => return Ok(tod),
+    };
+    let resolved = enrich::resolved_key(lookups.hwgrok, &ereport, &key);
+    if !config.filter.wants_device(&key, resolved.as_ref()) {
+        return Ok(tod);
+    }
+
+    if plugins.decodes() {
+        let value: serde_json::Value = serde_json::from_str(line)?;
+        plugins.decode(&key, &value, &mut ereport.payload)?;
+    }
+    //
+    // CPU and memory ereports are also decoded for their own sections.
+    //
+    if ereport.class.starts_with(cpu::CLASS_PREFIX) {
+        log.cpus.add(&key, &ereport);
+    }
+    process_event(&mut log.device_hash, &key, ereport)?;
+    Ok(tod)
+}
+
+//
+// Read in the FM logs at the given paths, or the lines of one already supplied
+// by the caller, building up the device hash from the ereports and gathering
+// any diagnosed fault cases and faults.
+//
+fn read_fmlog(
+    config: &Config,
+    paths: &[String],
+    source: Option<Lines>,
+    input: &InputReader,
+    lookups: &Lookups,
+    plugins: &mut PluginSet
+) -> Result<LogData, Box<dyn Error>> {
+
+    let mut timings = Timings::default();
+    let start = Instant::now();
+    let (logs, partial) = match (source, config.preview) {
+        (Some(lines), _) => {
+            (vec![(paths.first().cloned().unwrap_or_default(), lines)], None)
+        }
+        (None, Some(bytes)) => match paths {
+            [path] => {
+                let (lines, partial) = input.read_tail(path, bytes)?;
+                (vec![(path.clone(), lines)], partial)
+            }
+            _ => {
+                return Err(Box::new(SimpleError("--preview can only be used \
+                    with a single FM log".to_string())));
+            }
+        },
+        (None, None) => (logset::open_logs(input, paths)?, None),
+    };
+    let names: Vec<String> = logs.iter().map(|(path, _)| path.clone())
+        .collect();
+    let mut lines = logs.into_iter().enumerate()
+        .flat_map(|(n, (_, lines))| lines.enumerate()
+            .map(move |(i, line)| (n, i + 1, line)));
+    let mut read_time = start.elapsed();
+    let mut nevents = 0;
+    //
+    // Time spent fetching each line is counted as reading, and the rest as
+    // parsing.
+    //
+    let timed_lines = iter::from_fn(|| {
+        let start = Instant::now();
+        let line = lines.next();
+        read_time += start.elapsed();
+        nevents += line.is_some() as usize;
+        line
+    });
+
+    let mut log = LogData {
+        device_hash: HashMap::new(),
+        cases: Vec::new(),
+        faults: Faults::default(),
+        zfs: ZfsPools::default(),
+        cpus: CpuErrors::default(),
+        panics: Vec::new(),
+        other_events: OtherEvents::default(),
+        newest_event: None,
+        partial,
+        coverage: None,
+        timings: Timings::default(),
+    };
+    let mut oldest_event: Option<i64> = None;
+
+    let start = Instant::now();
+    for (n, line_no, l) in timed_lines {
+        // a log read from a reader or from fmdump may have no name
+        let line = l.map_err(|e| FmLogError::Io {
+            path: Some(names[n].clone()).filter(|name| !name.is_empty()),
+            source: e,
+        })?;
+        if let Some(t) = read_event(config, &mut log, &line, line_no,
+            lookups, plugins)? {
+            oldest_event = Some(oldest_event.map_or(t, |o| o.min(t)));
+        }
+    }
+
+    log.panics.sort();
+    log.panics.dedup();
+
+    //
+    // The events of logs which overlap in time are read out of order, so
+    // each device's events are put back in order.
+    //
+    if names.len() > 1 {
+        for devent in log.device_hash.values_mut() {
+            devent.ereports.sort_by_key(|e| (e.tod[0], e.tod.get(1).cloned()));
+            devent.ereports_ts.sort();
+        }
+        log.coverage = Some(Coverage { logs: names, first: oldest_event,
+            last: log.newest_event });
+    }
+
+    timings.add(Phase::Parse, start.elapsed().saturating_sub(read_time));
+    timings.add(Phase::Read, read_time);
+    timings.events = nevents;
+    if config.timing {
+        timings.memory = Some(memstats::measure(&log.device_hash));
+    }
+    log.timings = timings;
+
+    Ok(log)
+}
+
+//
+// The values available to output path templates.
+//
+fn template_vars(config: &Config, format: ReportFormat)
+    -> Vec<(&'static str, String)> {
+
+    let now = Utc::now();
+    let hostname = match &config.hostname {
+        Some(h) => h.clone(),
+        None => output::local_hostname(),
+    };
+    vec![
+        ("hostname", hostname),
+        ("date", now.format("%Y-%m-%d").to_string()),
+        ("time", now.format("%H%M%S").to_string()),
+        ("ext", format.ext().to_string()),
+    ]
+}
+
+//
+// Write a separate report file for each device into the output directory,
+// named by the device's alias or bay label if it has one, and otherwise by its
+// path.
+//
+fn write_split_reports(
+    config: &Config,
+    device_hash: &DeviceHash,
+    data: &ReportData,
+    dir: &str
+) -> Result<(), Box<dyn Error>> {
+
+    let vars = template_vars(config, config.format);
+    let dir = &output::expand_template(dir, &vars)?;
+    fs::create_dir_all(dir)?;
+
+    let mut devpaths: Vec<&DeviceKey> = device_hash.keys().collect();
+    devpaths.sort();
+
+    let mut used: HashSet<String> = HashSet::new();
+    for devpath in devpaths {
+        let label = data.aliases.lookup(devpath, &data.hwgrok)
+            .or_else(|| data.hwgrok.find_disk(devpath)
+                .map(|(bay, _)| bay.bay_label.as_str()))
+            .unwrap_or(devpath);
+        let base = output::sanitize_filename(label);
+        let mut name = base.clone();
+        let mut n = 1;
+        while !used.insert(name.clone()) {
+            n += 1;
+            name = format!("{}-{}", base, n);
+        }
+
+        let report = match config.format {
+            ReportFormat::Text => {
+                let mut report = Vec::new();
+                write_device_section(&mut report, devpath,
+                    &device_hash[devpath], data)?;
+                report
+            }
+            ReportFormat::Json => json_bytes(&model::versioned(
+                server::device_json(devpath, device_hash.get(devpath), data)))?,
+            ReportFormat::Csv => {
+                let mut report = Vec::new();
+                export::write_csv_header(&mut report)?;
+                export::write_device_csv(&mut report, devpath,
+                    &device_hash[devpath])?;
+                report
+            }
+            ReportFormat::Html => {
+                let mut report = Vec::new();
+                html::write_html_device(&mut report, &report_title(config),
+                    devpath, device_hash.get(devpath), data)?;
+                report
+            }
+            ReportFormat::Markdown => {
+                let mut report = Vec::new();
+                markdown::write_markdown_device(&mut report,
+                    &report_title(config), devpath, device_hash.get(devpath),
+                    data)?;
+                report
+            }
+        };
+        let ext = &vars.iter().find(|(n, _)| *n == "ext").unwrap().1;
+        let path = Path::new(dir).join(format!("{}.{}", name, ext));
+        output::write_file(&path.to_string_lossy(), &report,
+            config.compress)?;
+    }
+
+    Ok(())
+}
+
+//
+// Returns the title of the report, for formats that have one.
+//
+fn report_title(config: &Config) -> String {
+    let host = match &config.hostname {
+        Some(h) => h.clone(),
+        None => output::local_hostname(),
+    };
+    format!("FM Log Report: {}", host)
+}
+
+//
+// Serialize a JSON report, terminated by a newline so that it's a complete
+// line of output for tools such as jq.
+//
+fn json_bytes(value: &serde_json::Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = serde_json::to_vec_pretty(value)?;
+    bytes.push(b'\n');
+    Ok(bytes)
+}
+
+//
+// Returns the outputs the report is to be written to, each with its path (or
+// None for stdout) and format.  An output whose format was neither given nor
+// implied by its extension takes that of --format.
+//
+fn outputs(config: &Config) -> Vec<(Option<&str>, ReportFormat)> {
+    if config.outputs.is_empty() {
+        return vec![(None, config.format)];
+    }
+    config.outputs.iter()
+        .map(|o| (o.path.as_deref(), o.format.unwrap_or(config.format)))
+        .collect()
+}
+
+//
+// Write each finished report to its output file or to stdout (via the pager,
+// if appropriate).  If an archive directory was given, the first report is
+// also stored there and the archive pruned.
+//
+fn emit(
+    config: &Config,
+    reports: &[(Option<&str>, ReportFormat, Vec<u8>)]
+) -> Result<(), Box<dyn Error>> {
+
+    if let (Some(dir), Some((_, format, report))) =
+        (&config.archive_dir, reports.first()) {
+        let host = match &config.hostname {
+            Some(h) => h.clone(),
+            None => output::local_hostname(),
+        };
+        archive::store(dir, &host, report, format.ext(),
+            config.compress, &config.retention, Utc::now())?;
+    }
+    for (path, format, report) in reports {
+        match path {
+            Some(template) => {
+                let path = output::expand_template(template,
+                    &template_vars(config, *format))?;
+                output::write_file(&path, report, config.compress)?;
+            }
+            None => pager::output(report, config.no_pager)?,
+        }
+    }
+    Ok(())
+}
+
+//
+// Render the report, or that of a single device, in the given format.
+//
+fn render(
+    config: &Config,
+    format: ReportFormat,
+    device_hash: &DeviceHash,
+    data: &ReportData,
+    devpath: Option<&DeviceKey>
+) -> Result<Vec<u8>, Box<dyn Error>> {
+
+    let mut report = Vec::new();
+    let devpath = match devpath {
+        Some(d) => d,
+        None => {
+            match format {
+                ReportFormat::Text => {
+                    write_report(&mut report, device_hash, data)?;
+                }
+                ReportFormat::Json => {
+                    report = json_bytes(&server::report_json(device_hash,
+                        data))?;
+                }
+                ReportFormat::Csv => export::write_csv(&mut report,
+                    device_hash)?,
+                ReportFormat::Html => {
+                    html::write_html_report(&mut report, &report_title(config),
+                        device_hash, data)?;
+                }
+                ReportFormat::Markdown => {
+                    markdown::write_markdown_report(&mut report,
+                        &report_title(config), device_hash, data)?;
+                }
+            }
+            return Ok(report);
+        }
+    };
+    match format {
+        ReportFormat::Text => {
+            device::write_device_report(&mut report, devpath,
+                device_hash.get(devpath), data)?;
+        }
+        ReportFormat::Json => {
+            report = json_bytes(&model::versioned(server::device_json(devpath,
+                device_hash.get(devpath), data)))?;
+        }
+        ReportFormat::Csv => {
+            export::write_csv_header(&mut report)?;
+            if let Some(devent) = device_hash.get(devpath) {
+                export::write_device_csv(&mut report, devpath, devent)?;
+            }
+        }
+        ReportFormat::Html => {
+            html::write_html_device(&mut report, &report_title(config),
+                devpath, device_hash.get(devpath), data)?;
+        }
+        ReportFormat::Markdown => {
+            markdown::write_markdown_device(&mut report, &report_title(config),
+                devpath, device_hash.get(devpath), data)?;
+        }
+    }
+    Ok(report)
+}
+
+//
+// Returns the same text report for every output, for the subcommands which
+// only produce text.
+//
+fn text_reports<'a>(config: &'a Config, report: &[u8])
+    -> Vec<(Option<&'a str>, ReportFormat, Vec<u8>)> {
+
+    outputs(config).into_iter()
+        .map(|(path, _)| (path, ReportFormat::Text, report.to_vec()))
+        .collect()
+}
+
+//
+// Everything needed to generate a report: the device hash, the data used to
+// augment it and the plugins which may be asked to enrich further devices.
+//
+struct Loaded {
+    device_hash: DeviceHash,
+    data: ReportData,
+    plugins: PluginSet,
+    timings: Timings,
+}
+
+//
+// Read the configuration file, if one was given, with the selected profile.
+//
+fn read_conf(config: &Config) -> Result<ConfFile, Box<dyn Error>> {
+    let mut conf = match &config.config_path {
+        Some(path) => ConfFile::from_file(path)?,
+        None => ConfFile::default(),
+    };
+    if let Some(profile) = &config.profile {
+        conf.set_profile(profile)?;
+    }
+    Ok(conf)
+}
+
+//
+// The FM log and hwgrok data passed to run_from_readers(), which take the
+// place of the files named by the config.
+//
+#[derive(Default)]
+struct Sources {
+    fmlog: Option<Lines>,
+    hwgrok: Option<HwGrok>,
+}
+
+//
+// Read in the configuration file, the FM log and the supplementary data
+// described by the config, and build up the device hash.
+//
+fn load(config: &Config, sources: Sources)
+    -> Result<Loaded, Box<dyn Error>> {
+
+    let start = Instant::now();
+    let conf = read_conf(config)?;
+    let input = InputReader::new(config.input_format, &conf)?;
+    let severity = SeverityMap::from_conf(&conf)?;
+    let rate_alerts = RateAlerts::from_conf(&conf)?;
+    let expected_rates = ExpectedRates::from_conf(&conf)?;
+    let columns = TableFormat::from_conf(&conf)?;
+    let numbers = NumberFormat::from_conf(&conf)?;
+    if let Some(lang) = &config.lang {
+        i18n::set_lang(lang, &conf)?;
+    }
+    let mut plugins = PluginSet::from_conf(&conf)?;
+
+    let mut snapshots = Vec::new();
+    for snapshot in &config.hwgrok_snapshots {
+        snapshots.push((snapshot.time, process_hwgrok_data(&snapshot.path)?));
+    }
+
+    //
+    // Without hwgrok output of its own, the report is of the hardware as of
+    // the latest snapshot.
+    //
+    let mut hwgrok : HwGrok = match (sources.hwgrok, &config.hwgrok_path) {
+        (Some(hwgrok), _) => hwgrok,
+        (None, Some(path)) => {
+            process_hwgrok_data(path)?
+        }
+        (None, None) => match &config.topo_path {
+            Some(path) => process_topo_data(path)?,
+            None if !config.hwgrok_snapshots.is_empty() => {
+                let latest = config.hwgrok_snapshots.iter()
+                    .max_by_key(|s| s.time).unwrap();
+                process_hwgrok_data(&latest.path)?
+            }
+            None => HwGrok::default(),
+        }
+    };
+    let hw_history = HwHistory::from_snapshots(snapshots);
+
+    let aliases = match &config.alias_path {
+        Some(path) => AliasMap::from_file(path)?,
+        None => AliasMap::default(),
+    };
+
+    let smart = match &config.smart_path {
+        Some(path) => SmartData::from_file(path)?,
+        None => SmartData::default(),
+    };
+
+    let ses = SesData::from_files(&config.ses_paths)?;
+
+    let faulty = match &config.faulty_path {
+        Some(path) => Some(FaultyCases::from_file(path, &conf)?),
+        None => None,
+    };
+
+    let drivers = match &config.path_to_inst {
+        Some(path) => DriverMap::from_file(path)?,
+        None => DriverMap::default(),
+    };
+
+    let sensors = match &config.sensor_path {
+        Some(path) => SensorHistory::from_file(path)?,
+        None => SensorHistory::default(),
+    };
+
+    let assets = match &config.assets_path {
+        Some(path) => AssetData::from_file(path)?,
+        None => AssetData::default(),
+    };
+
+    let iostats = match &config.iostat_path {
+        Some(path) => IoStats::from_file(path)?,
+        None => IoStats::default(),
+    };
+
+    let enrich_time = start.elapsed();
+    let LogData { mut device_hash, mut cases, faults, zfs, cpus, panics,
+        other_events, newest_event, partial, coverage, mut timings } =
+        read_fmlog(config, &config.fmlog_paths, sources.fmlog, &input,
+            &Lookups { severity: &severity, hwgrok: &hwgrok }, &mut plugins)?;
+    timings.add(Phase::Enrich, enrich_time);
+
+    let start = Instant::now();
+    if let Some(path) = &config.sel_path {
+        sel::merge(&sel::read_sel(path)?, &hwgrok, &severity,
+            config.min_severity, &mut device_hash)?;
+    }
+
+    enrich::resolve_fmris(&hwgrok, &mut device_hash);
+
+    let mut boundaries = Boundaries { boots: Vec::new(), panics };
+    if let Some(path) = &config.boot_times_path {
+        boundaries.read_boot_times(path)?;
+    }
+
+    let start = timings.since(Phase::Enrich, start);
+    if config.merge_multipath {
+        multipath::consolidate(&mut device_hash);
+    }
+    enrich::match_disks(&mut hwgrok, &device_hash);
+    let alt_paths = multipath::find_paths(&device_hash);
+    diagnosis::correlate(&mut cases, &device_hash);
+    let start = timings.since(Phase::Analyze, start);
+    let mut plugin_fields = HashMap::new();
+    if !plugins.is_empty() {
+        for devpath in device_hash.keys() {
+            let fields = plugins.enrich(devpath,
+                &hwgrok.identifiers(devpath))?;
+            plugin_fields.insert(devpath.clone(), fields);
+        }
+    }
+    timings.since(Phase::Enrich, start);
+    let mut data = ReportData {
+        hwgrok,
+        aliases,
+        owners: OwnerMap::from_conf(&conf),
+        smart,
+        ses,
+        sensors,
+        assets,
+        iostats,
+        boundaries,
+        other_events,
+        cases,
+        faults,
+        zfs,
+        cpus,
+        hw_history,
+        alt_paths,
+        cooccur_window: config.cooccur_window,
+        sort_by: config.sort_by,
+        severity,
+        rate_alerts,
+        expected_rates,
+        faulty,
+        drivers,
+        columns,
+        numbers,
+        color: config.color,
+        plugin_fields,
+        statuses: HashMap::new(),
+        class_docs: if config.verbose {
+            Some(ClassDocs::from_conf(&conf))
+        } else {
+            None
+        },
+        healthy_inventory: config.healthy_inventory,
+        stale: config.max_age.and_then(|max_age| staleness::check(max_age,
+            newest_event, Utc::now().timestamp())),
+        partial,
+        coverage,
+    };
+    data.statuses = status::evaluate(&device_hash, &data);
+
+    Ok(Loaded { device_hash, data, plugins, timings })
+}
+
+//
+// Generate the report described by the config.  On success, the exit status
+// for the program is returned, which is always zero except in check mode.
+// If the config names no FM logs, the report, device and check modes report
+// on the local system, running fmdump and hwgrok.
+//
+pub fn run(config: &Config) -> Result<i32, FmLogError> {
+    run_sources(config, Sources::default())
+}
+
+//
+// As run(), but with the FM log, and optionally the hwgrok output, read from
+// the given readers rather than from the files named by the config, for
+// callers which already hold them in memory (the first of config.fmlog_paths
+// then only names the log in messages).  The log must be in fmdump format,
+// and only the report, device and check modes are supported.
+//
+pub fn run_from_readers<F, H>(
+    config: &Config,
+    fmlog: F,
+    hwgrok: Option<H>
+) -> Result<i32, FmLogError>
+    where F: BufRead + 'static, H: Read {
+
+    let hwgrok = match hwgrok {
+        Some(mut reader) => {
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents)?;
+            Some(hwgrok::parse_hwgrok(&contents)?)
+        }
+        None => None,
+    };
+    run_sources(config, Sources {
+        fmlog: Some(Box::new(fmlog.lines())),
+        hwgrok,
+    })
+}
+
+fn run_sources(config: &Config, sources: Sources)
+    -> Result<i32, FmLogError> {
+
+    let mut summary = RunSummary::default();
+    let result = run_mode(config, sources, &mut summary);
+    let result = match &config.summary {
+        //
+        // A failure to write the summary mustn't hide the reason the run
+        // itself failed.
+        //
+        Some(dest) => {
+            let written = summary.write(dest, &config.mode, &result);
+            result.and_then(|status| written.map(|_| status))
+        }
+        None => result,
+    };
+    result.map_err(FmLogError::from)
+}
+
+//
+// Returns the device named on the command line, by its path or label, or an
+// error if there's no trace of it.
+//
+fn find_device(
+    arg: &str,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<DeviceKey, Box<dyn Error>> {
+
+    device::resolve_device(arg, device_hash, data).ok_or_else(|| {
+        let err: Box<dyn Error> = Box::new(SimpleError(
+            format!("no events or hardware found for {}", arg)));
+        err
+    })
+}
+
+fn run_mode(
+    config: &Config,
+    sources: Sources,
+    summary: &mut RunSummary
+) -> Result<i32, Box<dyn Error>> {
+
+    if sources.fmlog.is_some() {
+        let supported = matches!(config.mode,
+            Mode::Report | Mode::Device(_) | Mode::Check);
+        if !supported || config.validate || config.export_events.is_some() {
+            return Err(Box::new(SimpleError("only the report, device and \
+                check modes can read the FM log from a reader".to_string())));
+        }
+        if config.input_format != InputFormat::Fmdump {
+            return Err(Box::new(SimpleError("an FM log read from a reader \
+                must be in fmdump format".to_string())));
+        }
+    }
+
+    //
+    // Without FM logs, the report is of the local system.
+    //
+    let live = config.fmlog_paths.is_empty() && sources.fmlog.is_none() &&
+        matches!(config.mode, Mode::Report | Mode::Device(_) | Mode::Check);
+    let sources = if live {
+        if config.input_format != InputFormat::Fmdump {
+            return Err(Box::new(SimpleError("the FM logs of the local system \
+                are read with fmdump, so they must be in fmdump \
+                format".to_string())));
+        }
+        let hwgrok = match (sources.hwgrok, &config.hwgrok_path) {
+            (Some(hwgrok), _) => Some(hwgrok),
+            (None, Some(_)) => None,
+            (None, None) if config.topo_path.is_some() => None,
+            (None, None) => live::hwgrok(),
+        };
+        Sources { fmlog: Some(live::fmdump()?), hwgrok }
+    } else {
+        sources
+    };
+
+    //
+    // A followed log is read through a tail, which is kept to read the events
+    // appended to it afterwards.
+    //
+    let mut tail = None;
+    let sources = match (config.follow, &config.fmlog_paths[..]) {
+        (None, _) => sources,
+        (Some(_), [path]) if sources.fmlog.is_none() => {
+            let supported = matches!(config.mode,
+                Mode::Report | Mode::Device(_));
+            if !supported || config.validate || config.split_by.is_some() ||
+                config.export_events.is_some() {
+                return Err(Box::new(SimpleError("--follow can only be used \
+                    for the report of all devices or of one".to_string())));
+            }
+            if config.input_format != InputFormat::Fmdump ||
+                config.preview.is_some() {
+                return Err(Box::new(SimpleError("--follow can't be used with \
+                    --input-format or --preview".to_string())));
+            }
+            let t = Rc::new(RefCell::new(Tail::open(path)?));
+            let fmlog = Some(follow::lines(&t));
+            tail = Some(t);
+            Sources { fmlog, hwgrok: sources.hwgrok }
+        }
+        (Some(_), _) => {
+            return Err(Box::new(SimpleError("--follow can only be used with \
+                a single FM log".to_string())));
+        }
+    };
+
+    if config.validate {
+        let conf = read_conf(config)?;
+        let input = InputReader::new(config.input_format, &conf)?;
+        let mut report = Vec::new();
+        let valid = validate::validate(&mut report, config, &input)?;
+        pager::output(&report, true)?;
+        summary.reason = Some(if valid { "valid" } else { "invalid" });
+        return Ok(if valid { 0 } else { 1 });
+    }
+
+    if let Mode::CheckHwgrok(path) = &config.mode {
+        let mut report = Vec::new();
+        let ok = hwcheck::check_hwgrok(&mut report, path)?;
+        pager::output(&report, config.no_pager)?;
+        summary.reason = Some(if ok { "valid" } else { "invalid" });
+        return Ok(if ok { 0 } else { 1 });
+    }
+
+    if let Mode::Generate(dir) = &config.mode {
+        let conf = read_conf(config)?;
+        let synth = Synthetic::from_conf(&conf)?;
+        let ereports = synth.write(dir)?;
+        if config.verbose {
+            log::info(&format!("wrote {} ereport(s) for {} host(s) to {}",
+                ereports, synth.hosts, dir), &[("ereports", json!(ereports)),
+                ("hosts", json!(synth.hosts)), ("dir", json!(dir))]);
+        }
+        summary.reason = Some("generated");
+        return Ok(0);
+    }
+
+    if let Mode::Fleet(dir) = &config.mode {
+        let conf = read_conf(config)?;
+        let mut report = Vec::new();
+        fleet::write_fleet_report(&mut report, config, dir, &conf)?;
+        emit(config, &text_reports(config, &report))?;
+        return Ok(0);
+    }
+
+    if let Mode::Forward = &config.mode {
+        let conf = read_conf(config)?;
+        let input = InputReader::new(config.input_format, &conf)?;
+        let severity = SeverityMap::from_conf(&conf)?;
+        let hwgrok = match &config.hwgrok_path {
+            Some(path) => process_hwgrok_data(path)?,
+            None => HwGrok::default(),
+        };
+        //
+        // Events are streamed to stdout, so that the next stage can start on
+        // them straight away, but are collected to be written to files.
+        //
+        if config.outputs.is_empty() {
+            let stdout = io::stdout();
+            let mut out = io::BufWriter::new(stdout.lock());
+            forward::forward(&mut out, config, &input, &severity, &hwgrok)?;
+            out.flush()?;
+            return Ok(0);
+        }
+        let mut events = Vec::new();
+        forward::forward(&mut events, config, &input, &severity, &hwgrok)?;
+        for (path, _) in outputs(config) {
+            match path {
+                Some(template) => {
+                    let path = output::expand_template(template,
+                        &template_vars(config, ReportFormat::Json))?;
+                    output::write_file(&path, &events, config.compress)?;
+                }
+                None => io::stdout().write_all(&events)?,
+            }
+        }
+        return Ok(0);
+    }
+
+    let Loaded { device_hash, mut data, mut plugins, mut timings } =
+        load(config, sources)?;
+    let start = Instant::now();
+    summary.record_report(&device_hash, &data);
+    let start = timings.since(Phase::Analyze, start);
+    if let Some(stale) = &data.stale {
+        log::warn(&format!("stale FM log: {}", stale), &[]);
+    }
+
+    if let (Some(interval), Some(tail)) = (config.follow, &tail) {
+        follow::follow(config, tail, interval, Loaded { device_hash, data,
+            plugins, timings })?;
+        return Ok(0);
+    }
+
+    //
+    // The device's ereports are read from the logs again, to export them as
+    // they were logged rather than as they were parsed.
+    //
+    if let Some(arg) = &config.export_events {
+        let devpath = find_device(arg, &device_hash, &data)?;
+        let mut keys = vec![&devpath];
+        if let Some(devent) = device_hash.get(&devpath) {
+            keys.extend(devent.merged_paths.iter());
+        }
+        let conf = read_conf(config)?;
+        let input = InputReader::new(config.input_format, &conf)?;
+        let events = rawevents::export(config, &input, &keys)?;
+        for (path, _) in outputs(config) {
+            match path {
+                Some(template) => {
+                    let path = output::expand_template(template,
+                        &template_vars(config, ReportFormat::Json))?;
+                    output::write_file(&path, &events, config.compress)?;
+                }
+                None => io::stdout().write_all(&events)?,
+            }
+        }
+        summary.reason = Some("exported");
+        return Ok(0);
+    }
+
+    let mut reports = Vec::new();
+    let mut status = 0;
+    match &config.mode {
+        Mode::Report => {
+            if let (Some(SplitBy::Device), Some(dir)) =
+                (&config.split_by, &config.output_dir) {
+                write_split_reports(config, &device_hash, &data, dir)?;
+            } else {
+                for (path, format) in outputs(config) {
+                    reports.push((path, format, render(config, format,
+                        &device_hash, &data, None)?));
+                }
+            }
+        }
+        Mode::Device(arg) => {
+            let devpath = find_device(arg, &device_hash, &data)?;
+            if !plugins.is_empty() &&
+                !data.plugin_fields.contains_key(&devpath) {
+                let fields = plugins.enrich(&devpath,
+                    &data.hwgrok.identifiers(&devpath))?;
+                data.plugin_fields.insert(devpath.clone(), fields);
+            }
+            for (path, format) in outputs(config) {
+                reports.push((path, format, render(config, format,
+                    &device_hash, &data, Some(&devpath))?));
+            }
+        }
+        Mode::Check => {
+            let mut report = Vec::new();
+            status = write_check(&mut report, &device_hash, &data)?;
+            reports = text_reports(config, &report);
+            summary.reason = Some(match status {
+                CHECK_OK => "check-ok",
+                CHECK_WARNING => "check-warning",
+                _ if data.stale.is_some() => "stale-log",
+                _ => "check-critical",
+            });
+        }
+        Mode::Serve(addr) => {
+            server::serve(config, addr, Loaded { device_hash, data, plugins,
+                timings })?;
+            return Ok(0);
+        }
+        Mode::Fleet(_) | Mode::CheckHwgrok(_) | Mode::Forward |
+        Mode::Generate(_) => {
+            unreachable!()
+        }
+    }
+    // Split reports have already been written out.
+    if config.split_by.is_none() || !matches!(config.mode, Mode::Report) {
+        emit(config, &reports)?;
+    }
+    if let (Mode::Report, Some(upload)) = (&config.mode, &config.upload) {
+        let host = match &config.hostname {
+            Some(h) => h.clone(),
+            None => output::local_hostname(),
+        };
+        upload::upload(upload, &host, server::report_json(&device_hash,
+            &data))?;
+    }
+    let start = timings.since(Phase::Render, start);
+
+    //
+    // Report any tripped --fail-on-class gates last, so that they aren't
+    // lost in the report on a terminal.
+    //
+    let tripped = gate::evaluate(&config.fail_on_class, &device_hash);
+    for (gate, count) in &tripped {
+        log::warn(&format!("fail-on-class: {} ereport(s) matching {} \
+            (limit {})", count, gate.pattern, gate.count),
+            &[("class", json!(gate.pattern)), ("count", json!(count)),
+            ("limit", json!(gate.count))]);
+    }
+    summary.record_gates(&tripped);
+    if !tripped.is_empty() {
+        summary.reason = Some("fail-on-class");
+        status = match config.mode {
+            Mode::Check => CHECK_CRITICAL,
+            _ => gate::FAIL_ON_CLASS_STATUS,
+        };
+    }
+    timings.since(Phase::Analyze, start);
+    if config.timing {
+        timings.report();
+    }
+
+    Ok(status)
+}
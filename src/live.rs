@@ -0,0 +1,62 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Live mode, in which no FM logs are given and the report is of the local
+// system: fmdump is run to read its FM logs and, unless a hwgrok file is
+// given, hwgrok is run to describe its hardware, so that a health report is
+// a single command.  The report goes without the hardware details if hwgrok
+// can't be run, as it isn't installed everywhere, but not without fmdump.
+//
+use std::io::BufRead;
+use std::process::{Command, Stdio};
+
+use hwgrok::{self, HwGrok};
+use input;
+use log;
+use {FmLogError, Lines};
+
+const FMDUMP: &str = "fmdump";
+const FMDUMP_ARGS: [&str; 1] = ["-AVj"];
+const HWGROK: &str = "hwgrok";
+
+//
+// Returns the events of the local system's FM logs, as fmdump writes them.
+//
+pub fn fmdump() -> Result<Lines, FmLogError> {
+    let output = input::run_program(FMDUMP, &FMDUMP_ARGS, Stdio::null())?;
+    Ok(Box::new(output.lines()))
+}
+
+//
+// Returns the local system's hardware as hwgrok describes it, or None if
+// hwgrok can't be run.
+//
+pub fn hwgrok() -> Option<HwGrok> {
+    let output = match Command::new(HWGROK).stdin(Stdio::null()).output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            log::warn("hwgrok failed - no hardware details will be reported",
+                &[("status", json!(output.status.to_string())),
+                ("stderr", json!(String::from_utf8_lossy(&output.stderr)
+                    .trim()))]);
+            return None;
+        }
+        Err(e) => {
+            log::warn("failed to run hwgrok - no hardware details will be \
+                reported", &[("error", json!(e.to_string()))]);
+            return None;
+        }
+    };
+    match hwgrok::parse_hwgrok(&String::from_utf8_lossy(&output.stdout)) {
+        Ok(hwgrok) => Some(hwgrok),
+        Err(e) => {
+            log::warn("invalid hwgrok output - no hardware details will be \
+                reported", &[("error", json!(e.to_string()))]);
+            None
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Ordering of devices by their physical location, so that someone at the
+// chassis can work through the report bay by bay and slot by slot.  Devices
+// are located by their labels in the hwgrok data (see
+// HwGrok::location_label), which are compared in natural order, so that
+// "Slot 2" comes before "Slot 10".  Devices without a label follow the rest,
+// in order of their paths.
+//
+use std::cmp::Ordering;
+use std::error::Error;
+use std::io::Write;
+
+use devpath::DeviceKey;
+use hwgrok::HwGrok;
+use i18n::tr;
+use DeviceHashEnt;
+
+//
+// Compare two labels, treating each run of digits as a number.
+//
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().cloned(), b.peek().cloned()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x = String::new();
+                while let Some(c) = a.next_if(|c| c.is_ascii_digit()) {
+                    x.push(c);
+                }
+                let mut y = String::new();
+                while let Some(c) = b.next_if(|c| c.is_ascii_digit()) {
+                    y.push(c);
+                }
+                let (x, y) = (x.trim_start_matches('0'),
+                    y.trim_start_matches('0'));
+                let ord = x.len().cmp(&y.len()).then(x.cmp(y));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+//
+// Sort the devices by location.
+//
+pub fn sort_by_location<T>(devices: &mut [(&DeviceKey, T)], hwgrok: &HwGrok) {
+    devices.sort_by(|(a, _), (b, _)| {
+        match (hwgrok.location_label(a), hwgrok.location_label(b)) {
+            (Some(x), Some(y)) => natural_cmp(x, y).then(a.cmp(b)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.cmp(b),
+        }
+    });
+}
+
+//
+// Write an index of the devices in the report which have a physical
+// location, in location order.
+//
+pub fn write_location_index(
+    out: &mut dyn Write,
+    devices: &[(&DeviceKey, &DeviceHashEnt)],
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let mut located: Vec<(&str, &DeviceKey, usize)> = devices.iter()
+        .filter_map(|(devpath, devent)| hwgrok.location_label(devpath)
+            .map(|label| (label, *devpath, devent.ereports.len())))
+        .collect();
+    if located.is_empty() {
+        return Ok(());
+    }
+    located.sort_by(|a, b| natural_cmp(a.0, b.0).then(a.1.cmp(b.1)));
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Physical Location Index"))?;
+    let headings = [
+        ("location", "ereports", "device path"),
+        ("--------", "--------", "-----------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <20} {1: <9} {2}", h.0, h.1, h.2)?;
+    }
+    for (label, devpath, count) in &located {
+        writeln!(out, "{0: <20} {1: <9} {2}", label, count, devpath)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
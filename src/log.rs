@@ -0,0 +1,98 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// The tool's own diagnostics - events skipped, warnings and so on - which are
+// written to stderr, either as plain messages (the default) or, with
+// "--log-format json", as one JSON object per line for the benefit of
+// orchestration systems that collect and parse operational logs:
+//
+//   {"time":"2019-06-01T12:00:00.000Z","level":"warn",
+//    "msg":"no fmdump.json - skipping","host":"hosts/db1"}
+//
+// Any fields given with a message appear only in the JSON form, so the plain
+// message should make sense on its own.
+//
+use chrono::prelude::*;
+use serde_json::Value;
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use SimpleError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<LogFormat, Box<dyn Error>> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(Box::new(SimpleError(
+                format!("invalid log format: {}", s)))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Info => write!(f, "info"),
+            Level::Warn => write!(f, "warn"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+// Diagnostics may be logged from anywhere, so the format is global.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::SeqCst);
+}
+
+pub fn log(level: Level, msg: &str, fields: &[(&str, Value)]) {
+    if !JSON.load(Ordering::SeqCst) {
+        eprintln!("{}", msg);
+        return;
+    }
+    let mut entry = serde_json::Map::new();
+    entry.insert("time".to_string(),
+        json!(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)));
+    entry.insert("level".to_string(), json!(level.to_string()));
+    entry.insert("msg".to_string(), json!(msg));
+    for (name, value) in fields {
+        entry.insert(name.to_string(), value.clone());
+    }
+    eprintln!("{}", Value::Object(entry));
+}
+
+pub fn info(msg: &str, fields: &[(&str, Value)]) {
+    log(Level::Info, msg, fields);
+}
+
+pub fn warn(msg: &str, fields: &[(&str, Value)]) {
+    log(Level::Warn, msg, fields);
+}
+
+pub fn error(msg: &str, fields: &[(&str, Value)]) {
+    log(Level::Error, msg, fields);
+}
@@ -0,0 +1,85 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Reading of several FM logs as one, such as the current log and those fmd
+// has rotated out of the way (errlog, errlog.0, errlog.1 and so on), which
+// may be given in any order.  The logs are read in order of their first
+// event, so that their events are taken in time order, and the report notes
+// the time the logs cover together.
+//
+use chrono::prelude::*;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use input::{InputReader, Lines};
+use {FmEvent, FmLogError};
+
+//
+// The logs that were read and the times of the oldest and newest events in
+// them.
+//
+#[derive(Debug)]
+pub struct Coverage {
+    pub logs: Vec<String>,
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (first, last) = match (self.first, self.last) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return write!(f, "no events"),
+        };
+        let time = |tod| DateTime::from_timestamp(tod, 0).unwrap_or_default()
+            .format("%Y-%m-%d %H:%M:%S");
+        write!(f, "{} to {} ({} day(s))", time(first), time(last),
+            (last - first) / 86400)
+    }
+}
+
+//
+// Returns the time of the first event in the lines, along with the lines,
+// from which it has been peeked.
+//
+fn first_event(mut lines: Lines) -> io::Result<(Option<i64>, Lines)> {
+    let mut head = Vec::new();
+    let mut first = None;
+    for line in lines.by_ref() {
+        let line = line?;
+        let blank = line.trim().is_empty();
+        if !blank {
+            first = serde_json::from_str::<FmEvent>(&line).ok()
+                .and_then(|event| event.tod.first().cloned());
+        }
+        head.push(line);
+        if !blank {
+            break;
+        }
+    }
+    Ok((first, Box::new(head.into_iter().map(Ok).chain(lines))))
+}
+
+//
+// Open each of the logs, returning them paired with their paths in the order
+// in which they are to be read.
+//
+pub fn open_logs(input: &InputReader, paths: &[String])
+    -> Result<Vec<(String, Lines)>, Box<dyn Error>> {
+
+    let mut logs = Vec::new();
+    for path in paths {
+        let (first, lines) = first_event(input.read_events(path)?)
+            .map_err(|e| FmLogError::Io { path: Some(path.clone()),
+                source: e })?;
+        logs.push((first, path.clone(), lines));
+    }
+    logs.sort_by_key(|(first, _, _)| *first);
+    Ok(logs.into_iter().map(|(_, path, lines)| (path, lines)).collect())
+}
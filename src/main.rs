@@ -17,13 +17,25 @@
 extern crate getopts;
 use getopts::Options;
 
+extern crate serde_json;
+use serde_json::Value;
+
 use std::env;
+use std::io;
+use std::io::IsTerminal;
 use std::process;
 
 extern crate fm_log_report;
 
 fn usage(progname: &str, opts: &Options) {
-    let msg = format!("USAGE: {} -f <ERRLOG> [-H HWGROK]", progname);
+    let msg = format!("USAGE: {0} [-f <ERRLOG>] [-H HWGROK]\n       \
+        {0} device <PATH-OR-LABEL> [-f <ERRLOG>] [-H HWGROK]\n       \
+        {0} check [-f <ERRLOG>] [-H HWGROK]\n       \
+        {0} serve <ADDR> -f <ERRLOG> [-H HWGROK]\n       \
+        {0} forward -f <ERRLOG> [-H HWGROK] [-o FILE]\n       \
+        {0} fleet <DIR>\n       \
+        {0} generate <DIR> [-c CONFIG]\n       \
+        {0} check-hwgrok <HWGROK>", progname);
     print!("{}", opts.usage(&msg));
 }
 
@@ -34,11 +46,169 @@ fn main() {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this usage message");
     opts.optopt("H", "hwgrok", "Output of hwgrok", "HWGROK");
-    opts.optopt("f", "fmlog", "FM logs as JSON", "FMLOG");
+    opts.optopt("", "fmtopo", "output of fmtopo -V -j, for a system \
+        without hwgrok", "FMTOPO");
+    opts.optmulti("", "hwgrok-snapshot", "output of hwgrok captured at the \
+        given time, to find the hardware changes between snapshots (may be \
+        given more than once)", "TIME=HWGROK");
+    opts.optmulti("f", "fmlog", "FM logs as JSON (may be given more than \
+        once, e.g. for rotated logs)", "FMLOG");
+    opts.optopt("", "input-format", "format of the FM logs (fmdump, \
+        rasdaemon, mcelog or json)", "FORMAT");
+    opts.optopt("c", "config", "configuration file", "CONFIG");
+    opts.optopt("", "profile", "apply the options and settings of a profile \
+        in the configuration file", "NAME");
+    opts.optopt("s", "smart", "SMART data from smartctl -j", "SMART");
+    opts.optopt("", "sel", "IPMI SEL from ipmitool sel elist", "SEL");
+    opts.optopt("", "faulty", "output of fmadm faulty, to reconcile the \
+        ereports against the open cases", "FAULTY");
+    opts.optopt("", "path-to-inst", "the system's /etc/path_to_inst, to \
+        attribute devices to driver instances", "PATH_TO_INST");
+    opts.optmulti("", "ses", "SES enclosure status page from sg_ses (may be \
+        given more than once)", "SES");
+    opts.optopt("", "assets", "asset purchase and warranty dates as CSV",
+        "ASSETS");
+    opts.optopt("", "sensors", "temperature sensor history as CSV",
+        "SENSORS");
+    opts.optopt("", "boot-times", "file listing system boot times",
+        "BOOTTIMES");
+    opts.optopt("", "iostats", "per-device I/O volume as CSV", "IOSTATS");
+    opts.optopt("a", "aliases", "file mapping device paths or serial numbers \
+        to friendly names", "ALIASES");
+    opts.optflag("", "whole-disk", "roll up events against disk slices and \
+        partitions to the whole disk");
+    opts.optflag("", "merge-multipath", "consolidate the sections for all \
+        paths to the same multipathed device");
+    opts.optflag("", "exclude-virtual", "omit virtual and pseudo devices \
+        (lofi, zvols, ramdisks) from the report");
+    opts.optopt("", "cooccurrence-window", "window in seconds within which \
+        ereport classes are considered to co-occur (default 60)", "SECS");
+    opts.optopt("", "since", "omit ereports before this time (seconds since \
+        the epoch, RFC3339 or YYYY-MM-DD[ HH:MM:SS] in UTC)", "TIME");
+    opts.optopt("", "until", "omit ereports at or after this time", "TIME");
+    opts.optmulti("", "class", "include only ereports whose class matches \
+        the glob (may be given more than once)", "GLOB");
+    opts.optmulti("", "exclude-class", "omit ereports whose class matches \
+        the glob (may be given more than once)", "GLOB");
+    opts.optmulti("", "device", "include only ereports for devices whose \
+        path or FMRI contains PATTERN, matches it as a glob, or (with a \
+        leading '=') equals it (may be given more than once)", "PATTERN");
+    opts.optmulti("", "query", "include only ereports satisfying the \
+        expression, e.g. 'detector.device-path =~ /nvme/' (may be given \
+        more than once)", "EXPR");
+    opts.optopt("", "min-severity", "omit ereports below this severity \
+        (info, warning, error or critical)", "LEVEL");
+    opts.optmulti("", "fail-on-class", "exit with a non-zero status if more \
+        than COUNT (default 0) ereports match the class glob (may be given \
+        more than once)", "GLOB[:COUNT]");
+    opts.optopt("", "preview", "read only the last MB megabytes of the FM \
+        log, for a quick partial report of recent activity", "MB");
+    opts.optopt("", "export-events", "write every raw ereport for a device \
+        (path or label) to the output as JSON, in place of the report",
+        "DEVICE");
+    opts.optopt("", "max-age", "warn if the newest event in the FM logs is \
+        older than this, or there are none, and fail in check mode (seconds, \
+        or with a suffix of m, h, d or w)", "AGE");
+    opts.optopt("", "follow", "keep reading the FM log as it grows, writing \
+        the report again every SECS seconds if there are new events",
+        "SECS");
+    opts.optopt("", "summary", "write a one-line JSON summary of the run to \
+        this file, or to file descriptor N if given as fd:N", "DEST");
+    opts.optopt("", "color", "highlight event classes by severity \
+        (auto, always or never)", "WHEN");
+    opts.optopt("", "log-format", "format of the tool's own diagnostics \
+        (text or json)", "FORMAT");
+    opts.optflag("", "validate", "check that the FM logs and hwgrok output \
+        can be processed, without generating a report");
+    opts.optflag("", "timing", "report the time taken by each phase of the \
+        run, and the memory used");
+    opts.optflag("", "no-pager", "do not pipe the report through a pager");
+    opts.optflag("v", "verbose", "describe each ereport class in the device \
+        sections");
+    opts.optflag("", "healthy-inventory", "list the drive bays and PCI \
+        devices from hwgrok which have no ereports");
+    opts.optmulti("o", "output", "write the report to a file rather than \
+        stdout, in the format given or implied by its extension (may be \
+        repeated, and \"-\" is stdout)", "FILE[:FORMAT]");
+    opts.optopt("", "format", "format of the report (text, json, csv, \
+        html or markdown)",
+        "FORMAT");
+    opts.optopt("", "compress", "compress the output file (gzip or none)",
+        "ALGORITHM");
+    opts.optopt("", "sort-by", "order of the device sections (path or \
+        location)", "ORDER");
+    opts.optopt("", "lang", "language of the report's section titles and \
+        labels (en, de, fr or one with a catalog in the config file)", "LANG");
+    opts.optopt("", "split-by", "write a separate report file for each \
+        device (device)", "UNIT");
+    opts.optopt("", "output-dir", "directory for split report files", "DIR");
+    opts.optopt("", "archive-dir", "also store the report in this archive \
+        directory, pruning old reports", "DIR");
+    opts.optopt("", "keep-last", "number of most recent archived reports to \
+        keep (default 10)", "N");
+    opts.optopt("", "keep-daily", "days for which to keep one archived \
+        report per day (default 30)", "DAYS");
+    opts.optopt("", "keep-weekly", "weeks for which to keep one archived \
+        report per week (default 52)", "WEEKS");
+    opts.optopt("", "hostname", "host name to use in output path templates \
+        and archived report names (default is the local host name)", "NAME");
+    opts.optopt("", "upload", "POST the report as JSON to this https URL",
+        "URL");
+    opts.optopt("", "upload-token-file", "file containing a bearer token for \
+        the upload", "FILE");
+    opts.optopt("", "upload-retries", "number of times to retry a failed \
+        upload (default 3)", "N");
+    opts.optopt("", "upload-compress", "compress the upload (gzip or none)",
+        "ALGORITHM");
+    opts.optopt("", "pidfile", "in serve mode, write the process ID to this \
+        file", "PIDFILE");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(e) => panic!(e.to_string()),
+        Err(e) => panic!("{}", e),
+    };
+
+    //
+    // A profile's options are added to those on the command line, except
+    // where the command line gives the option itself.
+    //
+    let matches = match matches.opt_str("profile") {
+        Some(name) => {
+            let options = match fm_log_report::profile_options(
+                matches.opt_str("c").as_deref(), &name) {
+                Ok(o) => o,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+            let mut profile_args = Vec::new();
+            for (key, value) in options {
+                let arg = match value {
+                    Some(v) => format!("--{}={}", key, v),
+                    None => format!("--{}", key),
+                };
+                if let Err(e) = opts.parse([&arg]) {
+                    eprintln!("invalid option in profile {}: {}", name, e);
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+                if !matches.opt_present(&key) {
+                    profile_args.push(arg);
+                }
+            }
+            profile_args.extend(args[1..].iter().cloned());
+            match opts.parse(&profile_args) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("invalid options in profile {}: {}", name, e);
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            }
+        }
+        None => matches,
     };
 
     if matches.opt_present("h") {
@@ -46,25 +216,431 @@ fn main() {
         process::exit(2);
     }
 
-    let fmlog_path = match matches.opt_str("f") {
-        Some(path) => path,
-        None => {
-            eprintln!("-f argument is required");
+    if let Some(format) = matches.opt_str("log-format") {
+        match format.parse() {
+            Ok(f) => fm_log_report::log::set_format(f),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        }
+    }
+
+    let subcommand = matches.free.first().map(|s| s.as_str());
+    //
+    // In fleet mode, the logs are found in the fleet directory, and checking
+    // hwgrok output and generating synthetic logs don't involve them at all.
+    // Without logs, the report, device and check modes run fmdump on the
+    // local system, which can't be validated, exported from, previewed or
+    // followed.
+    //
+    let fmlog_paths = matches.opt_strs("f");
+    let no_logs = matches!(subcommand,
+        Some("fleet") | Some("check-hwgrok") | Some("generate"));
+    let live = matches!(subcommand, None | Some("device") | Some("check")) &&
+        !matches.opt_present("validate") &&
+        !matches.opt_present("export-events") &&
+        !matches.opt_present("preview") && !matches.opt_present("follow");
+    if fmlog_paths.is_empty() && !no_logs && !live {
+        eprintln!("-f argument is required");
+        usage(&progname, &opts);
+        process::exit(2);
+    }
+    let hwgrok_path = matches.opt_str("H");
+
+    if hwgrok_path.is_some() && matches.opt_present("fmtopo") {
+        eprintln!("-H and --fmtopo cannot be used together");
+        usage(&progname, &opts);
+        process::exit(2);
+    }
+
+    let mut config = fm_log_report::Config::new(fmlog_paths, hwgrok_path);
+    config.topo_path = matches.opt_str("fmtopo");
+    if let Some(format) = matches.opt_str("input-format") {
+        config.input_format = match format.parse() {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+    }
+    config.config_path = matches.opt_str("c");
+    config.profile = matches.opt_str("profile");
+    config.alias_path = matches.opt_str("a");
+    config.smart_path = matches.opt_str("s");
+    config.sel_path = matches.opt_str("sel");
+    config.ses_paths = matches.opt_strs("ses");
+    config.faulty_path = matches.opt_str("faulty");
+    config.path_to_inst = matches.opt_str("path-to-inst");
+    config.sensor_path = matches.opt_str("sensors");
+    config.assets_path = matches.opt_str("assets");
+    config.boot_times_path = matches.opt_str("boot-times");
+    config.iostat_path = matches.opt_str("iostats");
+    config.no_pager = matches.opt_present("no-pager");
+    config.timing = matches.opt_present("timing");
+    config.verbose = matches.opt_present("verbose");
+    config.healthy_inventory = matches.opt_present("healthy-inventory");
+    config.validate = matches.opt_present("validate");
+    if config.validate && !matches.free.is_empty() {
+        eprintln!("--validate cannot be used with a subcommand");
+        usage(&progname, &opts);
+        process::exit(2);
+    }
+    config.pidfile = matches.opt_str("pidfile");
+    for spec in matches.opt_strs("o") {
+        match spec.parse::<fm_log_report::OutputSpec>() {
+            Ok(o) => config.outputs.push(o),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        }
+    }
+    if config.outputs.iter().filter(|o| o.path.is_none()).count() > 1 {
+        eprintln!("only one --output can be stdout");
+        usage(&progname, &opts);
+        process::exit(2);
+    }
+    config.hostname = matches.opt_str("hostname");
+    config.archive_dir = matches.opt_str("archive-dir");
+    let retention_opt = |name: &str, default: i64| -> i64 {
+        match matches.opt_str(name).map(|n| n.parse::<i64>()) {
+            None => default,
+            Some(Ok(n)) if n >= 0 => n,
+            Some(_) => {
+                eprintln!("invalid value for --{}", name);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        }
+    };
+    config.retention.keep_last = retention_opt("keep-last",
+        config.retention.keep_last as i64) as usize;
+    config.retention.keep_daily = retention_opt("keep-daily",
+        config.retention.keep_daily);
+    config.retention.keep_weekly = retention_opt("keep-weekly",
+        config.retention.keep_weekly);
+    if let Some(format) = matches.opt_str("format") {
+        config.format = match format.parse() {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+        if !matches!(matches.free.first().map(|s| s.as_str()),
+            None | Some("device")) {
+            eprintln!("--format can only be used for the report and the \
+                device subcommand");
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+    }
+    if let Some(alg) = matches.opt_str("compress") {
+        config.compress = match alg.parse() {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+        if config.outputs.is_empty() && !matches.opt_present("split-by") &&
+            !matches.opt_present("archive-dir") {
+            eprintln!("--compress requires --output, --split-by or \
+                --archive-dir");
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+    }
+    if let Some(url) = matches.opt_str("upload") {
+        if !matches.free.is_empty() {
+            eprintln!("--upload cannot be used with a subcommand");
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+        let mut upload = match fm_log_report::Upload::new(&url) {
+            Ok(u) => u,
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+        upload.token_file = matches.opt_str("upload-token-file");
+        if let Some(n) = matches.opt_str("upload-retries") {
+            upload.retries = match n.parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    eprintln!("invalid value for --upload-retries");
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+        }
+        if let Some(alg) = matches.opt_str("upload-compress") {
+            upload.compress = match alg.parse() {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+        }
+        config.upload = Some(upload);
+    }
+    config.whole_disk = matches.opt_present("whole-disk");
+    config.merge_multipath = matches.opt_present("merge-multipath");
+    config.exclude_virtual = matches.opt_present("exclude-virtual");
+    if let Some(secs) = matches.opt_str("cooccurrence-window") {
+        config.cooccur_window = match secs.parse::<i64>() {
+            Ok(s) if s > 0 => s,
+            _ => {
+                eprintln!("invalid co-occurrence window: {}", secs);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+    }
+
+    config.sort_by = match matches.opt_str("sort-by").as_deref() {
+        None | Some("path") => fm_log_report::SortBy::Path,
+        Some("location") => fm_log_report::SortBy::Location,
+        Some(order) => {
+            eprintln!("invalid sort order: {}", order);
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+    };
+    config.lang = matches.opt_str("lang");
+    if let Some(unit) = matches.opt_str("split-by") {
+        if unit != "device" {
+            eprintln!("invalid split unit: {}", unit);
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+        config.split_by = Some(fm_log_report::SplitBy::Device);
+        config.output_dir = matches.opt_str("output-dir");
+        if config.output_dir.is_none() {
+            eprintln!("--split-by requires --output-dir");
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+    }
+    for (name, bound) in [("since", &mut config.since),
+        ("until", &mut config.until)] {
+        if let Some(time) = matches.opt_str(name) {
+            *bound = match fm_log_report::parse_timestamp(&time) {
+                Some(t) => Some(t),
+                None => {
+                    eprintln!("invalid --{} time: {}", name, time);
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+        }
+    }
+    if let (Some(since), Some(until)) = (config.since, config.until) {
+        if since >= until {
+            eprintln!("--since must be before --until");
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+    }
+    config.filter.classes = matches.opt_strs("class");
+    config.filter.exclude_classes = matches.opt_strs("exclude-class");
+    config.filter.devices = matches.opt_strs("device");
+    for query in matches.opt_strs("query") {
+        match query.parse() {
+            Ok(q) => config.filter.queries.push(q),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        }
+    }
+    if let Some(level) = matches.opt_str("min-severity") {
+        config.min_severity = match level.parse() {
+            Ok(sev) => Some(sev),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+    }
+    for snapshot in matches.opt_strs("hwgrok-snapshot") {
+        match snapshot.parse() {
+            Ok(s) => config.hwgrok_snapshots.push(s),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        }
+    }
+    for gate in matches.opt_strs("fail-on-class") {
+        match gate.parse() {
+            Ok(g) => config.fail_on_class.push(g),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        }
+    }
+    if let Some(size) = matches.opt_str("preview") {
+        config.preview = match fm_log_report::parse_preview_size(&size) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+    }
+    config.export_events = matches.opt_str("export-events");
+    if config.export_events.is_some() && !matches.free.is_empty() {
+        eprintln!("--export-events cannot be used with a subcommand");
+        usage(&progname, &opts);
+        process::exit(2);
+    }
+    if let Some(age) = matches.opt_str("max-age") {
+        config.max_age = match fm_log_report::parse_age(&age) {
+            Ok(secs) => Some(secs),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+    }
+    if let Some(secs) = matches.opt_str("follow") {
+        config.follow = match secs.parse::<u64>() {
+            Ok(s) if s > 0 => Some(s),
+            _ => {
+                eprintln!("invalid follow interval: {}", secs);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+    }
+    if let Some(dest) = matches.opt_str("summary") {
+        config.summary = match dest.parse() {
+            Ok(d) => Some(d),
+            Err(e) => {
+                eprintln!("{}", e);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+    }
+    config.color = match matches.opt_str("color").as_deref() {
+        None | Some("auto") => {
+            config.outputs.is_empty() && config.split_by.is_none() &&
+                config.format == fm_log_report::ReportFormat::Text &&
+                io::stdout().is_terminal()
+        }
+        Some("always") => config.format == fm_log_report::ReportFormat::Text,
+        Some("never") => false,
+        Some(when) => {
+            eprintln!("invalid color setting: {}", when);
             usage(&progname, &opts);
             process::exit(2);
         }
     };
-    let hwgrok_path = matches.opt_str("H");
 
-    let config = fm_log_report::Config::new(fmlog_path, hwgrok_path);
+    match matches.free.first().map(|s| s.as_str()) {
+        None => (),
+        Some("device") => {
+            let device = match matches.free.get(1) {
+                Some(d) => d.clone(),
+                None => {
+                    eprintln!("device subcommand requires a path or label");
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+            config.mode = fm_log_report::Mode::Device(device);
+        }
+        Some("check") => {
+            config.mode = fm_log_report::Mode::Check;
+        }
+        Some("forward") => {
+            config.mode = fm_log_report::Mode::Forward;
+        }
+        Some("serve") => {
+            let addr = match matches.free.get(1) {
+                Some(a) => a.clone(),
+                None => {
+                    eprintln!("serve subcommand requires an address");
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+            config.mode = fm_log_report::Mode::Serve(addr);
+        }
+        Some("fleet") => {
+            let dir = match matches.free.get(1) {
+                Some(d) => d.clone(),
+                None => {
+                    eprintln!("fleet subcommand requires a directory");
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+            config.mode = fm_log_report::Mode::Fleet(dir);
+        }
+        Some("generate") => {
+            let dir = match matches.free.get(1) {
+                Some(d) => d.clone(),
+                None => {
+                    eprintln!("generate subcommand requires a directory");
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+            config.mode = fm_log_report::Mode::Generate(dir);
+        }
+        Some("check-hwgrok") => {
+            let path = match matches.free.get(1) {
+                Some(p) => p.clone(),
+                None => {
+                    eprintln!("check-hwgrok subcommand requires a file");
+                    usage(&progname, &opts);
+                    process::exit(2);
+                }
+            };
+            config.mode = fm_log_report::Mode::CheckHwgrok(path);
+        }
+        Some(cmd) => {
+            eprintln!("unknown subcommand: {}", cmd);
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+    }
 
     match fm_log_report::run(&config) {
-        Ok(_r) => {
-            process::exit(0);
+        Ok(status) => {
+            process::exit(status);
         }
         Err(e) => {
-            eprintln!("An error occurred: {}", e.to_string());
-            process::exit(1);
+            fm_log_report::log::error(&format!("An error occurred: {}", e),
+                &[("error", Value::String(e.to_string())),
+                ("kind", Value::String(e.kind().to_string()))]);
+            match config.mode {
+                fm_log_report::Mode::Check => {
+                    process::exit(fm_log_report::CHECK_UNKNOWN);
+                }
+                _ => process::exit(1),
+            }
         }
     }
 }
@@ -0,0 +1,148 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Rendering of the report as Markdown, which can be pasted directly into
+// issues and chat.  Each device has a heading, followed by tables of its
+// identity, its ereport class counts and the day-by-day distribution of its
+// ereports.  The analysis sections follow as a preformatted block, as their
+// columns don't map onto Markdown tables.  Device paths and classes are shown
+// as code, so that their underscores and asterisks aren't taken as emphasis.
+//
+use chrono::prelude::*;
+
+use std::error::Error;
+use std::io::Write;
+
+use devpath::DeviceKey;
+use i18n::tr;
+use {device_identity, ordered_devices, write_analysis, DeviceHash,
+    DeviceHashEnt, ReportData};
+
+//
+// Escape a value for a table cell, in which a "|" would end the cell.
+//
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn code(s: &str) -> String {
+    format!("`{}`", cell(s))
+}
+
+fn write_head(out: &mut dyn Write, title: &str) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "# {}\n", title)?;
+    writeln!(out, "Generated {}\n",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC"))?;
+    Ok(())
+}
+
+fn write_device(
+    out: &mut dyn Write,
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    match data.statuses.get(devpath) {
+        Some(status) => {
+            writeln!(out, "### {} **{}**\n", code(devpath), status.status)?
+        }
+        None => writeln!(out, "### {}\n", code(devpath))?,
+    }
+    writeln!(out, "| | |\n|---|---|")?;
+    for (name, value) in device_identity(devpath, devent, data) {
+        let value = if name == tr("Device Path") { code(&value) } else {
+            cell(&value)
+        };
+        writeln!(out, "| {} | {} |", cell(&name), value)?;
+    }
+    writeln!(out)?;
+
+    let devent = match devent {
+        Some(d) => d,
+        None => return Ok(()),
+    };
+    let mut classes: Vec<(&String, &u32)> = devent.ereport_class_hash.iter()
+        .collect();
+    classes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    writeln!(out, "#### {}\n", tr("Ereport Classes"))?;
+    writeln!(out, "| class | count |\n|---|--:|")?;
+    for (class, count) in classes {
+        writeln!(out, "| {} | {} |", code(class), count)?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "#### {}\n", tr("Event Occurrence Distribution"))?;
+    writeln!(out, "| date | count |\n|---|--:|")?;
+    for ts in &devent.ereports_ts {
+        writeln!(out, "| {} | {} |", ts, devent.ereport_ts_hash[ts])?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+//
+// Write the whole report as Markdown.
+//
+pub fn write_markdown_report(
+    out: &mut dyn Write,
+    title: &str,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    write_head(out, title)?;
+    let system: Vec<String> = data.hwgrok.system_identity().iter()
+        .map(|(name, value)| format!("**{}** {}",
+            tr(&format!("{}:", name)), value))
+        .collect();
+    if !system.is_empty() {
+        writeln!(out, "{}\n", system.join("  \n"))?;
+    }
+    if let Some(coverage) = &data.coverage {
+        writeln!(out, "**{}** {}  \n**{}** {}\n", tr("FM Logs:"),
+            coverage.logs.join(", "), tr("Coverage:"), coverage)?;
+    }
+    if let Some(partial) = &data.partial {
+        writeln!(out, "> **{}** {}\n", tr("Partial Report:"), partial)?;
+    }
+    let (phys, virt) = ordered_devices(device_hash, data);
+    writeln!(out, "## {}\n", tr("Devices"))?;
+    for (devpath, devent) in phys {
+        write_device(out, devpath, Some(devent), data)?;
+    }
+    if !virt.is_empty() {
+        writeln!(out, "## {}\n", tr("Virtual Devices"))?;
+        for (devpath, devent) in virt {
+            write_device(out, devpath, Some(devent), data)?;
+        }
+    }
+
+    let mut text = Vec::new();
+    write_analysis(&mut text, device_hash, data)?;
+    if !text.is_empty() {
+        writeln!(out, "## {}\n", tr("Analysis"))?;
+        writeln!(out, "```\n{}```", String::from_utf8_lossy(&text))?;
+    }
+    Ok(())
+}
+
+//
+// Write a single device's report as Markdown.
+//
+pub fn write_markdown_device(
+    out: &mut dyn Write,
+    title: &str,
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    write_head(out, title)?;
+    write_device(out, devpath, devent, data)
+}
@@ -0,0 +1,232 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// An input adapter for mcelog, for older Linux hosts which don't run
+// rasdaemon.  Either mcelog's usual text output (as written to its log file,
+// or by "mcelog --ascii") or JSON records can be given.  In the text form,
+// each record is a block of lines such as:
+//
+//   Hardware event. This is not a software error.
+//   MCE 0
+//   CPU 1 BANK 8 TSC 2b9c0ba0ab0
+//   ADDR 12345000
+//   TIME 1551441600 Fri Mar  1 12:00:00 2019
+//   Corrected error
+//   MCA: MEMORY CONTROLLER RD_CHANNEL0_ERR
+//   STATUS 8c00004000010090 MCGSTATUS 0
+//   MCGCAP 1000c14 APICID 20 SOCKETID 1
+//
+// The JSON form is a sequence of objects (or an array of them) with the same
+// members in lower case, e.g. {"cpu": 1, "bank": 8, "time": 1551441600, ...}.
+//
+// Memory errors (those whose MCA decode names the memory controller) become
+// ereport.cpu.linux.mce.mem.{ce,uc} events detected by a mem-scheme FMRI for
+// the socket and, if known, the channel.  All others become
+// ereport.cpu.linux.mce.{ce,uc} events detected by a cpu-scheme FMRI.
+//
+use serde_json::{Map, Value};
+
+use std::error::Error;
+
+use input;
+
+//
+// Convert a record, as a map of lower-cased field names to values, into an
+// fmdump-style event.  Returns None if the record has no time.
+//
+fn record_event(record: Map<String, Value>) -> Option<Value> {
+    let as_i64 = |v: &Value| match v {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    let tod = record.get("time").and_then(as_i64)?;
+    let cpu = record.get("cpu").and_then(as_i64).unwrap_or(0);
+    let mca = record.get("mca").and_then(|v| v.as_str()).unwrap_or("");
+    let uncorrected = record.get("uncorrected").and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let sev = if uncorrected { "uc" } else { "ce" };
+
+    let (class, detector) = if mca.contains("MEMORY CONTROLLER") {
+        let socket = record.get("socketid").and_then(as_i64).unwrap_or(0);
+        let mut unum = format!("SOCKET {}", socket);
+        if let Some(off) = mca.find("CHANNEL") {
+            let chan: String = mca[off + 7..].chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            if !chan.is_empty() {
+                unum.push_str(&format!(" CHANNEL {}", chan));
+            }
+        }
+        (format!("ereport.cpu.linux.mce.mem.{}", sev),
+            json!({ "scheme": "mem", "unum": unum }))
+    } else {
+        (format!("ereport.cpu.linux.mce.{}", sev),
+            json!({ "scheme": "cpu", "cpuid": cpu }))
+    };
+
+    let mut ev = record;
+    ev.insert("class".to_string(), json!(class));
+    ev.insert("detector".to_string(), detector);
+    ev.insert("__tod".to_string(), json!([tod, 0]));
+    Some(Value::Object(ev))
+}
+
+//
+// Parse one line of a text record into the record's fields.  Most lines are
+// a sequence of "NAME value" pairs; a few are free text.
+//
+fn parse_text_line(line: &str, record: &mut Map<String, Value>) {
+    if let Some(mca) = line.strip_prefix("MCA: ") {
+        record.insert("mca".to_string(), json!(mca));
+        return;
+    }
+    if line.starts_with("Uncorrected error") {
+        record.insert("uncorrected".to_string(), json!(true));
+        return;
+    }
+    if line.starts_with("TIME ") {
+        // the epoch time is followed by the date in local time
+        if let Some(secs) = line.split_whitespace().nth(1) {
+            record.insert("time".to_string(), json!(secs));
+        }
+        return;
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let is_pairs = words.len().is_multiple_of(2) && words.chunks(2)
+        .all(|p| p[0].chars().all(|c| c.is_ascii_uppercase()));
+    if is_pairs {
+        for pair in words.chunks(2) {
+            let value = match pair[1].parse::<i64>() {
+                Ok(n) if pair[0] == "CPU" || pair[0] == "BANK" ||
+                    pair[0] == "SOCKETID" => json!(n),
+                _ => json!(pair[1]),
+            };
+            record.insert(pair[0].to_lowercase(), value);
+        }
+    }
+}
+
+fn parse_text(contents: &str) -> Vec<Value> {
+    let mut events = Vec::new();
+    let mut record = Map::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.starts_with("Hardware event") || line.starts_with("MCE ") {
+            if !record.is_empty() {
+                events.extend(record_event(record));
+                record = Map::new();
+            }
+            continue;
+        }
+        parse_text_line(line, &mut record);
+    }
+    if !record.is_empty() {
+        events.extend(record_event(record));
+    }
+    events
+}
+
+fn parse_json(contents: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+    let mut events = Vec::new();
+    for value in serde_json::Deserializer::from_str(contents)
+        .into_iter::<Value>() {
+        let records = match value? {
+            Value::Array(a) => a,
+            v => vec![v],
+        };
+        for record in records {
+            if let Value::Object(obj) = record {
+                let obj = obj.into_iter()
+                    .map(|(k, v)| (k.to_lowercase(), v))
+                    .collect();
+                events.extend(record_event(obj));
+            }
+        }
+    }
+    Ok(events)
+}
+
+//
+// Read the mcelog output at the given path, returning the records as
+// fmdump-style events in chronological order.
+//
+pub fn read_events(path: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+    let contents = input::read_to_string(path)?;
+    let mut events = if contents.trim_start().starts_with(['{', '[']) {
+        parse_json(&contents)?
+    } else {
+        parse_text(&contents)
+    };
+    events.sort_by_key(|e| e["__tod"][0].as_i64());
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_json, parse_text};
+
+    const TEXT: &str = "\
+Hardware event. This is not a software error.
+MCE 0
+CPU 1 BANK 8 TSC 2b9c0ba0ab0
+ADDR 12345000
+TIME 1551441600 Fri Mar  1 12:00:00 2019
+Corrected error
+MCA: MEMORY CONTROLLER RD_CHANNEL2_ERR
+STATUS 8c00004000010090 MCGSTATUS 0
+MCGCAP 1000c14 APICID 20 SOCKETID 1
+Hardware event. This is not a software error.
+MCE 1
+CPU 5 BANK 4
+TIME 1551445200 Fri Mar  1 13:00:00 2019
+Uncorrected error
+MCA: Internal parity error
+Hardware event. This is not a software error.
+MCE 2
+CPU 6 BANK 4
+";
+
+    #[test]
+    fn text() {
+        let events = parse_text(TEXT);
+        // the last record has no time, so is dropped
+        assert_eq!(events.len(), 2);
+
+        let mem = &events[0];
+        assert_eq!(mem["class"], "ereport.cpu.linux.mce.mem.ce");
+        assert_eq!(mem["detector"]["scheme"], "mem");
+        assert_eq!(mem["detector"]["unum"], "SOCKET 1 CHANNEL 2");
+        assert_eq!(mem["__tod"][0], 1551441600);
+        assert_eq!(mem["cpu"], 1);
+        assert_eq!(mem["bank"], 8);
+        assert_eq!(mem["addr"], "12345000");
+        assert_eq!(mem["status"], "8c00004000010090");
+
+        let cpu = &events[1];
+        assert_eq!(cpu["class"], "ereport.cpu.linux.mce.uc");
+        assert_eq!(cpu["detector"]["scheme"], "cpu");
+        assert_eq!(cpu["detector"]["cpuid"], 5);
+        assert_eq!(cpu["__tod"][0], 1551445200);
+    }
+
+    #[test]
+    fn json() {
+        let events = parse_json("{\"CPU\": 2, \"TIME\": 1551441600, \
+            \"MCA\": \"MEMORY CONTROLLER MS_CHANNEL_ERR\", \"SOCKETID\": 0}\n\
+            [{\"cpu\": 3, \"time\": \"1551441700\", \"uncorrected\": true}, \
+            {\"cpu\": 4}, 7]").unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["class"], "ereport.cpu.linux.mce.mem.ce");
+        assert_eq!(events[0]["detector"]["unum"], "SOCKET 0");
+        assert_eq!(events[1]["class"], "ereport.cpu.linux.mce.uc");
+        assert_eq!(events[1]["detector"]["cpuid"], 3);
+        assert_eq!(events[1]["__tod"][0], 1551441700);
+
+        assert!(parse_json("{\"cpu\": ").is_err());
+    }
+}
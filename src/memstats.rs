@@ -0,0 +1,121 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Approximate memory usage of the aggregation structures, reported along with
+// the timings by --timing to show how much memory a run needs on a constrained
+// host.  The device hash only grows while the FM log is read (consolidating
+// multipathed devices only shrinks it), so it's measured once the log has been
+// read in.  The sizes are estimates: they count the structures and the strings
+// and payload values they hold, but not the allocator's overhead or the spare
+// capacity of hash tables and vectors.
+//
+// The peak resident set size of the whole process is also reported where the
+// system makes it available (as VmHWM in /proc/self/status on Linux).
+//
+use serde_json::Value;
+
+use std::fs;
+use std::mem::size_of;
+
+use {DeviceHash, DeviceHashEnt, Ereport};
+
+#[derive(Debug, Default)]
+pub struct MemStats {
+    pub devices: usize,
+    pub ereports: usize,
+    // entries in the per-device tallies by class and by day
+    pub class_entries: usize,
+    pub daily_entries: usize,
+    // estimated bytes held by the device hash, and by the ereports within it
+    pub total_bytes: usize,
+    pub event_bytes: usize,
+}
+
+fn value_bytes(value: &Value) -> usize {
+    size_of::<Value>() + match value {
+        Value::String(s) => s.len(),
+        Value::Array(a) => a.iter().map(value_bytes).sum(),
+        Value::Object(o) => o.iter()
+            .map(|(k, v)| size_of::<String>() + k.len() + value_bytes(v))
+            .sum(),
+        _ => 0,
+    }
+}
+
+fn ereport_bytes(ereport: &Ereport) -> usize {
+    let opt_len = |s: &Option<String>| s.as_ref().map(|s| s.len()).unwrap_or(0);
+    let detector = &ereport.detector;
+
+    size_of::<Ereport>() + ereport.class.len() + detector.scheme.len() +
+        opt_len(&detector.device_path) + opt_len(&detector.devid) +
+        opt_len(&detector.mod_name) + opt_len(&detector.unum) +
+        detector.hc_list.as_ref().map(|l| l.iter()
+            .map(|p| 2 * size_of::<String>() + p.hc_name.len() + p.hc_id.len())
+            .sum()).unwrap_or(0) +
+        ereport.tod.len() * size_of::<i64>() +
+        ereport.payload.iter()
+            .map(|(k, v)| size_of::<String>() + k.len() + value_bytes(v))
+            .sum::<usize>()
+}
+
+fn tally_bytes(key: &str) -> usize {
+    size_of::<String>() + key.len() + size_of::<u32>()
+}
+
+pub fn measure(device_hash: &DeviceHash) -> MemStats {
+    let mut stats = MemStats::default();
+    for (devpath, devent) in device_hash.iter() {
+        stats.devices += 1;
+        stats.ereports += devent.ereports.len();
+        stats.class_entries += devent.ereport_class_hash.len();
+        stats.daily_entries += devent.ereport_ts_hash.len();
+
+        let event_bytes: usize = devent.ereports.iter().map(ereport_bytes)
+            .sum();
+        stats.event_bytes += event_bytes;
+        stats.total_bytes += size_of::<String>() + devpath.len() +
+            size_of::<DeviceHashEnt>() + event_bytes +
+            devent.ereport_class_hash.keys().map(|k| tally_bytes(k))
+                .sum::<usize>() +
+            devent.ereport_ts_hash.keys().map(|k| tally_bytes(k))
+                .sum::<usize>() +
+            devent.ereports_ts.iter().map(|t| size_of::<String>() + t.len())
+                .sum::<usize>() +
+            devent.merged_paths.iter().map(|p| size_of::<String>() + p.len())
+                .sum::<usize>();
+    }
+    stats
+}
+
+//
+// Returns the peak resident set size of the process in bytes, if known.
+//
+pub fn peak_rss() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line["VmHWM:".len()..].trim().trim_end_matches("kB").trim()
+        .parse().ok()?;
+    Some(kb * 1024)
+}
+
+//
+// Format a number of bytes for display, in binary units.
+//
+pub fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
@@ -0,0 +1,105 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Versioning of the JSON report model, the form in which reports are written
+// with --format json, archived, uploaded and served, so that reports saved by
+// one version of the tool remain loadable by later ones.  Each JSON report
+// carries the version of the model it was written with as "schema_version".
+// Adding a member is a compatible change, which doesn't change the version,
+// and readers must ignore members they don't know.  Removing or renaming a
+// member, or changing its meaning, increases the version, and comes with a
+// migration from the previous version, so that a saved report of any older
+// version can be brought up to date by applying the migrations in turn.
+//
+// The versions of the model are:
+//
+//   1    reports written before the model was versioned, which have no
+//        schema_version
+//   2    every device has "status" and "status_reasons" members, which are
+//        null and empty if its status wasn't evaluated
+//
+use serde_json::Value;
+
+use std::fs;
+
+use output;
+use {FmLogError, SimpleError};
+
+pub const SCHEMA_VERSION: u64 = 2;
+
+// The migration from each version to the next, starting with version 1
+const MIGRATIONS: [fn(Value) -> Value; 1] = [
+    v1_to_v2,
+];
+
+//
+// Returns the report, stamped with the current version of the model.
+//
+pub fn versioned(mut report: Value) -> Value {
+    if let Some(members) = report.as_object_mut() {
+        members.insert("schema_version".to_string(), json!(SCHEMA_VERSION));
+    }
+    report
+}
+
+//
+// Calls the function on each device in a report, which is either a full
+// report, with its devices in "devices", or that of a single device.
+//
+fn for_each_device(report: &mut Value, f: fn(&mut Value)) {
+    match report.get_mut("devices").and_then(|d| d.as_array_mut()) {
+        Some(devices) => devices.iter_mut().for_each(f),
+        None => f(report),
+    }
+}
+
+fn v1_to_v2(mut report: Value) -> Value {
+    for_each_device(&mut report, |device| {
+        if let Some(members) = device.as_object_mut() {
+            members.entry("status").or_insert(Value::Null);
+            members.entry("status_reasons").or_insert(json!([]));
+        }
+    });
+    report
+}
+
+//
+// Bring a report written with any version of the model up to the current
+// version.  Reports written by a newer version of the tool are rejected, as
+// their members may no longer mean what this version expects.
+//
+pub fn migrate_report(report: Value) -> Result<Value, FmLogError> {
+    let version = match report.get("schema_version") {
+        None => 1,
+        Some(v) => match v.as_u64() {
+            Some(v) if v >= 1 => v,
+            _ => {
+                return Err(FmLogError::SchemaVersion(v.to_string()));
+            }
+        },
+    };
+    if version > SCHEMA_VERSION {
+        return Err(FmLogError::SchemaVersion(version.to_string()));
+    }
+    let report = MIGRATIONS[(version - 1) as usize..].iter()
+        .fold(report, |report, migrate| migrate(report));
+    Ok(versioned(report))
+}
+
+//
+// Load a saved JSON report, which may be gzipped, as archived reports are
+// with --compress, migrating it to the current version of the model.
+//
+pub fn load_report(path: &str) -> Result<Value, FmLogError> {
+    let io_error = |e| FmLogError::Io { path: Some(path.to_string()),
+        source: e };
+    let contents = output::decompress(&fs::read(path).map_err(io_error)?)?;
+    let report = serde_json::from_slice(&contents)
+        .map_err(|e| FmLogError::Other(Box::new(SimpleError(
+            format!("{}: invalid report: {}", path, e)))))?;
+    migrate_report(report)
+}
@@ -0,0 +1,74 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Dual-ported SAS drives (and other multipathed LUNs) are reachable via more
+// than one device path, and so their ereports are split across several
+// sections of the report.  The devid carried in the telemetry identifies the
+// LUN regardless of the path used to reach it, which lets us find and either
+// cross-link or consolidate those sections.
+//
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use devpath::DeviceKey;
+use replacement::ereport_identity;
+use {DeviceHash, DeviceHashEnt};
+
+//
+// Returns the identity of the LUN most recently seen behind a device path.
+//
+fn path_identity(devent: &DeviceHashEnt) -> Option<String> {
+    devent.ereports.iter().rev().filter_map(ereport_identity).next()
+}
+
+//
+// Build a map of device path to the other device paths via which the same
+// LUN was reached.  Paths with a single route to the LUN are omitted.
+//
+pub fn find_paths(
+    device_hash: &DeviceHash
+) -> HashMap<DeviceKey, Vec<DeviceKey>> {
+
+    let mut luns: BTreeMap<String, Vec<DeviceKey>> = BTreeMap::new();
+    for (devpath, devent) in device_hash.iter() {
+        if let Some(id) = path_identity(devent) {
+            luns.entry(id).or_default().push(devpath.clone());
+        }
+    }
+
+    let mut alt_paths = HashMap::new();
+    for paths in luns.values_mut().filter(|p| p.len() > 1) {
+        paths.sort();
+        for path in paths.iter() {
+            let others = paths.iter().filter(|p| *p != path).cloned()
+                .collect();
+            alt_paths.insert(path.clone(), others);
+        }
+    }
+    alt_paths
+}
+
+//
+// Consolidate the entries for all paths to the same LUN into a single entry,
+// keyed by the lexically first of the paths.
+//
+pub fn consolidate(device_hash: &mut DeviceHash) {
+    let alt_paths = find_paths(device_hash);
+
+    for (path, others) in alt_paths.iter() {
+        if others.iter().any(|o| o < path) {
+            continue;
+        }
+        for other in others {
+            if let Some(ent) = device_hash.remove(other) {
+                let primary = device_hash.get_mut(path).unwrap();
+                primary.merge(ent);
+                primary.merged_paths.push(other.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Locale-aware formatting of numbers in the human-readable report, as large
+// counts such as 1302456 are easily misread without thousands separators.
+// The locale is taken from the [format] section of the configuration file,
+// falling back to the LC_ALL, LC_NUMERIC and LANG environment variables:
+//
+//   [format]
+//   locale = de_DE
+//   # or explicitly
+//   thousands-separator = .
+//   decimal-separator = ,
+//
+// Output intended for consumption by other programs, such as that of check
+// mode, is left unformatted.
+//
+use std::env;
+use std::error::Error;
+
+use conf::ConfFile;
+use SimpleError;
+
+#[derive(Clone, Copy, Debug)]
+pub struct NumberFormat {
+    thousands: Option<char>,
+    decimal: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> NumberFormat {
+        NumberFormat { thousands: Some(','), decimal: '.' }
+    }
+}
+
+//
+// Separators by language (or language_TERRITORY), for locales which don't
+// use the English convention of "1,302,456.5".
+//
+const LOCALE_SEPARATORS: [(&str, char, char); 16] = [
+    ("de_CH", '\'', '.'),
+    ("de", '.', ','),
+    ("nl", '.', ','),
+    ("it", '.', ','),
+    ("es", '.', ','),
+    ("pt", '.', ','),
+    ("da", '.', ','),
+    ("id", '.', ','),
+    ("tr", '.', ','),
+    ("fr", ' ', ','),
+    ("ru", ' ', ','),
+    ("sv", ' ', ','),
+    ("fi", ' ', ','),
+    ("nb", ' ', ','),
+    ("pl", ' ', ','),
+    ("cs", ' ', ','),
+];
+
+impl NumberFormat {
+    pub fn from_locale(locale: &str) -> NumberFormat {
+        // strip any codeset or modifier, e.g. "de_DE.UTF-8@euro"
+        let locale = locale.split(['.', '@']).next().unwrap_or("");
+        let lang = locale.split('_').next().unwrap_or("");
+        LOCALE_SEPARATORS.iter()
+            .find(|(l, _, _)| *l == locale)
+            .or_else(|| LOCALE_SEPARATORS.iter().find(|(l, _, _)| *l == lang))
+            .map(|(_, thousands, decimal)| NumberFormat {
+                thousands: Some(*thousands),
+                decimal: *decimal,
+            })
+            .unwrap_or_default()
+    }
+
+    fn from_env() -> NumberFormat {
+        for var in &["LC_ALL", "LC_NUMERIC", "LANG"] {
+            if let Ok(locale) = env::var(var) {
+                if !locale.is_empty() {
+                    return NumberFormat::from_locale(&locale);
+                }
+            }
+        }
+        NumberFormat::default()
+    }
+
+    pub fn from_conf(conf: &ConfFile) -> Result<NumberFormat, Box<dyn Error>> {
+        let mut fmt = NumberFormat::from_env();
+
+        for (key, value) in conf.section("format") {
+            match key {
+                "locale" => fmt = NumberFormat::from_locale(value),
+                "thousands-separator" => {
+                    fmt.thousands = match value {
+                        "" | "none" => None,
+                        "space" => Some(' '),
+                        _ => value.chars().next(),
+                    };
+                }
+                "decimal-separator" => {
+                    fmt.decimal = value.chars().next().unwrap_or('.');
+                }
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "unknown format setting: {}", key))));
+                }
+            }
+        }
+
+        Ok(fmt)
+    }
+
+    fn group(&self, digits: &str) -> String {
+        let sep = match self.thousands {
+            Some(sep) => sep,
+            None => return digits.to_string(),
+        };
+        let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+        for (idx, c) in digits.chars().enumerate() {
+            if idx > 0 && (digits.len() - idx).is_multiple_of(3) {
+                grouped.push(sep);
+            }
+            grouped.push(c);
+        }
+        grouped
+    }
+
+    pub fn int(&self, n: u64) -> String {
+        self.group(&n.to_string())
+    }
+
+    pub fn float(&self, x: f64, precision: usize) -> String {
+        let s = format!("{:.*}", precision, x.abs());
+        let (int, frac) = match s.find('.') {
+            Some(off) => (&s[..off], Some(&s[off + 1..])),
+            None => (s.as_str(), None),
+        };
+        let mut formatted = String::new();
+        if x < 0.0 && s.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            formatted.push('-');
+        }
+        formatted.push_str(&self.group(int));
+        if let Some(frac) = frac {
+            formatted.push(self.decimal);
+            formatted.push_str(frac);
+        }
+        formatted
+    }
+}
@@ -0,0 +1,94 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Tallying of the events in the log which aren't ereports, such as upsets,
+// sysevent-derived events and informational reports.  These aren't associated
+// with a device, but are summarized in their own section of the report rather
+// than being silently ignored.
+//
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use get_event_timestamp;
+use i18n::tr;
+use numfmt::NumberFormat;
+
+#[derive(Debug)]
+struct ClassSummary {
+    count: u32,
+    first: i64,
+    last: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct OtherEvents {
+    classes: BTreeMap<String, ClassSummary>,
+}
+
+//
+// The heading under which events of a given class are grouped.
+//
+fn category(class: &str) -> &'static str {
+    if class.starts_with("upset.") {
+        "Upsets"
+    } else if class.contains("sysevent") {
+        "Sysevents"
+    } else if class.starts_with("ireport.") {
+        "Informational Reports"
+    } else if class.starts_with("list.") {
+        "Case Updates"
+    } else {
+        "Other Events"
+    }
+}
+
+impl OtherEvents {
+    pub fn add(&mut self, class: &str, tod: i64) {
+        let summary = self.classes.entry(class.to_string())
+            .or_insert(ClassSummary { count: 0, first: tod, last: tod });
+        summary.count += 1;
+        summary.first = summary.first.min(tod);
+        summary.last = summary.last.max(tod);
+    }
+
+    pub fn write(
+        &self,
+        out: &mut dyn Write,
+        numbers: &NumberFormat
+    ) -> Result<(), Box<dyn Error>> {
+
+        let mut categories: BTreeMap<&str, Vec<(&String, &ClassSummary)>> =
+            BTreeMap::new();
+        for (class, summary) in self.classes.iter() {
+            categories.entry(category(class)).or_default()
+                .push((class, summary));
+        }
+
+        for (category, classes) in categories {
+            writeln!(out, "{}", "=".repeat(75))?;
+            writeln!(out, "{}\n", tr(category))?;
+            let headings = [
+                ("class", "# occurences", "first seen", "last seen"),
+                ("-----", "------------", "----------", "---------"),
+            ];
+            for h in &headings {
+                writeln!(out, "{0: <40} {1: <14} {2: <12} {3}", h.0, h.1, h.2,
+                    h.3)?;
+            }
+            for (class, summary) in classes {
+                writeln!(out, "{0: <40} {1: <14} {2: <12} {3}", class,
+                    numbers.int(summary.count as u64),
+                    get_event_timestamp(summary.first),
+                    get_event_timestamp(summary.last))?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Writing of reports to files, optionally compressed.  Compression is done by
+// piping the report through the system's compression utility, which is always
+// available on the hosts this tool runs on.
+//
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+
+use SimpleError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+impl FromStr for Compression {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Compression, Box<dyn Error>> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            _ => Err(Box::new(SimpleError(
+                format!("unsupported compression: {}", s)))),
+        }
+    }
+}
+
+impl Compression {
+    // The suffix conventionally given to files compressed this way
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+        }
+    }
+}
+
+//
+// The form in which the report is written: the fixed-width text meant for
+// reading, HTML or Markdown for attaching to tickets and pasting into issues,
+// or JSON or CSV for consumption by other tools.
+//
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Csv,
+    Html,
+    Markdown,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<ReportFormat, Box<dyn Error>> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            "html" => Ok(ReportFormat::Html),
+            "markdown" => Ok(ReportFormat::Markdown),
+            _ => Err(Box::new(SimpleError(
+                format!("unsupported report format: {}", s)))),
+        }
+    }
+}
+
+impl ReportFormat {
+    //
+    // Returns the format conventionally indicated by a file's extension,
+    // ignoring any compression suffix.
+    //
+    pub fn from_path(path: &str) -> Option<ReportFormat> {
+        let path = path.strip_suffix(Compression::Gzip.suffix())
+            .unwrap_or(path);
+        let (_, ext) = path.rsplit_once('.')?;
+        match ext {
+            "txt" => Some(ReportFormat::Text),
+            "json" => Some(ReportFormat::Json),
+            "csv" => Some(ReportFormat::Csv),
+            "html" | "htm" => Some(ReportFormat::Html),
+            "md" | "markdown" => Some(ReportFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    // The extension given to report files in this format
+    pub fn ext(self) -> &'static str {
+        match self {
+            ReportFormat::Text => "txt",
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Html => "html",
+            ReportFormat::Markdown => "md",
+        }
+    }
+}
+
+//
+// An output for the report given with --output, as "PATH[:FORMAT]", where a
+// path of "-" means stdout.  The format, if not given, is taken from the
+// path's extension.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputSpec {
+    pub path: Option<String>,
+    pub format: Option<ReportFormat>,
+}
+
+impl FromStr for OutputSpec {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<OutputSpec, Box<dyn Error>> {
+        //
+        // A path may itself contain colons, so a suffix which looks like part
+        // of a path isn't taken as a format.
+        //
+        let (path, format) = match s.rsplit_once(':') {
+            Some((path, format)) if !format.contains(['/', '.']) =>
+                (path, Some(format.parse::<ReportFormat>()?)),
+            _ => (s, None),
+        };
+        if path.is_empty() {
+            return Err(Box::new(SimpleError(
+                format!("invalid output: {}", s))));
+        }
+        let path = if path == "-" { None } else { Some(path.to_string()) };
+        let format = format.or_else(|| path.as_deref()
+            .and_then(ReportFormat::from_path));
+        Ok(OutputSpec { path, format })
+    }
+}
+
+//
+// Returns the path with the suffix for the compression appended, unless it's
+// already there.
+//
+pub fn compressed_path(path: &str, compress: Compression) -> String {
+    if path.ends_with(compress.suffix()) {
+        path.to_string()
+    } else {
+        format!("{}{}", path, compress.suffix())
+    }
+}
+
+//
+// Expand the {name} placeholders in an output path template, such as
+// "report-{hostname}-{date}.{ext}", from the given values.  A literal brace
+// can be written as "{{" or "}}".
+//
+pub fn expand_template(
+    template: &str,
+    vars: &[(&str, String)]
+) -> Result<String, Box<dyn Error>> {
+
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(off) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..off]);
+        let tail = &rest[off..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            expanded.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        let end = match (tail.starts_with('{'), tail.find('}')) {
+            (true, Some(end)) => end,
+            _ => {
+                return Err(Box::new(SimpleError(format!(
+                    "unbalanced braces in output template: {}", template))));
+            }
+        };
+        let name = &tail[1..end];
+        match vars.iter().find(|(n, _)| *n == name) {
+            Some((_, value)) => expanded.push_str(value),
+            None => {
+                return Err(Box::new(SimpleError(format!(
+                    "unknown placeholder in output template: {{{}}}", name))));
+            }
+        }
+        rest = &tail[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+//
+// Returns the name of the local host.
+//
+pub fn local_hostname() -> String {
+    Command::new("uname")
+        .arg("-n")
+        .output()
+        .ok()
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+//
+// Convert a device path or label into something usable as a file name, by
+// replacing each run of anything other than alphanumerics, '.', '-' and '_'
+// (notably the '/' in device paths) with a single '_'.
+//
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+            sanitized.push(c);
+        } else if !sanitized.ends_with('_') {
+            sanitized.push('_');
+        }
+    }
+    let sanitized = sanitized.trim_matches(|c| c == '_' || c == '.');
+    if sanitized.is_empty() {
+        "device".to_string()
+    } else {
+        sanitized.to_string()
+    }
+}
+
+//
+// Write the contents of the buffer to a file, compressing it as requested.
+// Returns the path of the file written, which will have had the compression
+// suffix added if necessary.  The file is written under a temporary name and
+// then renamed into place, so that anyone reading the file (such as another
+// instance of this tool pruning an archive) never sees it partially written.
+//
+pub fn write_file(
+    path: &str,
+    buf: &[u8],
+    compress: Compression
+) -> Result<String, Box<dyn Error>> {
+
+    let path = compressed_path(path, compress);
+    let tmp_path = {
+        let p = Path::new(&path);
+        let name = p.file_name().map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        p.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+    };
+    let result = write_compressed(&tmp_path, &path, buf, compress)
+        .and_then(|_| fs::rename(&tmp_path, &path).map_err(|e| e.into()));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.map(|_| path)
+}
+
+fn write_compressed(
+    tmp_path: &Path,
+    path: &str,
+    buf: &[u8],
+    compress: Compression
+) -> Result<(), Box<dyn Error>> {
+
+    match compress {
+        Compression::None => fs::write(tmp_path, buf)?,
+        Compression::Gzip => {
+            let file = fs::File::create(tmp_path)?;
+            let mut child = Command::new("gzip")
+                .arg("-c")
+                .stdin(Stdio::piped())
+                .stdout(file)
+                .spawn()
+                .map_err(|e| SimpleError(format!("failed to run gzip: {}",
+                    e)))?;
+            child.stdin.take().unwrap().write_all(buf)?;
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(Box::new(SimpleError(format!(
+                    "gzip failed writing {}: {}", path, status))));
+            }
+        }
+    }
+    Ok(())
+}
+
+//
+// Returns the contents of the buffer, compressed as requested.
+//
+pub fn compress(
+    buf: &[u8],
+    compress: Compression
+) -> Result<Vec<u8>, Box<dyn Error>> {
+
+    match compress {
+        Compression::None => Ok(buf.to_vec()),
+        Compression::Gzip => gzip(&["-c"], buf),
+    }
+}
+
+//
+// Returns the contents of the buffer, decompressed if it's gzipped (as told by
+// its magic number) and otherwise as is.
+//
+pub fn decompress(buf: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if buf.starts_with(&[0x1f, 0x8b]) {
+        gzip(&["-dc"], buf)
+    } else {
+        Ok(buf.to_vec())
+    }
+}
+
+//
+// Run gzip with the given arguments as a filter on the buffer.
+//
+fn gzip(args: &[&str], buf: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut child = Command::new("gzip")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| SimpleError(format!("failed to run gzip: {}", e)))?;
+    //
+    // The input is written from another thread, as gzip may fill the pipe to
+    // us before it has read all of its input.
+    //
+    let mut stdin = child.stdin.take().unwrap();
+    let input = buf.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    writer.join().map_err(|_| SimpleError(
+        "gzip input thread panicked".to_string()))??;
+    if !output.status.success() {
+        return Err(Box::new(SimpleError(format!(
+            "gzip failed: {}", output.status))));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,155 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Ownership of devices, for large machines shared between teams or services,
+// so that each can be handed the part of the report it's responsible for.
+// The [owners] section of the configuration file maps globs matching device
+// paths (or FMRIs) or bay labels from the hwgrok data to the owning team:
+//
+//   [owners]
+//   /pci@0,0/pci8086,6f08@3/* = storage
+//   Front Disk 1? = database
+//   Front Disk * = analytics
+//
+// The first matching entry wins, so more specific patterns must come before
+// more general ones.  Each device's owner is shown with it, and the devices
+// are rolled up by owner.
+//
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use conf::ConfFile;
+use glob::glob_match;
+use hwgrok::HwGrok;
+use i18n::tr;
+use status::Status;
+use {DeviceHash, ReportData};
+
+// The rollup of the devices without an owner
+const UNOWNED: &str = "(unowned)";
+
+#[derive(Debug, Default)]
+pub struct OwnerMap {
+    entries: Vec<(String, String)>,
+}
+
+//
+// The devices of one owner.
+//
+#[derive(Debug)]
+pub struct OwnerRollup<'a> {
+    // None for the devices without an owner
+    pub owner: Option<&'a str>,
+    pub devices: usize,
+    pub ereports: usize,
+    // the devices with a status of WATCH or worse
+    pub flagged: Vec<&'a str>,
+    pub worst: Status,
+}
+
+impl OwnerMap {
+    pub fn from_conf(conf: &ConfFile) -> OwnerMap {
+        let entries = conf.section("owners").iter()
+            .map(|(glob, owner)| (glob.to_string(), owner.to_string()))
+            .collect();
+        OwnerMap { entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    //
+    // Returns the owner of a device, matched by its device path or the label
+    // of the bay it's in.
+    //
+    pub fn lookup(&self, devpath: &str, hwgrok: &HwGrok) -> Option<&str> {
+        let label = hwgrok.location_label(devpath);
+        self.entries.iter()
+            .find(|(glob, _)| glob_match(glob, devpath) ||
+                label.is_some_and(|l| glob_match(glob, l)))
+            .map(|(_, owner)| owner.as_str())
+    }
+
+    //
+    // Returns the devices rolled up by owner, in order of owner, with the
+    // devices without one last.
+    //
+    pub fn rollup<'a>(
+        &'a self,
+        device_hash: &'a DeviceHash,
+        data: &ReportData
+    ) -> Vec<OwnerRollup<'a>> {
+
+        let mut rollups: BTreeMap<(bool, Option<&str>), OwnerRollup> =
+            BTreeMap::new();
+        for (devpath, devent) in device_hash.iter() {
+            let owner = self.lookup(devpath, &data.hwgrok);
+            let key = (owner.is_none(), owner);
+            let rollup = rollups.entry(key).or_insert(OwnerRollup {
+                owner,
+                devices: 0,
+                ereports: 0,
+                flagged: Vec::new(),
+                worst: Status::Ok,
+            });
+            rollup.devices += 1;
+            rollup.ereports += devent.ereports.len();
+            if let Some(status) = data.statuses.get(devpath) {
+                rollup.worst = rollup.worst.max(status.status);
+                if status.status > Status::Ok {
+                    rollup.flagged.push(devpath);
+                }
+            }
+        }
+        rollups.into_values()
+            .map(|mut rollup| {
+                rollup.flagged.sort_unstable();
+                rollup
+            })
+            .collect()
+    }
+
+    pub fn write_rollup(
+        &self,
+        out: &mut dyn Write,
+        device_hash: &DeviceHash,
+        data: &ReportData
+    ) -> Result<(), Box<dyn Error>> {
+
+        if self.is_empty() || device_hash.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{}\n", tr("Devices By Owner"))?;
+        let headings = [
+            ("owner", "devices", "ereports", "worst status"),
+            ("-----", "-------", "--------", "------------"),
+        ];
+        for h in &headings {
+            writeln!(out, "{0: <20} {1: <8} {2: <9} {3}", h.0, h.1, h.2,
+                h.3)?;
+        }
+        for rollup in self.rollup(device_hash, data) {
+            let worst = rollup.worst.severity()
+                .paint(&rollup.worst.to_string(), data.color);
+            writeln!(out, "{0: <20} {1: <8} {2: <9} {3}",
+                rollup.owner.unwrap_or(UNOWNED), rollup.devices,
+                rollup.ereports, worst)?;
+            for devpath in &rollup.flagged {
+                let name = data.aliases.lookup(devpath, &data.hwgrok)
+                    .unwrap_or(devpath);
+                writeln!(out, "{0: <20} {1}", "", name)?;
+            }
+        }
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const DEFAULT_PAGER: &str = "less";
+const DEFAULT_TERM_LINES: usize = 24;
+
+//
+// Determine the height of the controlling terminal.  We honor $LINES if it
+// is set and otherwise ask stty, reading from /dev/tty since our own stdin
+// may well be a pipe.
+//
+fn term_lines() -> usize {
+    if let Ok(lines) = env::var("LINES") {
+        if let Ok(n) = lines.trim().parse::<usize>() {
+            return n;
+        }
+    }
+
+    let tty = match fs::File::open("/dev/tty") {
+        Ok(f) => f,
+        Err(_) => return DEFAULT_TERM_LINES,
+    };
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(tty)
+        .stderr(Stdio::null())
+        .output();
+
+    match output {
+        Ok(out) if out.status.success() => {
+            let size = String::from_utf8_lossy(&out.stdout);
+            size.split_whitespace()
+                .next()
+                .and_then(|rows| rows.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_TERM_LINES)
+        }
+        _ => DEFAULT_TERM_LINES,
+    }
+}
+
+//
+// Returns the pager command to use, or None if paging has been disabled by
+// setting $PAGER to an empty string or to "cat".
+//
+fn pager_cmd() -> Option<String> {
+    match env::var("PAGER") {
+        Ok(pager) => {
+            let pager = pager.trim().to_string();
+            if pager.is_empty() || pager == "cat" {
+                None
+            } else {
+                Some(pager)
+            }
+        }
+        Err(_) => Some(DEFAULT_PAGER.to_string()),
+    }
+}
+
+fn write_stdout(buf: &[u8]) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    match handle.write_all(buf).and_then(|_| handle.flush()) {
+        Ok(_) => Ok(()),
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+//
+// Emit the rendered report.  Much like git, if stdout is a terminal and the
+// report won't fit on a single screen, then the output is piped through
+// $PAGER (or less, if $PAGER isn't set).  If the pager can't be started we
+// quietly fall back to writing directly to stdout.
+//
+pub fn output(buf: &[u8], no_pager: bool) -> Result<(), Box<dyn Error>> {
+    if no_pager || !io::stdout().is_terminal() {
+        return write_stdout(buf);
+    }
+
+    let nlines = buf.iter().filter(|&&c| c == b'\n').count();
+    if nlines < term_lines() {
+        return write_stdout(buf);
+    }
+
+    let pager = match pager_cmd() {
+        Some(p) => p,
+        None => return write_stdout(buf),
+    };
+
+    //
+    // As git does, default LESS to FRX so that less exits if the output
+    // turns out to fit after all, passes through escape sequences and
+    // doesn't clear the screen on exit.
+    //
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(&pager).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        cmd.env("LESS", "FRX");
+    }
+
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(_) => return write_stdout(buf),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(buf) {
+            Ok(_) => (),
+            // The user quit the pager before reading everything.
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+    child.wait()?;
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Rollup of PCIe errors up the PCIe tree.  A failing root port, switch or
+// riser shows up as errors against many of the devices beneath it, which are
+// otherwise reported as unrelated leaf devices.  The tree is given by the
+// device paths themselves: each "pci" node in a path (after the root complex)
+// is a root port or switch port, and every device whose path runs through it
+// is beneath it.  The ports beneath which at least two devices have logged
+// PCIe errors are reported, skipping any port whose erroring devices are all
+// beneath a single port further down the tree, so that the most specific
+// common point of failure is listed.
+//
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use devpath;
+use hwgrok::HwGrok;
+use i18n::tr;
+use DeviceHash;
+
+// The minimum number of erroring devices beneath a port for it to be reported
+const MIN_DEVICES: usize = 2;
+
+//
+// The physical and data link layer errors which PCIe corrects, in addition
+// to the "ce" classes.
+//
+const CORRECTABLE: [&str; 6] = ["re", "te", "btlp", "bdllp", "rto", "rr"];
+
+fn is_correctable(class: &str) -> bool {
+    let last = class.rsplit('.').next().unwrap_or("");
+    last.starts_with("ce") || CORRECTABLE.contains(&last)
+}
+
+#[derive(Default)]
+struct PortErrors<'a> {
+    // the erroring devices beneath the port, which may include the port
+    devices: Vec<&'a str>,
+    correctable: u32,
+    uncorrectable: u32,
+}
+
+//
+// Returns the paths of the ports above (and including) the device, from the
+// root port down, e.g. for "/pci@0,0/pci8086,2f02@1/pci15d9,808@0/disk@0":
+//
+//   /pci@0,0/pci8086,2f02@1
+//   /pci@0,0/pci8086,2f02@1/pci15d9,808@0
+//
+fn ports(devpath: &str) -> Vec<&str> {
+    let mut ports = Vec::new();
+    let mut end = 0;
+    for (i, component) in devpath.split('/').enumerate().skip(1) {
+        end += component.len() + 1;
+        // the first component is the root complex itself
+        if i > 1 && component.starts_with("pci") {
+            ports.push(&devpath[..end]);
+        }
+    }
+    ports
+}
+
+pub fn write_pcie_rollup(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let mut tree: BTreeMap<&str, PortErrors> = BTreeMap::new();
+    for (devpath, devent) in device_hash.iter() {
+        let (mut ce, mut ue) = (0, 0);
+        for (class, count) in &devent.ereport_class_hash {
+            if !class.starts_with("ereport.io.pciex.") {
+                continue;
+            }
+            if is_correctable(class) {
+                ce += count;
+            } else {
+                ue += count;
+            }
+        }
+        if ce + ue == 0 {
+            continue;
+        }
+        for port in ports(devpath) {
+            let errors = tree.entry(port).or_default();
+            errors.devices.push(devpath);
+            errors.correctable += ce;
+            errors.uncorrectable += ue;
+        }
+    }
+
+    //
+    // Work up from the deepest ports, so that a port can be skipped if a port
+    // beneath it accounts for all of its erroring devices.
+    //
+    let mut candidates: Vec<(&str, &PortErrors)> = tree.iter()
+        .filter(|(_, e)| e.devices.len() >= MIN_DEVICES)
+        .map(|(p, e)| (*p, e))
+        .collect();
+    candidates.sort_by_key(|(p, _)| (Reverse(p.matches('/').count()), *p));
+    let mut reported: Vec<(&str, &PortErrors)> = Vec::new();
+    for (port, errors) in candidates {
+        let covered = reported.iter().any(|(p, e)|
+            p.starts_with(&format!("{}/", port)) &&
+            e.devices.len() == errors.devices.len());
+        if !covered {
+            reported.push((port, errors));
+        }
+    }
+    if reported.is_empty() {
+        return Ok(());
+    }
+    reported.sort_by(|a, b| (b.1.correctable + b.1.uncorrectable)
+        .cmp(&(a.1.correctable + a.1.uncorrectable)).then(a.0.cmp(b.0)));
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("PCIe Errors By Port"))?;
+    writeln!(out, "Ports beneath which {} or more devices have logged PCIe \
+        errors.\n", MIN_DEVICES)?;
+    for (port, errors) in &reported {
+        writeln!(out, "{0: <40} {1}", tr("Port:"), port)?;
+        let label = hwgrok.pci_devices.iter()
+            .find(|p| devpath::canonicalize(&p.pci_device_path) == *port)
+            .map(|p| p.pci_label.as_str());
+        if let Some(label) = label {
+            writeln!(out, "{0: <40} {1}", tr("Slot:"), label)?;
+        }
+        writeln!(out, "{0: <40} {1}", tr("Correctable errors:"),
+            errors.correctable)?;
+        writeln!(out, "{0: <40} {1}", tr("Uncorrectable errors:"),
+            errors.uncorrectable)?;
+        let mut devices = errors.devices.clone();
+        devices.sort();
+        for device in devices {
+            writeln!(out, "{0: <40} {1}", tr("Device:"), device)?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
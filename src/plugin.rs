@@ -0,0 +1,245 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for decoder and enricher plugins, so that proprietary hardware
+// decoders or site-specific inventory lookups can be dropped in without
+// rebuilding this tool.  Each plugin is a shared object (e.g. a Rust crate
+// built as a cdylib) which is loaded into the process with dlopen(3).  Plugins
+// are listed in the [plugins] section of the configuration file:
+//
+//   [plugins]
+//   vendor-decoder = /opt/site/lib/libdecode_ereports.so
+//   cmdb = /opt/site/lib/libcmdb_lookup.so
+//
+// To keep the ABI small and stable, independent of the compiler used to
+// build either side, a plugin exports three C functions:
+//
+//   uint32_t fmlr_plugin_abi_version(void);
+//   char *fmlr_plugin_request(const char *request);
+//   void fmlr_plugin_free(char *reply);
+//
+// The version must be PLUGIN_ABI_VERSION.  Requests and replies are
+// NUL-terminated UTF-8 JSON objects, and each reply is handed back to the
+// plugin's fmlr_plugin_free() once it has been read.  A plugin may return
+// NULL if it has nothing to add.  For each ereport that is read in, the
+// plugin is sent:
+//
+//   {"type": "ereport", "device": "<device key>", "ereport": {...}}
+//
+// and replies with an object containing any members to add to the ereport's
+// payload (which are then shown when decoding events).  For each device in
+// the report, the plugin is sent:
+//
+//   {"type": "device", "device": "<device key>", "identifiers": [...]}
+//
+// where the identifiers are the device path, bay label and serial number,
+// and replies with an object whose members are displayed as additional
+// "name: value" lines in the device's header.
+//
+// Requests are made from a single thread, one at a time.  Plugins are never
+// unloaded, as a plugin's thread-local destructors may still be registered
+// when the report is done with it.
+//
+// Asset metadata from a CMDB with an HTTP interface, if one is configured
+// (see cmdb.rs), is added to the device's header in the same way.
+//
+use serde_json::{Map, Value};
+
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+
+use cmdb::Cmdb;
+use conf::ConfFile;
+use SimpleError;
+
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+// These values are the same on illumos and Linux.
+const RTLD_NOW: c_int = 2;
+const RTLD_LOCAL: c_int = 0;
+
+//
+// There's no libc crate to hand, so the dynamic linker's interfaces are
+// declared here.  On Linux they're in libdl with older versions of glibc, and
+// in libc itself with newer ones, which also provide libdl for compatibility.
+//
+#[cfg_attr(target_os = "linux", link(name = "dl"))]
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *mut c_char;
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type RequestFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+//
+// Returns the dynamic linker's description of its last error.
+//
+fn dl_error() -> String {
+    unsafe {
+        let msg = dlerror();
+        if msg.is_null() {
+            "unknown error".to_string()
+        } else {
+            CStr::from_ptr(msg).to_string_lossy().into_owned()
+        }
+    }
+}
+
+struct Plugin {
+    name: String,
+    request: RequestFn,
+    free: FreeFn,
+}
+
+impl Plugin {
+    fn load(name: &str, path: &str) -> Result<Plugin, Box<dyn Error>> {
+        let err = |msg: String| -> Box<dyn Error> {
+            Box::new(SimpleError(format!("plugin {}: {}", name, msg)))
+        };
+        let cpath = CString::new(path)
+            .map_err(|_| err(format!("invalid path: {}", path)))?;
+        let handle = unsafe { dlopen(cpath.as_ptr(), RTLD_NOW | RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(err(dl_error()));
+        }
+        let symbol = |sym: &str| -> Result<*mut c_void, Box<dyn Error>> {
+            let csym = CString::new(sym).unwrap();
+            let addr = unsafe { dlsym(handle, csym.as_ptr()) };
+            if addr.is_null() {
+                return Err(err(format!("{}: missing {}", path, sym)));
+            }
+            Ok(addr)
+        };
+
+        //
+        // The symbols are looked up by name, so the best we can do is check
+        // the version before trusting the others to have the types given.
+        //
+        let version = unsafe {
+            let f = std::mem::transmute::<*mut c_void, AbiVersionFn>(
+                symbol("fmlr_plugin_abi_version")?);
+            f()
+        };
+        if version != PLUGIN_ABI_VERSION {
+            return Err(err(format!("{}: ABI version {} (expected {})", path,
+                version, PLUGIN_ABI_VERSION)));
+        }
+        let request = symbol("fmlr_plugin_request")?;
+        let free = symbol("fmlr_plugin_free")?;
+        unsafe {
+            Ok(Plugin {
+                name: name.to_string(),
+                request: std::mem::transmute::<*mut c_void, RequestFn>(request),
+                free: std::mem::transmute::<*mut c_void, FreeFn>(free),
+            })
+        }
+    }
+
+    //
+    // Send a request to the plugin and return its reply.
+    //
+    fn request(&mut self, req: &Value)
+        -> Result<Map<String, Value>, Box<dyn Error>> {
+
+        let err = |msg: String| -> Box<dyn Error> {
+            Box::new(SimpleError(format!("plugin {}: {}", self.name, msg)))
+        };
+        // serialized JSON escapes any NUL in a string, so can't contain one
+        let creq = CString::new(req.to_string()).unwrap();
+        let reply = unsafe {
+            let ptr = (self.request)(creq.as_ptr());
+            if ptr.is_null() {
+                return Ok(Map::new());
+            }
+            let reply = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+            (self.free)(ptr);
+            reply
+        };
+        match serde_json::from_str(&reply) {
+            Ok(Value::Object(reply)) => Ok(reply),
+            _ => Err(err(format!("invalid reply: {}", reply.trim()))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PluginSet {
+    plugins: Vec<Plugin>,
+    cmdb: Option<Cmdb>,
+}
+
+impl PluginSet {
+    pub fn from_conf(conf: &ConfFile) -> Result<PluginSet, Box<dyn Error>> {
+        let mut plugins = Vec::new();
+        for (name, path) in conf.section("plugins") {
+            plugins.push(Plugin::load(name, path)?);
+        }
+        Ok(PluginSet { plugins, cmdb: Cmdb::from_conf(conf)? })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty() && self.cmdb.is_none()
+    }
+
+    //
+    // Returns true if there are plugins to decode ereports.
+    //
+    pub fn decodes(&self) -> bool {
+        !self.plugins.is_empty()
+    }
+
+    //
+    // Pass an ereport, as parsed from the log, through each plugin and merge
+    // the decoded members into its payload.
+    //
+    pub fn decode(
+        &mut self,
+        devkey: &str,
+        event: &Value,
+        payload: &mut Map<String, Value>
+    ) -> Result<(), Box<dyn Error>> {
+
+        let req = json!({"type": "ereport", "device": devkey,
+            "ereport": event});
+        for plugin in self.plugins.iter_mut() {
+            payload.extend(plugin.request(&req)?);
+        }
+        Ok(())
+    }
+
+    //
+    // Ask each plugin for any additional information about a device,
+    // returning (name, value) pairs to be displayed in its header.
+    //
+    pub fn enrich(
+        &mut self,
+        devkey: &str,
+        identifiers: &[&str]
+    ) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+
+        let req = json!({"type": "device", "device": devkey,
+            "identifiers": identifiers});
+        let mut fields = Vec::new();
+        for plugin in self.plugins.iter_mut() {
+            for (name, value) in plugin.request(&req)? {
+                let value = match value {
+                    Value::String(s) => s,
+                    v => v.to_string(),
+                };
+                fields.push((name, value));
+            }
+        }
+        if let Some(cmdb) = self.cmdb.as_mut() {
+            fields.extend(cmdb.lookup(identifiers));
+        }
+        Ok(fields)
+    }
+}
@@ -0,0 +1,80 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --preview, which reads only the last N megabytes of the FM log
+// to give a quick report of recent activity on logs too large to read in full
+// in a reasonable time.  Rather than reading through the log to find where
+// its tail starts, the log is opened at N megabytes from its end, and the
+// rest of the line found there is skipped so that parsing starts on an event
+// boundary.  Reports built from a preview are labeled as partial, since
+// anything before the tail, including fault diagnoses and the history behind
+// rate alerts, is missing from them.
+//
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+use {FmLogError, SimpleError};
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+//
+// How much of the log a preview read.
+//
+#[derive(Debug)]
+pub struct Partial {
+    pub read: u64,
+    pub total: u64,
+}
+
+impl fmt::Display for Partial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "last {} MB of {} MB of the FM log",
+            self.read.div_ceil(MEGABYTE), self.total.div_ceil(MEGABYTE))
+    }
+}
+
+//
+// Parse the size of a preview, given in megabytes, returning it in bytes.
+//
+pub fn parse_preview_size(s: &str) -> Result<u64, Box<dyn Error>> {
+    match s.trim().parse::<u64>().ok().and_then(|n| n.checked_mul(MEGABYTE)) {
+        Some(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(Box::new(SimpleError(format!("invalid preview size: {}", s)))),
+    }
+}
+
+//
+// Open the log at the given path for reading the events in its last bytes,
+// returning the reader and, if the log is larger than that, how much of it is
+// to be read.
+//
+pub fn open_tail(path: &str, bytes: u64)
+    -> Result<(BufReader<fs::File>, Option<Partial>), Box<dyn Error>> {
+
+    let mut file = fs::File::open(path).map_err(|e| FmLogError::Io {
+        path: Some(path.to_string()),
+        source: e,
+    })?;
+    let total = file.metadata()?.len();
+    if total <= bytes {
+        return Ok((BufReader::new(file), None));
+    }
+
+    //
+    // Start from the byte before the tail, so that if the tail starts on a
+    // line boundary, skipping to the end of the line skips only its newline.
+    //
+    let offset = total - bytes - 1;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file);
+    let mut skipped = Vec::new();
+    reader.read_until(b'\n', &mut skipped)?;
+    let read = total - offset - skipped.len() as u64;
+    Ok((reader, Some(Partial { read, total })))
+}
@@ -0,0 +1,58 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for named profiles in the configuration file, selected with
+// --profile, which bundle the options for a common workflow so that they
+// needn't be copied around as long command lines.  A profile's options are
+// given in its [profile.<name>] section by their long names, with "true" for
+// options which take no value, and may be repeated for those which can be
+// given more than once:
+//
+//   [profile.burn-in]
+//   min-severity = warning
+//   fail-on-class = ereport.io.scsi.*
+//   fail-on-class = ereport.cpu.*:10
+//   whole-disk = true
+//
+// Options given on the command line take precedence over the profile's.  A
+// profile can also replace any other section of the configuration file, such
+// as the rate alerting thresholds, with a section named
+// [profile.<name>.<section>] (see conf.rs).
+//
+use std::error::Error;
+
+use conf::ConfFile;
+use SimpleError;
+
+// An option's long name and its value, if it takes one
+pub type ProfileOption = (String, Option<String>);
+
+//
+// Returns the options bundled by the named profile.
+//
+pub fn profile_options(config_path: Option<&str>, name: &str)
+    -> Result<Vec<ProfileOption>, Box<dyn Error>> {
+
+    let path = config_path.ok_or_else(|| SimpleError(
+        "--profile requires a configuration file (-c)".to_string()))?;
+    let mut conf = ConfFile::from_file(path)?;
+    conf.set_profile(name)?;
+
+    let mut options = Vec::new();
+    for (key, value) in conf.section(&format!("profile.{}", name)) {
+        if ["profile", "config", "help"].contains(&key) {
+            return Err(Box::new(SimpleError(format!(
+                "profile {} cannot set --{}", name, key))));
+        }
+        match value {
+            "true" => options.push((key.to_string(), None)),
+            "false" => (),
+            v => options.push((key.to_string(), Some(v.to_string()))),
+        }
+    }
+    Ok(options)
+}
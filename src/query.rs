@@ -0,0 +1,410 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --query, which filters ereports by an expression evaluated
+// against the raw JSON of each, for when a class glob or device pattern isn't
+// enough.  The grammar is:
+//
+//   expr       := term [ "or" term ]...
+//   term       := factor [ "and" factor ]...
+//   factor     := "not" factor | "(" expr ")" | comparison
+//   comparison := field ( "=~" | "!~" ) /regex/[i]
+//               | field ( "==" | "!=" ) value
+//
+// A field is a member of the ereport, with the members of nested objects
+// joined by dots, such as "detector.device-path" or "class".  If a member is
+// an array, the rest of the field is looked up in each of its elements, or in
+// the one given by a number, as in "detector.hc-list.0.hc-id".  A value is a
+// word, or a string in double quotes.  For example:
+//
+//   detector.device-path =~ /pci@0,0.*nvme/
+//   class =~ /\.pciex\./ and not (pcie_ue_status == 0 or pci-sts == 0)
+//
+// The regular expressions are as described in regex.rs, and with the "i"
+// flag ignore case.  Numbers and booleans are compared as they're printed, and
+// a comparison holds if it does for any of the values the field finds.  A
+// field the ereport doesn't have matches nothing, so that "!=" and "!~" hold
+// for it.
+//
+use serde_json::Value;
+
+use std::error::Error;
+use std::str::FromStr;
+
+use regex::Regex;
+use SimpleError;
+
+#[derive(Debug)]
+enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    Matches(Vec<String>, Regex),
+    Equals(Vec<String>, String),
+}
+
+#[derive(Debug)]
+pub struct Query {
+    expr: Expr,
+}
+
+// The deepest nesting of parentheses and "not"s, as queries can come from the
+// network in serve mode and are parsed and evaluated recursively
+const MAX_DEPTH: usize = 100;
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    text: &'a str,
+    // the number of parentheses and "not"s the parser is within
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, what: &str) -> Result<T, Box<dyn Error>> {
+        Err(Box::new(SimpleError(format!(
+            "invalid --query at column {} ({}): {}", self.pos + 1, what,
+            self.text))))
+    }
+
+    fn skip_space(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_space();
+        self.pos >= self.chars.len()
+    }
+
+    //
+    // Consume the given token if it comes next.  Keywords must be followed by
+    // a space or parenthesis, so that e.g. "order" isn't taken as "or".
+    //
+    fn accept(&mut self, token: &str) -> bool {
+        self.skip_space();
+        let len = token.chars().count();
+        if !self.chars[self.pos..].iter().take(len).cloned().eq(token.chars()) {
+            return false;
+        }
+        let keyword = token.chars().all(|c| c.is_ascii_alphabetic());
+        if keyword && self.chars.get(self.pos + len)
+            .is_some_and(|c| !c.is_whitespace() && *c != '(') {
+            return false;
+        }
+        self.pos += len;
+        true
+    }
+
+    fn expr(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut terms = vec![self.term()?];
+        while self.accept("or") {
+            terms.push(self.term()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::Or(terms) })
+    }
+
+    fn term(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut factors = vec![self.factor()?];
+        while self.accept("and") {
+            factors.push(self.factor()?);
+        }
+        Ok(if factors.len() == 1 {
+            factors.remove(0)
+        } else {
+            Expr::And(factors)
+        })
+    }
+
+    fn factor(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let not = self.accept("not");
+        if !not && !self.accept("(") {
+            return self.comparison();
+        }
+        if self.depth == MAX_DEPTH {
+            return self.error("nested too deeply");
+        }
+        self.depth += 1;
+        let expr = if not {
+            Expr::Not(Box::new(self.factor()?))
+        } else {
+            let expr = self.expr()?;
+            if !self.accept(")") {
+                return self.error("expected )");
+            }
+            expr
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    //
+    // Returns the next run of characters which aren't space, parentheses or
+    // the start of an operator.
+    //
+    fn word(&mut self) -> String {
+        self.skip_space();
+        let start = self.pos;
+        while let Some(&c) = self.chars.get(self.pos) {
+            let op = (c == '=' || c == '!') &&
+                matches!(self.chars.get(self.pos + 1), Some('~') | Some('='));
+            if c.is_whitespace() || c == '(' || c == ')' || op {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn value(&mut self) -> Result<String, Box<dyn Error>> {
+        self.skip_space();
+        if self.chars.get(self.pos) != Some(&'"') {
+            let word = self.word();
+            if word.is_empty() {
+                return self.error("expected a value");
+            }
+            return Ok(word);
+        }
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.chars.get(self.pos) {
+                Some('"') => break,
+                Some('\\') if self.pos + 1 < self.chars.len() => {
+                    self.pos += 1;
+                    value.push(self.chars[self.pos]);
+                }
+                Some(&c) => value.push(c),
+                None => return self.error("missing \""),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn regex(&mut self) -> Result<Regex, Box<dyn Error>> {
+        if !self.accept("/") {
+            return self.error("expected /regex/");
+        }
+        //
+        // The regex runs to the next unescaped slash.  Escaped slashes are
+        // unescaped, and other escapes are left for the regex.
+        //
+        let mut pattern = String::new();
+        loop {
+            match self.chars.get(self.pos) {
+                Some('/') => break,
+                Some('\\') if self.chars.get(self.pos + 1) == Some(&'/') => {
+                    self.pos += 1;
+                    pattern.push('/');
+                }
+                Some(&c) => pattern.push(c),
+                None => return self.error("missing /"),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        let ignore_case = self.chars.get(self.pos) == Some(&'i');
+        if ignore_case {
+            self.pos += 1;
+        }
+        match Regex::new(&pattern, ignore_case) {
+            Ok(regex) => Ok(regex),
+            Err(e) => self.error(&format!("bad regex: {}", e)),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let field = self.word();
+        if field.is_empty() {
+            return self.error("expected a field");
+        }
+        let field: Vec<String> = field.split('.').map(String::from).collect();
+        if self.accept("=~") {
+            Ok(Expr::Matches(field, self.regex()?))
+        } else if self.accept("!~") {
+            Ok(Expr::Not(Box::new(Expr::Matches(field, self.regex()?))))
+        } else if self.accept("==") {
+            Ok(Expr::Equals(field, self.value()?))
+        } else if self.accept("!=") {
+            Ok(Expr::Not(Box::new(Expr::Equals(field, self.value()?))))
+        } else {
+            self.error("expected =~, !~, == or !=")
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Query, Box<dyn Error>> {
+        let mut parser = Parser { chars: s.chars().collect(), pos: 0, text: s,
+            depth: 0 };
+        let expr = parser.expr()?;
+        if !parser.at_end() {
+            return parser.error("unexpected text");
+        }
+        Ok(Query { expr })
+    }
+}
+
+//
+// Add the values found by the field in the given value to the list.
+//
+fn lookup<'a>(value: &'a Value, field: &[String], found: &mut Vec<&'a Value>) {
+    let (name, rest) = match field.split_first() {
+        Some(split) => split,
+        None => {
+            found.push(value);
+            return;
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            if let Some(member) = map.get(name) {
+                lookup(member, rest, found);
+            }
+        }
+        Value::Array(elements) => match name.parse::<usize>() {
+            Ok(i) => {
+                if let Some(element) = elements.get(i) {
+                    lookup(element, rest, found);
+                }
+            }
+            Err(_) => {
+                for element in elements {
+                    lookup(element, field, found);
+                }
+            }
+        },
+        _ => (),
+    }
+}
+
+//
+// Returns the values found by the field, as strings.
+//
+fn values(event: &Value, field: &[String]) -> Vec<String> {
+    let mut found = Vec::new();
+    lookup(event, field, &mut found);
+    found.into_iter().map(|v| match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }).collect()
+}
+
+fn eval(expr: &Expr, event: &Value) -> bool {
+    match expr {
+        Expr::Or(exprs) => exprs.iter().any(|e| eval(e, event)),
+        Expr::And(exprs) => exprs.iter().all(|e| eval(e, event)),
+        Expr::Not(expr) => !eval(expr, event),
+        Expr::Matches(field, regex) => {
+            values(event, field).iter().any(|v| regex.is_match(v))
+        }
+        Expr::Equals(field, want) => {
+            values(event, field).iter().any(|v| v == want)
+        }
+    }
+}
+
+impl Query {
+    //
+    // Returns true if the ereport, given as its JSON, satisfies the query.
+    //
+    pub fn matches(&self, event: &Value) -> bool {
+        eval(&self.expr, event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+
+    fn ereport() -> serde_json::Value {
+        json!({
+            "class": "ereport.io.pciex.pl.re",
+            "detector": {
+                "scheme": "dev",
+                "device-path": "/pci@0,0/pci8086,2030@0/nvme@0",
+                "hc-list": [{"hc-name": "motherboard", "hc-id": "0"},
+                    {"hc-name": "bay", "hc-id": "3"}],
+            },
+            "pcie_ue_status": 0,
+            "retry": true,
+            "serial": "S3 EV",
+        })
+    }
+
+    fn matches(query: &str) -> bool {
+        query.parse::<Query>().unwrap().matches(&ereport())
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(matches("detector.device-path =~ /pci@0,0.*nvme/"));
+        assert!(!matches("detector.device-path =~ /sd@/"));
+        assert!(matches("detector.device-path !~ /sd@/"));
+        assert!(matches("class == ereport.io.pciex.pl.re"));
+        assert!(matches("class != ereport.io.pciex.pl.ce"));
+        assert!(matches("class =~ /PCIEX/i"));
+        assert!(!matches("class =~ /PCIEX/"));
+        assert!(matches(r#"serial == "S3 EV""#));
+        assert!(matches(r#"serial == "S3\ EV""#));
+        assert!(matches(r"detector.device-path =~ /^\/pci@0,0\//"));
+    }
+
+    #[test]
+    fn values() {
+        assert!(matches("pcie_ue_status == 0"));
+        assert!(matches("retry == true"));
+        assert!(matches("detector.hc-list.hc-id == 3"));
+        assert!(matches("detector.hc-list.1.hc-id == 3"));
+        assert!(!matches("detector.hc-list.0.hc-id == 3"));
+        // a missing field matches nothing
+        assert!(!matches("missing == 0"));
+        assert!(matches("missing != 0"));
+        assert!(matches("missing !~ /x/"));
+    }
+
+    #[test]
+    fn operators() {
+        assert!(matches("class =~ /pciex/ and pcie_ue_status == 0"));
+        assert!(!matches("class =~ /pciex/ and pcie_ue_status == 1"));
+        assert!(matches("class =~ /cpu/ or pcie_ue_status == 0"));
+        assert!(matches("not class =~ /cpu/"));
+        assert!(matches("not (class =~ /cpu/ or retry == false)"));
+        // "and" binds more tightly than "or"
+        assert!(matches("retry == true or retry == false and class == x"));
+        assert!(!matches("(retry == true or retry == false) and class == x"));
+        // keywords must be followed by a space or parenthesis
+        assert!("order == 1".parse::<Query>().is_ok());
+        assert!(!matches("order == 1"));
+    }
+
+    #[test]
+    fn errors() {
+        for query in &["", "class", "class ==", "class =~ pciex",
+            "class =~ /pciex", "(class == x", "class == x)",
+            r#"class == "x"#, "class =~ /(/", "class == x and"] {
+            assert!(query.parse::<Query>().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = format!("{}class == x{}", "(".repeat(5000),
+            ")".repeat(5000));
+        assert!(nested.parse::<Query>().is_err());
+        let nots = format!("{}class == x", "not ".repeat(5000));
+        assert!(nots.parse::<Query>().is_err());
+        let nested = format!("{}class == x{}", "(".repeat(50),
+            ")".repeat(50));
+        assert!(nested.parse::<Query>().is_ok());
+    }
+}
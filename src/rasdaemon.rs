@@ -0,0 +1,385 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// An input adapter for rasdaemon, which records hardware errors on Linux, so
+// that Linux hosts in a mixed fleet can be reported on in the same way.  Either
+// rasdaemon's SQLite database (read using the system's sqlite3 utility) or the
+// output of "ras-mc-ctl --errors" can be given.  Three kinds of record are
+// converted into events:
+//
+//   memory controller (EDAC) errors    ereport.cpu.linux.edac.{ce,uc}
+//   machine check exceptions           ereport.cpu.linux.mce.{ce,uc}
+//   PCIe AER errors                    ereport.io.pciex.linux.{ce,ue}
+//
+// Memory errors are detected by the DIMM (or, failing a label, the location
+// within the memory controller), machine checks by the CPU and AER errors by
+// the PCIe function.  As Linux has no /devices paths, all of these are
+// represented as hc-scheme detectors.
+//
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::process::Command;
+
+use input;
+use SimpleError;
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+#[derive(Debug, PartialEq)]
+enum Section {
+    Memory,
+    Aer,
+    Mce,
+    Other,
+}
+
+fn parse_time(s: &str) -> Option<i64> {
+    DateTime::parse_from_str(s.trim(), TIME_FORMAT).ok().map(|t| t.timestamp())
+}
+
+fn hc_detector(pairs: &[(&str, &str)]) -> Value {
+    let hc_list: Vec<Value> = pairs.iter()
+        .map(|(name, id)| json!({ "hc-name": name, "hc-id": id }))
+        .collect();
+    json!({ "scheme": "hc", "hc-list": hc_list })
+}
+
+fn event(class: &str, detector: Value, tod: i64, payload: Map<String, Value>)
+    -> Value {
+    let mut ev = payload;
+    ev.insert("class".to_string(), json!(class));
+    ev.insert("detector".to_string(), detector);
+    ev.insert("__tod".to_string(), json!([tod, 0]));
+    Value::Object(ev)
+}
+
+fn memory_event(
+    tod: i64,
+    err_type: &str,
+    label: &str,
+    location: &str,
+    mut payload: Map<String, Value>
+) -> Value {
+    let class = if err_type.eq_ignore_ascii_case("corrected") {
+        "ereport.cpu.linux.edac.ce"
+    } else {
+        "ereport.cpu.linux.edac.uc"
+    };
+    let (mc, rest) = location.split_once(':').unwrap_or((location, ""));
+    let dimm = if label.is_empty() { rest } else { label };
+    payload.insert("error-type".to_string(), json!(err_type));
+    event(class, hc_detector(&[("memory-controller", mc), ("dimm", dimm)]),
+        tod, payload)
+}
+
+fn aer_event(
+    tod: i64,
+    dev: &str,
+    err_type: &str,
+    mut payload: Map<String, Value>
+) -> Value {
+    let class = if err_type.eq_ignore_ascii_case("corrected") {
+        "ereport.io.pciex.linux.ce"
+    } else {
+        "ereport.io.pciex.linux.ue"
+    };
+    payload.insert("error-type".to_string(), json!(err_type));
+    event(class, hc_detector(&[("pcie-function", dev)]), tod, payload)
+}
+
+fn mce_event(
+    tod: i64,
+    socket: &str,
+    cpu: &str,
+    uncorrected: bool,
+    payload: Map<String, Value>
+) -> Value {
+    let class = if uncorrected {
+        "ereport.cpu.linux.mce.uc"
+    } else {
+        "ereport.cpu.linux.mce.ce"
+    };
+    event(class, hc_detector(&[("chip", socket), ("cpu", cpu)]), tod, payload)
+}
+
+//
+// Parse a line of ras-mc-ctl --errors output, which consists of a record ID,
+// a timestamp and then a description whose format depends on the section.
+//
+fn parse_line(section: &Section, line: &str) -> Option<Value> {
+    let mut fields = line.splitn(5, ' ');
+    let _id = fields.next()?.parse::<u64>().ok()?;
+    let stamp = format!("{} {} {}", fields.next()?, fields.next()?,
+        fields.next()?);
+    let tod = parse_time(&stamp)?;
+    let rest = fields.next()?;
+    let mut payload = Map::new();
+
+    match section {
+        Section::Memory => {
+            // <count> <type> error(s): <msg> at <label> location: <loc>, ...
+            let (count, rest) = rest.split_once(' ')?;
+            let (err_type, rest) = rest.split_once(" error(s): ")?;
+            let (msg, rest) = match rest.split_once(" location: ") {
+                Some((m, r)) => (m, r),
+                None => (rest, ""),
+            };
+            let (msg, label) = match msg.rsplit_once(" at ") {
+                Some((m, l)) => (m, l),
+                None => (msg, ""),
+            };
+            let location = rest.split(',').next().unwrap_or("");
+            payload.insert("error-count".to_string(), json!(count));
+            payload.insert("message".to_string(), json!(msg));
+            if let Some((_, detail)) = rest.split_once(", ") {
+                payload.insert("detail".to_string(), json!(detail));
+            }
+            Some(memory_event(tod, err_type, label, location, payload))
+        }
+        Section::Aer => {
+            // <dev> <type> error: <msg>
+            let (dev, rest) = rest.split_once(' ')?;
+            let (err_type, msg) = rest.split_once(" error: ")?;
+            payload.insert("message".to_string(), json!(msg));
+            Some(aer_event(tod, dev, err_type, payload))
+        }
+        Section::Mce => {
+            // error: <msg>, ..., cpu=<n>, ..., socketid=<n>, ...
+            let mut cpu = "";
+            let mut socket = "";
+            for member in rest.split(", ") {
+                match member.split_once('=') {
+                    Some(("cpu", v)) => cpu = v,
+                    Some(("socketid", v)) => socket = v,
+                    Some((k, v)) => {
+                        payload.insert(k.to_string(), json!(v));
+                    }
+                    None => (),
+                }
+            }
+            let msg = rest.strip_prefix("error: ").unwrap_or(rest);
+            payload.insert("message".to_string(), json!(msg));
+            Some(mce_event(tod, socket, cpu, msg.contains("Uncorrected"),
+                payload))
+        }
+        Section::Other => None,
+    }
+}
+
+fn parse_errors_output(contents: &str) -> Vec<Value> {
+    let mut events = Vec::new();
+    let mut section = Section::Other;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.ends_with("events:") {
+            section = if line.starts_with("Memory controller") {
+                Section::Memory
+            } else if line.starts_with("PCIe AER") {
+                Section::Aer
+            } else if line.starts_with("MCE") {
+                Section::Mce
+            } else {
+                Section::Other
+            };
+            continue;
+        }
+        if let Some(ev) = parse_line(&section, line) {
+            events.push(ev);
+        }
+    }
+    events
+}
+
+//
+// Run a query against the database, returning the rows as JSON objects.
+//
+fn query(
+    db: &str,
+    sql: &str
+) -> Result<Vec<Map<String, Value>>, Box<dyn Error>> {
+
+    let output = Command::new("sqlite3")
+        .arg("-readonly")
+        .arg("-json")
+        .arg(db)
+        .arg(sql)
+        .output()?;
+    if !output.status.success() {
+        return Err(Box::new(SimpleError(format!("sqlite3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()))));
+    }
+    // sqlite3 prints nothing at all when there are no rows
+    if output.stdout.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+fn str_col<'a>(row: &'a Map<String, Value>, col: &str) -> &'a str {
+    row.get(col).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn col_string(row: &Map<String, Value>, col: &str) -> String {
+    match row.get(col) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(v) => v.to_string(),
+    }
+}
+
+fn read_database(db: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+    let tables: Vec<String> = query(db,
+        "SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .iter()
+        .map(|row| str_col(row, "name").to_string())
+        .collect();
+    let mut events = Vec::new();
+
+    if tables.iter().any(|t| t == "mc_event") {
+        for row in query(db, "SELECT * FROM mc_event ORDER BY id")? {
+            let tod = match parse_time(str_col(&row, "timestamp")) {
+                Some(t) => t,
+                None => continue,
+            };
+            let location = format!("{}:{}:{}:{}", col_string(&row, "mc"),
+                col_string(&row, "top_layer"), col_string(&row, "middle_layer"),
+                col_string(&row, "lower_layer"));
+            let mut payload = Map::new();
+            for col in &["err_count", "err_msg", "address", "grain",
+                "syndrome", "driver_detail"] {
+                payload.insert(col.replace('_', "-"), json!(col_string(&row,
+                    col)));
+            }
+            events.push(memory_event(tod, str_col(&row, "err_type"),
+                str_col(&row, "label"), &location, payload));
+        }
+    }
+    if tables.iter().any(|t| t == "aer_event") {
+        for row in query(db, "SELECT * FROM aer_event ORDER BY id")? {
+            let tod = match parse_time(str_col(&row, "timestamp")) {
+                Some(t) => t,
+                None => continue,
+            };
+            let mut payload = Map::new();
+            payload.insert("message".to_string(), json!(str_col(&row,
+                "err_msg")));
+            events.push(aer_event(tod, str_col(&row, "dev_name"),
+                str_col(&row, "err_type"), payload));
+        }
+    }
+    if tables.iter().any(|t| t == "mce_record") {
+        for row in query(db, "SELECT * FROM mce_record ORDER BY id")? {
+            let tod = match parse_time(str_col(&row, "timestamp")) {
+                Some(t) => t,
+                None => continue,
+            };
+            let mut payload = Map::new();
+            for col in &["bank", "bank_name", "status", "addr", "misc",
+                "error_msg", "mcistatus_msg", "mc_location"] {
+                payload.insert(col.replace('_', "-"), json!(col_string(&row,
+                    col)));
+            }
+            let uncorrected = str_col(&row, "mcistatus_msg")
+                .contains("Uncorrected");
+            events.push(mce_event(tod, &col_string(&row, "socketid"),
+                &col_string(&row, "cpu"), uncorrected, payload));
+        }
+    }
+
+    events.sort_by_key(|e| e["__tod"][0].as_i64());
+    Ok(events)
+}
+
+//
+// Read the rasdaemon database or ras-mc-ctl output at the given path,
+// returning the records as fmdump-style events in chronological order.
+//
+pub fn read_events(path: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+    let mut magic = [0; 16];
+    let is_db = fs::File::open(path)?.read_exact(&mut magic).is_ok() &&
+        &magic[..] == SQLITE_MAGIC;
+    if is_db {
+        read_database(path)
+    } else {
+        let mut events = parse_errors_output(&input::read_to_string(path)?);
+        events.sort_by_key(|e| e["__tod"][0].as_i64());
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_errors_output, parse_time};
+
+    const ERRORS: &str = "\
+Memory controller events:
+1 2019-06-01 12:00:00 +0000 1 Corrected error(s): memory read error at \
+CPU_SrcID#0_Channel#1_DIMM#0 location: 0:1:0:-1, addr 0x1000, grain 32
+2 2019-06-01 12:30:00 +0000 1 Uncorrected error(s): memory scrubbing error \
+location: 1:0:1:-1
+
+PCIe AER events:
+1 2019-06-01 12:05:00 +0000 0000:00:1c.0 Corrected error: Receiver Error
+
+MCE events:
+1 2019-06-01 11:00:00 +0100 error: Uncorrected error, mcgstatus=0x5, \
+cpu=3, socketid=1, bank=4
+2 2019-06-01 garbage
+
+Extlog events:
+1 2019-06-01 12:00:00 +0000 anything at all
+";
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("2019-06-01 12:00:00 +0000"), Some(1559390400));
+        assert_eq!(parse_time(" 2019-06-01 13:00:00 +0100 "),
+            Some(1559390400));
+        assert_eq!(parse_time("2019-06-01 12:00:00"), None);
+    }
+
+    #[test]
+    fn errors_output() {
+        let events = parse_errors_output(ERRORS);
+        assert_eq!(events.len(), 4);
+
+        let ce = &events[0];
+        assert_eq!(ce["class"], "ereport.cpu.linux.edac.ce");
+        assert_eq!(ce["__tod"][0], 1559390400);
+        assert_eq!(ce["detector"]["scheme"], "hc");
+        assert_eq!(ce["detector"]["hc-list"][0]["hc-id"], "0");
+        assert_eq!(ce["detector"]["hc-list"][1]["hc-id"],
+            "CPU_SrcID#0_Channel#1_DIMM#0");
+        assert_eq!(ce["message"], "memory read error");
+        assert_eq!(ce["detail"], "addr 0x1000, grain 32");
+        assert_eq!(ce["error-count"], "1");
+
+        // without a label, the DIMM is named by its location
+        let uc = &events[1];
+        assert_eq!(uc["class"], "ereport.cpu.linux.edac.uc");
+        assert_eq!(uc["detector"]["hc-list"][0]["hc-id"], "1");
+        assert_eq!(uc["detector"]["hc-list"][1]["hc-id"], "0:1:-1");
+
+        let aer = &events[2];
+        assert_eq!(aer["class"], "ereport.io.pciex.linux.ce");
+        assert_eq!(aer["detector"]["hc-list"][0]["hc-name"], "pcie-function");
+        assert_eq!(aer["detector"]["hc-list"][0]["hc-id"], "0000:00:1c.0");
+        assert_eq!(aer["message"], "Receiver Error");
+
+        let mce = &events[3];
+        assert_eq!(mce["class"], "ereport.cpu.linux.mce.uc");
+        assert_eq!(mce["__tod"][0], 1559383200);
+        assert_eq!(mce["detector"]["hc-list"][0]["hc-id"], "1");
+        assert_eq!(mce["detector"]["hc-list"][1]["hc-id"], "3");
+        assert_eq!(mce["bank"], "4");
+    }
+}
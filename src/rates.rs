@@ -0,0 +1,185 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Rate-of-change alerting, which flags devices whose error rate has jumped
+// rather than those with many errors, as a device that has logged a steady
+// trickle of errors for years is less of a concern than one whose errors have
+// suddenly doubled.  Alerting is enabled by the [rate-alerts] section of the
+// configuration file:
+//
+//   [rate-alerts]
+//   factor = 2
+//   recent-days = 1
+//   baseline-days = 7
+//   min-events = 3
+//   class = ereport.io.*
+//
+// A device is flagged if its errors per day over the most recent period are
+// at least "factor" times its average errors per day over the baseline period
+// before that, and it had at least "min-events" errors in the recent period.
+// Only ereports matching the "class" glob are counted, if one is given.  The
+// periods end at the newest event in the log, rather than the current time,
+// so that logs collected earlier are treated the same way.
+//
+use std::error::Error;
+use std::io::Write;
+
+use conf::ConfFile;
+use glob::glob_match;
+use i18n::tr;
+use numfmt::NumberFormat;
+use {DeviceHash, SimpleError};
+
+const SECS_PER_DAY: i64 = 86400;
+
+#[derive(Debug)]
+pub struct RateAlerts {
+    factor: f64,
+    recent_days: i64,
+    baseline_days: i64,
+    min_events: usize,
+    class: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RateAlert<'a> {
+    pub devpath: &'a str,
+    // errors per day in each period
+    pub recent_rate: f64,
+    pub baseline_rate: f64,
+}
+
+impl RateAlerts {
+    //
+    // Returns the rate alerting settings from the configuration file, or None
+    // if rate alerting isn't enabled.
+    //
+    pub fn from_conf(conf: &ConfFile)
+        -> Result<Option<RateAlerts>, Box<dyn Error>> {
+
+        let entries = conf.section("rate-alerts");
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let mut alerts = RateAlerts {
+            factor: 2.0,
+            recent_days: 1,
+            baseline_days: 7,
+            min_events: 3,
+            class: None,
+        };
+        let invalid = |key: &str, value: &str| {
+            let err: Box<dyn Error> = Box::new(SimpleError(format!(
+                "invalid value for {}: {}", key, value)));
+            err
+        };
+        for (key, value) in entries {
+            match key {
+                "factor" => {
+                    alerts.factor = value.parse().ok().filter(|f| *f > 0.0)
+                        .ok_or_else(|| invalid(key, value))?;
+                }
+                "recent-days" => {
+                    alerts.recent_days = value.parse().ok().filter(|d| *d > 0)
+                        .ok_or_else(|| invalid(key, value))?;
+                }
+                "baseline-days" => {
+                    alerts.baseline_days = value.parse().ok()
+                        .filter(|d| *d > 0)
+                        .ok_or_else(|| invalid(key, value))?;
+                }
+                "min-events" => {
+                    alerts.min_events = value.parse()
+                        .map_err(|_| invalid(key, value))?;
+                }
+                "class" => alerts.class = Some(value.to_string()),
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "unknown rate-alerts setting: {}", key))));
+                }
+            }
+        }
+        Ok(Some(alerts))
+    }
+
+    //
+    // Returns the devices whose error rate has risen enough to alert on,
+    // largest increase first.
+    //
+    pub fn evaluate<'a>(
+        &self,
+        device_hash: &'a DeviceHash
+    ) -> Vec<RateAlert<'a>> {
+
+        let newest = device_hash.values()
+            .filter_map(|d| d.ereports.last())
+            .map(|e| e.tod[0])
+            .max()
+            .unwrap_or(0);
+        let recent_start = newest - self.recent_days * SECS_PER_DAY;
+        let baseline_start = recent_start - self.baseline_days * SECS_PER_DAY;
+
+        let mut alerts = Vec::new();
+        for (devpath, devent) in device_hash.iter() {
+            let tods = devent.ereports.iter()
+                .filter(|e| self.class.as_ref()
+                    .map(|c| glob_match(c, &e.class)).unwrap_or(true))
+                .map(|e| e.tod[0]);
+            let (mut recent, mut baseline) = (0, 0);
+            for tod in tods {
+                if tod > recent_start {
+                    recent += 1;
+                } else if tod > baseline_start {
+                    baseline += 1;
+                }
+            }
+            let recent_rate = recent as f64 / self.recent_days as f64;
+            let baseline_rate = baseline as f64 / self.baseline_days as f64;
+            if recent >= self.min_events.max(1) &&
+                recent_rate >= self.factor * baseline_rate {
+                alerts.push(RateAlert { devpath, recent_rate, baseline_rate });
+            }
+        }
+        alerts.sort_by(|a, b| {
+            let ratio = |a: &RateAlert| a.recent_rate /
+                a.baseline_rate.max(f64::MIN_POSITIVE);
+            ratio(b).total_cmp(&ratio(a)).then(a.devpath.cmp(b.devpath))
+        });
+        alerts
+    }
+
+    pub fn write_alerts(
+        &self,
+        out: &mut dyn Write,
+        device_hash: &DeviceHash,
+        numbers: &NumberFormat
+    ) -> Result<(), Box<dyn Error>> {
+
+        let alerts = self.evaluate(device_hash);
+        if alerts.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{}\n", tr("Error Rate Alerts"))?;
+        writeln!(out, "Devices whose errors per day over the last {} day(s) \
+            were at least {}x their", self.recent_days, self.factor)?;
+        writeln!(out, "rate over the {} day(s) before.\n", self.baseline_days)?;
+        writeln!(out, "{0: <14} {1: <14} device", "recent/day",
+            "baseline/day")?;
+        writeln!(out, "{0: <14} {1: <14} ------", "----------",
+            "------------")?;
+        for alert in &alerts {
+            writeln!(out, "{0: <14} {1: <14} {2}",
+                numbers.float(alert.recent_rate, 1),
+                numbers.float(alert.baseline_rate, 1), alert.devpath)?;
+        }
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
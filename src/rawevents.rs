@@ -0,0 +1,76 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --export-events, which writes every ereport logged against a
+// single device, exactly as it appears in the FM log with its full payload,
+// so that a vendor can be sent the evidence it asks for without being sent
+// the whole log.  The ereports are attributed to devices just as they are for
+// the report (so with --whole-disk, those of a disk's slices are included),
+// and are written as a JSON array of the lines of the log, in time order.
+//
+use std::error::Error;
+
+use devpath::DeviceKey;
+use input::InputReader;
+use logset;
+use {device_key, parse_ereport, Config, FmEvent, FmLogError};
+
+//
+// Returns the ereports in the FM logs which are attributed to any of the
+// given device keys, as a JSON array, in time order.
+//
+pub fn export(
+    config: &Config,
+    input: &InputReader,
+    keys: &[&DeviceKey]
+) -> Result<Vec<u8>, Box<dyn Error>> {
+
+    let mut events: Vec<(Vec<i64>, String)> = Vec::new();
+    for (path, lines) in logset::open_logs(input, &config.fmlog_paths)? {
+        for (i, line) in lines.enumerate() {
+            let line = line.map_err(|e| FmLogError::Io {
+                path: Some(path.clone()),
+                source: e,
+            })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: FmEvent = serde_json::from_str(&line)
+                .map_err(|e| FmLogError::JsonParse { line_no: i + 1,
+                    source: e })?;
+            if !event.class.starts_with("ereport.") ||
+                event.class.starts_with("ereport.fs.") ||
+                event.class.starts_with("ereport.fm.fmd.log_") ||
+                !config.in_window(event.tod.first().cloned()) ||
+                !config.filter.wants_class(&event.class) ||
+                !config.filter.wants_event(&line)? {
+                continue;
+            }
+            let mut ereport = match parse_ereport(&line) {
+                Ok(ereport) => ereport,
+                Err(_) => continue,
+            };
+            match device_key(config, &mut ereport) {
+                Some(key) if keys.contains(&&key) => {
+                    events.push((event.tod, line.trim().to_string()));
+                }
+                _ => (),
+            }
+        }
+    }
+    events.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let lines: Vec<String> = events.into_iter()
+        .map(|(_, line)| format!("  {}", line))
+        .collect();
+    let array = if lines.is_empty() {
+        "[]\n".to_string()
+    } else {
+        format!("[\n{}\n]\n", lines.join(",\n"))
+    };
+    Ok(array.into_bytes())
+}
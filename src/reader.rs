@@ -0,0 +1,68 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// A streaming reader of the ereports in an FM log, for programs which use
+// this crate to build their own aggregations rather than going through the
+// report pipeline.  It reads fmdump -AVj output, one event per line, from any
+// BufRead, and yields each ereport as it's parsed, so that logs needn't fit
+// in memory.  As when reading a log for a report, events other than ereports,
+// such as suspect lists and faults, are passed over, as are the fmd log error
+// ereports which have no detector, and blank lines.  An error doesn't end the
+// iteration, so a caller can skip a corrupt line and carry on:
+//
+//   for ereport in EreportReader::new(BufReader::new(file)) {
+//       match ereport {
+//           Ok(e) => *counts.entry(e.class().to_string()).or_insert(0) += 1,
+//           Err(e) => eprintln!("skipping: {}", e),
+//       }
+//   }
+//
+use std::io::{BufRead, Lines};
+
+use {parse_ereport, Ereport, FmEvent, FmLogError};
+
+pub struct EreportReader<R> {
+    lines: Lines<R>,
+    line_no: usize,
+}
+
+impl<R: BufRead> EreportReader<R> {
+    pub fn new(reader: R) -> EreportReader<R> {
+        EreportReader { lines: reader.lines(), line_no: 0 }
+    }
+}
+
+impl<R: BufRead> Iterator for EreportReader<R> {
+    type Item = Result<Ereport, FmLogError>;
+
+    fn next(&mut self) -> Option<Result<Ereport, FmLogError>> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(FmLogError::from(e))),
+            };
+            self.line_no += 1;
+            let line_no = self.line_no;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let event: FmEvent = match serde_json::from_str(&line) {
+                Ok(event) => event,
+                Err(e) => {
+                    return Some(Err(FmLogError::JsonParse { line_no,
+                        source: e }));
+                }
+            };
+            if !event.class.starts_with("ereport.") ||
+                event.class.starts_with("ereport.fm.fmd.log_") {
+                continue;
+            }
+            return Some(parse_ereport(&line).map_err(|e| e.at_line(line_no)));
+        }
+    }
+}
@@ -0,0 +1,577 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+
+//
+// A small regular expression matcher, sufficient for matching the fields of
+// ereports in --query filters.  It supports:
+//
+//   c          a literal character (or \c for any of the special ones)
+//   .          any character
+//   [a-z0-9]   a class of characters, or [^...] for its complement
+//   \d \w \s   a digit, word character or whitespace (and \D, \W and \S for
+//              their complements)
+//   ^ $        the start and end of the text
+//   (...)      a group, and (a|b) for alternatives
+//   * + ?      zero or more, one or more and zero or one of the preceding
+//              item, and {n}, {n,} and {n,m} for a count of them
+//
+// Matches are searched for anywhere in the text, unless anchored.  As the
+// text comes from the logs being read, which may hold anything, the pattern
+// is compiled to a program which is run over the text by simulating all of
+// its threads in step (a "Pike VM"), which takes time linear in the length of
+// the text and no recursion, rather than by backtracking.
+//
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any,
+    // the ranges of characters, and whether the class is negated
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    // continue at both of the instructions
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+    ignore_case: bool,
+}
+
+// The longest program compiled, which bounds the expansion of counted
+// repetitions such as (a{100}){100}.  Patterns can come from the network in
+// serve mode, so the counts and the nesting of groups are bounded too: the
+// former as a repetition of an empty group compiles to nothing, and the
+// latter as the pattern is parsed and compiled recursively.
+const MAX_PROGRAM: usize = 10000;
+const MAX_REPEAT: usize = 1000;
+const MAX_DEPTH: usize = 100;
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'),
+    ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+    // the number of groups the parser is within
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    //
+    // Returns the class of characters for an escape such as \d, if it is
+    // one, and whether it's negated.
+    //
+    fn escape_class(c: char) -> Option<(Vec<(char, char)>, bool)> {
+        let class = match c.to_ascii_lowercase() {
+            'd' => DIGIT,
+            'w' => WORD,
+            's' => SPACE,
+            _ => return None,
+        };
+        Some((class.to_vec(), c.is_ascii_uppercase()))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('(') => {
+                if self.depth == MAX_DEPTH {
+                    return Err("groups are nested too deeply".to_string());
+                }
+                // (?:...) is accepted as a plain group
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                self.depth += 1;
+                let alternatives = self.alternatives()?;
+                self.depth -= 1;
+                match self.next() {
+                    Some(')') => Ok(Node::Group(alternatives)),
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            Some('[') => self.class(),
+            Some('\\') => match self.next() {
+                Some(c) => Ok(match Parser::escape_class(c) {
+                    Some((ranges, negated)) => Node::Class(ranges, negated),
+                    None => Node::Char(match c {
+                        'n' => '\n',
+                        't' => '\t',
+                        c => c,
+                    }),
+                }),
+                None => Err("trailing \\".to_string()),
+            },
+            Some(c) if "*+?{".contains(c) => {
+                Err(format!("nothing to repeat before {}", c))
+            }
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => break,
+                Some('\\') => match self.next() {
+                    Some(c) => match Parser::escape_class(c) {
+                        Some((class, false)) => {
+                            ranges.extend(class);
+                            continue;
+                        }
+                        Some((_, true)) => {
+                            return Err(format!("\\{} in a class", c));
+                        }
+                        None => c,
+                    },
+                    None => return Err("trailing \\".to_string()),
+                },
+                Some(c) => c,
+                None => return Err("missing ]".to_string()),
+            };
+            first = false;
+            let is_range = self.peek() == Some('-') &&
+                self.chars.get(self.pos + 1).is_some_and(|&c| c != ']');
+            if is_range {
+                self.pos += 1;
+                let end = match self.next() {
+                    Some('\\') => self.next(),
+                    end => end,
+                };
+                match end {
+                    Some(end) if end >= c => ranges.push((c, end)),
+                    _ => return Err(format!("invalid range from {}", c)),
+                }
+            } else {
+                ranges.push((c, c));
+            }
+        }
+        Ok(Node::Class(ranges, negated))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.next() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let min = self.number().ok_or("invalid count")?;
+                let max = match self.next() {
+                    Some('}') => Some(min),
+                    Some(',') if self.peek() == Some('}') => {
+                        self.pos += 1;
+                        None
+                    }
+                    Some(',') => {
+                        let max = self.number().ok_or("invalid count")?;
+                        if self.next() != Some('}') || max < min {
+                            return Err("invalid count".to_string());
+                        }
+                        Some(max)
+                    }
+                    _ => return Err("invalid count".to_string()),
+                };
+                (min, max)
+            }
+            _ => {
+                self.pos -= 1;
+                return Ok(atom);
+            }
+        };
+        if let Node::Start | Node::End = atom {
+            return Err("nothing to repeat".to_string());
+        }
+        if max.unwrap_or(min) > MAX_REPEAT {
+            return Err(format!("count is larger than {}", MAX_REPEAT));
+        }
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+}
+
+//
+// Returns an upper bound on the work of compiling a node, or the alternatives
+// of a group, counting each repetition even if the node compiles to nothing.
+//
+fn cost_alternatives(alternatives: &[Vec<Node>]) -> usize {
+    alternatives.iter()
+        .map(|seq| seq.iter().map(cost).fold(1, usize::saturating_add))
+        .fold(1, usize::saturating_add)
+}
+
+fn cost(node: &Node) -> usize {
+    match node {
+        Node::Group(alternatives) => cost_alternatives(alternatives),
+        Node::Repeat(node, min, max) => cost(node).saturating_add(1)
+            .saturating_mul(max.unwrap_or(*min).saturating_add(1)),
+        _ => 1,
+    }
+}
+
+//
+// Compile a sequence of nodes, or the alternatives of a group, to the end of
+// the program.
+//
+fn compile_sequence(nodes: &[Node], program: &mut Vec<Inst>)
+    -> Result<(), String> {
+    for node in nodes {
+        compile(node, program)?;
+    }
+    Ok(())
+}
+
+fn compile_alternatives(alternatives: &[Vec<Node>], program: &mut Vec<Inst>)
+    -> Result<(), String> {
+    let mut jumps = Vec::new();
+    for (i, seq) in alternatives.iter().enumerate() {
+        if i + 1 == alternatives.len() {
+            compile_sequence(seq, program)?;
+            break;
+        }
+        let split = program.len();
+        program.push(Inst::Split(split + 1, 0));
+        compile_sequence(seq, program)?;
+        jumps.push(program.len());
+        program.push(Inst::Jump(0));
+        program[split] = Inst::Split(split + 1, program.len());
+    }
+    let end = program.len();
+    for jump in jumps {
+        program[jump] = Inst::Jump(end);
+    }
+    Ok(())
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_PROGRAM {
+        return Err("pattern is too large".to_string());
+    }
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(ranges, negated) => {
+            program.push(Inst::Class(ranges.clone(), *negated))
+        }
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Group(alternatives) => compile_alternatives(alternatives,
+            program)?,
+        Node::Repeat(node, min, max) => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    // a loop of the node, which may be skipped
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    // each optional repetition may skip to the end
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+//
+// The state of matching a regular expression against a text: the threads at
+// the current position, by the instruction each is at.
+//
+struct Matcher<'a> {
+    regex: &'a Regex,
+    len: usize,
+    // the position each instruction was last added to a list of threads at,
+    // so that each is run at most once per position
+    added: Vec<usize>,
+    stack: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn in_class(&self, ranges: &[(char, char)], c: char) -> bool {
+        let within = |c: char| ranges.iter()
+            .any(|&(lo, hi)| lo <= c && c <= hi);
+        within(c) || (self.regex.ignore_case &&
+            (within(c.to_ascii_lowercase()) || within(c.to_ascii_uppercase())))
+    }
+
+    //
+    // Add a thread at the given instruction to the list for the position,
+    // following jumps, splits and anchors to the instructions which consume a
+    // character.  Returns true if a thread reaches a match.
+    //
+    fn add(&mut self, list: &mut Vec<usize>, pc: usize, pos: usize) -> bool {
+        self.stack.push(pc);
+        while let Some(pc) = self.stack.pop() {
+            if self.added[pc] == pos {
+                continue;
+            }
+            self.added[pc] = pos;
+            match self.regex.program[pc] {
+                Inst::Jump(to) => self.stack.push(to),
+                Inst::Split(a, b) => {
+                    self.stack.push(b);
+                    self.stack.push(a);
+                }
+                Inst::Start if pos == 0 => self.stack.push(pc + 1),
+                Inst::End if pos == self.len => self.stack.push(pc + 1),
+                Inst::Start | Inst::End => (),
+                Inst::Match => {
+                    self.stack.clear();
+                    return true;
+                }
+                _ => list.push(pc),
+            }
+        }
+        false
+    }
+
+    //
+    // Returns true if the instruction consumes the character.
+    //
+    fn consumes(&self, pc: usize, c: char) -> bool {
+        match &self.regex.program[pc] {
+            Inst::Char(want) => c == *want ||
+                (self.regex.ignore_case && c.eq_ignore_ascii_case(want)),
+            Inst::Any => true,
+            Inst::Class(ranges, negated) =>
+                self.in_class(ranges, c) != *negated,
+            _ => false,
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = Parser { chars: &chars, pos: 0, depth: 0 };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < chars.len() {
+            return Err("unmatched )".to_string());
+        }
+        if cost_alternatives(&alternatives) > MAX_PROGRAM {
+            return Err("pattern is too large".to_string());
+        }
+        let mut program = Vec::new();
+        compile_alternatives(&alternatives, &mut program)?;
+        if program.len() > MAX_PROGRAM {
+            return Err("pattern is too large".to_string());
+        }
+        program.push(Inst::Match);
+        Ok(Regex { program, ignore_case })
+    }
+
+    //
+    // Returns true if the regular expression matches anywhere in the text.  A
+    // new thread is started at each position, so that this is done in a
+    // single pass.
+    //
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let mut matcher = Matcher {
+            regex: self,
+            len: text.len(),
+            added: vec![usize::MAX; self.program.len()],
+            stack: Vec::new(),
+        };
+        let mut threads = Vec::new();
+        let mut next = Vec::new();
+        for pos in 0..=text.len() {
+            if matcher.add(&mut threads, 0, pos) {
+                return true;
+            }
+            let c = match text.get(pos) {
+                Some(&c) => c,
+                None => break,
+            };
+            for &pc in &threads {
+                if matcher.consumes(pc, c) && matcher.add(&mut next, pc + 1,
+                    pos + 1) {
+                    return true;
+                }
+            }
+            threads.clear();
+            std::mem::swap(&mut threads, &mut next);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern, false).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals() {
+        assert!(matches("nvme", "/pci@0,0/pci8086,2030@0/nvme@0"));
+        assert!(!matches("nvme", "/pci@0,0/sd@0"));
+        assert!(matches("a.c", "xabcx"));
+        assert!(matches(r"\.pciex\.", "ereport.io.pciex.pl.re"));
+        assert!(!matches(r"\.pciex\.", "ereport.io.pciexpl"));
+        assert!(matches("", "anything"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^ereport", "ereport.io"));
+        assert!(!matches("^io", "ereport.io"));
+        assert!(matches(r"\.io$", "ereport.io"));
+        assert!(!matches("^ereport$", "ereport.io"));
+        assert!(matches("^$", ""));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("disk@w[0-9a-f]+$", "disk@w5000cca2"));
+        assert!(!matches("^[^/]", "/pci@0,0"));
+        assert!(matches(r"^\d+$", "12345"));
+        assert!(!matches(r"^\d+$", "123a5"));
+        assert!(matches(r"\s", "a b"));
+        assert!(matches(r"^[\w-]+$", "hc-list"));
+        assert!(matches("[]a]", "]"));
+        assert!(Regex::new("[z-a]", false).is_err());
+        assert!(Regex::new("[abc", false).is_err());
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(matches("^(sd|nvme)@", "nvme@0"));
+        assert!(matches("^(sd|nvme)@", "sd@0"));
+        assert!(!matches("^(sd|nvme)@", "ses@0"));
+        assert!(matches("cpu|mem", "ereport.cpu.intel"));
+        assert!(matches("^(?:a|b)c$", "bc"));
+        assert!(Regex::new("(ab", false).is_err());
+        assert!(Regex::new("ab)", false).is_err());
+    }
+
+    #[test]
+    fn repeats() {
+        assert!(matches("^a*$", ""));
+        assert!(matches("^ab+c$", "abbbc"));
+        assert!(!matches("^ab+c$", "ac"));
+        assert!(matches("^ab?c$", "ac"));
+        assert!(matches("^a{3}$", "aaa"));
+        assert!(!matches("^a{3}$", "aaaa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2,}$", "a"));
+        assert!(matches("^(ab){1,2}$", "abab"));
+        assert!(!matches("^(ab){1,2}$", "ababab"));
+        assert!(Regex::new("a{2,1}", false).is_err());
+        assert!(Regex::new("*a", false).is_err());
+    }
+
+    #[test]
+    fn ignore_case() {
+        let regex = Regex::new("^disk@W5000[A-F]+", true).unwrap();
+        assert!(regex.is_match("DISK@w5000cca"));
+        assert!(!Regex::new("W5000", false).unwrap().is_match("w5000"));
+    }
+
+    #[test]
+    fn pathological() {
+        // linear time, where backtracking would take exponential time
+        let text = "a".repeat(30);
+        assert!(!matches("^(a|a)*(a*)*b$", &text));
+        assert!(matches("(x+x+)+y", &format!("{}y", "x".repeat(30))));
+    }
+
+    #[test]
+    fn limits() {
+        assert!(Regex::new("(){9999999999}", false).is_err());
+        assert!(Regex::new("a{1001}", false).is_err());
+        assert!(Regex::new("a{1000}", false).is_ok());
+        assert!(Regex::new("((){1000}){1000}", false).is_err());
+        assert!(Regex::new("(a{100}){101}", false).is_err());
+        let nested = format!("{}a{}", "(".repeat(5000), ")".repeat(5000));
+        assert!(Regex::new(&nested, false).is_err());
+        let nested = format!("{}a{}", "(".repeat(100), ")".repeat(100));
+        assert!(matches(&nested, "a"));
+    }
+}
@@ -0,0 +1,120 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Detection of disks that were replaced in-place during the period covered by
+// the logs.  When a disk is swapped, the device path usually stays the same
+// but the devid (which encodes the WWN) and serial number carried in the
+// ereport telemetry change.  Lumping the errors from the old and new disk
+// together is misleading, so the statistics for the path are split into
+// segments, one per distinct disk.
+//
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use i18n::tr;
+use numfmt::NumberFormat;
+use {get_event_timestamp, DeviceHashEnt, Ereport};
+
+//
+// A contiguous run of ereports which were generated by the same disk.
+//
+pub struct Segment<'a> {
+    pub identity: String,
+    pub ereports: Vec<&'a Ereport>,
+}
+
+//
+// Returns a string identifying the physical disk which generated an ereport,
+// if one is available in the telemetry.
+//
+pub fn ereport_identity(ereport: &Ereport) -> Option<String> {
+    if let Some(devid) = &ereport.detector.devid {
+        return Some(devid.clone());
+    }
+    for member in &["devid", "serial", "serial-number"] {
+        if let Some(id) = ereport.payload.get(*member)
+            .and_then(|v| v.as_str()) {
+            return Some(id.to_string());
+        }
+    }
+    None
+}
+
+//
+// Split the ereports for a device into segments based on the identity of the
+// disk.  Ereports which carry no identity are attributed to the current
+// segment.
+//
+pub fn segments(devent: &DeviceHashEnt) -> Vec<Segment<'_>> {
+    let mut segs: Vec<Segment> = Vec::new();
+
+    for ereport in &devent.ereports {
+        let identity = ereport_identity(ereport);
+        match (segs.last_mut(), identity) {
+            (Some(seg), Some(id)) if seg.identity != id => {
+                segs.push(Segment { identity: id, ereports: vec![ereport] });
+            }
+            (Some(seg), _) => seg.ereports.push(ereport),
+            (None, id) => {
+                segs.push(Segment {
+                    identity: id.unwrap_or_else(|| "unknown".to_string()),
+                    ereports: vec![ereport],
+                });
+            }
+        }
+    }
+
+    segs
+}
+
+//
+// If the disk behind this device path was replaced during the log window,
+// write out the replacement date(s) along with separate statistics for each
+// disk.
+//
+pub fn write_replacements(
+    out: &mut dyn Write,
+    devent: &DeviceHashEnt,
+    numbers: &NumberFormat
+) -> Result<(), Box<dyn Error>> {
+
+    let segs = segments(devent);
+    if segs.len() < 2 {
+        return Ok(());
+    }
+
+    for pair in segs.windows(2) {
+        writeln!(out, "{0: <40} {1} ({2} -> {3})", tr("Disk Replaced:"),
+            get_event_timestamp(pair[1].ereports[0].tod[0]),
+            pair[0].identity, pair[1].identity)?;
+    }
+
+    for (idx, seg) in segs.iter().enumerate() {
+        let first = seg.ereports.first().unwrap();
+        let last = seg.ereports.last().unwrap();
+        writeln!(out, "\nDisk {} of {}: {}", idx + 1, segs.len(),
+            seg.identity)?;
+        writeln!(out, "{0: <40} {1} - {2}", tr("Period:"),
+            get_event_timestamp(first.tod[0]),
+            get_event_timestamp(last.tod[0]))?;
+        writeln!(out, "{0: <40} {1}", tr("Total ereports:"),
+            numbers.int(seg.ereports.len() as u64))?;
+
+        let mut class_counts: BTreeMap<&str, u32> = BTreeMap::new();
+        for ereport in &seg.ereports {
+            *class_counts.entry(&ereport.class).or_insert(0) += 1;
+        }
+        for (class, count) in &class_counts {
+            writeln!(out, "  {0: <38} {1}", class,
+                numbers.int(*count as u64))?;
+        }
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
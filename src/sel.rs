@@ -0,0 +1,325 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for merging the IPMI System Event Log into the report.  The SEL
+// often records hardware events, such as PSU and thermal events, which FMA
+// never sees.  The SEL can be given as the output of "ipmitool sel elist":
+//
+//    1 | 03/01/2019 | 12:00:00 | Memory DIMM_A1 | Correctable ECC | Asserted
+//    2 | 03/01/2019 | 12:05:00 | Power Supply PS1 Status | Power Supply AC lost | Asserted
+//
+// or as JSON objects with the same columns as members ("id", "date", "time",
+// "sensor", "event" and "direction"), where "sensor-type" can also be given
+// separately rather than as the start of "sensor".
+//
+// Each asserted record becomes an ereport.sel.<sensor type>.<event> event.
+// Records are attributed to the hwgrok component whose sensor or label matches
+// the record's sensor name, and otherwise to the sensor itself, as
+// ipmi:///sensor=<name>.
+//
+use chrono::prelude::*;
+use serde_json::Value;
+
+use std::error::Error;
+use std::fs;
+
+use devpath::DeviceKey;
+use hwgrok::HwGrok;
+use severity::{Severity, SeverityMap};
+use {process_event, DeviceHash, Ereport};
+
+//
+// The sensor types that ipmitool prefixes to sensor names, longest first
+// where one is a prefix of another.
+//
+const SENSOR_TYPES: [&str; 30] = [
+    "Temperature", "Voltage", "Current", "Fan", "Physical Security",
+    "Platform Security", "Processor", "Power Supply", "Power Unit",
+    "Cooling Device", "Memory", "Drive Slot / Bay", "POST Memory Resize",
+    "System Firmwares", "Event Logging Disabled", "Watchdog", "System Event",
+    "Critical Interrupt", "Module / Board", "Add-in Card", "Chassis",
+    "Chip Set", "Cable / Interconnect", "System Boot Initiated", "Boot Error",
+    "OS Boot", "OS Critical Stop", "Slot / Connector", "Battery",
+    "Management Subsys Health",
+];
+
+#[derive(Debug)]
+pub struct SelRecord {
+    tod: i64,
+    sensor_type: String,
+    sensor: String,
+    event: String,
+    detail: String,
+}
+
+//
+// Convert a description into a class component, e.g. "Power Supply AC lost"
+// becomes "power_supply_ac_lost".
+//
+fn class_component(s: &str) -> String {
+    let mut c = String::new();
+    for ch in s.chars() {
+        if ch.is_ascii_alphanumeric() {
+            c.push(ch.to_ascii_lowercase());
+        } else if !c.is_empty() && !c.ends_with('_') {
+            c.push('_');
+        }
+    }
+    c.trim_end_matches('_').to_string()
+}
+
+fn split_sensor(sensor: &str) -> (String, String) {
+    for t in &SENSOR_TYPES {
+        if let Some(rest) = sensor.strip_prefix(t) {
+            if rest.is_empty() || rest.starts_with(' ') {
+                return (t.to_string(), rest.trim().to_string());
+            }
+        }
+    }
+    ("Other".to_string(), sensor.trim().to_string())
+}
+
+fn parse_time(date: &str, time: &str) -> Option<i64> {
+    let dt = NaiveDateTime::parse_from_str(
+        &format!("{} {}", date.trim(), time.trim()), "%m/%d/%Y %H:%M:%S")
+        .ok()?;
+    Some(dt.and_utc().timestamp())
+}
+
+//
+// Parse a line of "ipmitool sel elist" output.  Records which aren't asserted,
+// or which have no valid time (e.g. "Pre-Init" records), are skipped.
+//
+fn parse_line(line: &str) -> Option<SelRecord> {
+    let cols: Vec<&str> = line.split('|').map(|c| c.trim()).collect();
+    if cols.len() < 6 || cols[5] != "Asserted" {
+        return None;
+    }
+    let tod = parse_time(cols[1], cols[2])?;
+    let (sensor_type, sensor) = split_sensor(cols[3]);
+    Some(SelRecord {
+        tod,
+        sensor_type,
+        sensor,
+        event: cols[4].to_string(),
+        detail: cols[6..].join(" | "),
+    })
+}
+
+fn parse_json(value: &Value) -> Option<SelRecord> {
+    let get = |k: &str| value.get(k).and_then(|v| v.as_str()).unwrap_or("");
+    if !get("direction").eq_ignore_ascii_case("asserted") {
+        return None;
+    }
+    let tod = parse_time(get("date"), get("time"))?;
+    let (sensor_type, sensor) = match value.get("sensor-type")
+        .and_then(|v| v.as_str()) {
+        Some(t) => (t.to_string(), get("sensor").to_string()),
+        None => split_sensor(get("sensor")),
+    };
+    Some(SelRecord {
+        tod,
+        sensor_type,
+        sensor,
+        event: get("event").to_string(),
+        detail: get("detail").to_string(),
+    })
+}
+
+pub fn read_sel(path: &str) -> Result<Vec<SelRecord>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    if !contents.trim_start().starts_with(['{', '[']) {
+        return Ok(contents.lines().filter_map(parse_line).collect());
+    }
+    let mut records = Vec::new();
+    for value in serde_json::Deserializer::from_str(&contents)
+        .into_iter::<Value>() {
+        match value? {
+            Value::Array(a) => records.extend(a.iter().filter_map(parse_json)),
+            v => records.extend(parse_json(&v)),
+        }
+    }
+    Ok(records)
+}
+
+//
+// Find the hwgrok component that a SEL sensor belongs to, either because the
+// component has a sensor of that name or because the sensor's name contains
+// the component's label.  Returns the component's FMRI.
+//
+fn find_component<'a>(sensor: &str, hwgrok: &'a HwGrok) -> Option<&'a str> {
+    let chassis = [(&hwgrok.chassis.chassis_fmri, "",
+        &hwgrok.chassis.chassis_sensors)];
+    let chips = hwgrok.processors.iter()
+        .map(|c| (&c.chip_fmri, c.chip_label.as_str(), &c.chip_sensors));
+    let psus = hwgrok.psus.iter()
+        .map(|p| (&p.psu_fmri, p.psu_label.as_str(), &p.psu_sensors));
+    let fans = hwgrok.fans.iter()
+        .map(|f| (&f.fan_fmri, f.fan_label.as_str(), &f.fan_sensors));
+    let components: Vec<_> = chips.chain(psus).chain(fans)
+        .chain(chassis.iter().cloned())
+        .collect();
+
+    for (fmri, _, sensors) in &components {
+        if sensors.iter().any(|s| s.sensor_name == sensor) {
+            return Some(fmri.as_str());
+        }
+    }
+
+    //
+    // DIMMs and drive bays have no sensors of their own, so can only be
+    // matched by label.  The longest matching label wins, so that e.g.
+    // "PS10" isn't mistaken for "PS1".
+    //
+    let sensor = sensor.to_lowercase();
+    let dimms = hwgrok.memory.iter()
+        .map(|m| (&m.slot_fmri, m.slot_label.as_str()));
+    let bays = hwgrok.drive_bays.iter()
+        .map(|b| (&b.bay_fmri, b.bay_label.as_str()));
+    components.iter().map(|(fmri, label, _)| (*fmri, *label))
+        .chain(dimms)
+        .chain(bays)
+        .filter(|(_, label)| !label.is_empty() &&
+            sensor.contains(&label.to_lowercase()))
+        .max_by_key(|(_, label)| label.len())
+        .map(|(fmri, _)| fmri.as_str())
+}
+
+//
+// Add the SEL records to the device hash as ereports, skipping those whose
+// severity is below the minimum.
+//
+pub fn merge(
+    records: &[SelRecord],
+    hwgrok: &HwGrok,
+    severity: &SeverityMap,
+    min_severity: Option<Severity>,
+    device_hash: &mut DeviceHash
+) -> Result<(), Box<dyn Error>> {
+
+    for rec in records {
+        let class = format!("ereport.sel.{}.{}",
+            class_component(&rec.sensor_type), class_component(&rec.event));
+        if let Some(min) = min_severity {
+            if severity.lookup(&class) < min {
+                continue;
+            }
+        }
+        let key = match find_component(&rec.sensor, hwgrok) {
+            Some(fmri) => DeviceKey::from_fmri(fmri),
+            None => DeviceKey::from_fmri(&format!("ipmi:///sensor={}",
+                rec.sensor)),
+        };
+        let ereport: Ereport = serde_json::from_value(json!({
+            "class": class,
+            "detector": { "scheme": "ipmi" },
+            "__tod": [rec.tod, 0],
+            "sensor": rec.sensor,
+            "event": rec.event,
+            "detail": rec.detail,
+        }))?;
+        process_event(device_hash, &key, ereport)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{class_component, find_component, parse_json, parse_line,
+        split_sensor};
+    use hwgrok::parse_hwgrok;
+
+    #[test]
+    fn classes() {
+        assert_eq!(class_component("Power Supply AC lost"),
+            "power_supply_ac_lost");
+        assert_eq!(class_component("Drive Slot / Bay"), "drive_slot_bay");
+        assert_eq!(class_component(" -Lower Critical- "), "lower_critical");
+        assert_eq!(split_sensor("Power Supply PS1 Status"),
+            ("Power Supply".to_string(), "PS1 Status".to_string()));
+        assert_eq!(split_sensor("Power Unit"),
+            ("Power Unit".to_string(), String::new()));
+        assert_eq!(split_sensor("Fans"),
+            ("Other".to_string(), "Fans".to_string()));
+    }
+
+    #[test]
+    fn lines() {
+        let rec = parse_line("   2 | 03/01/2019 | 12:05:00 | Power Supply PS1 \
+            Status | Power Supply AC lost | Asserted | extra").unwrap();
+        assert_eq!(rec.tod, 1551441900);
+        assert_eq!(rec.sensor_type, "Power Supply");
+        assert_eq!(rec.sensor, "PS1 Status");
+        assert_eq!(rec.event, "Power Supply AC lost");
+        assert_eq!(rec.detail, "extra");
+
+        assert!(parse_line("3 | 03/01/2019 | 12:06:00 | Power Supply PS1 \
+            Status | Power Supply AC lost | Deasserted").is_none());
+        assert!(parse_line("4 | Pre-Init | 0000012345 | Memory DIMM_A1 | \
+            Correctable ECC | Asserted").is_none());
+        assert!(parse_line("SEL has no entries").is_none());
+    }
+
+    #[test]
+    fn json() {
+        let rec = parse_json(&json!({ "date": "03/01/2019",
+            "time": "12:00:00", "sensor": "Memory DIMM_A1",
+            "event": "Correctable ECC", "direction": "asserted" })).unwrap();
+        assert_eq!(rec.tod, 1551441600);
+        assert_eq!(rec.sensor_type, "Memory");
+        assert_eq!(rec.sensor, "DIMM_A1");
+
+        let rec = parse_json(&json!({ "date": "03/01/2019",
+            "time": "12:00:00", "sensor-type": "Fan", "sensor": "FAN1",
+            "event": "Lower Critical", "direction": "Asserted" })).unwrap();
+        assert_eq!(rec.sensor_type, "Fan");
+        assert_eq!(rec.sensor, "FAN1");
+
+        assert!(parse_json(&json!({ "date": "03/01/2019",
+            "time": "12:00:00", "direction": "Deasserted" })).is_none());
+    }
+
+    #[test]
+    fn components() {
+        let hwgrok = parse_hwgrok(&json!({
+            "chassis": { "hc-fmri": "hc:///chassis=0", "manufacturer": "",
+                "model": "", "leds": [], "sensors": [{ "name": "Inlet Temp",
+                "type": "temperature" }] },
+            "pci-devices": [],
+            "drive-bays": [{ "hc-fmri": "hc:///chassis=0/bay=0",
+                "label": "Front Disk 0", "leds": [] }],
+            "processors": [],
+            "memory": [{ "hc-fmri": "hc:///motherboard=0/chip=0/dimm=0",
+                "label": "DIMM_A1" }],
+            "power-supplies": [
+                { "hc-fmri": "hc:///psu=0", "label": "PS1",
+                    "manufacturer": "", "model": "",
+                    "firmware-revision": "", "leds": [] },
+                { "hc-fmri": "hc:///psu=9", "label": "PS10",
+                    "manufacturer": "", "model": "",
+                    "firmware-revision": "", "leds": [] },
+            ],
+            "fans": [{ "hc-fmri": "hc:///fan=0", "label": "FAN1",
+                "leds": [], "sensors": [{ "name": "System Fan",
+                "type": "fan" }] }],
+        }).to_string()).unwrap();
+
+        assert_eq!(find_component("System Fan", &hwgrok),
+            Some("hc:///fan=0"));
+        assert_eq!(find_component("Inlet Temp", &hwgrok),
+            Some("hc:///chassis=0"));
+        assert_eq!(find_component("PS1 Status", &hwgrok),
+            Some("hc:///psu=0"));
+        assert_eq!(find_component("PS10 Status", &hwgrok),
+            Some("hc:///psu=9"));
+        assert_eq!(find_component("dimm_a1", &hwgrok),
+            Some("hc:///motherboard=0/chip=0/dimm=0"));
+        assert_eq!(find_component("Front Disk 0 Fault", &hwgrok),
+            Some("hc:///chassis=0/bay=0"));
+        assert_eq!(find_component("CPU1 Temp", &hwgrok), None);
+    }
+}
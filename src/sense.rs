@@ -0,0 +1,169 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Decoding of the SCSI sense data in the payloads of disk ereports
+// (ereport.io.scsi.cmd.disk.*).  The sd driver includes the sense key and the
+// additional sense code and qualifier (ASC/ASCQ) returned by the disk, as the
+// "key", "asc" and "ascq" payload members, and these say far more about what
+// went wrong than the ereport class does: the same class covers both a
+// medium error from an unreadable sector and a hardware error from a failed
+// self-test.  Each disk's section summarizes its ereports by sense data, e.g.
+//
+//   Medium Error (3/11/00)           42       Unrecovered read error
+//
+// with descriptions of the common ASC/ASCQ codes from the SCSI Primary
+// Commands standard.  As for the registers of CPU and PCIe ereports, the codes
+// may be given as numbers or as strings of hex digits.
+//
+use serde_json::{Map, Value};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use cpu::register;
+use i18n::tr;
+use numfmt::NumberFormat;
+use DeviceHashEnt;
+
+pub const CLASS_PREFIX: &str = "ereport.io.scsi.cmd.disk.";
+
+const SENSE_KEYS: [&str; 16] = [
+    "No Sense",
+    "Recovered Error",
+    "Not Ready",
+    "Medium Error",
+    "Hardware Error",
+    "Illegal Request",
+    "Unit Attention",
+    "Data Protect",
+    "Blank Check",
+    "Vendor Specific",
+    "Copy Aborted",
+    "Aborted Command",
+    "Reserved",
+    "Volume Overflow",
+    "Miscompare",
+    "Completed",
+];
+
+const ASC_ASCQ: [(u8, u8, &str); 34] = [
+    (0x00, 0x00, "No additional sense information"),
+    (0x04, 0x00, "Logical unit not ready, cause not reportable"),
+    (0x04, 0x01, "Logical unit is in process of becoming ready"),
+    (0x04, 0x02, "Logical unit not ready, initializing command required"),
+    (0x04, 0x03, "Logical unit not ready, manual intervention required"),
+    (0x0b, 0x01, "Warning - specified temperature exceeded"),
+    (0x0c, 0x00, "Write error"),
+    (0x0c, 0x02, "Write error - auto reallocation failed"),
+    (0x11, 0x00, "Unrecovered read error"),
+    (0x11, 0x01, "Read retries exhausted"),
+    (0x11, 0x04, "Unrecovered read error - auto reallocate failed"),
+    (0x14, 0x01, "Record not found"),
+    (0x15, 0x01, "Mechanical positioning error"),
+    (0x16, 0x00, "Data synchronization mark error"),
+    (0x17, 0x01, "Recovered data with retries"),
+    (0x18, 0x00, "Recovered data with error correction applied"),
+    (0x1d, 0x00, "Miscompare during verify operation"),
+    (0x20, 0x00, "Invalid command operation code"),
+    (0x21, 0x00, "Logical block address out of range"),
+    (0x24, 0x00, "Invalid field in CDB"),
+    (0x25, 0x00, "Logical unit not supported"),
+    (0x29, 0x00, "Power on, reset, or bus device reset occurred"),
+    (0x2a, 0x01, "Mode parameters changed"),
+    (0x31, 0x00, "Medium format corrupted"),
+    (0x32, 0x00, "No defect spare location available"),
+    (0x3e, 0x01, "Logical unit failure"),
+    (0x3e, 0x02, "Timeout on logical unit"),
+    (0x3e, 0x03, "Logical unit failed self-test"),
+    (0x3f, 0x0e, "Reported LUNs data has changed"),
+    (0x44, 0x00, "Internal target failure"),
+    (0x47, 0x00, "SCSI parity error"),
+    (0x4b, 0x00, "Data phase error"),
+    (0x5d, 0x00, "Failure prediction threshold exceeded"),
+    (0x5d, 0xff, "Failure prediction threshold exceeded (false)"),
+];
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Sense {
+    pub key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+}
+
+impl Sense {
+    //
+    // Returns the sense data in an ereport's payload, if it has any.
+    //
+    pub fn from_payload(payload: &Map<String, Value>) -> Option<Sense> {
+        let byte = |name: &str| register(payload.get(name))
+            .filter(|v| *v <= 0xff)
+            .map(|v| v as u8);
+        Some(Sense {
+            key: byte("key")?,
+            asc: byte("asc").unwrap_or(0),
+            ascq: byte("ascq").unwrap_or(0),
+        })
+    }
+
+    pub fn key_name(&self) -> &'static str {
+        SENSE_KEYS[(self.key & 0xf) as usize]
+    }
+
+    pub fn description(&self) -> Option<&'static str> {
+        ASC_ASCQ.iter()
+            .find(|(asc, ascq, _)| *asc == self.asc && *ascq == self.ascq)
+            .map(|(_, _, desc)| *desc)
+    }
+
+    //
+    // The sense key name and the codes, e.g. "Medium Error (3/11/00)".
+    //
+    pub fn code(&self) -> String {
+        format!("{} ({:x}/{:02x}/{:02x})", self.key_name(), self.key,
+            self.asc, self.ascq)
+    }
+}
+
+//
+// Write the number of the disk's ereports with each sense key and ASC/ASCQ,
+// if any of them carried sense data.
+//
+pub fn write_device_sense(
+    out: &mut dyn Write,
+    devent: &DeviceHashEnt,
+    numbers: &NumberFormat
+) -> Result<(), Box<dyn Error>> {
+
+    let mut counts: BTreeMap<Sense, u64> = BTreeMap::new();
+    for ereport in devent.ereports.iter()
+        .filter(|e| e.class.starts_with(CLASS_PREFIX)) {
+        if let Some(sense) = Sense::from_payload(&ereport.payload) {
+            *counts.entry(sense).or_insert(0) += 1;
+        }
+    }
+    if counts.is_empty() {
+        return Ok(());
+    }
+
+    let heading = tr("SCSI Sense Data");
+    writeln!(out, "{}\n{}", heading, "-".repeat(heading.chars().count()))?;
+    let headings = [
+        ("sense (key/asc/ascq)", "count", "description"),
+        ("--------------------", "-----", "-----------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <32} {1: <8} {2}", h.0, h.1, h.2)?;
+    }
+    for (sense, count) in counts {
+        let line = format!("{0: <32} {1: <8} {2}", sense.code(),
+            numbers.int(count), sense.description().unwrap_or(""));
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
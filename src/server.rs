@@ -0,0 +1,566 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for the "serve" subcommand, which reads the logs once and then
+// answers HTTP requests for slices of the report, so that dashboards can fetch
+// just the devices and classes they're interested in.  The server is
+// deliberately minimal - it handles one request at a time, dropping clients
+// which take too long over it, and speaks just enough HTTP/1.0 for curl,
+// browsers and GraphQL clients.
+//
+// The report endpoint, /report, accepts these query parameters:
+//
+//   device   a device path or label, as for the device subcommand
+//   class    a glob pattern that ereport classes must match
+//   since    only include ereports at or after this time
+//   format   "text" (the default) or "json"
+//
+// The /ready endpoint returns 200 once the logs have been read in and the
+// server is answering requests, for use as a readiness check.
+//
+// The /graphql endpoint accepts a query either as the "query" parameter of a
+// GET, or as the "query" member of the JSON body of a POST (see graphql.rs).
+//
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use device;
+use devpath::DeviceKey;
+use glob::glob_match;
+use graphql;
+use log;
+use model;
+use signals;
+use {json_bytes, load, parse_timestamp, process_event, write_report, Config,
+    DeviceHash, DeviceHashEnt, Loaded, ReportData, Sources};
+
+// How often to check for signals while waiting for a connection
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+//
+// How long to wait on a client reading a request or writing a response,
+// before the connection is dropped.  Connections are handled one at a time,
+// so this bounds how long a stalled client can hold up the others, and the
+// handling of signals.
+//
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The largest request body accepted, as a GraphQL query needs far less
+const MAX_BODY: usize = 1024 * 1024;
+
+//
+// The longest request line or header accepted, and the most headers.  As with
+// the body, these stop a client from having us buffer whatever it sends,
+// which the timeout alone doesn't do while the client keeps sending.
+//
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn error(status: u16, msg: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain",
+            body: format!("{}\n", msg).into_bytes(),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+//
+// Decode a percent-encoded query string component, in which '+' also stands
+// for a space.
+//
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            //
+            // Both characters are checked as hex digits, as from_str_radix()
+            // would also accept a leading sign.
+            //
+            b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit() => {
+                let hex = String::from_utf8_lossy(&bytes[i + 1..i + 3]);
+                decoded.push(u8::from_str_radix(&hex, 16).unwrap_or(b'%'));
+                i += 2;
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+//
+// Read a line of the request, of at most MAX_LINE bytes, returning false if
+// the line is longer than that.
+//
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    line: &mut String
+) -> Result<bool, Box<dyn Error>> {
+
+    let len = reader.take(MAX_LINE as u64).read_line(line)?;
+    Ok(len < MAX_LINE || line.ends_with('\n'))
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query.split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.split_once('=') {
+            Some((k, v)) => (percent_decode(k), percent_decode(v)),
+            None => (percent_decode(p), String::new()),
+        })
+        .collect()
+}
+
+//
+// Build a new device hash containing only the ereports which match the
+// request's filters.  Devices left with no ereports are omitted.
+//
+pub fn filter_devices(
+    device_hash: &DeviceHash,
+    device: Option<&DeviceKey>,
+    class: Option<&str>,
+    since: Option<i64>
+) -> Result<DeviceHash, Box<dyn Error>> {
+
+    let mut filtered = HashMap::new();
+    for (devpath, devent) in device_hash.iter() {
+        if device.is_some() && device != Some(devpath) {
+            continue;
+        }
+        for ereport in &devent.ereports {
+            if class.map(|c| !glob_match(c, &ereport.class)).unwrap_or(false) {
+                continue;
+            }
+            if since.map(|t| ereport.tod[0] < t).unwrap_or(false) {
+                continue;
+            }
+            process_event(&mut filtered, devpath, ereport.clone())?;
+        }
+        if let Some(ent) = filtered.get_mut(devpath) {
+            ent.merged_paths = devent.merged_paths.clone();
+        }
+    }
+    Ok(filtered)
+}
+
+//
+// Returns the JSON form of a device's entry in the report: its class counts
+// and the day-by-day distribution of its ereports.
+//
+pub fn device_json(
+    devpath: &DeviceKey,
+    devent: Option<&DeviceHashEnt>,
+    data: &ReportData
+) -> Value {
+
+    let mut classes = serde_json::Map::new();
+    let mut daily = serde_json::Map::new();
+    if let Some(devent) = devent {
+        for (class, count) in &devent.ereport_class_hash {
+            classes.insert(class.clone(), json!(count));
+        }
+        for ts in &devent.ereports_ts {
+            daily.insert(ts.clone(), json!(devent.ereport_ts_hash[ts]));
+        }
+    }
+    let status = data.statuses.get(devpath);
+    json!({
+        "path": devpath.as_str(),
+        "alias": data.aliases.lookup(devpath, &data.hwgrok),
+        "owner": data.owners.lookup(devpath, &data.hwgrok),
+        "total": devent.map(|d| d.ereports.len()).unwrap_or(0),
+        "status": status.map(|s| s.status.to_string()),
+        "status_reasons": status.map(|s| s.reasons.clone())
+            .unwrap_or_default(),
+        "classes": classes,
+        "daily": daily,
+    })
+}
+
+//
+// Render the device hash as JSON, with the devices sorted by path.
+//
+pub fn report_json(
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Value {
+
+    let mut devpaths: Vec<&DeviceKey> = device_hash.keys().collect();
+    devpaths.sort();
+
+    let devices: Vec<Value> = devpaths.iter()
+        .map(|devpath| device_json(devpath, device_hash.get(*devpath), data))
+        .collect();
+
+    let mut report = json!({ "devices": devices });
+    if !data.owners.is_empty() {
+        let owners: Vec<Value> = data.owners.rollup(device_hash, data).iter()
+            .map(|rollup| json!({
+                "owner": rollup.owner,
+                "devices": rollup.devices,
+                "ereports": rollup.ereports,
+                "worst_status": rollup.worst.to_string(),
+                "flagged": rollup.flagged,
+            }))
+            .collect();
+        report["owners"] = json!(owners);
+    }
+    let system = data.hwgrok.system_identity();
+    if !system.is_empty() {
+        let members: serde_json::Map<String, Value> = system.iter()
+            .map(|(name, value)| (name.to_lowercase().replace(' ', "_"),
+                json!(value)))
+            .collect();
+        report["system"] = Value::Object(members);
+    }
+    if let Some(coverage) = &data.coverage {
+        report["coverage"] = json!({
+            "logs": coverage.logs,
+            "first": coverage.first,
+            "last": coverage.last,
+        });
+    }
+    if let Some(partial) = &data.partial {
+        report["partial"] = json!({
+            "read_bytes": partial.read,
+            "total_bytes": partial.total,
+        });
+    }
+    model::versioned(report)
+}
+
+fn handle_report(
+    query: &HashMap<String, String>,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<Response, Box<dyn Error>> {
+
+    let device = match query.get("device") {
+        Some(arg) => match device::resolve_device(arg, device_hash, data) {
+            Some(devpath) => Some(devpath),
+            None => {
+                return Ok(Response::error(404,
+                    &format!("no events or hardware found for {}", arg)));
+            }
+        },
+        None => None,
+    };
+    let since = match query.get("since") {
+        Some(s) => match parse_timestamp(s) {
+            Some(t) => Some(t),
+            None => {
+                return Ok(Response::error(400,
+                    &format!("invalid time: {}", s)));
+            }
+        },
+        None => None,
+    };
+    let filtered = filter_devices(device_hash, device.as_ref(),
+        query.get("class").map(|c| c.as_str()), since)?;
+
+    match query.get("format").map(|f| f.as_str()) {
+        None | Some("text") => {
+            let mut body = Vec::new();
+            write_report(&mut body, &filtered, data)?;
+            Ok(Response { status: 200, content_type: "text/plain", body })
+        }
+        Some("json") => {
+            let body = json_bytes(&report_json(&filtered, data))?;
+            Ok(Response { status: 200, content_type: "application/json",
+                body })
+        }
+        Some(f) => Ok(Response::error(400, &format!("invalid format: {}", f))),
+    }
+}
+
+fn handle_graphql(
+    method: &str,
+    query: &HashMap<String, String>,
+    body: &[u8],
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<Response, Box<dyn Error>> {
+
+    let text = if method == "POST" {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(r) => r,
+            Err(e) => {
+                return Ok(Response::error(400,
+                    &format!("invalid request body: {}", e)));
+            }
+        };
+        request.get("query").and_then(|q| q.as_str()).map(|q| q.to_string())
+    } else {
+        query.get("query").cloned()
+    };
+    let text = match text {
+        Some(t) => t,
+        None => return Ok(Response::error(400, "no query given")),
+    };
+
+    let mut body = serde_json::to_vec(&graphql::execute(&text, device_hash,
+        data))?;
+    body.push(b'\n');
+    Ok(Response { status: 200, content_type: "application/json", body })
+}
+
+//
+// Read a request from the connection and write out the response.
+//
+fn handle_connection(
+    stream: TcpStream,
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    if !read_line(&mut reader, &mut request_line)? {
+        return write_response(stream, &Response::error(400,
+            &format!("request line is over {} bytes", MAX_LINE)));
+    }
+    //
+    // Only the length of any body is of interest in the headers.  It's
+    // checked before the body is read, so that a client can't have us
+    // allocate whatever it claims to be sending.
+    //
+    let mut content_length = Ok(0);
+    let mut headers = 0;
+    loop {
+        let mut header = String::new();
+        if !read_line(&mut reader, &mut header)? {
+            return write_response(stream, &Response::error(431,
+                &format!("header is over {} bytes", MAX_LINE)));
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return write_response(stream, &Response::error(431,
+                &format!("request has over {} headers", MAX_HEADERS)));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = match value.trim().parse::<usize>() {
+                    Ok(len) if len > MAX_BODY => Err(Response::error(413,
+                        &format!("request body is over {} bytes", MAX_BODY))),
+                    Ok(len) => Ok(len),
+                    Err(_) => Err(Response::error(400,
+                        &format!("invalid Content-Length: {}",
+                        value.trim()))),
+                };
+            }
+        }
+    }
+    let content_length = match content_length {
+        Ok(len) => len,
+        Err(response) => return write_response(stream, &response),
+    };
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+    let (path, query) = match target.split_once('?') {
+        Some((p, q)) => (p, parse_query(q)),
+        None => (target, HashMap::new()),
+    };
+
+    let response = match (method, path) {
+        ("GET", "/ready") => Ok(Response { status: 200,
+            content_type: "text/plain", body: b"ready\n".to_vec() }),
+        ("GET", "/report") => handle_report(&query, device_hash, data),
+        ("GET", "/graphql") | ("POST", "/graphql") => handle_graphql(method,
+            &query, &body, device_hash, data),
+        (_, "/ready") | (_, "/report") | (_, "/graphql") =>
+            Ok(Response::error(405, &format!("{} is not supported", method))),
+        _ => Ok(Response::error(404, &format!("no such endpoint: {}", path))),
+    }.unwrap_or_else(|e| Response::error(500, &e.to_string()));
+
+    write_response(stream, &response)
+}
+
+fn write_response(
+    mut stream: TcpStream,
+    response: &Response
+) -> Result<(), Box<dyn Error>> {
+
+    write!(stream, "HTTP/1.0 {} {}\r\nContent-Type: {}\r\n\
+        Content-Length: {}\r\nConnection: close\r\n\r\n", response.status,
+        reason(response.status), response.content_type, response.body.len())?;
+    stream.write_all(&response.body)?;
+    Ok(())
+}
+
+//
+// The pid file, removed when dropped.
+//
+struct PidFile {
+    path: String,
+}
+
+impl PidFile {
+    fn create(path: &str) -> Result<PidFile, Box<dyn Error>> {
+        fs::write(path, format!("{}\n", process::id()))?;
+        Ok(PidFile { path: path.to_string() })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+//
+// Listen on the given address and answer requests until SIGTERM or SIGINT is
+// received.  On SIGHUP, the configuration file and the logs are read in again;
+// if that fails, the previously loaded report continues to be served.
+//
+pub fn serve(
+    config: &Config,
+    addr: &str,
+    loaded: Loaded
+) -> Result<(), Box<dyn Error>> {
+
+    let mut loaded = loaded;
+    if config.timing {
+        loaded.timings.report();
+    }
+    signals::install();
+    let listener = TcpListener::bind(addr)?;
+    //
+    // The listener is polled so that signals are noticed promptly, as they
+    // don't interrupt a blocking accept().
+    //
+    listener.set_nonblocking(true)?;
+    let _pidfile = match &config.pidfile {
+        Some(path) => Some(PidFile::create(path)?),
+        None => None,
+    };
+    let local_addr = listener.local_addr()?;
+    log::info(&format!("listening on {}", local_addr),
+        &[("addr", json!(local_addr.to_string()))]);
+
+    while !signals::shutdown_requested() {
+        if signals::take_reload() {
+            log::info("reloading", &[]);
+            match load(config, Sources::default()) {
+                Ok(l) => {
+                    loaded = l;
+                    if config.timing {
+                        loaded.timings.report();
+                    }
+                }
+                Err(e) => log::error(&format!("reload failed: {}", e),
+                    &[("error", json!(e.to_string()))]),
+            }
+        }
+        // The report goes to HTTP clients rather than a terminal.
+        loaded.data.color = false;
+
+        let result = match listener.accept() {
+            Ok((s, _)) => s.set_nonblocking(false)
+                .and_then(|_| s.set_read_timeout(Some(CLIENT_TIMEOUT)))
+                .and_then(|_| s.set_write_timeout(Some(CLIENT_TIMEOUT)))
+                .map_err(|e| e.into())
+                .and_then(|_| handle_connection(s, &loaded.device_hash,
+                    &loaded.data)),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => Err(e.into()),
+        };
+        // A misbehaving client shouldn't take the server down.
+        if let Err(e) = result {
+            log::warn(&format!("request failed: {}", e),
+                &[("error", json!(e.to_string()))]);
+        }
+    }
+    log::info("shutting down", &[]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_query, percent_decode, read_line, MAX_LINE};
+
+    #[test]
+    fn decoding() {
+        assert_eq!(percent_decode("a%20b+c"), "a b c");
+        assert_eq!(percent_decode("%2Fdev%2fsda"), "/dev/sda");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz"), "%zz");
+        assert_eq!(percent_decode("%+1"), "% 1");
+        assert_eq!(percent_decode("%-1"), "%-1");
+    }
+
+    #[test]
+    fn queries() {
+        let query = parse_query("device=%2Fdev%2Fsda&class=ereport.io.*&&x");
+        assert_eq!(query.len(), 3);
+        assert_eq!(query["device"], "/dev/sda");
+        assert_eq!(query["class"], "ereport.io.*");
+        assert_eq!(query["x"], "");
+    }
+
+    #[test]
+    fn lines() {
+        let mut reader = "GET / HTTP/1.0\r\nHost: x\r\n\r\n".as_bytes();
+        let mut line = String::new();
+        assert!(read_line(&mut reader, &mut line).unwrap());
+        assert_eq!(line, "GET / HTTP/1.0\r\n");
+
+        let long = format!("GET /{} HTTP/1.0\r\n", "a".repeat(MAX_LINE));
+        let mut reader = long.as_bytes();
+        let mut line = String::new();
+        assert!(!read_line(&mut reader, &mut line).unwrap());
+        assert_eq!(line.len(), MAX_LINE);
+
+        let exact = format!("{}\n", "a".repeat(MAX_LINE - 1));
+        let mut reader = exact.as_bytes();
+        let mut line = String::new();
+        assert!(read_line(&mut reader, &mut line).unwrap());
+    }
+}
@@ -0,0 +1,301 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for reporting the state of SES enclosures (e.g. JBODs) from captured
+// Enclosure Status diagnostic pages, as printed by "sg_ses --page=es":
+//
+//     HGST      H4060-J           3010
+//     Primary enclosure logical identifier (hex): 5000ccab0400bc00
+//   Enclosure Status diagnostic page:
+//     INVOP=0, INFO=0, NON-CRIT=0, CRIT=1, UNRECOV=0
+//     status descriptor list
+//       Element type: Array device slot, subenclosure id: 0 [ti=0]
+//         Overall descriptor:
+//           Predicted failure=0, Disabled=0, Swap=0, status: Unsupported
+//         Element 0 descriptor:
+//           Predicted failure=0, Disabled=0, Swap=0, status: OK
+//           ...
+//
+// A file may contain the pages of several enclosures, one after another.
+// The overall descriptors are ignored in favour of the individual elements.
+//
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+
+use i18n::tr;
+
+#[derive(Debug)]
+struct Element {
+    element_type: String,
+    index: u32,
+    status: String,
+    predicted_failure: bool,
+    fail: bool,
+    ident: bool,
+}
+
+#[derive(Debug)]
+struct Enclosure {
+    name: String,
+    elements: Vec<Element>,
+}
+
+#[derive(Debug, Default)]
+pub struct SesData {
+    enclosures: Vec<Enclosure>,
+}
+
+//
+// Returns the value of a "name=value" member of a comma-separated descriptor
+// line, if present.
+//
+fn flag(line: &str, name: &str) -> bool {
+    line.split(',')
+        .filter_map(|m| m.trim().split_once('='))
+        .any(|(k, v)| k.trim() == name && v.trim() == "1")
+}
+
+fn parse_pages(contents: &str, default_name: &str) -> Vec<Enclosure> {
+    let mut enclosures = Vec::new();
+    // the identity of the enclosure whose page is about to start
+    let mut product: Option<String> = None;
+    let mut ident: Option<String> = None;
+    let mut element_type = String::new();
+    let mut in_element = false;
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with("Enclosure Status diagnostic page") {
+            let name = match (product.take(), ident.take()) {
+                (Some(p), Some(i)) => format!("{} ({})", p, i),
+                (Some(p), None) => p,
+                (None, Some(i)) => i,
+                (None, None) => default_name.to_string(),
+            };
+            enclosures.push(Enclosure { name, elements: Vec::new() });
+            in_element = false;
+            continue;
+        }
+        if let Some((_, id)) = line.split_once("logical identifier (hex):") {
+            ident = Some(id.trim().to_string());
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("Element type:") {
+            element_type = rest.split(',').next().unwrap_or("").trim()
+                .to_string();
+            in_element = false;
+            continue;
+        }
+        if line.starts_with("Overall descriptor") {
+            in_element = false;
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("Element ") {
+            if let Some(idx) = rest.strip_suffix(" descriptor:")
+                .and_then(|i| i.trim().parse().ok()) {
+                if let Some(encl) = enclosures.last_mut() {
+                    encl.elements.push(Element {
+                        element_type: element_type.clone(),
+                        index: idx,
+                        status: String::new(),
+                        predicted_failure: false,
+                        fail: false,
+                        ident: false,
+                    });
+                    in_element = true;
+                }
+                continue;
+            }
+        }
+        if in_element {
+            let elem = enclosures.last_mut().unwrap().elements.last_mut()
+                .unwrap();
+            if let Some((_, status)) = line.split_once("status: ") {
+                elem.status = status.trim().to_string();
+            }
+            elem.predicted_failure |= flag(line, "Predicted failure");
+            elem.fail |= flag(line, "Fail");
+            elem.ident |= flag(line, "Ident");
+            continue;
+        }
+        //
+        // Outside of the status descriptors, a line with no name/value
+        // separators is the vendor, product and revision of the next
+        // enclosure.
+        //
+        if product.is_none() && !line.contains(':') && !line.contains('=') &&
+            !line.contains("descriptor list") {
+            product = Some(line.split_whitespace().collect::<Vec<_>>()
+                .join(" "));
+        }
+    }
+    enclosures
+}
+
+impl SesData {
+    pub fn from_files(paths: &[String]) -> Result<SesData, Box<dyn Error>> {
+        let mut enclosures = Vec::new();
+        for path in paths {
+            let contents = fs::read_to_string(path)?;
+            enclosures.extend(parse_pages(&contents, path));
+        }
+        Ok(SesData { enclosures })
+    }
+
+    //
+    // Write the health of each enclosure: the number of elements of each
+    // type in each status, followed by the elements which aren't OK.
+    // Elements which are not installed or whose status is unsupported are
+    // counted but not listed.
+    //
+    pub fn write(&self, out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        if self.enclosures.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "{}", "=".repeat(75))?;
+        writeln!(out, "{}\n", tr("Enclosure Health"))?;
+        for encl in &self.enclosures {
+            writeln!(out, "{} {}\n", tr("Enclosure:"), encl.name)?;
+
+            let mut by_type: BTreeMap<&str, BTreeMap<&str, usize>> =
+                BTreeMap::new();
+            for elem in &encl.elements {
+                *by_type.entry(&elem.element_type).or_default()
+                    .entry(&elem.status).or_insert(0) += 1;
+            }
+            for (element_type, statuses) in &by_type {
+                let counts: Vec<String> = statuses.iter()
+                    .map(|(status, n)| format!("{} {}", n, status))
+                    .collect();
+                writeln!(out, "{0: <24} {1}", element_type,
+                    counts.join(", "))?;
+            }
+
+            let problems: Vec<&Element> = encl.elements.iter()
+                .filter(|e| e.predicted_failure || e.fail ||
+                    !["OK", "Not installed", "Unsupported", "Unknown"]
+                        .contains(&e.status.as_str()))
+                .collect();
+            if !problems.is_empty() {
+                writeln!(out)?;
+                let headings = [
+                    ("element", "index", "status", "note"),
+                    ("-------", "-----", "------", "----"),
+                ];
+                for h in &headings {
+                    writeln!(out, "{0: <24} {1: <6} {2: <16} {3}", h.0, h.1,
+                        h.2, h.3)?;
+                }
+                for elem in problems {
+                    let mut notes = Vec::new();
+                    if elem.predicted_failure {
+                        notes.push("predicted failure");
+                    }
+                    if elem.fail {
+                        notes.push("fail indicator on");
+                    }
+                    if elem.ident {
+                        notes.push("ident indicator on");
+                    }
+                    let row = format!("{0: <24} {1: <6} {2: <16} {3}",
+                        elem.element_type, elem.index, elem.status,
+                        notes.join(", "));
+                    writeln!(out, "{}", row.trim_end())?;
+                }
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flag, parse_pages, SesData};
+
+    const PAGES: &str = "
+    HGST      H4060-J           3010
+    Primary enclosure logical identifier (hex): 5000ccab0400bc00
+  Enclosure Status diagnostic page:
+    INVOP=0, INFO=0, NON-CRIT=0, CRIT=1, UNRECOV=0
+    status descriptor list
+      Element type: Array device slot, subenclosure id: 0 [ti=0]
+        Overall descriptor:
+          Predicted failure=0, Disabled=0, Swap=0, status: Unsupported
+        Element 0 descriptor:
+          Predicted failure=0, Disabled=0, Swap=0, status: OK
+          OK=0, Reserved device=0, Hot spare=0, Cons check=0
+        Element 1 descriptor:
+          Predicted failure=1, Disabled=0, Swap=0, status: OK
+        Element 2 descriptor:
+          Predicted failure=0, Disabled=0, Swap=0, status: Critical
+          Ident=1, Rmv=0, Ready to insert=0, Fail=1
+      Element type: Power supply, subenclosure id: 0 [ti=1]
+        Element 0 descriptor:
+          Predicted failure=0, Disabled=0, Swap=0, status: Not installed
+  Enclosure Status diagnostic page:
+    status descriptor list
+      Element type: Cooling, subenclosure id: 0 [ti=0]
+        Element 0 descriptor:
+          Predicted failure=0, Disabled=0, Swap=0, status: OK
+";
+
+    #[test]
+    fn flags() {
+        assert!(flag("Ident=1, Rmv=0, Fail=1", "Fail"));
+        assert!(flag("Ident=1, Rmv=0, Fail=1", "Ident"));
+        assert!(!flag("Ident=1, Rmv=0, Fail=1", "Rmv"));
+        assert!(!flag("Predicted failure=0", "Fail"));
+    }
+
+    #[test]
+    fn pages() {
+        let enclosures = parse_pages(PAGES, "ses.txt");
+        assert_eq!(enclosures.len(), 2);
+        assert_eq!(enclosures[0].name, "HGST H4060-J 3010 (5000ccab0400bc00)");
+        assert_eq!(enclosures[1].name, "ses.txt");
+
+        let elements = &enclosures[0].elements;
+        assert_eq!(elements.len(), 4);
+        assert_eq!(elements[0].element_type, "Array device slot");
+        assert_eq!(elements[0].status, "OK");
+        assert!(!elements[0].fail && !elements[0].predicted_failure);
+        assert!(elements[1].predicted_failure);
+        assert_eq!(elements[2].index, 2);
+        assert_eq!(elements[2].status, "Critical");
+        assert!(elements[2].fail && elements[2].ident);
+        assert_eq!(elements[3].element_type, "Power supply");
+        assert_eq!(elements[3].status, "Not installed");
+        assert_eq!(enclosures[1].elements[0].element_type, "Cooling");
+    }
+
+    #[test]
+    fn report() {
+        let ses = SesData { enclosures: parse_pages(PAGES, "ses.txt") };
+        let mut out = Vec::new();
+        ses.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Array device slot        1 Critical, 2 OK\n"));
+        assert!(out.contains("Power supply             1 Not installed\n"));
+        assert!(out.contains("Array device slot        1      OK               \
+            predicted failure\n"));
+        assert!(out.contains("Array device slot        2      Critical         \
+            fail indicator on, ident indicator on\n"));
+        assert!(!out.contains("Power supply             0"));
+
+        let mut out = Vec::new();
+        SesData::default().write(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+}
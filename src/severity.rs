@@ -0,0 +1,148 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Mapping of event classes to severity levels.  A built-in map provides
+// sensible defaults, which can be overridden or extended by the [severity]
+// section of the configuration file.  The severity is used consistently for
+// color highlighting, filtering, failure scoring and the check mode exit
+// status.
+//
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use conf::ConfFile;
+use glob::glob_match;
+use SimpleError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+            Severity::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Severity, Box<dyn Error>> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(Box::new(SimpleError(
+                format!("invalid severity: {}", s)))),
+        }
+    }
+}
+
+impl Severity {
+    //
+    // The weight given to an ereport of this severity when scoring devices.
+    //
+    pub fn weight(self) -> f64 {
+        match self {
+            Severity::Info => 0.25,
+            Severity::Warning => 1.0,
+            Severity::Error => 3.0,
+            Severity::Critical => 5.0,
+        }
+    }
+
+    fn color(self) -> Option<&'static str> {
+        match self {
+            Severity::Info => None,
+            Severity::Warning => Some("\x1b[33m"),
+            Severity::Error => Some("\x1b[31m"),
+            Severity::Critical => Some("\x1b[1;31m"),
+        }
+    }
+
+    //
+    // Wrap the text in the ANSI color escape sequence for this severity.
+    //
+    pub fn paint(self, text: &str, enabled: bool) -> String {
+        match self.color() {
+            Some(color) if enabled => format!("{}{}\x1b[0m", color, text),
+            _ => text.to_string(),
+        }
+    }
+}
+
+//
+// The built-in severity map.  The first matching glob wins, so more specific
+// patterns must come before more general ones.
+//
+const DEFAULT_MAP: [(&str, Severity); 18] = [
+    ("ereport.io.scsi.cmd.disk.recovered", Severity::Info),
+    ("ereport.io.scsi.cmd.disk.dev.rqs.merr", Severity::Critical),
+    ("ereport.io.scsi.cmd.disk.dev.rqs.derr", Severity::Error),
+    ("ereport.io.scsi.cmd.disk.dev.serr", Severity::Error),
+    ("ereport.io.scsi.cmd.disk.dev.uderr", Severity::Error),
+    ("ereport.io.scsi.cmd.disk.tran", Severity::Warning),
+    ("ereport.io.service.lost", Severity::Critical),
+    ("ereport.io.pciex.*.ce*", Severity::Warning),
+    ("ereport.io.pciex.*", Severity::Error),
+    ("ereport.io.pci.fabric", Severity::Warning),
+    ("ereport.cpu.*.uc*", Severity::Critical),
+    ("ereport.cpu.*", Severity::Warning),
+    ("ereport.fm.*", Severity::Info),
+    ("ereport.sel.*uncorrectable*", Severity::Critical),
+    ("ereport.sel.*failure*", Severity::Error),
+    ("ereport.sel.*critical*", Severity::Error),
+    ("ereport.sel.*lost*", Severity::Error),
+    ("ereport.*", Severity::Warning),
+];
+
+#[derive(Debug)]
+pub struct SeverityMap {
+    entries: Vec<(String, Severity)>,
+}
+
+impl Default for SeverityMap {
+    fn default() -> SeverityMap {
+        let entries = DEFAULT_MAP.iter()
+            .map(|(glob, sev)| (glob.to_string(), *sev))
+            .collect();
+        SeverityMap { entries }
+    }
+}
+
+impl SeverityMap {
+    //
+    // Build the severity map from the built-in defaults, with the entries in
+    // the [severity] section of the configuration file taking precedence.
+    //
+    pub fn from_conf(conf: &ConfFile) -> Result<SeverityMap, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for (glob, level) in conf.section("severity") {
+            entries.push((glob.to_string(), level.parse::<Severity>()?));
+        }
+        entries.extend(SeverityMap::default().entries);
+        Ok(SeverityMap { entries })
+    }
+
+    pub fn lookup(&self, class: &str) -> Severity {
+        self.entries.iter()
+            .find(|(glob, _)| glob_match(glob, class))
+            .map(|(_, sev)| *sev)
+            .unwrap_or(Severity::Info)
+    }
+}
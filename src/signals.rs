@@ -0,0 +1,139 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Minimal signal handling for serve mode, which needs to shut down cleanly on
+// SIGTERM (as sent by SMF and systemd when stopping a service) and to reload
+// its data on SIGHUP, and for follow mode, which runs until it's interrupted.
+// The handlers only record that the signal arrived; the server polls for that
+// between requests, and follow mode between reads of the log.
+//
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// These numbers are the same on illumos and Linux.
+const SIGHUP: c_int = 1;
+const SIGINT: c_int = 2;
+const SIGTERM: c_int = 15;
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+//
+// The handlers are installed with sigaction() rather than signal(), as on
+// illumos the latter has the System V semantics of resetting the disposition
+// once the handler has run, so that a second SIGHUP would kill the process.
+// There's no libc crate to hand, so struct sigaction is laid out here for
+// each platform.
+//
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+mod sys {
+    use std::os::raw::c_int;
+
+    const SA_RESTART: c_int = 0x4;
+
+    #[repr(C)]
+    pub struct SigAction {
+        sa_flags: c_int,
+        sa_handler: extern "C" fn(c_int),
+        sa_mask: [u32; 4],
+    }
+
+    impl SigAction {
+        pub fn new(handler: extern "C" fn(c_int)) -> SigAction {
+            SigAction { sa_flags: SA_RESTART, sa_handler: handler,
+                sa_mask: [0; 4] }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::os::raw::c_int;
+
+    const SA_RESTART: c_int = 0x10000000;
+
+    #[repr(C)]
+    pub struct SigAction {
+        sa_handler: extern "C" fn(c_int),
+        sa_mask: [u64; 16],
+        sa_flags: c_int,
+        sa_restorer: usize,
+    }
+
+    impl SigAction {
+        pub fn new(handler: extern "C" fn(c_int)) -> SigAction {
+            SigAction { sa_handler: handler, sa_mask: [0; 16],
+                sa_flags: SA_RESTART, sa_restorer: 0 }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use std::os::raw::c_int;
+
+    const SA_RESTART: c_int = 0x2;
+
+    #[repr(C)]
+    pub struct SigAction {
+        sa_handler: extern "C" fn(c_int),
+        sa_mask: u32,
+        sa_flags: c_int,
+    }
+
+    impl SigAction {
+        pub fn new(handler: extern "C" fn(c_int)) -> SigAction {
+            SigAction { sa_handler: handler, sa_mask: 0,
+                sa_flags: SA_RESTART }
+        }
+    }
+}
+
+extern "C" {
+    fn sigaction(
+        signum: c_int,
+        act: *const sys::SigAction,
+        oldact: *mut sys::SigAction
+    ) -> c_int;
+}
+
+extern "C" fn handler(signum: c_int) {
+    match signum {
+        SIGHUP => RELOAD.store(true, Ordering::SeqCst),
+        _ => SHUTDOWN.store(true, Ordering::SeqCst),
+    }
+}
+
+pub fn install() {
+    for signum in &[SIGHUP, SIGINT, SIGTERM] {
+        //
+        // Setting an atomic flag is async-signal-safe.  The signal mask is
+        // empty, SA_RESETHAND is left out so that the handler stays
+        // installed, and SA_RESTART has system calls interrupted by the
+        // signal restarted rather than failing.
+        //
+        let act = sys::SigAction::new(handler);
+        unsafe {
+            sigaction(*signum, &act, ptr::null_mut());
+        }
+    }
+}
+
+//
+// Returns true if a shutdown has been requested.
+//
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+//
+// Returns true, once, for each reload requested.
+//
+pub fn take_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
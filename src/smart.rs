@@ -0,0 +1,192 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for augmenting the report for each disk with a summary of its SMART
+// attributes, as collected by running "smartctl -j -a" against each disk.
+//
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+
+use hwgrok::HwGrok;
+use i18n::tr;
+
+// ATA SMART attribute IDs that we summarize
+const ATA_REALLOCATED: u64 = 5;
+const ATA_POWER_ON_HOURS: u64 = 9;
+const ATA_WEAR_LEVELING: u64 = 177;
+const ATA_PENDING: u64 = 197;
+const ATA_CRC_ERRORS: u64 = 199;
+const ATA_MEDIA_WEAROUT: u64 = 233;
+
+//
+// A compact summary of the SMART data for a single disk.  Not every attribute
+// is available for every type of disk, hence the Options.
+//
+#[derive(Debug, Default)]
+pub struct SmartSummary {
+    pub health_passed: Option<bool>,
+    pub reallocated: Option<u64>,
+    pub pending: Option<u64>,
+    pub crc_errors: Option<u64>,
+    pub power_on_hours: Option<u64>,
+    // percentage of rated endurance used, for SSDs
+    pub wear_pct: Option<u64>,
+    pub temperature: Option<u64>,
+}
+
+//
+// SMART summaries, hashed by disk serial number.
+//
+#[derive(Debug, Default)]
+pub struct SmartData {
+    disks: HashMap<String, SmartSummary>,
+}
+
+fn ata_attr_raw(doc: &Value, id: u64) -> Option<u64> {
+    let table = doc.pointer("/ata_smart_attributes/table")?.as_array()?;
+    for attr in table {
+        if attr.get("id").and_then(|i| i.as_u64()) == Some(id) {
+            return attr.pointer("/raw/value").and_then(|v| v.as_u64());
+        }
+    }
+    None
+}
+
+fn ata_attr_value(doc: &Value, id: u64) -> Option<u64> {
+    let table = doc.pointer("/ata_smart_attributes/table")?.as_array()?;
+    for attr in table {
+        if attr.get("id").and_then(|i| i.as_u64()) == Some(id) {
+            return attr.get("value").and_then(|v| v.as_u64());
+        }
+    }
+    None
+}
+
+fn u64_at(doc: &Value, pointer: &str) -> Option<u64> {
+    doc.pointer(pointer).and_then(|v| v.as_u64())
+}
+
+impl SmartSummary {
+    fn from_json(doc: &Value) -> SmartSummary {
+        //
+        // Wear is reported as percentage used by NVMe devices, but ATA SSDs
+        // report a normalized value that counts down from 100.
+        //
+        let wear_pct = u64_at(doc,
+            "/nvme_smart_health_information_log/percentage_used")
+            .or_else(|| ata_attr_value(doc, ATA_MEDIA_WEAROUT)
+                .or_else(|| ata_attr_value(doc, ATA_WEAR_LEVELING))
+                .map(|v| 100u64.saturating_sub(v)))
+            .or_else(|| u64_at(doc,
+                "/scsi_percentage_used_endurance_indicator"));
+
+        SmartSummary {
+            health_passed: doc.pointer("/smart_status/passed")
+                .and_then(|v| v.as_bool()),
+            reallocated: ata_attr_raw(doc, ATA_REALLOCATED)
+                .or_else(|| u64_at(doc, "/scsi_grown_defect_list")),
+            pending: ata_attr_raw(doc, ATA_PENDING),
+            crc_errors: ata_attr_raw(doc, ATA_CRC_ERRORS)
+                .or_else(|| u64_at(doc,
+                    "/nvme_smart_health_information_log/media_errors")),
+            power_on_hours: u64_at(doc, "/power_on_time/hours")
+                .or_else(|| ata_attr_raw(doc, ATA_POWER_ON_HOURS)),
+            wear_pct,
+            temperature: u64_at(doc, "/temperature/current"),
+        }
+    }
+}
+
+impl SmartData {
+    //
+    // The file may contain a single smartctl JSON document, a JSON array of
+    // them, or several documents concatenated together (e.g. the result of
+    // running smartctl in a loop over all disks).
+    //
+    pub fn from_file(path: &str) -> Result<SmartData, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut disks = HashMap::new();
+
+        let stream = serde_json::Deserializer::from_str(&contents)
+            .into_iter::<Value>();
+        for doc in stream {
+            let doc = doc?;
+            let docs = match doc {
+                Value::Array(docs) => docs,
+                doc => vec![doc],
+            };
+            for doc in docs {
+                let serial = match doc.get("serial_number")
+                    .and_then(|s| s.as_str()) {
+                    Some(s) => s.trim().to_string(),
+                    None => continue,
+                };
+                disks.insert(serial, SmartSummary::from_json(&doc));
+            }
+        }
+
+        Ok(SmartData { disks })
+    }
+
+    pub fn lookup(&self, devpath: &str, hwgrok: &HwGrok)
+        -> Option<&SmartSummary> {
+
+        let (_, disk) = hwgrok.find_disk(devpath)?;
+        self.disks.get(disk.disk_serial_number.trim())
+    }
+}
+
+fn fmt_opt(val: Option<u64>, suffix: &str) -> String {
+    match val {
+        Some(v) => format!("{}{}", v, suffix),
+        None => "-".to_string(),
+    }
+}
+
+//
+// Write a compact table of the SMART attributes for the disk at this device
+// path, if we have any.
+//
+pub fn write_smart(
+    out: &mut dyn Write,
+    devpath: &str,
+    smart: &SmartData,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let summary = match smart.lookup(devpath, hwgrok) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    let health = match summary.health_passed {
+        Some(true) => "PASSED",
+        Some(false) => "FAILED",
+        None => "-",
+    };
+    let headings = [
+        ("health", "realloc", "pending", "crc/media", "power-on", "wear",
+            "temp"),
+        ("------", "-------", "-------", "---------", "--------", "----",
+            "----"),
+    ];
+    writeln!(out, "{}", tr("SMART Attributes"))?;
+    for h in &headings {
+        writeln!(out, "{0: <8} {1: <9} {2: <9} {3: <11} {4: <10} {5: <6} {6}",
+            h.0, h.1, h.2, h.3, h.4, h.5, h.6)?;
+    }
+    writeln!(out, "{0: <8} {1: <9} {2: <9} {3: <11} {4: <10} {5: <6} {6}\n",
+        health, fmt_opt(summary.reallocated, ""), fmt_opt(summary.pending, ""),
+        fmt_opt(summary.crc_errors, ""), fmt_opt(summary.power_on_hours, "h"),
+        fmt_opt(summary.wear_pct, "%"), fmt_opt(summary.temperature, "C"))?;
+
+    Ok(())
+}
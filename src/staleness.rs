@@ -0,0 +1,82 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --max-age, a watchdog for the FM log itself.  A log whose
+// newest event is older than the given age, or which has no events at all,
+// most likely means that fmd (or whatever feeds the log) has stopped logging
+// rather than that the hardware is healthy, and aggregating it would quietly
+// report a clean machine.  The run warns of a stale log, and check mode
+// treats it as critical.  The age is given as a number of seconds, or with a
+// suffix of m, h, d or w for minutes, hours, days or weeks, e.g. "2d".
+//
+use chrono::DateTime;
+
+use std::error::Error;
+
+use SimpleError;
+
+//
+// Parse a maximum age, returning it in seconds.
+//
+pub fn parse_age(s: &str) -> Result<i64, Box<dyn Error>> {
+    let s = s.trim();
+    let (digits, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c),
+        _ => (s, 's'),
+    };
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => 0,
+    };
+    match digits.parse::<i64>().ok().and_then(|n| n.checked_mul(scale)) {
+        Some(secs) if secs > 0 => Ok(secs),
+        _ => Err(Box::new(SimpleError(format!("invalid maximum age: {}", s)))),
+    }
+}
+
+//
+// Describe a number of seconds in its two largest units, e.g. "3d 4h".
+//
+fn format_age(secs: i64) -> String {
+    let units = [("w", 7 * 24 * 60 * 60), ("d", 24 * 60 * 60),
+        ("h", 60 * 60), ("m", 60), ("s", 1)];
+    let mut parts = Vec::new();
+    let mut rest = secs.max(0);
+    for (name, size) in units.iter() {
+        if rest >= *size || (parts.is_empty() && *size == 1) {
+            parts.push(format!("{}{}", rest / size, name));
+            rest %= size;
+        }
+        if parts.len() == 2 || (!parts.is_empty() && rest == 0) {
+            break;
+        }
+    }
+    parts.join(" ")
+}
+
+//
+// Returns a description of why the log is stale, if it is: either it has no
+// events, or its newest event is older than the maximum age.
+//
+pub fn check(max_age: i64, newest: Option<i64>, now: i64) -> Option<String> {
+    let newest = match newest {
+        Some(t) => t,
+        None => return Some("the FM log contains no events".to_string()),
+    };
+    let age = now - newest;
+    if age <= max_age {
+        return None;
+    }
+    let time = DateTime::from_timestamp(newest, 0).unwrap_or_default();
+    Some(format!("the newest event in the FM log, at {}, is {} old \
+        (maximum {})", time.format("%Y-%m-%d %H:%M:%S"), format_age(age),
+        format_age(max_age)))
+}
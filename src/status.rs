@@ -0,0 +1,185 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Per-device status badges.  Each device is given a single verdict (OK,
+// WATCH, DEGRADED or CRITICAL) which is carried into every output format and
+// check mode, so that everything consuming the report agrees on the state of
+// a device.  The status is the worst of:
+//
+//   - the severity of the device's worst ereport class: warning gives WATCH,
+//     error DEGRADED and critical CRITICAL, passing over classes logged at
+//     no more than their expected background rate (see expected.rs)
+//   - ereports logged at more than their expected rate (WATCH)
+//   - a sensor of the device beyond its warning (WATCH) or critical
+//     (CRITICAL) threshold
+//   - a failure score on the "replace soon" list (DEGRADED)
+//   - a fault diagnosed against the device (CRITICAL)
+//
+// and is then raised a level if the device's error rate has jumped (see
+// rates.rs), to at least WATCH.  The reasons behind a status other than OK
+// are listed with it.
+//
+use std::collections::HashMap;
+use std::fmt;
+
+use analysis;
+use devpath::DeviceKey;
+use severity::Severity;
+use thresholds::{self, Breach};
+use {DeviceHash, ReportData};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok,
+    Watch,
+    Degraded,
+    Critical,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "OK"),
+            Status::Watch => write!(f, "WATCH"),
+            Status::Degraded => write!(f, "DEGRADED"),
+            Status::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+impl Status {
+    //
+    // The severity whose color the badge is highlighted in.
+    //
+    pub fn severity(self) -> Severity {
+        match self {
+            Status::Ok => Severity::Info,
+            Status::Watch => Severity::Warning,
+            Status::Degraded => Severity::Error,
+            Status::Critical => Severity::Critical,
+        }
+    }
+
+    fn raise(self) -> Status {
+        match self {
+            Status::Ok => Status::Watch,
+            Status::Watch => Status::Degraded,
+            Status::Degraded | Status::Critical => Status::Critical,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeviceStatus {
+    pub status: Status,
+    pub reasons: Vec<String>,
+}
+
+impl fmt::Display for DeviceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.reasons.is_empty() {
+            write!(f, "{}", self.status)
+        } else {
+            write!(f, "{} - {}", self.status, self.reasons.join("; "))
+        }
+    }
+}
+
+impl DeviceStatus {
+    fn ok() -> DeviceStatus {
+        DeviceStatus { status: Status::Ok, reasons: Vec::new() }
+    }
+
+    fn escalate(&mut self, status: Status, reason: String) {
+        self.status = self.status.max(status);
+        self.reasons.push(reason);
+    }
+}
+
+//
+// Returns the status of every device in the device hash.
+//
+pub fn evaluate(
+    device_hash: &DeviceHash,
+    data: &ReportData
+) -> HashMap<DeviceKey, DeviceStatus> {
+
+    let mut statuses: HashMap<DeviceKey, DeviceStatus> = device_hash.keys()
+        .map(|devpath| (devpath.clone(), DeviceStatus::ok()))
+        .collect();
+
+    let background = match &data.expected_rates {
+        Some(expected_rates) => {
+            expected_rates.background(device_hash, &data.drivers)
+        }
+        None => HashMap::new(),
+    };
+
+    for (devpath, devent) in device_hash.iter() {
+        let status = statuses.get_mut(devpath).unwrap();
+        let noise = background.get(devpath.as_str());
+        let worst = devent.ereport_class_hash.keys()
+            .filter(|class| !noise.is_some_and(|n| n.contains(class.as_str())))
+            .map(|class| (data.severity.lookup(class), class.as_str()))
+            .max();
+        if let Some((sev, class)) = worst {
+            let badge = match sev {
+                Severity::Info => Status::Ok,
+                Severity::Warning => Status::Watch,
+                Severity::Error => Status::Degraded,
+                Severity::Critical => Status::Critical,
+            };
+            if badge > Status::Ok {
+                status.escalate(badge, format!("worst class {} ({})", class,
+                    sev));
+            }
+        }
+        if data.faults.implicates(devpath) {
+            status.escalate(Status::Critical, "fault diagnosed".to_string());
+        }
+    }
+
+    if let Some(expected_rates) = &data.expected_rates {
+        for a in expected_rates.assess(device_hash, &data.drivers).iter()
+            .filter(|a| a.exceeded()) {
+            statuses.get_mut(a.devpath).unwrap().escalate(Status::Watch,
+                format!("{} at {:.1}/day, expected {:.1}/day", a.expectation,
+                a.rate, a.expected));
+        }
+    }
+
+    for (label, sensor, breach) in thresholds::find_breaches(&data.hwgrok) {
+        let devpaths = device_hash.keys().filter(|devpath|
+            data.hwgrok.location_label(devpath) == Some(label));
+        for devpath in devpaths {
+            let badge = match breach {
+                Breach::Warning => Status::Watch,
+                Breach::Critical => Status::Critical,
+            };
+            statuses.get_mut(devpath).unwrap().escalate(badge,
+                format!("{} {}", sensor.sensor_name,
+                breach.to_string().to_lowercase()));
+        }
+    }
+
+    for score in analysis::score_disks(device_hash, data).iter()
+        .filter(|s| s.score >= analysis::REPLACE_SOON_SCORE) {
+        statuses.get_mut(score.devpath).unwrap().escalate(Status::Degraded,
+            format!("failure score {}", score.score));
+    }
+
+    if let Some(rate_alerts) = &data.rate_alerts {
+        for alert in rate_alerts.evaluate(device_hash) {
+            let status = statuses.get_mut(alert.devpath).unwrap();
+            let badge = status.status.raise();
+            status.escalate(badge, format!("error rate up from {:.1}/day to \
+                {:.1}/day", alert.baseline_rate, alert.recent_rate));
+        }
+    }
+
+    statuses
+}
@@ -0,0 +1,225 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --summary, which writes a compact, single-line JSON summary of
+// the run to a file or an already open file descriptor, so that wrappers can
+// learn the outcome without parsing the report itself.  The summary records
+// the exit status and the reason for it, the numbers of devices and ereports
+// (with the devices counted by the severity of their worst ereport), and any
+// thresholds that were breached: tripped --fail-on-class gates, devices with
+// rising error rates and sensors beyond their thresholds.
+//
+use serde_json::Value;
+
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::os::raw::c_int;
+use std::os::unix::io::FromRawFd;
+use std::str::FromStr;
+
+use gate::ClassGate;
+use thresholds;
+use {DeviceHash, Mode, ReportData, Severity, SimpleError, CHECK_UNKNOWN};
+
+// This value is the same on illumos and Linux.
+const F_GETFD: c_int = 1;
+
+// There's no libc crate to hand, so fcntl() is declared here.
+extern "C" {
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+}
+
+#[derive(Debug)]
+pub enum SummaryDest {
+    // A file descriptor inherited from the caller, given as "fd:N"
+    Fd(i32),
+    File(String),
+}
+
+impl FromStr for SummaryDest {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<SummaryDest, Box<dyn Error>> {
+        match s.strip_prefix("fd:") {
+            Some(fd) => match fd.parse::<i32>() {
+                Ok(fd) if fd >= 0 => Ok(SummaryDest::Fd(fd)),
+                _ => Err(Box::new(SimpleError(
+                    format!("invalid summary file descriptor: {}", s)))),
+            },
+            None if s.is_empty() => Err(Box::new(SimpleError(
+                "invalid summary file: empty path".to_string()))),
+            None => Ok(SummaryDest::File(s.to_string())),
+        }
+    }
+}
+
+//
+// What's known about the run, filled in as it progresses.
+//
+#[derive(Default)]
+pub struct RunSummary {
+    pub reason: Option<&'static str>,
+    counts: Option<Value>,
+    gates: Vec<Value>,
+    rate_alerts: Vec<String>,
+    sensor_breaches: Vec<Value>,
+}
+
+impl RunSummary {
+    //
+    // Record the numbers of devices and ereports and the thresholds breached
+    // in the device hash and its supplementary data.
+    //
+    pub fn record_report(&mut self, device_hash: &DeviceHash,
+        data: &ReportData) {
+
+        let mut by_severity = [0; 4];
+        for devent in device_hash.values() {
+            let worst = devent.ereport_class_hash.keys()
+                .map(|class| data.severity.lookup(class))
+                .max();
+            if let Some(sev) = worst {
+                by_severity[sev as usize] += 1;
+            }
+        }
+        self.counts = Some(json!({
+            "devices": device_hash.len(),
+            "ereports": device_hash.values()
+                .map(|devent| devent.ereports.len()).sum::<usize>(),
+            "devices_by_severity": {
+                "info": by_severity[Severity::Info as usize],
+                "warning": by_severity[Severity::Warning as usize],
+                "error": by_severity[Severity::Error as usize],
+                "critical": by_severity[Severity::Critical as usize],
+            },
+        }));
+
+        if let Some(rate_alerts) = &data.rate_alerts {
+            let mut devpaths: Vec<String> = rate_alerts.evaluate(device_hash)
+                .iter().map(|alert| alert.devpath.to_string()).collect();
+            devpaths.sort();
+            self.rate_alerts = devpaths;
+        }
+        self.sensor_breaches = thresholds::find_breaches(&data.hwgrok).iter()
+            .map(|(label, sensor, breach)| json!({
+                "component": label,
+                "sensor": sensor.sensor_name,
+                "status": breach.to_string(),
+            }))
+            .collect();
+    }
+
+    pub fn record_gates(&mut self, tripped: &[(&ClassGate, usize)]) {
+        self.gates = tripped.iter()
+            .map(|(gate, count)| json!({
+                "class": gate.pattern,
+                "count": count,
+                "limit": gate.count,
+            }))
+            .collect();
+    }
+
+    fn to_json(&self, mode: &Mode, result: &Result<i32, Box<dyn Error>>)
+        -> Value {
+
+        let name = match mode {
+            Mode::Report => "report",
+            Mode::Device(_) => "device",
+            Mode::Fleet(_) => "fleet",
+            Mode::Check => "check",
+            Mode::Serve(_) => "serve",
+            Mode::CheckHwgrok(_) => "check-hwgrok",
+            Mode::Forward => "forward",
+            Mode::Generate(_) => "generate",
+        };
+        let (status, reason, error) = match result {
+            Ok(status) => (json!(status),
+                self.reason.unwrap_or("completed"), Value::Null),
+            // the status with which main() exits on an error
+            Err(e) => (json!(match mode {
+                Mode::Check => CHECK_UNKNOWN,
+                _ => 1,
+            }), "error", json!(e.to_string())),
+        };
+        let mut summary = json!({
+            "mode": name,
+            "status": status,
+            "reason": reason,
+            "error": error,
+            "fail_on_class": self.gates,
+            "rate_alerts": self.rate_alerts,
+            "sensor_breaches": self.sensor_breaches,
+        });
+        if let (Some(Value::Object(counts)), Value::Object(obj)) =
+            (&self.counts, &mut summary) {
+            for (key, value) in counts {
+                obj.insert(key.clone(), value.clone());
+            }
+        }
+        summary
+    }
+
+    pub fn write(
+        &self,
+        dest: &SummaryDest,
+        mode: &Mode,
+        result: &Result<i32, Box<dyn Error>>
+    ) -> Result<(), Box<dyn Error>> {
+
+        let mut line = serde_json::to_vec(&self.to_json(mode, result))?;
+        line.push(b'\n');
+        match dest {
+            SummaryDest::Fd(fd) => {
+                //
+                // The descriptor belongs to the caller, so it's left open
+                // (it may well be stdout or stderr).  It's checked to be
+                // open first, as a File mustn't be made from a descriptor
+                // that isn't, or that may later be opened for something
+                // else.
+                //
+                if unsafe { fcntl(*fd, F_GETFD) } == -1 {
+                    return Err(Box::new(SimpleError(format!(
+                        "summary file descriptor {}: {}", fd,
+                        io::Error::last_os_error()))));
+                }
+                let mut file = ManuallyDrop::new(unsafe {
+                    File::from_raw_fd(*fd)
+                });
+                file.write_all(&line)?;
+            }
+            SummaryDest::File(path) => {
+                File::create(path)?.write_all(&line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunSummary, SummaryDest};
+    use Mode;
+
+    #[test]
+    fn destinations() {
+        assert!(matches!("fd:3".parse(), Ok(SummaryDest::Fd(3))));
+        assert!(matches!("out.json".parse(), Ok(SummaryDest::File(_))));
+        for dest in &["fd:-1", "fd:x", "fd:", ""] {
+            assert!(dest.parse::<SummaryDest>().is_err());
+        }
+    }
+
+    #[test]
+    fn closed_fd() {
+        let summary = RunSummary::default();
+        assert!(summary.write(&SummaryDest::Fd(i32::MAX), &Mode::Report,
+            &Ok(0)).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Correlation of component temperatures with error rates.  When a component's
+// errors rise and fall with its temperature, a cooling problem is a more likely
+// culprit than the part itself.  Conversely, errors that are independent of
+// temperature point at the part.
+//
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+
+use devpath;
+use i18n::tr;
+use {get_event_timestamp, parse_timestamp, DeviceHash, ReportData, SimpleError};
+
+// The minimum number of days of data needed to compute a correlation
+const MIN_DAYS: usize = 3;
+
+// Correlation coefficients at or above which we consider there to be a link
+const STRONG_CORRELATION: f64 = 0.7;
+const MODERATE_CORRELATION: f64 = 0.4;
+
+//
+// Sensor history, as the maximum temperature reading per day, hashed by the
+// component the sensor is associated with.
+//
+#[derive(Debug, Default)]
+pub struct SensorHistory {
+    components: HashMap<String, BTreeMap<String, f64>>,
+}
+
+impl SensorHistory {
+    //
+    // The sensor history file is CSV with the columns:
+    //
+    //   timestamp,component,temperature
+    //
+    // where the timestamp is either seconds since the epoch or an RFC3339 or
+    // "YYYY-MM-DD HH:MM:SS" date, and the component is a device path, a bay
+    // label or a disk serial number.  A header line is permitted.
+    //
+    pub fn from_file(path: &str) -> Result<SensorHistory, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut components: HashMap<String, BTreeMap<String, f64>> =
+            HashMap::new();
+
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            //
+            // Device paths may themselves contain commas, so the component is
+            // everything between the first and last commas.
+            //
+            let fields: Vec<&str> = match (line.find(','), line.rfind(',')) {
+                (Some(first), Some(last)) if first < last => vec![
+                    line[..first].trim(),
+                    line[first + 1..last].trim(),
+                    line[last + 1..].trim(),
+                ],
+                _ => Vec::new(),
+            };
+            if fields.len() != 3 {
+                return Err(Box::new(SimpleError(format!(
+                    "{}:{}: expected 3 fields", path, lineno + 1))));
+            }
+            let (tod, temp) = match (parse_timestamp(fields[0]),
+                fields[2].parse::<f64>()) {
+                (Some(tod), Ok(temp)) => (tod, temp),
+                // Skip the header line, if present
+                _ if lineno == 0 => continue,
+                _ => {
+                    return Err(Box::new(SimpleError(format!(
+                        "{}:{}: invalid timestamp or temperature", path,
+                        lineno + 1))));
+                }
+            };
+
+            let component = if fields[1].starts_with('/') {
+                devpath::canonicalize(fields[1]).into()
+            } else {
+                fields[1].to_string()
+            };
+            let day = get_event_timestamp(tod);
+            let max = components.entry(component).or_default()
+                .entry(day).or_insert(temp);
+            if temp > *max {
+                *max = temp;
+            }
+        }
+
+        Ok(SensorHistory { components })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    //
+    // Find the sensor history for a device, either by its device path or via
+    // the bay label or serial number of the disk at that path.
+    //
+    fn lookup(&self, devpath: &str, data: &ReportData)
+        -> Option<&BTreeMap<String, f64>> {
+
+        data.hwgrok.identifiers(devpath).iter()
+            .find_map(|id| self.components.get(*id))
+    }
+}
+
+//
+// Pearson's correlation coefficient of two equal-length series.
+//
+fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    let n = xs.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let mut cov = 0.0;
+    let mut var_x = 0.0;
+    let mut var_y = 0.0;
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    Some(cov / (var_x.sqrt() * var_y.sqrt()))
+}
+
+fn assessment(r: f64) -> &'static str {
+    if r >= STRONG_CORRELATION {
+        "strong - suspect cooling"
+    } else if r >= MODERATE_CORRELATION {
+        "moderate"
+    } else {
+        "weak - suspect the part"
+    }
+}
+
+pub fn write_correlation(
+    out: &mut dyn Write,
+    device_hash: &DeviceHash,
+    history: &SensorHistory,
+    data: &ReportData
+) -> Result<(), Box<dyn Error>> {
+
+    let mut results = Vec::new();
+    for (devpath, devent) in device_hash.iter() {
+        let temps = match history.lookup(devpath, data) {
+            Some(t) => t,
+            None => continue,
+        };
+        //
+        // Correlate over the days for which we have temperature readings,
+        // treating days without any ereports as having zero errors.
+        //
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        for (day, temp) in temps.iter() {
+            xs.push(*temp);
+            ys.push(*devent.ereport_ts_hash.get(day).unwrap_or(&0) as f64);
+        }
+        if xs.len() < MIN_DAYS {
+            continue;
+        }
+        let max_temp = xs.iter().cloned().fold(f64::MIN, f64::max);
+        results.push((devpath, xs.len(), max_temp, pearson(&xs, &ys)));
+    }
+    if results.is_empty() {
+        return Ok(());
+    }
+    results.sort_by(|a, b| a.0.cmp(b.0));
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Temperature/Error Correlation"))?;
+    let headings = [
+        ("days", "max temp", "correlation", "assessment"),
+        ("----", "--------", "-----------", "----------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <6} {1: <9} {2: <12} {3}", h.0, h.1, h.2, h.3)?;
+    }
+    for (devpath, ndays, max_temp, r) in results {
+        let (rstr, assess) = match r {
+            Some(r) => (format!("{:.2}", r), assessment(r)),
+            None => ("-".to_string(), "insufficient variation"),
+        };
+        writeln!(out, "{}", devpath)?;
+        writeln!(out, "{0: <6} {1: <9} {2: <12} {3}", ndays,
+            format!("{:.1}C", max_temp), rstr, assess)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Reporting of components whose sensor readings, as captured by hwgrok, are
+// beyond their warning or critical thresholds.  A component running hot may
+// not have generated any ereports yet, so this is reported independently of
+// the error telemetry.
+//
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+
+use hwgrok::{HwGrok, HwGrokSensor};
+use i18n::tr;
+
+#[derive(Debug, PartialEq, PartialOrd)]
+pub enum Breach {
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Breach {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breach::Warning => write!(f, "WARNING"),
+            Breach::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+//
+// Compare a sensor's reading against its thresholds.
+//
+pub fn check_sensor(sensor: &HwGrokSensor) -> Option<Breach> {
+    let reading = sensor.sensor_reading?;
+
+    let above = |t: Option<f64>| t.map(|t| reading >= t).unwrap_or(false);
+    let below = |t: Option<f64>| t.map(|t| reading <= t).unwrap_or(false);
+
+    if above(sensor.sensor_upper_critical) ||
+        below(sensor.sensor_lower_critical) {
+        Some(Breach::Critical)
+    } else if above(sensor.sensor_upper_warning) ||
+        below(sensor.sensor_lower_warning) {
+        Some(Breach::Warning)
+    } else {
+        None
+    }
+}
+
+//
+// Returns each component with sensors, identified by its label, along with
+// its sensors.
+//
+fn components(hwgrok: &HwGrok) -> Vec<(&str, &[HwGrokSensor])> {
+    let mut comps: Vec<(&str, &[HwGrokSensor])> = Vec::new();
+
+    comps.push(("chassis", &hwgrok.chassis.chassis_sensors));
+    for chip in &hwgrok.processors {
+        comps.push((&chip.chip_label, &chip.chip_sensors));
+    }
+    for psu in &hwgrok.psus {
+        comps.push((&psu.psu_label, &psu.psu_sensors));
+    }
+    for fan in &hwgrok.fans {
+        comps.push((&fan.fan_label, &fan.fan_sensors));
+    }
+    comps
+}
+
+//
+// Returns each sensor beyond its thresholds, along with the label of its
+// component.
+//
+pub fn find_breaches(hwgrok: &HwGrok) -> Vec<(&str, &HwGrokSensor, Breach)> {
+    let mut breaches = Vec::new();
+    for (label, sensors) in components(hwgrok) {
+        for sensor in sensors {
+            if let Some(breach) = check_sensor(sensor) {
+                breaches.push((label, sensor, breach));
+            }
+        }
+    }
+    breaches
+}
+
+pub fn write_breaches(
+    out: &mut dyn Write,
+    hwgrok: &HwGrok
+) -> Result<(), Box<dyn Error>> {
+
+    let breaches = find_breaches(hwgrok);
+    if breaches.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "{}", "=".repeat(75))?;
+    writeln!(out, "{}\n", tr("Sensor Threshold Breaches"))?;
+    let headings = [
+        ("component", "sensor", "reading", "threshold", "status"),
+        ("---------", "------", "-------", "---------", "------"),
+    ];
+    for h in &headings {
+        writeln!(out, "{0: <12} {1: <24} {2: <12} {3: <12} {4}", h.0, h.1,
+            h.2, h.3, h.4)?;
+    }
+    for (label, sensor, breach) in breaches {
+        let units = sensor.sensor_units.as_deref().unwrap_or("");
+        let reading = sensor.sensor_reading.unwrap();
+        //
+        // Report whichever threshold was crossed, upper or lower.
+        //
+        let threshold = match breach {
+            Breach::Critical => sensor.sensor_upper_critical
+                .filter(|t| reading >= *t)
+                .or(sensor.sensor_lower_critical),
+            Breach::Warning => sensor.sensor_upper_warning
+                .filter(|t| reading >= *t)
+                .or(sensor.sensor_lower_warning),
+        };
+        writeln!(out, "{0: <12} {1: <24} {2: <12} {3: <12} {4}", label,
+            sensor.sensor_name, format!("{}{}", reading, units),
+            format!("{}{}", threshold.unwrap_or_default(), units), breach)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
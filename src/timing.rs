@@ -0,0 +1,110 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --timing, which reports how long each phase of a run took, so
+// that it's clear whether a slow run is spending its time reading the logs or
+// parsing them, say.  The phases are:
+//
+//   read     reading the raw events from the FM log (including decompression
+//            and conversion from other input formats)
+//   parse    deserializing the events and building up the device hash
+//   enrich   reading hwgrok, SMART and the other supplementary data, and
+//            running the plugins
+//   analyze  consolidating multipathed devices and evaluating gates and
+//            thresholds
+//   render   writing out the report (including the analyses which are
+//            computed as their sections are written)
+//
+use std::time::{Duration, Instant};
+
+use log;
+use memstats::{self, MemStats};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Phase {
+    Read,
+    Parse,
+    Enrich,
+    Analyze,
+    Render,
+}
+
+const PHASE_NAMES: [&str; 5] = ["read", "parse", "enrich", "analyze", "render"];
+
+#[derive(Debug, Default)]
+pub struct Timings {
+    durations: [Duration; 5],
+    // the number of events read from the FM log
+    pub events: usize,
+    // the size of the device hash once the log had been read
+    pub memory: Option<MemStats>,
+}
+
+impl Timings {
+    pub fn add(&mut self, phase: Phase, duration: Duration) {
+        self.durations[phase as usize] += duration;
+    }
+
+    //
+    // Add the time since the given instant to the phase, returning the
+    // current instant so that consecutive phases can be timed.
+    //
+    pub fn since(&mut self, phase: Phase, start: Instant) -> Instant {
+        let now = Instant::now();
+        self.add(phase, now - start);
+        now
+    }
+
+    //
+    // Log the duration of each phase, followed by the total and the rate at
+    // which events were read and parsed, and then the memory used.  Phases
+    // which didn't take place, such as rendering in serve mode (where it
+    // happens for each request), are omitted.
+    //
+    pub fn report(&self) {
+        for (phase, duration) in PHASE_NAMES.iter().zip(self.durations.iter()) {
+            if duration.is_zero() {
+                continue;
+            }
+            log::info(&format!("timing: {: <8} {:.3}s", phase,
+                duration.as_secs_f64()),
+                &[("phase", json!(phase)),
+                ("seconds", json!(duration.as_secs_f64()))]);
+        }
+        let total: Duration = self.durations.iter().sum();
+        let ingest = (self.durations[Phase::Read as usize] +
+            self.durations[Phase::Parse as usize]).as_secs_f64();
+        let rate = if ingest > 0.0 {
+            self.events as f64 / ingest
+        } else {
+            0.0
+        };
+        log::info(&format!("timing: {} events in {:.3}s ({:.0} events/s \
+            read and parsed)", self.events, total.as_secs_f64(), rate),
+            &[("events", json!(self.events)),
+            ("seconds", json!(total.as_secs_f64())),
+            ("events_per_second", json!(rate))]);
+        if let Some(mem) = &self.memory {
+            log::info(&format!("memory: {} devices, {} ereports, {} class and \
+                {} daily tallies, ~{} ({} in ereports)", mem.devices,
+                mem.ereports, mem.class_entries, mem.daily_entries,
+                memstats::human_bytes(mem.total_bytes as u64),
+                memstats::human_bytes(mem.event_bytes as u64)),
+                &[("devices", json!(mem.devices)),
+                ("ereports", json!(mem.ereports)),
+                ("class_entries", json!(mem.class_entries)),
+                ("daily_entries", json!(mem.daily_entries)),
+                ("bytes", json!(mem.total_bytes)),
+                ("event_bytes", json!(mem.event_bytes))]);
+        }
+        if let Some(rss) = memstats::peak_rss() {
+            log::info(&format!("memory: peak RSS {}",
+                memstats::human_bytes(rss)),
+                &[("peak_rss_bytes", json!(rss))]);
+        }
+    }
+}
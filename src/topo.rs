@@ -0,0 +1,392 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Reading of the hc topology snapshot printed by fmtopo -V -j, for systems
+// which have it but not hwgrok.  The snapshot is a list of topology nodes,
+// each with its FMRI and property groups, and optionally its children:
+//
+//   [
+//     {
+//       "fmri": "hc://:product-id=S2600WF:chassis-id=AB12/chassis=0/bay=0",
+//       "property-groups": [
+//         {
+//           "name": "protocol",
+//           "properties": [
+//             { "name": "label", "type": "string", "value": "Front Disk 0" }
+//           ]
+//         }
+//       ],
+//       "children": [ ... ]
+//     },
+//     ...
+//   ]
+//
+// The snapshot is converted to the HwGrok structures, so that the rest of
+// the report uses it just as it would hwgrok's output.  The node's type is
+// the hc-name of the last component of its FMRI, and these are used:
+//
+//   chassis            the manufacturer, product and serial number, falling
+//                      back to the product-id and chassis-id of the authority
+//   bay, disk          drive bays, and the disk in each; the disk's device
+//                      path is its devfs-path, and its devid, serial number,
+//                      model and so on are from its storage properties
+//   pciexfn, pcifn     PCI devices, by their dev property
+//   chip               processors, with the number of cores and of strands
+//                      per core counted from the chip's children
+//   dimm               DIMMs, each taken to be in a slot of its own label
+//   psu, fan           power supplies and fans
+//
+// fmtopo has nothing to say about the BIOS, the service processor, network
+// interfaces, LEDs or sensor readings, so those parts of the report are left
+// out.  Properties are looked up by name in any of a node's groups.
+//
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use fmri;
+use hwgrok::{HwGrok, HwGrokDimm, HwGrokDimmSlot, HwGrokDisk, HwGrokDriveBay,
+    HwGrokFan, HwGrokPSU, HwGrokPciDevices, HwGrokProcessors};
+use FmLogError;
+
+#[derive(Debug, Deserialize)]
+struct TopoProperty {
+    name: String,
+    #[serde(default)]
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopoGroup {
+    #[serde(default)]
+    properties: Vec<TopoProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopoNode {
+    fmri: String,
+    #[serde(rename = "property-groups", default)]
+    groups: Vec<TopoGroup>,
+    #[serde(default)]
+    children: Vec<TopoNode>,
+}
+
+impl TopoNode {
+    fn prop(&self, name: &str) -> Option<&Value> {
+        self.groups.iter()
+            .flat_map(|g| g.properties.iter())
+            .find(|p| p.name == name)
+            .map(|p| &p.value)
+    }
+
+    //
+    // Returns the value of the first of the named properties the node has,
+    // as a string, or an empty string if it has none of them.
+    //
+    fn string(&self, names: &[&str]) -> String {
+        names.iter()
+            .filter_map(|name| match self.prop(name)? {
+                Value::String(s) => Some(s.trim().to_string()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .find(|s| !s.is_empty())
+            .unwrap_or_default()
+    }
+
+    fn number(&self, names: &[&str]) -> Option<u64> {
+        names.iter().find_map(|name| match self.prop(name)? {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        })
+    }
+
+    fn label(&self) -> String {
+        self.string(&["label"])
+    }
+}
+
+//
+// Returns the hc-name of the last component of an FMRI, and the key of the
+// FMRI of its parent.
+//
+fn split_fmri(fmri: &str) -> (String, String) {
+    let key = fmri::from_str(fmri);
+    match key.rsplit_once('/') {
+        Some((parent, last)) => {
+            let name = last.split('=').next().unwrap_or("");
+            (name.to_string(), parent.to_string())
+        }
+        None => (String::new(), String::new()),
+    }
+}
+
+//
+// Add the node and its descendants to the list of nodes.
+//
+fn flatten<'a>(node: &'a TopoNode, nodes: &mut Vec<&'a TopoNode>) {
+    nodes.push(node);
+    for child in &node.children {
+        flatten(child, nodes);
+    }
+}
+
+fn disk(node: &TopoNode) -> HwGrokDisk {
+    HwGrokDisk {
+        disk_fmri: node.fmri.clone(),
+        disk_manufacturer: node.string(&["manufacturer"]),
+        disk_model: node.string(&["model"]),
+        disk_serial_number: node.string(&["serial-number"]),
+        disk_firmware_rev: node.string(&["firmware-revision"]),
+        disk_device_path: node.string(&["devfs-path", "dev"]),
+        disk_size: node.number(&["capacity-in-bytes"]).unwrap_or(0),
+        disk_rpm: node.number(&["speed-in-rpm"]),
+        disk_devid: node.string(&["devid"]),
+        ..Default::default()
+    }
+}
+
+//
+// Build the HwGrok structures from the topology nodes.
+//
+fn convert(roots: &[TopoNode]) -> HwGrok {
+    let mut nodes = Vec::new();
+    for root in roots {
+        flatten(root, &mut nodes);
+    }
+
+    //
+    // Count the children of each type of each node, to give the number of
+    // cores of each chip and of strands of each core.
+    //
+    let mut nchildren: BTreeMap<(String, String), u64> = BTreeMap::new();
+    for node in &nodes {
+        let (name, parent) = split_fmri(&node.fmri);
+        *nchildren.entry((parent, name)).or_insert(0) += 1;
+    }
+    let count = |fmri: &str, name: &str| nchildren
+        .get(&(fmri::from_str(fmri), name.to_string())).cloned();
+
+    let mut hwgrok = HwGrok::default();
+    let mut disks: BTreeMap<String, HwGrokDisk> = BTreeMap::new();
+    for node in &nodes {
+        let (name, parent) = split_fmri(&node.fmri);
+        match name.as_str() {
+            "chassis" => {
+                let chassis = &mut hwgrok.chassis;
+                chassis.chassis_fmri = node.fmri.clone();
+                chassis.chassis_manufacturer = node.string(&["manufacturer"]);
+                chassis.chassis_model =
+                    node.string(&["product", "product-id"]);
+                chassis.chassis_serial =
+                    node.string(&["serial-number", "chassis-id"]);
+            }
+            "bay" => hwgrok.drive_bays.push(HwGrokDriveBay {
+                bay_fmri: node.fmri.clone(),
+                bay_label: node.label(),
+                ..Default::default()
+            }),
+            "disk" => {
+                disks.insert(parent, disk(node));
+            }
+            "pciexfn" | "pcifn" => {
+                let path = node.string(&["dev"]);
+                if path.is_empty() {
+                    continue;
+                }
+                hwgrok.pci_devices.push(HwGrokPciDevices {
+                    pci_fmri: node.fmri.clone(),
+                    pci_label: node.label(),
+                    pci_vendor_name: node.string(&["vendor-name"]),
+                    pci_device_name: node.string(&["device-name"]),
+                    pci_subsystem_name: node.string(&["subsystem-name"]),
+                    pci_device_path: path,
+                });
+            }
+            "chip" => {
+                let cores = count(&node.fmri, "core");
+                let first_core = nodes.iter().find(|n| {
+                    let (name, parent) = split_fmri(&n.fmri);
+                    name == "core" && parent == fmri::from_str(&node.fmri)
+                });
+                hwgrok.processors.push(HwGrokProcessors {
+                    chip_fmri: node.fmri.clone(),
+                    chip_label: node.label(),
+                    chip_brand: node.string(&["brand"]),
+                    chip_cores: cores,
+                    chip_threads_per_core: first_core
+                        .and_then(|c| count(&c.fmri, "strand")),
+                    ..Default::default()
+                });
+            }
+            "dimm" => hwgrok.memory.push(HwGrokDimmSlot {
+                slot_fmri: node.fmri.clone(),
+                slot_label: node.label(),
+                dimm: Some(HwGrokDimm {
+                    dimm_fmri: node.fmri.clone(),
+                    dimm_manufacturer: node.string(&["manufacturer"]),
+                    dimm_part: node.string(&["part-number"]),
+                    dimm_serial: node.string(&["serial-number"]),
+                    dimm_type: node.string(&["type"]),
+                    dimm_size: node.number(&["size"]).unwrap_or(0),
+                    ..Default::default()
+                }),
+            }),
+            "psu" => hwgrok.psus.push(HwGrokPSU {
+                psu_fmri: node.fmri.clone(),
+                psu_label: node.label(),
+                psu_manufacturer: node.string(&["manufacturer"]),
+                psu_model: node.string(&["model"]),
+                psu_firmware_rev: node.string(&["firmware-revision"]),
+                ..Default::default()
+            }),
+            "fan" => hwgrok.fans.push(HwGrokFan {
+                fan_fmri: node.fmri.clone(),
+                fan_label: node.label(),
+                ..Default::default()
+            }),
+            _ => (),
+        }
+    }
+
+    //
+    // Disks are placed in their bays, and those which aren't in a bay (such
+    // as a boot device on the motherboard) are left out, as is the case with
+    // hwgrok.
+    //
+    for bay in hwgrok.drive_bays.iter_mut() {
+        bay.bay_disk = disks.remove(&fmri::from_str(&bay.bay_fmri));
+    }
+
+    if hwgrok.chassis.chassis_fmri.is_empty() {
+        if let Some(node) = nodes.first() {
+            hwgrok.chassis.chassis_model = node.string(&["product-id"]);
+            hwgrok.chassis.chassis_serial = node.string(&["chassis-id"]);
+        }
+    }
+    hwgrok
+}
+
+//
+// Parse the output of fmtopo -V -j.  Malformed input results in an error,
+// never a panic.
+//
+pub fn parse_topo(contents: &str) -> Result<HwGrok, FmLogError> {
+    let roots: Vec<TopoNode> = serde_json::from_str(contents)
+        .map_err(|e| FmLogError::TopoParse { path: None, source: e })?;
+    Ok(convert(&roots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_topo, split_fmri};
+
+    //
+    // Returns a topology node with the given FMRI, properties and children.
+    //
+    fn node(
+        fmri: &str,
+        props: serde_json::Value,
+        children: Vec<serde_json::Value>
+    ) -> serde_json::Value {
+        let properties: Vec<serde_json::Value> = props.as_object().unwrap()
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        json!({
+            "fmri": format!("hc://:product-id=S2600WF:chassis-id=AB12{}",
+                fmri),
+            "property-groups": [{ "name": "protocol",
+                "properties": properties }],
+            "children": children,
+        })
+    }
+
+    #[test]
+    fn fmris() {
+        assert_eq!(split_fmri("hc://:chassis-id=AB12/chassis=0/bay=3"),
+            ("bay".to_string(), "hc:///chassis=0".to_string()));
+        assert_eq!(split_fmri("hc:///chassis=0"),
+            ("chassis".to_string(), "hc://".to_string()));
+        assert_eq!(split_fmri("nothing"), (String::new(), String::new()));
+    }
+
+    #[test]
+    fn snapshot() {
+        let strands = |core| (0..2).map(|s| node(&format!(
+            "/motherboard=0/chip=0/core={}/strand={}", core, s), json!({}),
+            vec![])).collect();
+        let topo = json!([
+            node("/chassis=0", json!({ "manufacturer": "Intel",
+                "product": "S2600WF", "serial-number": "SN1" }), vec![
+                node("/chassis=0/bay=0", json!({ "label": "Front Disk 0" }),
+                    vec![node("/chassis=0/bay=0/disk=0", json!({
+                        "model": "HUH721212AL4200", "serial-number": "8DG1",
+                        "devfs-path": "/pci@0,0/disk@w5000cca2,0",
+                        "capacity-in-bytes": "12000138625024" }), vec![])]),
+                node("/chassis=0/bay=1", json!({ "label": "Front Disk 1" }),
+                    vec![]),
+                node("/chassis=0/psu=0", json!({ "label": "PS1",
+                    "model": "PWS-1K28P-SQ" }), vec![]),
+            ]),
+            node("/motherboard=0", json!({}), vec![
+                node("/motherboard=0/chip=0", json!({ "label": "CPU1",
+                    "brand": "Xeon" }), vec![
+                    node("/motherboard=0/chip=0/core=0", json!({}),
+                        strands(0)),
+                    node("/motherboard=0/chip=0/core=1", json!({}),
+                        strands(1)),
+                ]),
+                node("/motherboard=0/chip=0/dimm=0", json!({
+                    "label": "DIMM_A1", "size": 17179869184u64 }), vec![]),
+                node("/motherboard=0/hostbridge=0/pciexrc=0/pciexbus=1/\
+                    pciexdev=0/pciexfn=0", json!({ "label": "HBA",
+                    "dev": "/pci@0,0/pci8086,2f02@1" }), vec![]),
+                node("/motherboard=0/hostbridge=0/pciexrc=0/pciexbus=2/\
+                    pciexdev=0/pciexfn=0", json!({}), vec![]),
+                node("/motherboard=0/disk=0", json!({}), vec![]),
+            ]),
+        ]);
+
+        let hwgrok = parse_topo(&topo.to_string()).unwrap();
+        assert_eq!(hwgrok.chassis.chassis_manufacturer, "Intel");
+        assert_eq!(hwgrok.chassis.chassis_model, "S2600WF");
+        assert_eq!(hwgrok.chassis.chassis_serial, "SN1");
+
+        assert_eq!(hwgrok.drive_bays.len(), 2);
+        assert_eq!(hwgrok.drive_bays[0].bay_label, "Front Disk 0");
+        let disk = hwgrok.drive_bays[0].bay_disk.as_ref().unwrap();
+        assert_eq!(disk.disk_device_path, "/pci@0,0/disk@w5000cca2,0");
+        assert_eq!(disk.disk_size, 12000138625024);
+        assert!(hwgrok.drive_bays[1].bay_disk.is_none());
+
+        assert_eq!(hwgrok.processors.len(), 1);
+        assert_eq!(hwgrok.processors[0].chip_cores, Some(2));
+        assert_eq!(hwgrok.processors[0].chip_threads_per_core, Some(2));
+        assert_eq!(hwgrok.memory[0].slot_label, "DIMM_A1");
+        assert_eq!(hwgrok.memory[0].dimm.as_ref().unwrap().dimm_size,
+            17179869184);
+        // the PCI function without a device path is left out
+        assert_eq!(hwgrok.pci_devices.len(), 1);
+        assert_eq!(hwgrok.pci_devices[0].pci_label, "HBA");
+        assert_eq!(hwgrok.psus[0].psu_model, "PWS-1K28P-SQ");
+
+        assert!(parse_topo("{").is_err());
+        assert!(parse_topo("[{}]").is_err());
+    }
+
+    #[test]
+    fn no_chassis() {
+        let hwgrok = parse_topo(&json!([node("/motherboard=0", json!({
+            "product-id": "S2600WF", "chassis-id": "AB12" }), vec![])])
+            .to_string()).unwrap();
+        assert_eq!(hwgrok.chassis.chassis_model, "S2600WF");
+        assert_eq!(hwgrok.chassis.chassis_serial, "AB12");
+    }
+}
@@ -0,0 +1,184 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --upload, which POSTs the report, as JSON, to a central
+// collection service once it has been generated, so that many hosts can run
+// the tool locally and be reported on centrally.  The upload is done by the
+// system's curl utility, which takes care of TLS.
+//
+// The bearer token for the service, if it needs one, is read from a file and
+// handed to curl in a header file readable only by us, rather than on its
+// command line where other users could see it.  Failed uploads are retried
+// with exponential backoff, except where the service has rejected the request
+// outright (a 4xx status other than 408 or 429).
+//
+use chrono::prelude::*;
+use serde_json::Value;
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use log;
+use output::{self, Compression};
+use SimpleError;
+
+// The longest wait between attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct Upload {
+    pub url: String,
+    pub token_file: Option<String>,
+    pub retries: u32,
+    pub compress: Compression,
+}
+
+impl Upload {
+    pub fn new(url: &str) -> Result<Upload, Box<dyn Error>> {
+        if !url.starts_with("https://") {
+            return Err(Box::new(SimpleError(
+                format!("upload URL must use https: {}", url))));
+        }
+        Ok(Upload {
+            url: url.to_string(),
+            token_file: None,
+            retries: 3,
+            compress: Compression::None,
+        })
+    }
+}
+
+//
+// The header file given to curl, removed when dropped.  The purpose
+// distinguishes the files of different requests made by the same process.
+//
+pub struct HeaderFile {
+    pub path: PathBuf,
+}
+
+impl HeaderFile {
+    pub fn create(
+        purpose: &str,
+        headers: &[String]
+    ) -> Result<HeaderFile, Box<dyn Error>> {
+
+        let path = env::temp_dir().join(format!("fm-log-report-{}.{}",
+            purpose, process::id()));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        let header_file = HeaderFile { path };
+        for header in headers {
+            writeln!(file, "{}", header)?;
+        }
+        Ok(header_file)
+    }
+}
+
+impl Drop for HeaderFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+//
+// Make one attempt at the upload, returning the HTTP status, or an error if
+// no response was received.
+//
+fn post(
+    upload: &Upload,
+    headers: &HeaderFile,
+    body: &[u8]
+) -> Result<u16, Box<dyn Error>> {
+
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--request", "POST",
+            "--max-time", "120", "--output", "/dev/null",
+            "--write-out", "%{http_code}", "--data-binary", "@-"])
+        .arg("--header")
+        .arg(format!("@{}", headers.path.display()))
+        .arg(&upload.url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| SimpleError(format!("failed to run curl: {}", e)))?;
+    child.stdin.take().unwrap().write_all(body)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Box::new(SimpleError(format!("curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()))));
+    }
+    let code = String::from_utf8_lossy(&output.stdout);
+    code.trim().parse().map_err(|_| From::from(SimpleError(
+        format!("unexpected response from curl: {}", code))))
+}
+
+//
+// Upload the report, as rendered in JSON, for the given host.
+//
+pub fn upload(
+    upload: &Upload,
+    host: &str,
+    report: Value
+) -> Result<(), Box<dyn Error>> {
+
+    let document = json!({
+        "host": host,
+        "generated": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "report": report,
+    });
+    let body = output::compress(&serde_json::to_vec(&document)?,
+        upload.compress)?;
+
+    let mut headers = vec!["Content-Type: application/json".to_string()];
+    if upload.compress == Compression::Gzip {
+        headers.push("Content-Encoding: gzip".to_string());
+    }
+    if let Some(path) = &upload.token_file {
+        let token = fs::read_to_string(path)?;
+        headers.push(format!("Authorization: Bearer {}", token.trim()));
+    }
+    let headers = HeaderFile::create("upload", &headers)?;
+
+    let mut backoff = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let failure = match post(upload, &headers, &body) {
+            Ok(status) if (200..300).contains(&status) => return Ok(()),
+            Ok(status) if (400..500).contains(&status) && status != 408 &&
+                status != 429 => {
+                return Err(Box::new(SimpleError(format!(
+                    "upload to {} rejected with HTTP status {}", upload.url,
+                    status))));
+            }
+            Ok(status) => format!("HTTP status {}", status),
+            Err(e) => e.to_string(),
+        };
+        if attempt > upload.retries {
+            return Err(Box::new(SimpleError(format!(
+                "upload to {} failed after {} attempts: {}", upload.url,
+                attempt, failure))));
+        }
+        log::warn(&format!("upload attempt {} failed: {} - retrying in {}s",
+            attempt, failure, backoff.as_secs()),
+            &[("attempt", json!(attempt)), ("error", json!(failure))]);
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
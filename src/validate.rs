@@ -0,0 +1,189 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Support for --validate, which checks that the FM logs and hwgrok inputs can
+// be processed, without generating a report, so that a damaged bundle is
+// found before a long analysis run rather than part-way through it.  Each
+// event in the log is checked for:
+//
+//   - being valid JSON with a "class" member
+//   - a "__tod" member of [seconds, nanoseconds], with the time after 1990
+//     and no more than a day in the future (allowing for clock skew)
+//   - for ereports, a detector in a supported FMRI scheme with the members
+//     that scheme needs (such as "device-path" for the dev scheme)
+//
+// The hwgrok or fmtopo file, if one was given, must deserialize.
+//
+use chrono::prelude::*;
+use serde_json::Value;
+
+use std::error::Error;
+use std::io::Write;
+
+use input::InputReader;
+use {process_hwgrok_data, process_topo_data, Config, Ereport};
+
+// The number of problems listed before the rest are just counted
+const MAX_LISTED: usize = 50;
+
+// No FMA telemetry predates this (1990-01-01)
+const EARLIEST_TOD: i64 = 631152000;
+
+//
+// Check the event time, returning a description of any problem.
+//
+fn check_tod(value: &Value) -> Option<String> {
+    let tod = match value.get("__tod") {
+        Some(Value::Array(tod)) => tod,
+        Some(_) => return Some("__tod is not an array".to_string()),
+        None => return Some("no __tod member".to_string()),
+    };
+    let (secs, nsecs) = match (tod.first().and_then(|s| s.as_i64()),
+        tod.get(1).and_then(|n| n.as_i64())) {
+        (Some(s), Some(n)) if tod.len() == 2 => (s, n),
+        _ => return Some(format!("__tod is not [seconds, nanoseconds]: {}",
+            Value::Array(tod.clone()))),
+    };
+    if !(0..1_000_000_000).contains(&nsecs) {
+        return Some(format!("__tod nanoseconds out of range: {}", nsecs));
+    }
+    let latest = Utc::now().timestamp() + 86400;
+    if !(EARLIEST_TOD..=latest).contains(&secs) {
+        let when = DateTime::from_timestamp(secs, 0)
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| secs.to_string());
+        return Some(format!("implausible event time: {}", when));
+    }
+    None
+}
+
+//
+// Check that an ereport can be attributed to a device, returning a
+// description of any problem.
+//
+fn check_detector(value: &Value) -> Option<String> {
+    let mut ereport: Ereport = match serde_json::from_value(value.clone()) {
+        Ok(e) => e,
+        Err(e) => return Some(format!("malformed ereport: {}", e)),
+    };
+    let detector = &mut ereport.detector;
+    let missing = match detector.scheme.as_str() {
+        "dev" if detector.device_path.is_none() => Some("device-path"),
+        "hc" if detector.hc_list.is_none() => Some("hc-list"),
+        "fmd" if detector.mod_name.is_none() => Some("mod-name"),
+        "dev" | "hc" | "fmd" => None,
+        "cpu" | "mem" => {
+            return detector.get_fmristr().err().map(|e| e.to_string());
+        }
+        scheme => {
+            return Some(format!("unsupported detector scheme: {}", scheme));
+        }
+    };
+    missing.map(|m| format!("{}-scheme detector has no {}", detector.scheme,
+        m))
+}
+
+//
+// Check the events in the FM log at the given path, adding any problems found
+// to the list.  Returns the number of events.
+//
+fn validate_log(
+    path: &str,
+    input: &InputReader,
+    problems: &mut Vec<String>
+) -> Result<usize, Box<dyn Error>> {
+
+    let mut nevents = 0;
+
+    for (i, line) in input.read_events(path)?.enumerate() {
+        let lineno = i + 1;
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                problems.push(format!("{}: event {}: unreadable: {}",
+                    path, lineno, e));
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        nevents += 1;
+        let problem = match serde_json::from_str::<Value>(&line) {
+            Err(e) => Some(format!("invalid JSON: {}", e)),
+            Ok(value) => match value.get("class").and_then(|c| c.as_str()) {
+                None => Some("no class member".to_string()),
+                Some(class) => check_tod(&value).or_else(|| {
+                    if class.starts_with("ereport.") &&
+                        !class.starts_with("ereport.fs.") &&
+                        !class.starts_with("ereport.fm.fmd.log_") {
+                        check_detector(&value)
+                    } else {
+                        None
+                    }
+                }),
+            },
+        };
+        if let Some(p) = problem {
+            problems.push(format!("{}: event {}: {}", path, lineno, p));
+        }
+    }
+
+    Ok(nevents)
+}
+
+//
+// Check the inputs named in the configuration, writing out any problems
+// found.  Returns true if there were none.
+//
+pub fn validate(
+    out: &mut dyn Write,
+    config: &Config,
+    input: &InputReader
+) -> Result<bool, Box<dyn Error>> {
+
+    let mut problems: Vec<String> = Vec::new();
+    let mut counts = Vec::new();
+
+    for path in &config.fmlog_paths {
+        let before = problems.len();
+        let nevents = validate_log(path, input, &mut problems)?;
+        counts.push((path, nevents, problems.len() - before));
+    }
+    let log_problems = problems.len();
+
+    if let Some(path) = &config.hwgrok_path {
+        if let Err(e) = process_hwgrok_data(path) {
+            problems.push(e.to_string());
+        }
+    }
+    if let Some(path) = &config.topo_path {
+        if let Err(e) = process_topo_data(path) {
+            problems.push(e.to_string());
+        }
+    }
+
+    for p in problems.iter().take(MAX_LISTED) {
+        writeln!(out, "{}", p)?;
+    }
+    if problems.len() > MAX_LISTED {
+        writeln!(out, "... and {} more problems", problems.len() - MAX_LISTED)?;
+    }
+    for (path, nevents, nproblems) in counts {
+        writeln!(out, "{}: {} events, {} problems", path, nevents, nproblems)?;
+    }
+    let hw_path = config.hwgrok_path.as_ref().or(config.topo_path.as_ref());
+    if let Some(path) = hw_path {
+        writeln!(out, "{}: {}", path, if problems.len() > log_problems {
+            "invalid"
+        } else {
+            "ok"
+        })?;
+    }
+
+    Ok(problems.is_empty())
+}
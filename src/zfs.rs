@@ -0,0 +1,154 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2019 Joyent, Inc.
+//
+// Handling of ZFS ereports (ereport.fs.zfs.*).  These have a zfs-scheme
+// detector naming the pool and vdev by GUID rather than a device path, so
+// they can't be attributed to a device in the device hash.  Instead they are
+// grouped by pool and vdev, using the pool name, the GUIDs and the vdev's path
+// from the payload, and each pool gets a section of the report giving the
+// checksum, I/O and probe failure counts of each of its vdevs.  Ereports which
+// concern the pool as a whole, such as ereport.fs.zfs.data, have no vdev and
+// are counted against the pool itself.
+//
+// The payload is read as untyped JSON, as its numeric members (GUIDs in
+// particular) may exceed the range of the types the other ereports are
+// parsed into.
+//
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use get_event_timestamp;
+use i18n::tr;
+use numfmt::NumberFormat;
+
+pub const CLASS_PREFIX: &str = "ereport.fs.zfs.";
+
+#[derive(Debug, Default)]
+struct VdevCounts {
+    path: Option<String>,
+    checksum: u64,
+    io: u64,
+    probe: u64,
+    other: u64,
+}
+
+#[derive(Debug, Default)]
+struct Pool {
+    name: Option<String>,
+    first: i64,
+    last: i64,
+    // keyed by vdev GUID, or "" for ereports which concern the whole pool
+    vdevs: BTreeMap<String, VdevCounts>,
+}
+
+#[derive(Debug, Default)]
+pub struct ZfsPools {
+    // keyed by pool GUID
+    pools: BTreeMap<String, Pool>,
+}
+
+//
+// GUIDs are usually given as numbers, but some tools render them as strings.
+// Either way, they're shown in hex, as zdb and fmdump do.
+//
+fn guid(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::Number(n) => n.as_u64().map(|g| format!("0x{:x}", g)),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+impl ZfsPools {
+    pub fn add(&mut self, event: &Value) {
+        let class = match event.get("class").and_then(|c| c.as_str()) {
+            Some(c) => c,
+            None => return,
+        };
+        let detector = event.get("detector");
+        let pool_guid = guid(event.get("pool_guid"))
+            .or_else(|| guid(detector.and_then(|d| d.get("pool"))))
+            .unwrap_or_else(|| "-".to_string());
+        let vdev_guid = guid(event.get("vdev_guid"))
+            .or_else(|| guid(detector.and_then(|d| d.get("vdev"))))
+            .unwrap_or_default();
+        let tod = event.get("__tod").and_then(|t| t.get(0))
+            .and_then(|t| t.as_i64()).unwrap_or(0);
+
+        let pool = self.pools.entry(pool_guid).or_insert(Pool {
+            first: tod,
+            last: tod,
+            ..Pool::default()
+        });
+        if pool.name.is_none() {
+            pool.name = event.get("pool").and_then(|p| p.as_str())
+                .map(|p| p.to_string());
+        }
+        pool.first = pool.first.min(tod);
+        pool.last = pool.last.max(tod);
+
+        let vdev = pool.vdevs.entry(vdev_guid).or_default();
+        if vdev.path.is_none() {
+            vdev.path = event.get("vdev_path").and_then(|p| p.as_str())
+                .map(|p| p.to_string());
+        }
+        match &class[CLASS_PREFIX.len().min(class.len())..] {
+            "checksum" => vdev.checksum += 1,
+            "io" => vdev.io += 1,
+            "probe_failure" => vdev.probe += 1,
+            _ => vdev.other += 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    //
+    // Write a section for each pool.
+    //
+    pub fn write(
+        &self,
+        out: &mut dyn Write,
+        numbers: &NumberFormat
+    ) -> Result<(), Box<dyn Error>> {
+
+        for (pool_guid, pool) in &self.pools {
+            writeln!(out, "{}", "=".repeat(75))?;
+            writeln!(out, "{0: <40} {1}", tr("ZFS Pool:"),
+                pool.name.as_deref().unwrap_or("-"))?;
+            writeln!(out, "{0: <40} {1}", tr("Pool GUID:"), pool_guid)?;
+            writeln!(out, "{0: <40} {1} - {2}\n", tr("Period:"),
+                get_event_timestamp(pool.first),
+                get_event_timestamp(pool.last))?;
+
+            let headings = [
+                ("vdev", "checksum", "io", "probe", "other"),
+                ("----", "--------", "--", "-----", "-----"),
+            ];
+            for h in &headings {
+                writeln!(out, "{0: <40} {1: <9} {2: <7} {3: <7} {4}", h.0,
+                    h.1, h.2, h.3, h.4)?;
+            }
+            for (vdev_guid, vdev) in &pool.vdevs {
+                let name = match (&vdev.path, vdev_guid.is_empty()) {
+                    (_, true) => "(pool)".to_string(),
+                    (Some(path), false) => path.clone(),
+                    (None, false) => vdev_guid.clone(),
+                };
+                writeln!(out, "{0: <40} {1: <9} {2: <7} {3: <7} {4}", name,
+                    numbers.int(vdev.checksum), numbers.int(vdev.io),
+                    numbers.int(vdev.probe), numbers.int(vdev.other))?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}